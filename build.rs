@@ -13,8 +13,28 @@ fn main() -> io::Result<()> {
             )
             .compile()?;
     }
+    // Used by self-update to pick the release asset for this platform.
+    println!("cargo:rustc-env=TARGET={}", env::var("TARGET").unwrap());
     let mut out_dir = env::var("OUT_DIR").unwrap();
     out_dir.push_str("../../../../tracer.ino");
     let _ = fs::copy("./assets/tracer/tracer.ino", out_dir);
+    #[cfg(feature = "grpc")]
+    compile_protos()?;
     Ok(())
 }
+
+///
+/// Generate the gRPC service from proto/solar.proto, using the bundled protoc
+/// unless PROTOC points to another one.
+///
+#[cfg(feature = "grpc")]
+fn compile_protos() -> io::Result<()> {
+    if env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().map_err(io::Error::other)?;
+        env::set_var("PROTOC", protoc);
+    }
+    println!("cargo:rerun-if-changed=proto/solar.proto");
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/solar.proto"], &["proto"])
+}
@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use solar_core::datapoint::DataPoint;
+use std::hint::black_box;
+use std::str::FromStr;
+
+/// A line as sent by the controller, with the line ending already trimmed.
+const FRAME: &[u8] = b"12.64:17.90:0.80:11.10:14.40:0.00:1.00:22.50:2.50:1";
+/// The values of `FRAME`.
+const VALUES: [f64; 10] = [12.64, 17.9, 0.8, 11.1, 14.4, 0.0, 1.0, 22.5, 2.5, 1.0];
+
+fn parse_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(FRAME.len() as u64));
+    group.bench_function("bytes", |b| {
+        b.iter(|| DataPoint::parse(black_box(FRAME)).unwrap())
+    });
+    let line = std::str::from_utf8(FRAME).unwrap();
+    group.bench_function("from_str", |b| {
+        b.iter(|| DataPoint::from_str(black_box(line)).unwrap())
+    });
+    // A frame cut off by a reconnect, rejected with an error.
+    let truncated = &FRAME[..20];
+    group.bench_function("invalid", |b| {
+        b.iter(|| DataPoint::parse(black_box(truncated)).unwrap_err())
+    });
+    group.finish();
+}
+
+fn construct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("datapoint");
+    group.bench_function("new", |b| b.iter(|| DataPoint::new(black_box(&VALUES))));
+    group.bench_function("with_time", |b| {
+        b.iter(|| DataPoint::with_time(black_box(1_700_000_000), black_box(&VALUES)))
+    });
+    let datapoint = DataPoint::with_time(1_700_000_000, &VALUES);
+    group.bench_function("values", |b| b.iter(|| black_box(&datapoint).values()));
+    group.finish();
+}
+
+criterion_group!(benches, parse_frame, construct);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use solar_core::config::DatabaseConfig;
+use solar_core::database::Database;
+use solar_core::datapoint::DataPoint;
+use std::{fs, path::PathBuf, time::Duration};
+
+/// Datapoints written per transaction, from unbuffered to ten minutes of
+/// one second polls.
+const BATCH_SIZES: [usize; 4] = [1, 10, 60, 600];
+const VALUES: [f64; 10] = [12.64, 17.9, 0.8, 11.1, 14.4, 0.0, 1.0, 22.5, 2.5, 1.0];
+
+///
+/// A database file of its own in the temp directory, removed when dropped.
+///
+struct TempDatabase {
+    path: PathBuf,
+}
+
+impl TempDatabase {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("solar-bench-{}-{}.sql", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        Self { path }
+    }
+
+    fn open(&self, buffer_size: usize) -> Database {
+        let config = DatabaseConfig {
+            path: self.path.clone(),
+            buffer_size,
+            read_only: false,
+        };
+        Database::new(&config).expect("open the benchmark database")
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+///
+/// Batched inserts into the Data table, each batch written in one
+/// transaction together with the refresh of the hourly rollup.
+///
+fn batched_inserts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    group.sample_size(20);
+    for batch in BATCH_SIZES {
+        let file = TempDatabase::new(&format!("insert-{}", batch));
+        let mut database = file.open(batch);
+        let mut time = 1_700_000_000;
+        group.throughput(Throughput::Elements(batch as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(batch), &batch, |b, &batch| {
+            // The last datapoint fills the buffer, which writes the batch.
+            b.iter(|| {
+                for _ in 0..batch {
+                    time += 1;
+                    let datapoint = DataPoint::with_time(time, &VALUES);
+                    database.add_datapoint(datapoint, Some(Duration::from_secs(1)));
+                }
+            })
+        });
+        drop(database);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, batched_inserts);
+criterion_main!(benches);
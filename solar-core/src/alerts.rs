@@ -0,0 +1,715 @@
+use crate::config::{parse_duration, AlertThresholds};
+use crate::database::DATA_FIELDS;
+use crate::datapoint::DataPoint;
+use chrono::{DateTime, Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, io, str::FromStr};
+
+//
+// Structs
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A value left its allowed range.
+    Alert,
+    /// A value returned to its allowed range.
+    Cleared,
+    /// The load was switched on or off.
+    Load,
+    /// Notifications a notifier held back, see `Throttle`.
+    Summary,
+}
+
+/// How urgent an event is, ordered from `Info` to `Critical`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// Something worth notifying about, raised once per state change.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub kind: EventKind,
+    /// Name of the rule, e.g. `battery_low` or `load_on`.
+    pub name: String,
+    pub severity: Severity,
+    pub message: String,
+    pub value: f64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Below,
+    AtMost,
+    Above,
+    AtLeast,
+}
+
+/// A condition on one field, written in `[alerts] rules` as
+/// `[name:] <field>[/<unit>] <op> <threshold> [for <duration>]
+/// [between <HH:MM>-<HH:MM>] [=> <severity>]`, e.g.
+/// `battery_voltage < 11.8 for 60s => critical` or
+/// `charge_current/min < -2 between 10:00-15:00`.
+#[derive(Debug, Clone)]
+pub struct AlertRule {
+    name: String,
+    description: String,
+    field: &'static str,
+    /// Compare the change of the field per this many seconds, measured over
+    /// as many seconds, instead of its value.
+    rate: Option<i64>,
+    /// Local time of day the rule applies in. It ends the next day when its
+    /// end comes before its start.
+    hours: Option<(NaiveTime, NaiveTime)>,
+    comparison: Comparison,
+    threshold: f64,
+    /// Seconds the condition must hold before the alert is raised.
+    hold: i64,
+    severity: Severity,
+}
+
+/// Turns the stream of datapoints into alert and load events by tracking
+/// which rules are currently violated.
+pub struct AlertMonitor {
+    rules: Vec<AlertRule>,
+    /// Time of the first datapoint of the current violation of each rule,
+    /// and whether its alert was raised.
+    violations: Vec<Option<(i64, bool)>>,
+    /// Recent values of the field of each rate rule.
+    history: Vec<VecDeque<(i64, f64)>>,
+    load_on: Option<bool>,
+}
+
+//
+// implementations
+//
+impl EventKind {
+    ///
+    /// Name of the kind in notification templates.
+    ///
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Alert => "alert",
+            EventKind::Cleared => "cleared",
+            EventKind::Load => "load",
+            EventKind::Summary => "summary",
+        }
+    }
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl Event {
+    ///
+    /// The critical `link_lost` alert raised when the controller stops
+    /// answering, or its clearing once it answers again.
+    ///
+    pub fn link(lost: bool, timestamp: i64) -> Self {
+        let (kind, message) = match lost {
+            true => (EventKind::Alert, "Serial link to the controller lost"),
+            false => (EventKind::Cleared, "Serial link to the controller restored"),
+        };
+        Self {
+            kind,
+            name: "link_lost".to_string(),
+            severity: Severity::Critical,
+            message: message.to_string(),
+            value: 0.0,
+            timestamp,
+        }
+    }
+
+    ///
+    /// The `load_on` or `load_off` event of a load state change, with what
+    /// switched it, e.g. `ui`, `schedule` or `controller`.
+    ///
+    pub fn load(on: bool, source: &str, timestamp: i64) -> Self {
+        let (name, state) = match on {
+            true => ("load_on", "on"),
+            false => ("load_off", "off"),
+        };
+        Self {
+            kind: EventKind::Load,
+            name: name.to_string(),
+            severity: Severity::Info,
+            message: format!("Load switched {} ({})", state, source),
+            value: if on { 1.0 } else { 0.0 },
+            timestamp,
+        }
+    }
+
+    ///
+    /// The critical `data_stale` alert raised when no datapoint was read for
+    /// `age` seconds, or its clearing once one is read again.
+    ///
+    pub fn stale(stale: bool, age: i64, timestamp: i64) -> Self {
+        let (kind, message) = match stale {
+            true => (EventKind::Alert, format!("No datapoint read for {} s", age)),
+            false => (
+                EventKind::Cleared,
+                format!("Datapoints read again after {} s", age),
+            ),
+        };
+        Self {
+            kind,
+            name: "data_stale".to_string(),
+            severity: Severity::Critical,
+            message,
+            value: age as f64,
+            timestamp,
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Below => value < threshold,
+            Comparison::AtMost => value <= threshold,
+            Comparison::Above => value > threshold,
+            Comparison::AtLeast => value >= threshold,
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!(
+                "unknown severity {:?}, expected info, warning or critical",
+                s
+            )),
+        }
+    }
+}
+
+impl AlertRule {
+    ///
+    /// One of the rules configured by the fixed `[alerts]` limits.
+    ///
+    fn builtin(
+        name: &str,
+        description: &str,
+        field: &'static str,
+        comparison: Comparison,
+        threshold: f64,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            field,
+            rate: None,
+            hours: None,
+            comparison,
+            threshold,
+            hold: 0,
+            severity: Severity::Warning,
+        }
+    }
+}
+
+impl FromStr for AlertRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (condition, severity) = match s.split_once("=>") {
+            Some((condition, severity)) => (condition, severity.trim().parse()?),
+            None => (s, Severity::Warning),
+        };
+        let (condition, hours) = match condition.split_once(" between ") {
+            Some((condition, hours)) => (condition, Some(parse_hours(hours.trim())?)),
+            None => (condition, None),
+        };
+        let (name, condition) = match condition.split_once(':') {
+            Some((name, condition)) => (Some(name.trim()), condition.trim()),
+            None => (None, condition.trim()),
+        };
+        if name.is_some_and(|name| name.is_empty() || name.contains(char::is_whitespace)) {
+            return Err("the name before ':' must be a single word".into());
+        }
+        let tokens: Vec<&str> = condition.split_whitespace().collect();
+        let (field, comparison, threshold, hold) = match tokens[..] {
+            [field, comparison, threshold] => (field, comparison, threshold, None),
+            [field, comparison, threshold, "for", hold] => {
+                (field, comparison, threshold, Some(hold))
+            }
+            _ => return Err("expected <field> <op> <threshold> [for <duration>]".into()),
+        };
+        let (field, rate) = match field.split_once('/') {
+            Some((field, "s")) => (field, Some(1)),
+            Some((field, "min")) => (field, Some(60)),
+            Some((field, "h")) => (field, Some(3600)),
+            Some((_, per)) => {
+                return Err(format!(
+                    "unknown rate unit /{}, expected /s, /min or /h",
+                    per
+                ))
+            }
+            None => (field, None),
+        };
+        let field = DATA_FIELDS
+            .into_iter()
+            .find(|f| *f == field)
+            .ok_or_else(|| {
+                format!(
+                    "unknown field {}, expected one of {}",
+                    field,
+                    DATA_FIELDS.join(", ")
+                )
+            })?;
+        let comparison = match comparison {
+            "<" => Comparison::Below,
+            "<=" => Comparison::AtMost,
+            ">" => Comparison::Above,
+            ">=" => Comparison::AtLeast,
+            _ => {
+                return Err(format!(
+                    "unknown operator {}, expected <, <=, > or >=",
+                    comparison
+                ))
+            }
+        };
+        let threshold: f64 = threshold
+            .parse()
+            .map_err(|_| format!("invalid threshold {}", threshold))?;
+        let hold = match hold {
+            Some(hold) => parse_duration(hold)? as i64,
+            None => 0,
+        };
+        let direction = match comparison {
+            Comparison::Below | Comparison::AtMost => "below",
+            Comparison::Above | Comparison::AtLeast => "above",
+        };
+        let per = match rate {
+            Some(1) => "_per_s",
+            Some(60) => "_per_min",
+            Some(_) => "_per_h",
+            None => "",
+        };
+        Ok(Self {
+            name: name
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{}{}_{}_{}", field, per, direction, threshold)),
+            description: condition.trim().to_string(),
+            field,
+            rate,
+            hours,
+            comparison,
+            threshold,
+            hold,
+            severity,
+        })
+    }
+}
+
+impl AlertMonitor {
+    ///
+    /// Monitor the fixed limits of `[alerts]` and the rules of `alerts.rules`,
+    /// failing on a rule which cannot be parsed.
+    ///
+    pub fn new(thresholds: &AlertThresholds) -> io::Result<Self> {
+        let mut rules = builtin_rules(thresholds);
+        for rule in &thresholds.rules {
+            rules.push(rule.parse().map_err(|e| {
+                io::Error::other(format!("alerts.rules: invalid rule {:?}: {}.", rule, e))
+            })?);
+        }
+        Ok(Self {
+            violations: vec![None; rules.len()],
+            history: vec![VecDeque::new(); rules.len()],
+            rules,
+            load_on: None,
+        })
+    }
+
+    ///
+    /// Change the fixed limits of `[alerts]`, the rules are kept.
+    ///
+    pub fn set_limits(&mut self, thresholds: &AlertThresholds) {
+        for (rule, builtin) in self.rules.iter_mut().zip(builtin_rules(thresholds)) {
+            rule.threshold = builtin.threshold;
+        }
+    }
+
+    ///
+    /// Check a datapoint against the rules and return the events caused by
+    /// it. The initial load state does not raise an event.
+    ///
+    pub fn check(&mut self, datapoint: &DataPoint) -> Vec<Event> {
+        let mut events = Vec::new();
+        let timestamp = datapoint.get_time();
+        let time = DateTime::from_timestamp(timestamp, 0)
+            .map(|t| t.with_timezone(&Local).time())
+            .unwrap_or_default();
+        let rules = self.rules.iter().zip(&mut self.violations);
+        for ((rule, violation), history) in rules.zip(&mut self.history) {
+            let mut value = datapoint.get(rule.field).unwrap_or(f64::NAN);
+            if let Some(per) = rule.rate {
+                let Some(rate) = rate_of_change(history, timestamp, value, per) else {
+                    continue;
+                };
+                value = rate;
+            }
+            let violated = rule.comparison.holds(value, rule.threshold)
+                && rule
+                    .hours
+                    .is_none_or(|(start, end)| within(time, start, end));
+            let kind = match (violated, *violation) {
+                (true, None) if rule.hold > 0 => {
+                    *violation = Some((timestamp, false));
+                    continue;
+                }
+                (true, None) => {
+                    *violation = Some((timestamp, true));
+                    EventKind::Alert
+                }
+                (true, Some((since, false))) if timestamp - since >= rule.hold => {
+                    *violation = Some((since, true));
+                    EventKind::Alert
+                }
+                (false, Some((_, raised))) => {
+                    *violation = None;
+                    if !raised {
+                        continue;
+                    }
+                    EventKind::Cleared
+                }
+                _ => continue,
+            };
+            let state = match kind {
+                EventKind::Cleared => " cleared",
+                _ => "",
+            };
+            events.push(Event {
+                kind,
+                name: rule.name.clone(),
+                severity: rule.severity,
+                message: format!(
+                    "{}{}: {} {}{}",
+                    rule.description,
+                    state,
+                    value,
+                    unit(rule.field),
+                    match rule.rate {
+                        Some(1) => "/s",
+                        Some(60) => "/min",
+                        Some(_) => "/h",
+                        None => "",
+                    }
+                )
+                .trim_end()
+                .to_string(),
+                value,
+                timestamp,
+            });
+        }
+
+        let load_on = datapoint.get_load_onoff() > 0.0;
+        if self.load_on.is_some_and(|previous| previous != load_on) {
+            // Attributed to a load command by the datalogger, if one caused it.
+            events.push(Event::load(load_on, "controller", timestamp));
+        }
+        self.load_on = Some(load_on);
+        events
+    }
+}
+
+///
+/// The rules of the fixed `[alerts]` limits.
+///
+fn builtin_rules(thresholds: &AlertThresholds) -> Vec<AlertRule> {
+    vec![
+        AlertRule::builtin(
+            "battery_low",
+            "Battery voltage low",
+            "battery_voltage",
+            Comparison::Below,
+            thresholds.battery_low_voltage,
+        ),
+        AlertRule::builtin(
+            "battery_high",
+            "Battery voltage high",
+            "battery_voltage",
+            Comparison::Above,
+            thresholds.battery_high_voltage,
+        ),
+        AlertRule::builtin(
+            "battery_temp",
+            "Battery temperature high",
+            "battery_temp",
+            Comparison::Above,
+            thresholds.battery_max_temp,
+        ),
+        AlertRule::builtin(
+            "load_current",
+            "Load current high",
+            "load_current",
+            Comparison::Above,
+            thresholds.load_max_current,
+        ),
+    ]
+}
+
+///
+/// Add a value to the history of a rate rule and return its change per `per`
+/// seconds since the value at least `per` seconds before, once there is one.
+///
+fn rate_of_change(
+    history: &mut VecDeque<(i64, f64)>,
+    timestamp: i64,
+    value: f64,
+    per: i64,
+) -> Option<f64> {
+    history.push_back((timestamp, value));
+    while history
+        .get(1)
+        .is_some_and(|&(time, _)| timestamp - time >= per)
+    {
+        history.pop_front();
+    }
+    let &(since, first) = history.front()?;
+    let elapsed = timestamp - since;
+    // Rounded, as the difference of two readings is off in the last digits.
+    let rate = (value - first) * per as f64 / elapsed as f64;
+    (elapsed >= per).then(|| (rate * 1000.0).round() / 1000.0)
+}
+
+///
+/// Parse the `<HH:MM>-<HH:MM>` hours a rule applies in.
+///
+pub fn parse_hours(s: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let parse = |time: &str| {
+        NaiveTime::parse_from_str(time.trim(), "%H:%M")
+            .map_err(|_| format!("invalid time {:?}, expected HH:MM", time.trim()))
+    };
+    match s.split_once('-') {
+        Some((start, end)) => Ok((parse(start)?, parse(end)?)),
+        None => Err(format!("invalid hours {:?}, expected <HH:MM>-<HH:MM>", s)),
+    }
+}
+
+///
+/// Whether a time of day lies within hours which may span midnight.
+///
+pub fn within(time: NaiveTime, start: NaiveTime, end: NaiveTime) -> bool {
+    match start <= end {
+        true => start <= time && time < end,
+        false => time >= start || time < end,
+    }
+}
+
+///
+/// Unit appended to values of a field in messages, if it has one.
+///
+fn unit(field: &str) -> &'static str {
+    if field.ends_with("voltage") || field == "over_discharge" || field == "battery_max" {
+        "V"
+    } else if field.ends_with("current") {
+        "A"
+    } else if field == "battery_temp" {
+        "C"
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Values of the datapoints, in the order of `DataPoint::with_time`.
+    const VALUES: [f64; 10] = [13.0, 17.9, 0.8, 11.1, 14.4, 0.0, 1.0, 22.5, 8.0, 1.0];
+    const BATTERY_VOLTAGE: usize = 0;
+    const CHARGE_CURRENT: usize = 8;
+
+    fn monitor(rule: &str) -> AlertMonitor {
+        AlertMonitor::new(&AlertThresholds {
+            rules: vec![rule.to_string()],
+            ..AlertThresholds::default()
+        })
+        .expect("parse the rule")
+    }
+
+    ///
+    /// Feed the monitor a datapoint every 10 seconds from `start`, with the
+    /// value at `index` given by `value(seconds since start)`, and return the
+    /// events of `name` with their seconds since start.
+    ///
+    fn run(
+        monitor: &mut AlertMonitor,
+        name: &str,
+        start: i64,
+        index: usize,
+        value: impl Fn(i64) -> f64,
+    ) -> Vec<(i64, EventKind)> {
+        let mut events = Vec::new();
+        for t in (0..=300).step_by(10) {
+            let mut values = VALUES;
+            values[index] = value(t);
+            let datapoint = DataPoint::with_time(start + t, &values);
+            for event in monitor.check(&datapoint) {
+                if event.name == name {
+                    events.push((t, event.kind));
+                }
+            }
+        }
+        events
+    }
+
+    fn local(hour: u32, minute: u32) -> i64 {
+        Local
+            .with_ymd_and_hms(2024, 6, 1, hour, minute, 0)
+            .single()
+            .expect("an unambiguous local time")
+            .timestamp()
+    }
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").expect("a valid time")
+    }
+
+    #[test]
+    fn example_rules_parse() {
+        let rule: AlertRule = "battery_voltage < 11.8 for 60s => critical"
+            .parse()
+            .expect("parse");
+        assert_eq!(rule.name, "battery_voltage_below_11.8");
+        assert_eq!(rule.field, "battery_voltage");
+        assert_eq!(rule.comparison, Comparison::Below);
+        assert_eq!(rule.threshold, 11.8);
+        assert_eq!(rule.hold, 60);
+        assert_eq!(rule.rate, None);
+        assert_eq!(rule.severity, Severity::Critical);
+
+        let rule: AlertRule = "pv_dark: pv_voltage < 1 for 30m => info"
+            .parse()
+            .expect("parse");
+        assert_eq!(rule.name, "pv_dark");
+        assert_eq!(rule.hold, 1800);
+        assert_eq!(rule.severity, Severity::Info);
+
+        let rule: AlertRule = "battery_voltage/min < -0.2 for 2m => critical"
+            .parse()
+            .expect("parse");
+        assert_eq!(rule.name, "battery_voltage_per_min_below_-0.2");
+        assert_eq!(rule.rate, Some(60));
+        assert_eq!(rule.threshold, -0.2);
+        assert_eq!(rule.hold, 120);
+
+        let rule: AlertRule = "shading: charge_current/min < -2 between 10:00-15:00"
+            .parse()
+            .expect("parse");
+        assert_eq!(rule.name, "shading");
+        assert_eq!(rule.field, "charge_current");
+        assert_eq!(rule.rate, Some(60));
+        assert_eq!(rule.hours, Some((time("10:00"), time("15:00"))));
+        assert_eq!(rule.hold, 0);
+        assert_eq!(rule.severity, Severity::Warning);
+    }
+
+    #[test]
+    fn invalid_rules_are_refused() {
+        for rule in [
+            "pv_current/min < -2",
+            "battery_voltage/d < -0.2",
+            "battery_voltage == 12",
+            "battery_voltage < low",
+            "battery_voltage < 11.8 for",
+            "battery_voltage < 11.8 for ever",
+            "battery_voltage < 11.8 => urgent",
+            "low battery: battery_voltage < 11.8",
+            "charge_current < 1 between 10:00",
+            "charge_current < 1 between 10:00-25:00",
+        ] {
+            assert!(rule.parse::<AlertRule>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn rate_of_change_needs_a_full_window() {
+        let mut history = VecDeque::new();
+        // Rising by 0.5 every 10 seconds, i.e. 3 per minute.
+        for t in (0..60).step_by(10) {
+            assert_eq!(rate_of_change(&mut history, t, t as f64 / 20.0, 60), None);
+        }
+        assert_eq!(rate_of_change(&mut history, 60, 3.0, 60), Some(3.0));
+        assert_eq!(rate_of_change(&mut history, 70, 3.5, 60), Some(3.0));
+        // Measured from the newest value at least a minute old.
+        assert_eq!(rate_of_change(&mut history, 95, 3.5, 60), Some(1.846));
+        assert_eq!(history.front().map(|&(t, _)| t), Some(30));
+    }
+
+    #[test]
+    fn rate_rule_fires_after_its_hold_and_clears() {
+        let mut monitor = monitor("battery_voltage/min < -0.2 for 2m => critical");
+        let name = "battery_voltage_per_min_below_-0.2";
+        // Falling by 0.375 V a minute for 3 minutes, then steady.
+        let voltage = |t: i64| 13.0 - 0.0625 * (t.min(180) / 10) as f64;
+        let events = run(&mut monitor, name, 1_700_000_000, BATTERY_VOLTAGE, voltage);
+        // The first rate is known after a minute, then held for two.
+        assert_eq!(
+            events,
+            vec![(180, EventKind::Alert), (210, EventKind::Cleared)]
+        );
+    }
+
+    #[test]
+    fn rules_apply_between_their_hours() {
+        // Falling by 6 A a minute.
+        let current = |t: i64| 8.0 - (t.min(60) / 10) as f64;
+        let name = "shading";
+        let rule = "shading: charge_current/min < -2 between 10:00-15:00";
+        let events = run(
+            &mut monitor(rule),
+            name,
+            local(12, 0),
+            CHARGE_CURRENT,
+            current,
+        );
+        assert_eq!(
+            events,
+            vec![(60, EventKind::Alert), (100, EventKind::Cleared)]
+        );
+        let events = run(
+            &mut monitor(rule),
+            name,
+            local(16, 0),
+            CHARGE_CURRENT,
+            current,
+        );
+        assert_eq!(events, vec![]);
+    }
+
+    #[test]
+    fn hours_may_span_midnight() {
+        let (start, end) = parse_hours("22:00-06:00").expect("parse");
+        assert!(within(time("22:00"), start, end));
+        assert!(within(time("23:30"), start, end));
+        assert!(within(time("03:00"), start, end));
+        assert!(!within(time("06:00"), start, end));
+        assert!(!within(time("12:00"), start, end));
+        let (start, end) = parse_hours("10:00-15:00").expect("parse");
+        assert!(within(time("10:00"), start, end));
+        assert!(!within(time("15:00"), start, end));
+        assert!(!within(time("23:00"), start, end));
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+//
+// Structs
+//
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SerialConfig {
+    pub port: Option<String>,
+    pub baud_rate: u32,
+    pub timeout_ms: u64,
+    pub poll_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub path: PathBuf,
+    pub buffer_size: usize,
+    /// Open the database read-only, set for dry-run and offline mode.
+    #[serde(skip)]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AlertThresholds {
+    pub battery_low_voltage: f64,
+    pub battery_high_voltage: f64,
+    pub battery_max_temp: f64,
+    pub load_max_current: f64,
+    pub stale_after_s: u64,
+    pub rules: Vec<String>,
+}
+
+///
+/// Parse a duration such as `90`, `30s`, `15m`, `1h` or `1d` into seconds.
+///
+pub fn parse_duration(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}'.", value))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit '{}', use s, m, h or d.",
+                unit
+            ))
+        }
+    };
+    Ok(number * multiplier)
+}
+
+//
+// implementations
+//
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            port: None,
+            baud_rate: 57600,
+            timeout_ms: 2000,
+            poll_interval_ms: 1000,
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("solar_data.sql"),
+            buffer_size: 256, //88 * 256 = ~22.5 kb (buffer size)
+            read_only: false,
+        }
+    }
+}
+
+impl Default for AlertThresholds {
+    fn default() -> Self {
+        Self {
+            battery_low_voltage: 11.8,
+            battery_high_voltage: 14.8,
+            battery_max_temp: 45.0,
+            load_max_current: 10.0,
+            stale_after_s: 120,
+            rules: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+const CYCLES_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS ChargeCycles ",
+    "(ID INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE NOT NULL,",
+    " device TEXT, start TIMESTAMP, end TIMESTAMP, depth_percent DOUBLE)"
+);
+const CYCLE_STATE_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS CycleState ",
+    "(device TEXT PRIMARY KEY NOT NULL, capacity_ah DOUBLE, deficit_ah DOUBLE,",
+    " discharging BOOLEAN, top_ah DOUBLE, bottom_ah DOUBLE, since TIMESTAMP,",
+    " discharged_ah DOUBLE, updated TIMESTAMP)"
+);
+const CYCLE_STATE_INSERT: &str = concat!(
+    "INSERT OR REPLACE INTO CycleState(device, capacity_ah, deficit_ah, discharging, ",
+    "top_ah, bottom_ah, since, discharged_ah, updated) VALUES(?,?,?,?,?,?,?,?,?)"
+);
+const CYCLE_STATE_SELECT: &str = concat!(
+    "SELECT deficit_ah, discharging, top_ah, bottom_ah, since, discharged_ah ",
+    "FROM CycleState WHERE device = ?1"
+);
+const CAPACITY_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS CapacityMeasurements ",
+    "(ID INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE NOT NULL,",
+    " device TEXT, time TIMESTAMP, measured_ah DOUBLE)"
+);
+
+/// The usable capacity is estimated as the mean of this many of the latest
+/// measurements, single discharges vary with temperature and load.
+pub const CAPACITY_AVERAGE: i64 = 3;
+
+/// Depth of discharge classes as (label, upper bound in percent), matching
+/// the depths lead-acid (50%) and LiFePO4 (80%) cycle lives are rated at.
+pub const DEPTH_CLASSES: [(&str, f64); 4] = [
+    ("0-25%", 25.0),
+    ("25-50%", 50.0),
+    ("50-80%", 80.0),
+    ("80-100%", f64::INFINITY),
+];
+
+//
+// Structs
+//
+/// Where a battery is within its current cycle, in amp hours below full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CycleState {
+    /// Amp hours taken out since the battery was last full.
+    pub deficit_ah: f64,
+    /// Whether the battery is being discharged, else recharged.
+    pub discharging: bool,
+    /// Deficit where the current discharge started.
+    pub top_ah: f64,
+    /// Deepest deficit of the current discharge.
+    pub bottom_ah: f64,
+    /// Start of the current cycle.
+    pub since: i64,
+    /// Amp hours discharged in total.
+    pub discharged_ah: f64,
+}
+
+/// The cycles counted for a device.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CycleCounts {
+    pub device: String,
+    pub cycles: i64,
+    /// Cycles per class of `DEPTH_CLASSES`.
+    pub by_depth: Vec<DepthCount>,
+    /// Amp hours discharged in total.
+    pub discharged_ah: f64,
+    /// The discharged amp hours in cycles of the full capacity.
+    pub equivalent_full_cycles: f64,
+    /// Usable capacity estimated from discharges from full to empty, if any
+    /// were measured.
+    #[serde(default)]
+    pub estimated_capacity_ah: Option<f64>,
+    #[serde(default)]
+    pub capacity_measurements: i64,
+}
+
+/// The cycles of a depth of discharge class.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DepthCount {
+    /// e.g. `25-50%`.
+    pub depth: String,
+    pub cycles: i64,
+}
+
+//
+// implementations
+//
+impl CycleState {
+    ///
+    /// The state of a battery counted from `time` on, assumed to be full.
+    ///
+    pub fn new(time: i64) -> Self {
+        Self {
+            deficit_ah: 0.0,
+            discharging: false,
+            top_ah: 0.0,
+            bottom_ah: 0.0,
+            since: time,
+            discharged_ah: 0.0,
+        }
+    }
+}
+
+///
+/// Create the cycle tables.
+///
+pub fn init(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(CYCLES_CREATE_STMT, ())?;
+    connection.execute(CYCLE_STATE_CREATE_STMT, ())?;
+    connection.execute(CAPACITY_CREATE_STMT, ())?;
+    Ok(())
+}
+
+///
+/// The state a device was last stored in, if any.
+///
+pub fn load_state(connection: &Connection, device: &str) -> rusqlite::Result<Option<CycleState>> {
+    connection
+        .query_row(CYCLE_STATE_SELECT, [device], |row| {
+            Ok(CycleState {
+                deficit_ah: row.get(0)?,
+                discharging: row.get(1)?,
+                top_ah: row.get(2)?,
+                bottom_ah: row.get(3)?,
+                since: row.get(4)?,
+                discharged_ah: row.get(5)?,
+            })
+        })
+        .optional()
+}
+
+///
+/// Store the state of a device at `time` with the capacity its depths are
+/// measured against.
+///
+pub fn store_state(
+    connection: &Connection,
+    device: &str,
+    capacity_ah: f64,
+    state: &CycleState,
+    time: i64,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        CYCLE_STATE_INSERT,
+        (
+            device,
+            capacity_ah,
+            state.deficit_ah,
+            state.discharging,
+            state.top_ah,
+            state.bottom_ah,
+            state.since,
+            state.discharged_ah,
+            time,
+        ),
+    )?;
+    Ok(())
+}
+
+///
+/// Record a cycle from `start` to `end` discharging `depth_percent` of the capacity.
+///
+pub fn store_cycle(
+    connection: &Connection,
+    device: &str,
+    start: i64,
+    end: i64,
+    depth_percent: f64,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO ChargeCycles(device, start, end, depth_percent) VALUES(?,?,?,?)",
+        (device, start, end, depth_percent),
+    )?;
+    Ok(())
+}
+
+///
+/// Record the amp hours taken out of a device from full to empty at `time`.
+///
+pub fn store_capacity(
+    connection: &Connection,
+    device: &str,
+    time: i64,
+    measured_ah: f64,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO CapacityMeasurements(device, time, measured_ah) VALUES(?,?,?)",
+        (device, time, measured_ah),
+    )?;
+    Ok(())
+}
+
+///
+/// The usable capacity of a device as the mean of the latest
+/// `CAPACITY_AVERAGE` measurements, and the number of all measurements.
+///
+pub fn capacity(connection: &Connection, device: &str) -> rusqlite::Result<(Option<f64>, i64)> {
+    connection.query_row(
+        "SELECT (SELECT AVG(measured_ah) FROM (SELECT measured_ah FROM CapacityMeasurements \
+         WHERE device = ?1 ORDER BY time DESC LIMIT ?2)), \
+         (SELECT COUNT(*) FROM CapacityMeasurements WHERE device = ?1)",
+        (device, CAPACITY_AVERAGE),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+///
+/// The cumulative cycle counts of every device, by name.
+///
+pub fn counts(connection: &Connection) -> rusqlite::Result<Vec<CycleCounts>> {
+    let mut devices = connection
+        .prepare("SELECT device, capacity_ah, discharged_ah FROM CycleState ORDER BY device")?;
+    let mut depths =
+        connection.prepare("SELECT depth_percent FROM ChargeCycles WHERE device = ?1")?;
+    let devices = devices.query_map((), |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, f64>(1)?,
+            row.get::<_, f64>(2)?,
+        ))
+    })?;
+    let mut counts = Vec::new();
+    for device in devices {
+        let (device, capacity_ah, discharged_ah) = device?;
+        let mut by_depth: Vec<DepthCount> = DEPTH_CLASSES
+            .iter()
+            .map(|(label, _)| DepthCount {
+                depth: label.to_string(),
+                cycles: 0,
+            })
+            .collect();
+        let mut cycles = 0;
+        for depth in depths.query_map([&device], |row| row.get::<_, f64>(0))? {
+            let depth = depth?;
+            let class = DEPTH_CLASSES
+                .iter()
+                .position(|(_, upper)| depth < *upper)
+                .unwrap_or(DEPTH_CLASSES.len() - 1);
+            by_depth[class].cycles += 1;
+            cycles += 1;
+        }
+        let (estimated_capacity_ah, capacity_measurements) = capacity(connection, &device)?;
+        counts.push(CycleCounts {
+            device,
+            cycles,
+            by_depth,
+            discharged_ah,
+            equivalent_full_cycles: discharged_ah / capacity_ah.max(f64::EPSILON),
+            estimated_capacity_ah,
+            capacity_measurements,
+        });
+    }
+    Ok(counts)
+}
@@ -0,0 +1,837 @@
+use crate::alerts::{Event, EventKind};
+use crate::config::DatabaseConfig;
+use crate::cycles::{self, CycleCounts, CycleState};
+use crate::datapoint::{DataPoint, DATA_POINT_VALUES};
+use crate::downsample;
+use crate::error::SolarError;
+use crate::forecast;
+use crate::health::{self, HealthDay};
+use crate::records::{self, Record};
+use crate::rollup::{self, HourlyEnergy, Summary};
+use crate::timings::{Stage, TIMINGS};
+use crate::weather::{self, WeatherHour};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::mem;
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+const DATABASE_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS Data ",
+    "(ID INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE NOT",
+    " NULL,battery_voltage DOUBLE, pv_voltage DOUBLE, load_current DOUBLE,",
+    " over_discharge DOUBLE,battery_max DOUBLE, battery_full BOOLEAN, charging",
+    " BOOLEAN, battery_temp DOUBLE,charge_current DOUBLE, load_onoff BOOLEAN, time",
+    " TIMESTAMP DEFAULT CURRENT_TIMESTAMP, interval_ms INTEGER)"
+);
+/// Adds the interval column to databases created before it, failing
+/// harmlessly when it exists.
+const DATA_INTERVAL_ALTER_STMT: &str = "ALTER TABLE Data ADD COLUMN interval_ms INTEGER";
+const LOAD_EVENTS_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS LoadEvents ",
+    "(ID INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE NOT NULL,",
+    " time TIMESTAMP, state BOOLEAN, source TEXT)"
+);
+const ALERTS_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS Alerts ",
+    "(ID INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE NOT NULL,",
+    " rule TEXT, severity TEXT, message TEXT, value DOUBLE, raised TIMESTAMP,",
+    " cleared TIMESTAMP, acknowledged TIMESTAMP)"
+);
+const DATA_GAPS_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS DataGaps ",
+    "(ID INTEGER PRIMARY KEY AUTOINCREMENT UNIQUE NOT NULL,",
+    " start TIMESTAMP, end TIMESTAMP, reason TEXT)"
+);
+const DATABASE_INSERT: &str = concat!(
+    "INSERT INTO Data(",
+    "battery_voltage, ",
+    "pv_voltage, ",
+    "load_current, ",
+    "over_discharge,",
+    "battery_max, ",
+    "battery_full, ",
+    "charging, ",
+    "battery_temp, ",
+    "charge_current, ",
+    "load_onoff,",
+    "time, ",
+    "interval_ms",
+    ") VALUES(?,?,?,?,?,?,?,?,?,?,?,?)"
+);
+const DATABASE_SELECT_RANGE: &str = concat!(
+    "SELECT time, ",
+    "battery_voltage, ",
+    "pv_voltage, ",
+    "load_current, ",
+    "over_discharge, ",
+    "battery_max, ",
+    "battery_full, ",
+    "charging, ",
+    "battery_temp, ",
+    "charge_current, ",
+    "load_onoff ",
+    "FROM Data WHERE time >= ?1 AND time <= ?2 ORDER BY time"
+);
+
+/// Columns of the Data table which can be aggregated.
+pub const DATA_FIELDS: [&str; 10] = [
+    "battery_voltage",
+    "pv_voltage",
+    "load_current",
+    "over_discharge",
+    "battery_max",
+    "battery_full",
+    "charging",
+    "battery_temp",
+    "charge_current",
+    "load_onoff",
+];
+
+//
+// Structs
+//
+pub struct Database {
+    connection: Connection,
+    datapoint_buffer: Vec<(DataPoint, Option<Duration>)>,
+    buffer_limit: usize,
+    /// Opened read-only, nothing is stored.
+    read_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+}
+
+/// One aggregated time bucket, `time` being the start of the bucket.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Bucket {
+    pub time: i64,
+    pub value: f64,
+    pub samples: i64,
+}
+
+/// One raised alert from the Alerts table.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AlertRecord {
+    pub id: i64,
+    /// Name of the rule, e.g. `battery_low`.
+    pub rule: String,
+    pub severity: String,
+    pub message: String,
+    /// Value which raised the alert.
+    pub value: f64,
+    pub raised: i64,
+    /// `None` while the alert is active.
+    pub cleared: Option<i64>,
+    /// Seconds from being raised until cleared, `None` while active.
+    pub duration_s: Option<i64>,
+    pub acknowledged: Option<i64>,
+}
+
+/// Position in the Data table where the next page of history starts,
+/// serialized as `<time>.<id>` for API clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryCursor {
+    time: i64,
+    /// ID of the last row returned at `time`, -1 before the first one.
+    id: i64,
+}
+
+//
+// implementations
+//
+impl HistoryCursor {
+    ///
+    /// The cursor of the first page, starting at `from`.
+    ///
+    pub fn start(from: i64) -> Self {
+        Self { time: from, id: -1 }
+    }
+}
+
+impl fmt::Display for HistoryCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.time, self.id)
+    }
+}
+
+impl FromStr for HistoryCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid cursor {:?}", s);
+        let (time, id) = s.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            time: time.parse().map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Aggregation {
+    fn sql_function(self) -> &'static str {
+        match self {
+            Aggregation::Avg => "AVG",
+            Aggregation::Min => "MIN",
+            Aggregation::Max => "MAX",
+        }
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        if !self.datapoint_buffer.is_empty() {
+            let data = mem::take(&mut self.datapoint_buffer);
+            self.insert_datapoints(data);
+        }
+    }
+}
+
+impl Database {
+    ///
+    /// Open (or create) the database described by the given config, or open
+    /// it read-only without creating or migrating it, see `open_read_only`.
+    ///
+    pub fn new(config: &DatabaseConfig) -> Result<Self, SolarError> {
+        if config.read_only {
+            return Self::open_read_only(config);
+        }
+        let connection = Connection::open(&config.path)?;
+        create_schema(&connection);
+        Ok(Self {
+            connection,
+            datapoint_buffer: Vec::with_capacity(config.buffer_size),
+            buffer_limit: config.buffer_size,
+            read_only: false,
+        })
+    }
+
+    ///
+    /// Open the database read-only, e.g. in dry-run or offline mode, so its
+    /// file is never written. A database which does not exist yet is not
+    /// created, an empty one is used in memory instead. Stores are skipped.
+    ///
+    fn open_read_only(config: &DatabaseConfig) -> Result<Self, SolarError> {
+        let connection = match config.path.exists() {
+            true => Connection::open_with_flags(
+                &config.path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?,
+            false => {
+                debug!(
+                    "{} does not exist, using an empty database in memory.",
+                    config.path.display()
+                );
+                let connection = Connection::open_in_memory()?;
+                create_schema(&connection);
+                connection
+            }
+        };
+        Ok(Self {
+            connection,
+            datapoint_buffer: Vec::new(),
+            buffer_limit: config.buffer_size,
+            read_only: true,
+        })
+    }
+
+    ///
+    /// Add a datapoint to the internal buffer which will be added into
+    /// the database when drop is called on this database object or
+    /// when the buffer is filled. `interval` is the time since the datapoint
+    /// before on the monotonic clock, which the energy totals are counted
+    /// over rather than the difference of the wall clock times.
+    ///
+    pub fn add_datapoint(&mut self, datapoint: DataPoint, interval: Option<Duration>) {
+        self.datapoint_buffer.push((datapoint, interval));
+        if self.datapoint_buffer.len() >= self.buffer_limit {
+            let data = mem::replace(
+                &mut self.datapoint_buffer,
+                Vec::with_capacity(self.buffer_limit),
+            );
+            self.insert_datapoints(data);
+        }
+    }
+
+    ///
+    /// Record that the load was switched, written immediately rather than buffered.
+    ///
+    pub fn add_load_event(&self, time: i64, on: bool, source: &str) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = self.connection.execute(
+            "INSERT INTO LoadEvents(time, state, source) VALUES(?,?,?)",
+            (time, on, source),
+        ) {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// Record that no data was read from `start` to `end`, e.g. while the
+    /// system was suspended, so no energy is counted across it. Written
+    /// immediately rather than buffered, before the datapoints after the gap.
+    ///
+    pub fn add_data_gap(&self, start: i64, end: i64, reason: &str) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = self.connection.execute(
+            "INSERT INTO DataGaps(start, end, reason) VALUES(?,?,?)",
+            (start, end, reason),
+        ) {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// Record an alert being raised, or the time its open row was cleared,
+    /// written immediately rather than buffered.
+    ///
+    pub fn add_alert_event(&self, event: &Event) {
+        if self.read_only {
+            return;
+        }
+        let result = match event.kind {
+            EventKind::Alert => self.connection.execute(
+                "INSERT INTO Alerts(rule, severity, message, value, raised) VALUES(?,?,?,?,?)",
+                (
+                    &event.name,
+                    event.severity.name(),
+                    &event.message,
+                    event.value,
+                    event.timestamp,
+                ),
+            ),
+            EventKind::Cleared => self.connection.execute(
+                "UPDATE Alerts SET cleared = ?2 WHERE rule = ?1 AND cleared IS NULL",
+                (&event.name, event.timestamp),
+            ),
+            EventKind::Load | EventKind::Summary => return,
+        };
+        if let Err(e) = result {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// At most `limit` alerts active at some time in `from..=to`, the most
+    /// recently raised first.
+    ///
+    pub fn alerts(&self, from: i64, to: i64, limit: usize) -> rusqlite::Result<Vec<AlertRecord>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT ID, rule, severity, message, value, raised, cleared, acknowledged FROM Alerts \
+             WHERE raised <= ?2 AND (cleared IS NULL OR cleared >= ?1) \
+             ORDER BY raised DESC, ID DESC LIMIT ?3",
+        )?;
+        let rows = stmt.query_map((from, to, limit as i64), |row| {
+            let raised: i64 = row.get(5)?;
+            let cleared: Option<i64> = row.get(6)?;
+            Ok(AlertRecord {
+                id: row.get(0)?,
+                rule: row.get(1)?,
+                severity: row.get(2)?,
+                message: row.get(3)?,
+                value: row.get(4)?,
+                raised,
+                cleared,
+                duration_s: cleared.map(|cleared| cleared - raised),
+                acknowledged: row.get(7)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    ///
+    /// Mark an alert as acknowledged at `time`, keeping an earlier
+    /// acknowledgement. Returns whether the alert exists.
+    ///
+    pub fn acknowledge_alert(&self, id: i64, time: i64) -> rusqlite::Result<bool> {
+        self.connection
+            .execute(
+                "UPDATE Alerts SET acknowledged = COALESCE(acknowledged, ?2) WHERE ID = ?1",
+                (id, time),
+            )
+            .map(|changed| changed > 0)
+    }
+
+    ///
+    /// Load all stored datapoints with a timestamp in the inclusive range `from..=to`.
+    ///
+    pub fn datapoints_between(&self, from: i64, to: i64) -> rusqlite::Result<Vec<DataPoint>> {
+        let mut stmt = self.connection.prepare(DATABASE_SELECT_RANGE)?;
+        let rows = stmt.query_map((from, to), |row| {
+            let mut data = [0.0; 10];
+            for (i, value) in data.iter_mut().enumerate() {
+                *value = row.get(i + 1)?;
+            }
+            Ok(DataPoint::with_time(row.get(0)?, &data))
+        })?;
+        rows.collect()
+    }
+
+    ///
+    /// Average all fields over buckets of `step` seconds in the inclusive range
+    /// `from..=to`, each datapoint being stamped with the start of its bucket.
+    ///
+    pub fn history(&self, from: i64, to: i64, step: i64) -> rusqlite::Result<Vec<DataPoint>> {
+        let averages: Vec<String> = DATA_FIELDS.iter().map(|f| format!("AVG({})", f)).collect();
+        let sql = format!(
+            "SELECT (time / ?3) * ?3 AS bucket, {} FROM Data \
+             WHERE time >= ?1 AND time <= ?2 GROUP BY bucket ORDER BY bucket",
+            averages.join(", ")
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map((from, to, step.max(1)), |row| {
+            let mut data = [0.0; 10];
+            for (i, value) in data.iter_mut().enumerate() {
+                *value = row.get(i + 1)?;
+            }
+            Ok(DataPoint::with_time(row.get(0)?, &data))
+        })?;
+        rows.collect()
+    }
+
+    ///
+    /// At most `points` of the stored datapoints in the inclusive range
+    /// `from..=to`, picked with LTTB so a chart of them looks like one of all
+    /// datapoints. The rows are streamed, keeping memory bounded for long ranges.
+    ///
+    pub fn downsampled(
+        &self,
+        from: i64,
+        to: i64,
+        points: usize,
+    ) -> rusqlite::Result<Vec<DataPoint>> {
+        let ranges: Vec<String> = DATA_FIELDS
+            .iter()
+            .map(|f| format!("MAX({0}) - MIN({0})", f))
+            .collect();
+        let sql = format!(
+            "SELECT COUNT(*), {} FROM Data WHERE time >= ?1 AND time <= ?2",
+            ranges.join(", ")
+        );
+        let (len, scale) = self.connection.query_row(&sql, (from, to), |row| {
+            let mut scale = [0.0; DATA_POINT_VALUES];
+            for (i, value) in scale.iter_mut().enumerate() {
+                *value = match row.get::<_, Option<f64>>(i + 1)? {
+                    Some(range) if range > 0.0 => 1.0 / range,
+                    _ => 0.0,
+                };
+            }
+            Ok((row.get::<_, i64>(0)? as usize, scale))
+        })?;
+        let mut stmt = self.connection.prepare(DATABASE_SELECT_RANGE)?;
+        let mut error = None;
+        let rows = stmt
+            .query_map((from, to), |row| {
+                let mut data = [0.0; 10];
+                for (i, value) in data.iter_mut().enumerate() {
+                    *value = row.get(i + 1)?;
+                }
+                Ok(DataPoint::with_time(row.get(0)?, &data))
+            })?
+            .map_while(|row| row.map_err(|e| error = Some(e)).ok());
+        let datapoints = downsample::lttb(rows, len, points, &scale);
+        match error {
+            Some(e) => Err(e),
+            None => Ok(datapoints),
+        }
+    }
+
+    ///
+    /// Check that the database can be written to, by taking and releasing
+    /// the write lock.
+    ///
+    pub fn check_writable(&self) -> rusqlite::Result<()> {
+        self.connection.busy_timeout(Duration::from_secs(2))?;
+        self.connection.execute_batch("BEGIN IMMEDIATE; ROLLBACK;")
+    }
+
+    ///
+    /// At most `limit` datapoints from `cursor` up to `to`, raw or averaged over
+    /// buckets of `step` seconds like `history`, and the cursor of the next page
+    /// if there is one.
+    ///
+    pub fn history_page(
+        &self,
+        cursor: HistoryCursor,
+        to: i64,
+        step: Option<i64>,
+        limit: usize,
+    ) -> rusqlite::Result<(Vec<DataPoint>, Option<HistoryCursor>)> {
+        let fields = match step {
+            Some(_) => DATA_FIELDS.map(|f| format!("AVG({})", f)).join(", "),
+            None => DATA_FIELDS.join(", "),
+        };
+        // One row more than requested tells whether there is a next page.
+        let sql = match step {
+            Some(_) => format!(
+                "SELECT (time / ?4) * ?4 AS bucket, {}, MAX(ID) FROM Data \
+                 WHERE time >= ?1 AND time <= ?3 GROUP BY bucket ORDER BY bucket LIMIT ?5",
+                fields
+            ),
+            None => format!(
+                "SELECT time, {}, ID FROM Data \
+                 WHERE (time > ?1 OR (time = ?1 AND ID > ?2)) AND time <= ?3 \
+                 ORDER BY time, ID LIMIT ?5",
+                fields
+            ),
+        };
+        let averaged = step.is_some();
+        let step = step.unwrap_or(1).max(1);
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map(
+            (cursor.time, cursor.id, to, step, limit as i64 + 1),
+            |row| {
+                let mut data = [0.0; 10];
+                for (i, value) in data.iter_mut().enumerate() {
+                    *value = row.get(i + 1)?;
+                }
+                let id: i64 = row.get(DATA_FIELDS.len() + 1)?;
+                Ok((DataPoint::with_time(row.get(0)?, &data), id))
+            },
+        )?;
+        let mut rows = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        let next = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|(dp, id)| match averaged {
+                true => HistoryCursor::start(dp.get_time() + step),
+                false => HistoryCursor {
+                    time: dp.get_time(),
+                    id: *id,
+                },
+            })
+        } else {
+            None
+        };
+        Ok((rows.into_iter().map(|(dp, _)| dp).collect(), next))
+    }
+
+    ///
+    /// Aggregate a single field over fixed size time buckets of `interval` seconds.
+    ///
+    pub fn aggregate(
+        &self,
+        aggregation: Aggregation,
+        field: &str,
+        interval: i64,
+        from: i64,
+        to: i64,
+    ) -> rusqlite::Result<Vec<Bucket>> {
+        if !DATA_FIELDS.contains(&field) {
+            return Err(rusqlite::Error::InvalidColumnName(field.to_string()));
+        }
+        let sql = format!(
+            "SELECT (time / ?3) * ?3 AS bucket, {}({}), COUNT(*) FROM Data \
+             WHERE time >= ?1 AND time <= ?2 GROUP BY bucket ORDER BY bucket",
+            aggregation.sql_function(),
+            field
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map((from, to, interval.max(1)), |row| {
+            Ok(Bucket {
+                time: row.get(0)?,
+                value: row.get(1)?,
+                samples: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    ///
+    /// Summary statistics of all hours starting in `from..to`, taken from the rollup table.
+    ///
+    pub fn summary(&self, from: i64, to: i64) -> rusqlite::Result<Summary> {
+        let mut summary = rollup::summarize(&self.connection, from, to)?;
+        summary.energy_forecast_wh = forecast::expected(&self.connection, from, to)?;
+        Ok(summary)
+    }
+
+    ///
+    /// The highest charge power in `from..to` averaged over buckets of `step`
+    /// seconds, counting only datapoints taken while the battery was not full.
+    ///
+    pub fn peak_charge_power(
+        &self,
+        from: i64,
+        to: i64,
+        step: i64,
+    ) -> rusqlite::Result<Option<f64>> {
+        self.connection.query_row(
+            "SELECT MAX(power) FROM (SELECT AVG(battery_voltage * charge_current) AS power \
+             FROM Data WHERE time >= ?1 AND time < ?2 AND battery_full = 0 GROUP BY time / ?3)",
+            (from, to, step.max(1)),
+            |row| row.get(0),
+        )
+    }
+
+    ///
+    /// Store the expected energy per hour start, see `forecast::store`.
+    ///
+    pub fn store_forecast(&mut self, hours: &BTreeMap<i64, f64>) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = forecast::store(&mut self.connection, hours) {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// Store weather observations per hour, see `weather::store`.
+    ///
+    pub fn store_weather(&mut self, hours: &[WeatherHour]) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = weather::store(&mut self.connection, hours) {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// The weather observed in the hours starting in `from..to`.
+    ///
+    pub fn weather(&self, from: i64, to: i64) -> rusqlite::Result<Vec<WeatherHour>> {
+        weather::hours(&self.connection, from, to)
+    }
+
+    ///
+    /// Measure the battery health indicators of the day `day.0..day.1`: the
+    /// sag of its load steps and the self-discharge over `night`, while no
+    /// more than `rest_current` flows, see `health`.
+    ///
+    pub fn measure_battery_health(
+        &self,
+        day: (i64, i64),
+        night: (i64, i64),
+        rest_current: f64,
+        min_load_step: f64,
+    ) -> rusqlite::Result<HealthDay> {
+        Ok(HealthDay {
+            day: day.0,
+            sag_mohm: health::sag(&self.connection, day.0, day.1, rest_current, min_load_step)?,
+            self_discharge_mv_h: health::self_discharge(
+                &self.connection,
+                night.0,
+                night.1,
+                rest_current,
+            )?,
+        })
+    }
+
+    ///
+    /// Store the battery health indicators of a day.
+    ///
+    pub fn store_battery_health(&self, day: &HealthDay) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = health::store(&self.connection, day) {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// The stored battery health indicators of the days starting in `from..to`.
+    ///
+    pub fn battery_health(&self, from: i64, to: i64) -> rusqlite::Result<Vec<HealthDay>> {
+        health::days(&self.connection, from, to)
+    }
+
+    ///
+    /// The cycle state a device was last stored in, see `cycles`.
+    ///
+    pub fn cycle_state(&self, device: &str) -> rusqlite::Result<Option<CycleState>> {
+        cycles::load_state(&self.connection, device)
+    }
+
+    ///
+    /// Store the cycle state of a device, and the cycle it completed if any
+    /// as (start, end, depth in percent), in one transaction.
+    ///
+    pub fn store_cycle_state(
+        &mut self,
+        device: &str,
+        capacity_ah: f64,
+        state: &CycleState,
+        cycle: Option<(i64, i64, f64)>,
+        time: i64,
+    ) {
+        if self.read_only {
+            return;
+        }
+        let result = self.connection.transaction().and_then(|trans| {
+            if let Some((start, end, depth)) = cycle {
+                cycles::store_cycle(&trans, device, start, end, depth)?;
+            }
+            cycles::store_state(&trans, device, capacity_ah, state, time)?;
+            trans.commit()
+        });
+        if let Err(e) = result {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// Record a capacity measurement of a device, see `cycles::store_capacity`.
+    ///
+    pub fn store_capacity(&self, device: &str, time: i64, measured_ah: f64) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = cycles::store_capacity(&self.connection, device, time, measured_ah) {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// The estimated usable capacity of a device and the number of
+    /// measurements, see `cycles::capacity`.
+    ///
+    pub fn capacity(&self, device: &str) -> rusqlite::Result<(Option<f64>, i64)> {
+        cycles::capacity(&self.connection, device)
+    }
+
+    ///
+    /// The cumulative cycle counts of every device.
+    ///
+    pub fn cycle_counts(&self) -> rusqlite::Result<Vec<CycleCounts>> {
+        cycles::counts(&self.connection)
+    }
+
+    ///
+    /// The all-time and monthly records, see `records::load`.
+    ///
+    pub fn records(&self) -> rusqlite::Result<Vec<Record>> {
+        records::load(&self.connection)
+    }
+
+    ///
+    /// Store new records, replacing the ones they beat.
+    ///
+    pub fn store_records(&mut self, records: &[Record]) {
+        if self.read_only {
+            return;
+        }
+        if let Err(e) = records::store(&mut self.connection, records) {
+            warn!("{}", e);
+        }
+    }
+
+    ///
+    /// The energy harvested per hour of the hours starting in `from..to`.
+    ///
+    pub fn hourly_harvest(&self, from: i64, to: i64) -> rusqlite::Result<Vec<HourlyEnergy>> {
+        rollup::hourly_harvest(&self.connection, from, to)
+    }
+
+    ///
+    /// A metric of the rollup table (see `rollup::ROLLUP_METRICS`) or the
+    /// forecast over buckets of `interval` seconds, at least an hour.
+    ///
+    pub fn rollup_series(
+        &self,
+        metric: &str,
+        interval: i64,
+        from: i64,
+        to: i64,
+    ) -> rusqlite::Result<Vec<(i64, f64)>> {
+        if metric == forecast::FORECAST_METRIC {
+            return forecast::series(&self.connection, interval, from, to);
+        }
+        rollup::series(&self.connection, metric, interval, from, to)
+    }
+
+    ///
+    /// Insert a vector of datapoints into the database in one atomic operation.
+    ///
+    fn insert_datapoints(&mut self, datapoints: Vec<(DataPoint, Option<Duration>)>) {
+        if self.read_only {
+            return;
+        }
+        let start = Instant::now();
+        let trans = match self.connection.transaction() {
+            Ok(t) => t,
+            Err(e) => return warn!("{}", e),
+        };
+        let num_data = datapoints.len();
+        let first = datapoints.iter().map(|(dp, _)| dp.get_time()).min();
+        let last = datapoints.iter().map(|(dp, _)| dp.get_time()).max();
+        for (dp, interval) in datapoints {
+            match trans.execute(
+                DATABASE_INSERT,
+                (
+                    dp.get_battery_voltage(),
+                    dp.get_pv_voltage(),
+                    dp.get_load_current(),
+                    dp.get_over_discharge(),
+                    dp.get_battery_max(),
+                    dp.get_battery_full(),
+                    dp.get_charging(),
+                    dp.get_battery_temp(),
+                    dp.get_charge_current(),
+                    dp.get_load_onoff(),
+                    dp.get_time(),
+                    interval.map(|i| i.as_millis() as i64),
+                ),
+            ) {
+                Ok(_) => {}
+                Err(e) => warn!("{}", e),
+            }
+        }
+        if let (Some(first), Some(last)) = (first, last) {
+            if let Err(e) = rollup::refresh(&trans, first, last) {
+                warn!("{}", e);
+            }
+        }
+        match trans.commit() {
+            Ok(()) => {
+                let elapsed = start.elapsed();
+                TIMINGS.record(Stage::DbFlush, elapsed);
+                info!(
+                    "Wrote {} datapoints to database in {} ms.",
+                    num_data,
+                    elapsed.as_millis()
+                );
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+///
+/// Create the tables, add columns missing from databases created before
+/// them and set up the rollups. Failures are logged, the tables which exist
+/// can still be used.
+///
+fn create_schema(connection: &Connection) {
+    let _ = connection.execute(DATABASE_CREATE_STMT, ());
+    let _ = connection.execute(DATA_INTERVAL_ALTER_STMT, ());
+    let _ = connection.execute(LOAD_EVENTS_CREATE_STMT, ());
+    let _ = connection.execute(ALERTS_CREATE_STMT, ());
+    let _ = connection.execute(DATA_GAPS_CREATE_STMT, ());
+    if let Err(e) = rollup::init(connection)
+        .and_then(|_| forecast::init(connection))
+        .and_then(|_| health::init(connection))
+        .and_then(|_| cycles::init(connection))
+        .and_then(|_| weather::init(connection))
+        .and_then(|_| records::init(connection))
+    {
+        warn!("{}", e);
+    }
+}
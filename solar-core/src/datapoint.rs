@@ -0,0 +1,248 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Result, SolarError};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Values on a line sent by the device.
+pub const DATA_POINT_VALUES: usize = 10;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct DataPoint {
+    timestamp: i64,
+    battery_voltage: f64,
+    pv_voltage: f64,
+    load_current: f64,
+    over_discharge: f64,
+    battery_max: f64,
+    battery_full: f64,
+    charging: f64,
+    battery_temp: f64,
+    charge_current: f64,
+    load_onoff: f64,
+}
+
+impl fmt::Display for DataPoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}, {}v, {}v, {}A, {}, {}v, {}, {}, {}C, {}A, {})",
+            self.timestamp,
+            self.battery_voltage,
+            self.pv_voltage,
+            self.load_current,
+            self.over_discharge,
+            self.battery_max,
+            self.battery_full,
+            self.charging,
+            self.battery_temp,
+            self.charge_current,
+            self.load_onoff
+        )
+    }
+}
+
+impl Default for DataPoint {
+    fn default() -> Self {
+        Self {
+            timestamp: now(),
+            battery_voltage: 0.0,
+            pv_voltage: 0.0,
+            load_current: 0.0,
+            over_discharge: 0.0,
+            battery_max: 0.0,
+            battery_full: 0.0,
+            charging: 0.0,
+            battery_temp: 0.0,
+            charge_current: 0.0,
+            load_onoff: 0.0,
+        }
+    }
+}
+
+impl FromStr for DataPoint {
+    type Err = SolarError;
+
+    ///
+    /// Parse a line of ten `:` separated values as sent by the device.
+    ///
+    fn from_str(data_str: &str) -> Result<Self> {
+        Self::parse(data_str.as_bytes())
+    }
+}
+
+impl DataPoint {
+    pub fn new(data: &[f64]) -> Self {
+        Self::with_time(now(), data)
+    }
+
+    ///
+    /// Create a datapoint with an explicit timestamp, e.g. when loading it from the database.
+    ///
+    pub fn with_time(timestamp: i64, data: &[f64]) -> Self {
+        Self {
+            timestamp,
+            battery_voltage: data[0],
+            pv_voltage: data[1],
+            load_current: data[2],
+            over_discharge: data[3],
+            battery_max: data[4],
+            battery_full: data[5],
+            charging: data[6],
+            battery_temp: data[7],
+            charge_current: data[8],
+            load_onoff: data[9],
+        }
+    }
+
+    ///
+    /// Parse a line of ten `:` separated decimal numbers as sent by the
+    /// device, straight from the bytes read and without allocating unless the
+    /// line is invalid.
+    ///
+    pub fn parse(line: &[u8]) -> Result<Self> {
+        let invalid = || SolarError::InvalidDatapoint(String::from_utf8_lossy(line).into_owned());
+        let mut data = [0.0; DATA_POINT_VALUES];
+        let mut fields = line.split(|&b| b == b':');
+        for value in data.iter_mut() {
+            *value = fields.next().and_then(parse_decimal).ok_or_else(invalid)?;
+        }
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self::new(&data))
+    }
+
+    pub fn get_time(&self) -> i64 {
+        self.timestamp
+    }
+
+    ///
+    /// The values in the order of the line sent by the device and of `with_time`.
+    ///
+    pub fn values(&self) -> [f64; DATA_POINT_VALUES] {
+        [
+            self.battery_voltage,
+            self.pv_voltage,
+            self.load_current,
+            self.over_discharge,
+            self.battery_max,
+            self.battery_full,
+            self.charging,
+            self.battery_temp,
+            self.charge_current,
+            self.load_onoff,
+        ]
+    }
+
+    ///
+    /// The values as a line sent by the device, without its line ending,
+    /// which `parse` reads back into the same values.
+    ///
+    pub fn to_line(&self) -> String {
+        let values = self.values().map(|v| v.to_string());
+        values.join(":")
+    }
+
+    pub fn get_time_formatted(&self) -> Result<String> {
+        let date = DateTime::from_timestamp(self.timestamp, 0)
+            .ok_or(SolarError::InvalidTimestamp(self.timestamp))?;
+        let binding = chrono::Local::now();
+        let tz = binding.offset();
+        let tzdate = date.with_timezone(tz);
+        Ok(tzdate.to_rfc2822())
+    }
+
+    pub fn get_battery_voltage(&self) -> f64 {
+        self.battery_voltage
+    }
+
+    pub fn get_pv_voltage(&self) -> f64 {
+        self.pv_voltage
+    }
+
+    pub fn get_load_current(&self) -> f64 {
+        self.load_current
+    }
+
+    pub fn get_over_discharge(&self) -> f64 {
+        self.over_discharge
+    }
+
+    pub fn get_battery_max(&self) -> f64 {
+        self.battery_max
+    }
+
+    pub fn get_battery_full(&self) -> f64 {
+        self.battery_full
+    }
+
+    pub fn get_charging(&self) -> f64 {
+        self.charging
+    }
+
+    pub fn get_battery_temp(&self) -> f64 {
+        self.battery_temp
+    }
+
+    pub fn get_charge_current(&self) -> f64 {
+        self.charge_current
+    }
+
+    pub fn get_load_onoff(&self) -> f64 {
+        self.load_onoff
+    }
+
+    ///
+    /// The value of a field by its name in the Data table, e.g. `battery_voltage`.
+    ///
+    pub fn get(&self, field: &str) -> Option<f64> {
+        match field {
+            "battery_voltage" => Some(self.battery_voltage),
+            "pv_voltage" => Some(self.pv_voltage),
+            "load_current" => Some(self.load_current),
+            "over_discharge" => Some(self.over_discharge),
+            "battery_max" => Some(self.battery_max),
+            "battery_full" => Some(self.battery_full),
+            "charging" => Some(self.charging),
+            "battery_temp" => Some(self.battery_temp),
+            "charge_current" => Some(self.charge_current),
+            "load_onoff" => Some(self.load_onoff),
+            _ => None,
+        }
+    }
+}
+
+///
+/// A decimal number like `-12.5`, `3` or `.5`, without the exponents,
+/// infinities and NaN `f64::from_str` also accepts.
+///
+fn parse_decimal(field: &[u8]) -> Option<f64> {
+    let digits = field.strip_prefix(b"+").unwrap_or(field);
+    let digits = digits.strip_prefix(b"-").unwrap_or(digits);
+    let (whole, fraction) = match digits.iter().position(|&b| b == b'.') {
+        Some(dot) => (&digits[..dot], &digits[dot + 1..]),
+        None => (&[][..], digits),
+    };
+    if fraction.is_empty() || !whole.iter().chain(fraction).all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(field).ok()?.parse().ok()
+}
+
+///
+/// Seconds since the UNIX epoch, 0 if the clock is set before it.
+///
+fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(n) => i64::try_from(n.as_secs()).unwrap_or(i64::MAX),
+        Err(_) => {
+            warn!("WARNING: SystemTime is before UNIX EPOCH!");
+            0
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::alerts::Event;
+use crate::config::DatabaseConfig;
+use crate::database::Database;
+use crate::datapoint::DataPoint;
+use crate::queue::{self, QueueSender};
+use crate::supervisor;
+use chrono::Local;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+
+//
+// Constants
+//
+/// Rows queued for the writer, the oldest are dropped when the database falls
+/// behind.
+const WRITE_QUEUE_SIZE: usize = 4096;
+
+//
+// Structs
+//
+/// Rows for the writer task.
+enum Write {
+    Datapoint(DataPoint, Option<Duration>),
+    LoadEvent {
+        time: i64,
+        on: bool,
+        source: &'static str,
+    },
+    AlertEvent(Event),
+    DataGap {
+        start: i64,
+        end: i64,
+        reason: &'static str,
+    },
+}
+
+/// Sends rows to a task owning the writing database connection, so a slow
+/// disk or a locked database does not delay reading the serial port.
+pub struct DbWriter {
+    /// `None` in dry-run mode, nothing is then persisted.
+    tx: Option<QueueSender<Write>>,
+    task: Option<JoinHandle<()>>,
+    /// Times the writer panicked and was restarted.
+    restarts: Arc<AtomicU64>,
+}
+
+//
+// implementations
+//
+impl DbWriter {
+    ///
+    /// Open the database and write to it from a blocking task of the current
+    /// runtime, or store nothing in dry-run mode. The database is opened by
+    /// the task, so creating it or its rollups does not delay the caller,
+    /// rows are queued meanwhile. If it cannot be opened that is logged and
+    /// nothing is stored.
+    ///
+    pub fn spawn(config: &DatabaseConfig, dry_run: bool) -> Self {
+        if dry_run {
+            return Self {
+                tx: None,
+                task: None,
+                restarts: Arc::default(),
+            };
+        }
+        let config = config.clone();
+        let (tx, rx) = queue::bounded(WRITE_QUEUE_SIZE);
+        let restarts = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&restarts);
+        // A panic loses the row being written, the writer then carries on
+        // with the rest of the queue and the same connection.
+        let task = tokio::task::spawn_blocking(move || {
+            let mut database = match Database::new(&config) {
+                Ok(database) => database,
+                Err(e) => {
+                    return error!(
+                        "Opening the database {} failed, nothing is stored: {}",
+                        config.path.display(),
+                        e
+                    )
+                }
+            };
+            supervisor::run_supervised(
+                "database writer",
+                &counter,
+                || rx.is_closed(),
+                || {
+                    while let Some(write) = rx.recv() {
+                        match write {
+                            Write::Datapoint(datapoint, interval) => {
+                                database.add_datapoint(datapoint, interval)
+                            }
+                            Write::LoadEvent { time, on, source } => {
+                                database.add_load_event(time, on, source)
+                            }
+                            Write::AlertEvent(event) => database.add_alert_event(&event),
+                            Write::DataGap { start, end, reason } => {
+                                database.add_data_gap(start, end, reason)
+                            }
+                        }
+                    }
+                },
+            );
+            // Dropping the database flushes the buffered datapoints.
+        });
+        Self {
+            tx: Some(tx),
+            task: Some(task),
+            restarts,
+        }
+    }
+
+    ///
+    /// Times the writer panicked and was restarted.
+    ///
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Store a datapoint read `interval` after the one before, if known.
+    ///
+    pub fn datapoint(&self, datapoint: DataPoint, interval: Option<Duration>) {
+        self.send(Write::Datapoint(datapoint, interval));
+    }
+
+    ///
+    /// Store a load switch in the load events table.
+    ///
+    pub fn load_event(&self, on: bool, source: &'static str) {
+        self.send(Write::LoadEvent {
+            time: Local::now().timestamp(),
+            on,
+            source,
+        });
+    }
+
+    ///
+    /// Store an alert being raised or cleared.
+    ///
+    pub fn alert_event(&self, event: &Event) {
+        self.send(Write::AlertEvent(event.clone()));
+    }
+
+    ///
+    /// Record that no data was read from `start` to `end`.
+    ///
+    pub fn data_gap(&self, start: i64, end: i64, reason: &'static str) {
+        self.send(Write::DataGap { start, end, reason });
+    }
+
+    ///
+    /// Write what is queued and close the database.
+    ///
+    pub async fn close(mut self) {
+        self.tx = None;
+        if let Some(task) = self.task.take() {
+            if task.await.is_err() {
+                error!("Database writer panicked.");
+            }
+        }
+    }
+
+    fn send(&self, write: Write) {
+        if let Some(tx) = &self.tx {
+            if tx.send(write) {
+                warn!("Database writer is falling behind, dropping the oldest rows.");
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::datapoint::{DataPoint, DATA_POINT_VALUES as FIELDS};
+
+///
+/// Pick at most `threshold` of the `len` datapoints yielded in time order by
+/// `datapoints` with Largest-Triangle-Three-Buckets, keeping the first and the
+/// last one. From every bucket the datapoint forming the largest triangle with
+/// the one picked before and the average of the next bucket is kept, summed
+/// over all fields scaled by `scale`, e.g. one over the range of each field so
+/// every field counts the same. Only two buckets are held at a time, so
+/// millions of rows can be streamed from the database.
+///
+pub fn lttb(
+    mut datapoints: impl Iterator<Item = DataPoint>,
+    len: usize,
+    threshold: usize,
+    scale: &[f64; FIELDS],
+) -> Vec<DataPoint> {
+    if threshold < 3 || len <= threshold {
+        return datapoints.take(len).collect();
+    }
+    let buckets = threshold - 2;
+    let every = (len - 2) as f64 / buckets as f64;
+    // Start of each bucket, the last one holding only the last datapoint.
+    let start = |i: usize| match i {
+        i if i > buckets => len,
+        i => ((i as f64 * every) as usize + 1).min(len - 1),
+    };
+    let mut sampled = Vec::with_capacity(threshold);
+    let Some(first) = datapoints.next() else {
+        return sampled;
+    };
+    sampled.push(first);
+    let mut picked = first;
+    let mut current: Vec<DataPoint> = datapoints.by_ref().take(start(1) - start(0)).collect();
+    for i in 0..buckets {
+        let next: Vec<DataPoint> = datapoints
+            .by_ref()
+            .take(start(i + 2) - start(i + 1))
+            .collect();
+        let average = average(&next).unwrap_or(picked);
+        let largest = current.iter().max_by(|a, b| {
+            area(&picked, a, &average, scale).total_cmp(&area(&picked, b, &average, scale))
+        });
+        if let Some(&largest) = largest {
+            sampled.push(largest);
+            picked = largest;
+        }
+        current = next;
+    }
+    sampled.extend(current);
+    sampled
+}
+
+///
+/// The datapoint at the mean time and with the mean values of `datapoints`.
+///
+fn average(datapoints: &[DataPoint]) -> Option<DataPoint> {
+    if datapoints.is_empty() {
+        return None;
+    }
+    let n = datapoints.len() as f64;
+    let mut values = [0.0; FIELDS];
+    let mut time = 0.0;
+    for dp in datapoints {
+        for (sum, value) in values.iter_mut().zip(dp.values()) {
+            *sum += value / n;
+        }
+        time += dp.get_time() as f64 / n;
+    }
+    Some(DataPoint::with_time(time.round() as i64, &values))
+}
+
+///
+/// Twice the area of the triangle `a`, `b`, `c` summed over the scaled fields.
+///
+fn area(a: &DataPoint, b: &DataPoint, c: &DataPoint, scale: &[f64; FIELDS]) -> f64 {
+    let (ta, tb, tc) = (
+        a.get_time() as f64,
+        b.get_time() as f64,
+        c.get_time() as f64,
+    );
+    a.values()
+        .iter()
+        .zip(b.values())
+        .zip(c.values())
+        .zip(scale)
+        .map(|(((ya, yb), yc), scale)| {
+            ((ta - tc) * (yb - ya) - (ta - tb) * (yc - ya)).abs() * scale
+        })
+        .sum()
+}
@@ -0,0 +1,36 @@
+use std::io;
+use thiserror::Error;
+
+//
+// Structs
+//
+/// Errors of reading the charge controller and storing its data, returned
+/// instead of panicking so a bad line or a missing file only fails one call.
+#[derive(Debug, Error)]
+pub enum SolarError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("Serial port: {0}")]
+    Serial(#[from] tokio_serial::Error),
+    #[error("Database: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Invalid datapoint '{0}'.")]
+    InvalidDatapoint(String),
+    #[error("Invalid timestamp {0}.")]
+    InvalidTimestamp(i64),
+}
+
+pub type Result<T> = std::result::Result<T, SolarError>;
+
+//
+// implementations
+//
+impl From<SolarError> for io::Error {
+    fn from(e: SolarError) -> Self {
+        match e {
+            SolarError::Io(e) => e,
+            SolarError::Serial(e) => e.into(),
+            e => io::Error::other(e),
+        }
+    }
+}
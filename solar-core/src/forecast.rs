@@ -0,0 +1,65 @@
+use chrono::Local;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+//
+// Constants
+//
+const FORECAST_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS Forecast ",
+    "(hour INTEGER PRIMARY KEY NOT NULL, expected_wh DOUBLE, fetched INTEGER)"
+);
+const FORECAST_INSERT: &str =
+    "INSERT OR REPLACE INTO Forecast(hour, expected_wh, fetched) VALUES(?,?,?)";
+/// Name of the forecast as a Grafana metric.
+pub const FORECAST_METRIC: &str = "energy_forecast_wh";
+
+///
+/// Create the forecast table.
+///
+pub fn init(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(FORECAST_CREATE_STMT, ())?;
+    Ok(())
+}
+
+///
+/// Store the expected energy per hour, replacing older forecasts of the same hours.
+///
+pub fn store(connection: &mut Connection, hours: &BTreeMap<i64, f64>) -> rusqlite::Result<()> {
+    let fetched = Local::now().timestamp();
+    let trans = connection.transaction()?;
+    for (hour, expected_wh) in hours {
+        trans.execute(FORECAST_INSERT, (hour, expected_wh, fetched))?;
+    }
+    trans.commit()
+}
+
+///
+/// Expected energy of all hours starting in `from..to`, `None` without any forecast.
+///
+pub fn expected(connection: &Connection, from: i64, to: i64) -> rusqlite::Result<Option<f64>> {
+    connection.query_row(
+        "SELECT SUM(expected_wh) FROM Forecast WHERE hour >= ?1 AND hour < ?2",
+        (from, to),
+        |row| row.get(0),
+    )
+}
+
+///
+/// Expected energy over buckets of `interval` seconds (at least an hour).
+///
+pub fn series(
+    connection: &Connection,
+    interval: i64,
+    from: i64,
+    to: i64,
+) -> rusqlite::Result<Vec<(i64, f64)>> {
+    let mut stmt = connection.prepare(
+        "SELECT (hour / ?3) * ?3 AS bucket, SUM(expected_wh) FROM Forecast \
+         WHERE hour >= ?1 AND hour < ?2 GROUP BY bucket ORDER BY bucket",
+    )?;
+    let rows = stmt.query_map((from, to, interval.max(3600)), |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,201 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+/// Days of measurements the trend and the health score cover.
+pub const TREND_DAYS: u64 = 12 * 7;
+const HEALTH_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS BatteryHealth ",
+    "(day INTEGER PRIMARY KEY NOT NULL, sag_mohm DOUBLE, self_discharge_mv_h DOUBLE)"
+);
+const HEALTH_INSERT: &str =
+    "INSERT OR REPLACE INTO BatteryHealth(day, sag_mohm, self_discharge_mv_h) VALUES(?,?,?)";
+/// The voltage drop per amp over consecutive datapoints at most `?3` seconds
+/// apart whose load current changed by at least `?4` amps, neither charging
+/// more than `?5` amps, so the change in voltage is due to the load alone.
+const SAG_SELECT: &str = concat!(
+    "SELECT AVG((prev_v - battery_voltage) / (load_current - prev_i)) * 1000.0, COUNT(*) ",
+    "FROM (SELECT time, battery_voltage, load_current, charge_current, ",
+    "LAG(time) OVER w AS prev_t, LAG(battery_voltage) OVER w AS prev_v, ",
+    "LAG(load_current) OVER w AS prev_i, LAG(charge_current) OVER w AS prev_c ",
+    "FROM Data WHERE time >= ?1 AND time < ?2 WINDOW w AS (ORDER BY time)) ",
+    "WHERE time - prev_t <= ?3 AND ABS(load_current - prev_i) >= ?4 ",
+    "AND charge_current <= ?5 AND prev_c <= ?5"
+);
+/// The most load and charge current in `?1..?2`, and the average battery
+/// voltage of its first and last `?3` seconds.
+const REST_SELECT: &str = concat!(
+    "SELECT MAX(load_current), MAX(charge_current), ",
+    "(SELECT AVG(battery_voltage) FROM Data WHERE time >= ?1 AND time < ?1 + ?3), ",
+    "(SELECT AVG(battery_voltage) FROM Data WHERE time >= ?2 - ?3 AND time < ?2) ",
+    "FROM Data WHERE time >= ?1 AND time < ?2"
+);
+/// Seconds the voltage is averaged over at both ends of the rest period, as
+/// the controller reports it in steps of 10 mV.
+const REST_EDGE: i64 = 600;
+/// Most seconds between the datapoints of a load step, longer and the
+/// battery may have recovered or discharged meanwhile.
+const MAX_STEP_GAP: i64 = 5;
+/// Load steps needed in a day for its sag to count.
+const MIN_LOAD_STEPS: i64 = 3;
+/// Seconds in a week of the trend.
+const WEEK: i64 = 7 * 24 * 3600;
+/// Weeks of data needed before the score is given.
+const MIN_WEEKS: usize = 2;
+/// Baselines smaller than these do not make small changes look like large
+/// ones, in milliohms and millivolts per hour.
+const SAG_FLOOR_MOHM: f64 = 5.0;
+const SELF_DISCHARGE_FLOOR_MV_H: f64 = 1.0;
+
+//
+// Structs
+//
+/// The battery health indicators measured on one day.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct HealthDay {
+    /// Local midnight starting the day.
+    pub day: i64,
+    /// Battery voltage drop per amp of load switched on, in milliohms, which
+    /// rises with the internal resistance of an ageing battery.
+    pub sag_mohm: Option<f64>,
+    /// Voltage lost per hour at rest overnight, in millivolts per hour.
+    pub self_discharge_mv_h: Option<f64>,
+}
+
+/// The measurements of a range of days and the health score they give.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HealthTrend {
+    /// 0 to 100, see `score`.
+    pub score: Option<f64>,
+    /// The oldest first.
+    pub days: Vec<HealthDay>,
+}
+
+///
+/// Create the battery health table.
+///
+pub fn init(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(HEALTH_CREATE_STMT, ())?;
+    Ok(())
+}
+
+///
+/// The average voltage sag per amp of the load steps in `from..to` in
+/// milliohms, `None` with fewer than three steps of at least `min_step` amps
+/// while not charging more than `rest_current`.
+///
+pub fn sag(
+    connection: &Connection,
+    from: i64,
+    to: i64,
+    rest_current: f64,
+    min_step: f64,
+) -> rusqlite::Result<Option<f64>> {
+    let (sag, steps): (Option<f64>, i64) = connection.query_row(
+        SAG_SELECT,
+        (from, to, MAX_STEP_GAP, min_step, rest_current),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(sag.filter(|sag| steps >= MIN_LOAD_STEPS && *sag > 0.0))
+}
+
+///
+/// The battery voltage lost per hour over `from..to` in millivolts, `None`
+/// unless the battery rested then, with neither load nor charge current above
+/// `rest_current`.
+///
+pub fn self_discharge(
+    connection: &Connection,
+    from: i64,
+    to: i64,
+    rest_current: f64,
+) -> rusqlite::Result<Option<f64>> {
+    let hours = (to - from - REST_EDGE) as f64 / 3600.0;
+    if hours <= 0.0 {
+        return Ok(None);
+    }
+    let (load, charge, start, end): (Option<f64>, Option<f64>, Option<f64>, Option<f64>) =
+        connection.query_row(REST_SELECT, (from, to, REST_EDGE), |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+    let resting = load.is_some_and(|load| load <= rest_current)
+        && charge.is_some_and(|charge| charge <= rest_current);
+    Ok(match (resting, start, end) {
+        (true, Some(start), Some(end)) => Some(((start - end) * 1000.0 / hours).max(0.0)),
+        _ => None,
+    })
+}
+
+///
+/// Store the indicators of a day, replacing those measured before.
+///
+pub fn store(connection: &Connection, day: &HealthDay) -> rusqlite::Result<()> {
+    connection.execute(
+        HEALTH_INSERT,
+        (day.day, day.sag_mohm, day.self_discharge_mv_h),
+    )?;
+    Ok(())
+}
+
+///
+/// The stored indicators of the days starting in `from..to`, the oldest first.
+///
+pub fn days(connection: &Connection, from: i64, to: i64) -> rusqlite::Result<Vec<HealthDay>> {
+    let mut stmt = connection.prepare(
+        "SELECT day, sag_mohm, self_discharge_mv_h FROM BatteryHealth \
+         WHERE day >= ?1 AND day < ?2 ORDER BY day",
+    )?;
+    let rows = stmt.query_map((from, to), |row| {
+        Ok(HealthDay {
+            day: row.get(0)?,
+            sag_mohm: row.get(1)?,
+            self_discharge_mv_h: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+///
+/// A health score from 100, as good as the best week, down to 0 once an
+/// indicator of the last week is twice as bad as in its best week. The worse
+/// of the sag and self-discharge scores counts. `None` with less than two
+/// weeks of measurements.
+///
+pub fn score(days: &[HealthDay]) -> Option<f64> {
+    let latest = days.iter().map(|d| d.day).max()?;
+    let weekly = |value: fn(&HealthDay) -> Option<f64>| -> Vec<f64> {
+        let mut weeks: Vec<(f64, usize)> = Vec::new();
+        for day in days {
+            let Some(value) = value(day) else { continue };
+            let week = ((latest - day.day) / WEEK) as usize;
+            if weeks.len() <= week {
+                weeks.resize(week + 1, (0.0, 0));
+            }
+            weeks[week].0 += value;
+            weeks[week].1 += 1;
+        }
+        weeks
+            .into_iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(sum, n)| sum / n as f64)
+            .collect()
+    };
+    let indicator = |weeks: Vec<f64>, floor: f64| -> Option<f64> {
+        // The first week is the latest one, unless it had no measurement.
+        if weeks.len() < MIN_WEEKS {
+            return None;
+        }
+        let best = weeks.iter().copied().fold(f64::INFINITY, f64::min);
+        let worse = (weeks[0] - best) / best.max(floor);
+        Some(100.0 * (1.0 - worse).clamp(0.0, 1.0))
+    };
+    let sag = indicator(weekly(|d| d.sag_mohm), SAG_FLOOR_MOHM);
+    let discharge = indicator(weekly(|d| d.self_discharge_mv_h), SELF_DISCHARGE_FLOOR_MV_H);
+    match (sag, discharge) {
+        (Some(sag), Some(discharge)) => Some(sag.min(discharge)),
+        (score, None) | (None, score) => score,
+    }
+}
@@ -0,0 +1,29 @@
+//!
+//! Datapoint parsing, serial access, alerting and SQLite storage of
+//! rust-solar, for use by other Rust programs such as home automation daemons.
+//!
+#[macro_use]
+extern crate log;
+
+pub mod alerts;
+pub mod config;
+pub mod cycles;
+pub mod database;
+pub mod datapoint;
+pub mod db_writer;
+pub mod downsample;
+pub mod error;
+pub mod forecast;
+pub mod health;
+#[cfg(feature = "mock")]
+pub mod mock_serial;
+pub mod queue;
+pub mod recent;
+pub mod records;
+pub mod rollup;
+pub mod serial_data_logger;
+pub mod serial_transport;
+pub mod shutdown;
+pub mod supervisor;
+pub mod timings;
+pub mod weather;
@@ -0,0 +1,274 @@
+use crate::config::SerialConfig;
+use crate::error::Result;
+use crate::serial_transport::SerialTransport;
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{sleep, Sleep};
+
+//
+// Structs
+//
+/// A scripted charge controller for tests, opened by its port name as a
+/// `MockPort` through `SerialDatalogger::<MockPort>::new`. Reads return the
+/// scripted steps in order and wait for more once they run out, writes are
+/// recorded as commands.
+#[derive(Clone)]
+pub struct MockDevice {
+    state: Arc<Mutex<DeviceState>>,
+}
+
+/// An open port of a `MockDevice`.
+pub struct MockPort {
+    state: Arc<Mutex<DeviceState>>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+/// What the device does next when read.
+#[derive(Debug)]
+enum Step {
+    Bytes(Vec<u8>),
+    Delay(Duration),
+    Eof,
+    Error(io::ErrorKind),
+}
+
+#[derive(Default)]
+struct DeviceState {
+    steps: VecDeque<Step>,
+    /// Everything written to the device.
+    written: Vec<u8>,
+    /// Opens still failing as if the device was unplugged.
+    missing: usize,
+    opens: usize,
+    /// Ports currently open, and whether one of them holds the lock.
+    open_ports: usize,
+    exclusive: bool,
+    /// The reader waiting for more steps.
+    waker: Option<Waker>,
+}
+
+//
+// implementations
+//
+impl MockDevice {
+    ///
+    /// Attach a device with an empty script at `port_name`, replacing the one
+    /// attached there before.
+    ///
+    pub fn attach(port_name: &str) -> Self {
+        let device = Self {
+            state: Arc::default(),
+        };
+        devices().insert(port_name.to_string(), device.clone());
+        device
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DeviceState> {
+        lock(&self.state)
+    }
+
+    fn push(&self, step: Step) -> &Self {
+        let mut state = self.lock();
+        state.steps.push_back(step);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self
+    }
+
+    ///
+    /// Send a line, `\r\n` is appended as by the controller.
+    ///
+    pub fn line(&self, line: &str) -> &Self {
+        self.push(Step::Bytes(format!("{}\r\n", line).into_bytes()))
+    }
+
+    ///
+    /// Send bytes as they are, e.g. part of a line.
+    ///
+    pub fn bytes(&self, bytes: &[u8]) -> &Self {
+        self.push(Step::Bytes(bytes.to_vec()))
+    }
+
+    ///
+    /// Send nothing for a while.
+    ///
+    pub fn delay(&self, delay: Duration) -> &Self {
+        self.push(Step::Delay(delay))
+    }
+
+    ///
+    /// End the stream once, as when the device is unplugged.
+    ///
+    pub fn eof(&self) -> &Self {
+        self.push(Step::Eof)
+    }
+
+    ///
+    /// Fail a read once with an error of the given kind.
+    ///
+    pub fn error(&self, kind: io::ErrorKind) -> &Self {
+        self.push(Step::Error(kind))
+    }
+
+    ///
+    /// Fail the next `opens` opens as if the device was not plugged in.
+    ///
+    pub fn missing(&self, opens: usize) -> &Self {
+        self.lock().missing = opens;
+        self
+    }
+
+    ///
+    /// The lines written to the device so far, e.g. `["LON", "LOFF"]`.
+    ///
+    pub fn commands(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.lock().written)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    ///
+    /// Times the device was opened successfully.
+    ///
+    pub fn opens(&self) -> usize {
+        self.lock().opens
+    }
+
+    ///
+    /// Scripted steps not read yet.
+    ///
+    pub fn pending(&self) -> usize {
+        self.lock().steps.len()
+    }
+}
+
+impl SerialTransport for MockPort {
+    fn open(port_name: &str, _config: &SerialConfig, exclusive: bool) -> Result<Self> {
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "No such device");
+        let state = match devices().get(port_name) {
+            Some(device) => Arc::clone(&device.state),
+            None => return Err(not_found().into()),
+        };
+        let mut device = lock(&state);
+        if device.missing > 0 {
+            device.missing -= 1;
+            return Err(not_found().into());
+        }
+        if device.exclusive || (exclusive && device.open_ports > 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                "Unable to acquire exclusive lock on serial port",
+            )
+            .into());
+        }
+        device.opens += 1;
+        device.open_ports += 1;
+        device.exclusive = exclusive;
+        drop(device);
+        Ok(Self { state, delay: None })
+    }
+
+    fn set_exclusive(&mut self, exclusive: bool) -> Result<()> {
+        let mut device = lock(&self.state);
+        if exclusive && device.open_ports > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::ResourceBusy,
+                "Unable to acquire exclusive lock on serial port",
+            )
+            .into());
+        }
+        device.exclusive = exclusive;
+        Ok(())
+    }
+}
+
+impl Drop for MockPort {
+    fn drop(&mut self) {
+        let mut device = lock(&self.state);
+        device.open_ports -= 1;
+        if device.open_ports == 0 {
+            device.exclusive = false;
+        }
+    }
+}
+
+impl AsyncRead for MockPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(delay) = &mut this.delay {
+                match delay.as_mut().poll(cx) {
+                    Poll::Ready(()) => this.delay = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            let mut device = lock(&this.state);
+            match device.steps.pop_front() {
+                Some(Step::Bytes(mut bytes)) => {
+                    let n = bytes.len().min(buf.remaining());
+                    buf.put_slice(&bytes[..n]);
+                    if n < bytes.len() {
+                        bytes.drain(..n);
+                        device.steps.push_front(Step::Bytes(bytes));
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Some(Step::Delay(delay)) => this.delay = Some(Box::pin(sleep(delay))),
+                Some(Step::Eof) => return Poll::Ready(Ok(())),
+                Some(Step::Error(kind)) => return Poll::Ready(Err(kind.into())),
+                None => {
+                    device.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MockPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        lock(&self.state).written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+///
+/// The attached devices by port name.
+///
+fn devices() -> MutexGuard<'static, HashMap<String, MockDevice>> {
+    static DEVICES: OnceLock<Mutex<HashMap<String, MockDevice>>> = OnceLock::new();
+    DEVICES
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn lock(state: &Mutex<DeviceState>) -> MutexGuard<'_, DeviceState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+//
+// Structs
+//
+/// Sending half of a bounded queue. Sending never waits: when the queue is
+/// full the oldest item is dropped, so a stalled consumer catches up with the
+/// latest items and the memory used stays bounded.
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Receiving half of a bounded queue, for a blocking thread.
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    /// Set once the sender is dropped.
+    closed: bool,
+    /// Whether items were dropped since the queue was last empty.
+    overflowing: bool,
+    dropped: u64,
+}
+
+///
+/// Create a queue holding at most `capacity` items.
+///
+pub fn bounded<T>(capacity: usize) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(capacity.max(1)),
+            closed: false,
+            overflowing: false,
+            dropped: 0,
+        }),
+        ready: Condvar::new(),
+        capacity: capacity.max(1),
+    });
+    (
+        QueueSender {
+            shared: Arc::clone(&shared),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+//
+// implementations
+//
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> QueueSender<T> {
+    ///
+    /// Queue an item, dropping the oldest one when full. Returns true when
+    /// this starts an overflow, for the caller to log it once.
+    ///
+    pub fn send(&self, item: T) -> bool {
+        let mut state = self.shared.lock();
+        let mut started = false;
+        if state.items.len() >= self.shared.capacity {
+            state.items.pop_front();
+            state.dropped += 1;
+            started = !state.overflowing;
+            state.overflowing = true;
+        }
+        state.items.push_back(item);
+        drop(state);
+        self.shared.ready.notify_one();
+        started
+    }
+
+    ///
+    /// Items dropped so far because the queue was full.
+    ///
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().dropped
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.ready.notify_one();
+    }
+}
+
+impl<T> QueueReceiver<T> {
+    ///
+    /// Wait for the next item, `None` once the sender is dropped and every
+    /// queued item was received.
+    ///
+    pub fn recv(&self) -> Option<T> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                if state.items.is_empty() {
+                    state.overflowing = false;
+                }
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self
+                .shared
+                .ready
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    ///
+    /// Whether the sender was dropped, items may still be queued.
+    ///
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed
+    }
+}
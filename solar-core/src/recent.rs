@@ -0,0 +1,81 @@
+use crate::datapoint::DataPoint;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+//
+// Constants
+//
+/// Datapoints kept, an hour at the default poll interval.
+pub const RECENT_SAMPLES: usize = 3600;
+
+//
+// Structs
+//
+/// Ring buffer of the latest datapoints, written by the datalogger and read
+/// by the UI and the API without a copy of their own or a database query.
+/// The lock is only held to push or copy out datapoints.
+#[derive(Debug, Clone)]
+pub struct RecentData {
+    datapoints: Arc<RwLock<VecDeque<DataPoint>>>,
+    capacity: usize,
+}
+
+//
+// implementations
+//
+impl RecentData {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            datapoints: Arc::new(RwLock::new(VecDeque::with_capacity(capacity))),
+            capacity: capacity.max(1),
+        }
+    }
+
+    ///
+    /// Append a datapoint, dropping the oldest one when full.
+    ///
+    pub fn push(&self, datapoint: DataPoint) {
+        let mut datapoints = self
+            .datapoints
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if datapoints.len() >= self.capacity {
+            datapoints.pop_front();
+        }
+        datapoints.push_back(datapoint);
+    }
+
+    pub fn latest(&self) -> Option<DataPoint> {
+        self.datapoints
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .back()
+            .copied()
+    }
+
+    ///
+    /// The datapoints newer than `since`, the oldest first.
+    ///
+    pub fn since(&self, since: i64) -> Vec<DataPoint> {
+        let datapoints = self
+            .datapoints
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let start = datapoints.partition_point(|dp| dp.get_time() <= since);
+        datapoints.range(start..).copied().collect()
+    }
+
+    ///
+    /// Map every datapoint, the oldest first, e.g. to the points of a chart.
+    ///
+    pub fn map<T>(&self, f: impl FnMut(&DataPoint) -> T) -> Vec<T> {
+        self.datapoints
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(f)
+            .collect()
+    }
+}
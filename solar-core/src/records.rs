@@ -0,0 +1,187 @@
+use crate::datapoint::DataPoint;
+use chrono::{DateTime, Local};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+const RECORDS_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS Records ",
+    "(period TEXT NOT NULL, name TEXT NOT NULL, value DOUBLE, time INTEGER,",
+    " PRIMARY KEY(period, name))"
+);
+const RECORDS_INSERT: &str =
+    "INSERT OR REPLACE INTO Records(period, name, value, time) VALUES(?,?,?,?)";
+/// Period of the all-time records, the others are months as `YYYY-MM`.
+pub const ALL_TIME: &str = "all";
+
+/// The values records are kept of.
+pub const RECORD_KINDS: [RecordKind; 3] = [
+    RecordKind {
+        name: "pv_power_w",
+        label: "PV power",
+        unit: "W",
+        highest: true,
+        expression: "battery_voltage * charge_current",
+        value: |d| d.get_battery_voltage() * d.get_charge_current(),
+    },
+    RecordKind {
+        name: "charge_current_a",
+        label: "Charge current",
+        unit: "A",
+        highest: true,
+        expression: "charge_current",
+        value: DataPoint::get_charge_current,
+    },
+    RecordKind {
+        name: "battery_voltage_min_v",
+        label: "Battery low",
+        unit: "V",
+        highest: false,
+        expression: "battery_voltage",
+        value: DataPoint::get_battery_voltage,
+    },
+];
+
+//
+// Structs
+//
+/// A value records are kept of.
+pub struct RecordKind {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub unit: &'static str,
+    /// Whether the highest value is the record, else the lowest.
+    pub highest: bool,
+    /// The value of a row of the Data table.
+    expression: &'static str,
+    value: fn(&DataPoint) -> f64,
+}
+
+/// The best value of a kind within a period and when it was reached.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Record {
+    /// `all` or the local month as `YYYY-MM`.
+    pub period: String,
+    /// One of the names of `RECORD_KINDS`.
+    pub name: String,
+    pub value: f64,
+    pub time: i64,
+}
+
+//
+// implementations
+//
+impl RecordKind {
+    fn beats(&self, value: f64, record: f64) -> bool {
+        match self.highest {
+            true => value > record,
+            false => value < record,
+        }
+    }
+}
+
+///
+/// Create the records table and fill it from the stored datapoints when it
+/// is empty, e.g. for databases written by older versions.
+///
+pub fn init(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(RECORDS_CREATE_STMT, ())?;
+    let records: i64 = connection.query_row("SELECT COUNT(*) FROM Records", (), |r| r.get(0))?;
+    if records > 0 {
+        return Ok(());
+    }
+    for kind in &RECORD_KINDS {
+        let aggregate = match kind.highest {
+            true => "MAX",
+            false => "MIN",
+        };
+        // SQLite takes the bare time column from the row of the MAX or MIN.
+        let sql = format!(
+            "INSERT INTO Records(period, name, value, time) \
+             SELECT ?1, ?2, value, time FROM (SELECT {a}({e}) AS value, time FROM Data) \
+             WHERE value IS NOT NULL \
+             UNION ALL SELECT strftime('%Y-%m', time, 'unixepoch', 'localtime') AS month, \
+             ?2, {a}({e}), time FROM Data GROUP BY month",
+            a = aggregate,
+            e = kind.expression
+        );
+        connection.execute(&sql, (ALL_TIME, kind.name))?;
+    }
+    Ok(())
+}
+
+///
+/// All records, the all-time ones first and then the most recent months.
+///
+pub fn load(connection: &Connection) -> rusqlite::Result<Vec<Record>> {
+    let mut stmt = connection.prepare(
+        "SELECT period, name, value, time FROM Records \
+         ORDER BY period = ?1 DESC, period DESC, name",
+    )?;
+    let rows = stmt.query_map([ALL_TIME], |row| {
+        Ok(Record {
+            period: row.get(0)?,
+            name: row.get(1)?,
+            value: row.get(2)?,
+            time: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+///
+/// Store records, replacing those of the same period and kind.
+///
+pub fn store(connection: &mut Connection, records: &[Record]) -> rusqlite::Result<()> {
+    let trans = connection.transaction()?;
+    for record in records {
+        trans.execute(
+            RECORDS_INSERT,
+            (&record.period, &record.name, record.value, record.time),
+        )?;
+    }
+    trans.commit()
+}
+
+///
+/// Update the all-time and monthly records beaten by a datapoint and return
+/// the new ones.
+///
+pub fn update(records: &mut Vec<Record>, datapoint: &DataPoint) -> Vec<Record> {
+    let time = datapoint.get_time();
+    let Some(month) = month(time) else {
+        return Vec::new();
+    };
+    let mut beaten = Vec::new();
+    for period in [ALL_TIME, month.as_str()] {
+        for kind in &RECORD_KINDS {
+            let value = (kind.value)(datapoint);
+            let record = Record {
+                period: period.to_string(),
+                name: kind.name.to_string(),
+                value,
+                time,
+            };
+            match records
+                .iter_mut()
+                .find(|r| r.period == period && r.name == kind.name)
+            {
+                Some(old) if !kind.beats(value, old.value) => continue,
+                Some(old) => *old = record.clone(),
+                None => records.push(record.clone()),
+            }
+            beaten.push(record);
+        }
+    }
+    beaten
+}
+
+///
+/// The local month of a time as `YYYY-MM`.
+///
+pub fn month(time: i64) -> Option<String> {
+    DateTime::from_timestamp(time, 0).map(|t| t.with_timezone(&Local).format("%Y-%m").to_string())
+}
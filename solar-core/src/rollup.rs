@@ -0,0 +1,192 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+/// Samples further apart than this (in seconds) are treated as a gap and
+/// do not contribute energy or charge time, nor do samples with a recorded
+/// gap since the one before. The interval to the sample before is the one
+/// measured on the monotonic clock when stored, so a step of the wall clock
+/// does not inflate or zero it, else the difference of the sample times.
+const MAX_SAMPLE_GAP: i64 = 60;
+/// Least energy harvested for an efficiency, in Wh, less makes the ratio
+/// meaningless.
+const MIN_EFFICIENCY_WH: f64 = 1.0;
+const ROLLUP_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS Hourly ",
+    "(hour INTEGER PRIMARY KEY NOT NULL, samples INTEGER,",
+    " battery_voltage_min DOUBLE, battery_voltage_max DOUBLE, battery_voltage_avg DOUBLE,",
+    " pv_voltage_max DOUBLE, charge_wh DOUBLE, load_wh DOUBLE, charge_seconds DOUBLE)"
+);
+const DATA_TIME_INDEX_STMT: &str = "CREATE INDEX IF NOT EXISTS DataTime ON Data(time)";
+const ROLLUP_REFRESH: &str = concat!(
+    "INSERT OR REPLACE INTO Hourly(",
+    "hour, samples, battery_voltage_min, battery_voltage_max, battery_voltage_avg, ",
+    "pv_voltage_max, charge_wh, load_wh, charge_seconds) ",
+    "SELECT (time / 3600) * 3600 AS hour, COUNT(*), ",
+    "MIN(battery_voltage), MAX(battery_voltage), AVG(battery_voltage), MAX(pv_voltage), ",
+    "SUM(battery_voltage * charge_current * dt) / 3600.0, ",
+    "SUM(battery_voltage * load_current * dt) / 3600.0, ",
+    "SUM(CASE WHEN charging > 0 THEN dt ELSE 0 END) ",
+    "FROM (SELECT *, CASE WHEN EXISTS (SELECT 1 FROM DataGaps WHERE start < time AND end > prev) ",
+    "THEN 0 ELSE MIN(COALESCE(interval_ms / 1000.0, time - prev, 0), ?3) END AS dt ",
+    "FROM (SELECT *, LAG(time) OVER (ORDER BY time) AS prev ",
+    "FROM Data WHERE time >= ?1 AND time < ?2)) ",
+    "GROUP BY hour"
+);
+const ROLLUP_SUMMARY: &str = concat!(
+    "SELECT COALESCE(SUM(samples), 0), MIN(battery_voltage_min), MAX(battery_voltage_max), ",
+    "MAX(pv_voltage_max), COALESCE(SUM(charge_wh), 0), COALESCE(SUM(load_wh), 0), ",
+    "COALESCE(SUM(charge_seconds), 0) ",
+    "FROM Hourly WHERE hour >= ?1 AND hour < ?2"
+);
+
+/// Metrics available from the rollup table as (name, SQL aggregate over hours).
+pub const ROLLUP_METRICS: [(&str, &str); 6] = [
+    ("battery_voltage_min", "MIN(battery_voltage_min)"),
+    ("battery_voltage_max", "MAX(battery_voltage_max)"),
+    ("pv_voltage_max", "MAX(pv_voltage_max)"),
+    ("energy_harvested_wh", "SUM(charge_wh)"),
+    ("energy_consumed_wh", "SUM(load_wh)"),
+    ("charge_hours", "SUM(charge_seconds) / 3600.0"),
+];
+
+//
+// Structs
+//
+/// Summary statistics over a time range, computed from the hourly rollups.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct Summary {
+    pub from: i64,
+    pub to: i64,
+    pub samples: i64,
+    pub battery_voltage_min: Option<f64>,
+    pub battery_voltage_max: Option<f64>,
+    pub pv_voltage_max: Option<f64>,
+    pub energy_harvested_wh: f64,
+    pub energy_consumed_wh: f64,
+    pub charge_hours: f64,
+    /// Energy consumed per energy harvested in percent, see `efficiency`.
+    #[serde(default)]
+    pub efficiency_percent: Option<f64>,
+    /// Expected harvest according to the solar forecast, if one was fetched.
+    pub energy_forecast_wh: Option<f64>,
+}
+
+/// The energy harvested in an hour.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+pub struct HourlyEnergy {
+    /// Unix seconds starting the hour.
+    pub hour: i64,
+    pub energy_harvested_wh: f64,
+}
+
+///
+/// Create the rollup table and backfill it when it is empty but data exists,
+/// e.g. for databases written by older versions.
+///
+pub fn init(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(DATA_TIME_INDEX_STMT, ())?;
+    connection.execute(ROLLUP_CREATE_STMT, ())?;
+    let rollups: i64 = connection.query_row("SELECT COUNT(*) FROM Hourly", (), |r| r.get(0))?;
+    if rollups == 0 {
+        refresh(connection, i64::MIN, i64::MAX)?;
+    }
+    Ok(())
+}
+
+///
+/// Recompute the hourly rollups of every hour touched by the range `from..=to`.
+///
+pub fn refresh(connection: &Connection, from: i64, to: i64) -> rusqlite::Result<()> {
+    let start = from.div_euclid(3600).saturating_mul(3600);
+    let end = to.div_euclid(3600).saturating_add(1).saturating_mul(3600);
+    connection.execute(ROLLUP_REFRESH, (start, end, MAX_SAMPLE_GAP))?;
+    Ok(())
+}
+
+///
+/// A rollup metric over buckets of `interval` seconds (at least an hour) of
+/// the hours starting in `from..to`, as (bucket start, value).
+///
+pub fn series(
+    connection: &Connection,
+    metric: &str,
+    interval: i64,
+    from: i64,
+    to: i64,
+) -> rusqlite::Result<Vec<(i64, f64)>> {
+    let Some((_, aggregate)) = ROLLUP_METRICS.iter().find(|(name, _)| *name == metric) else {
+        return Err(rusqlite::Error::InvalidColumnName(metric.to_string()));
+    };
+    let sql = format!(
+        "SELECT (hour / ?3) * ?3 AS bucket, {} FROM Hourly \
+         WHERE hour >= ?1 AND hour < ?2 GROUP BY bucket ORDER BY bucket",
+        aggregate
+    );
+    let mut stmt = connection.prepare(&sql)?;
+    let rows = stmt.query_map((from, to, interval.max(3600)), |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    rows.collect()
+}
+
+///
+/// The energy harvested in each hour starting in `from..to` with data, the
+/// oldest first.
+///
+pub fn hourly_harvest(
+    connection: &Connection,
+    from: i64,
+    to: i64,
+) -> rusqlite::Result<Vec<HourlyEnergy>> {
+    let mut stmt = connection.prepare(
+        "SELECT hour, charge_wh FROM Hourly WHERE hour >= ?1 AND hour < ?2 ORDER BY hour",
+    )?;
+    let rows = stmt.query_map((from, to), |row| {
+        Ok(HourlyEnergy {
+            hour: row.get(0)?,
+            energy_harvested_wh: row.get(1)?,
+        })
+    })?;
+    rows.collect()
+}
+
+///
+/// Summarize the hourly rollups of all hours starting in `from..to`.
+///
+pub fn summarize(connection: &Connection, from: i64, to: i64) -> rusqlite::Result<Summary> {
+    connection.query_row(ROLLUP_SUMMARY, (from, to), |row| {
+        let charge_seconds: f64 = row.get(6)?;
+        let (harvested, consumed) = (row.get(4)?, row.get(5)?);
+        Ok(Summary {
+            from,
+            to,
+            samples: row.get(0)?,
+            battery_voltage_min: row.get(1)?,
+            battery_voltage_max: row.get(2)?,
+            pv_voltage_max: row.get(3)?,
+            energy_harvested_wh: harvested,
+            energy_consumed_wh: consumed,
+            charge_hours: charge_seconds / 3600.0,
+            efficiency_percent: efficiency(harvested, consumed),
+            energy_forecast_wh: None,
+        })
+    })
+}
+
+///
+/// The energy the load consumed per energy the controller charged the battery
+/// with, in percent: what is lost in the battery and the wiring between the
+/// charge and load outputs. Over a single day the energy stored in or taken
+/// from the battery skews it, over weeks it evens out. `None` below 1 Wh
+/// harvested.
+///
+pub fn efficiency(harvested_wh: f64, consumed_wh: f64) -> Option<f64> {
+    match harvested_wh >= MIN_EFFICIENCY_WH {
+        true => Some(100.0 * consumed_wh / harvested_wh),
+        false => None,
+    }
+}
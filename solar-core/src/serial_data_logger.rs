@@ -0,0 +1,234 @@
+use crate::config::SerialConfig;
+use crate::datapoint::DataPoint;
+use crate::error::{Result, SolarError};
+use crate::serial_transport::SerialTransport;
+use crate::shutdown::Shutdown;
+use crate::timings::{Stage, TIMINGS};
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::time::{sleep, timeout};
+use tokio_serial::SerialStream;
+
+//
+// Constants
+//
+/// Bytes reserved for a line, more than a line of the device takes.
+const LINE_CAPACITY: usize = 128;
+/// Longer lines are discarded, e.g. noise on a line without line endings.
+const MAX_LINE: usize = 1024;
+
+/// Reads datapoints from the charge controller and sends it load commands,
+/// over a serial port unless given another `SerialTransport`.
+pub struct SerialDatalogger<T: SerialTransport = SerialStream> {
+    dry_run: bool,
+    port: BufReader<T>,
+    read_timeout: Duration,
+    /// The line being read, kept when a read times out half way and reused
+    /// for the next one.
+    line: Vec<u8>,
+    /// Whether `line` holds a whole line, to be cleared by the next read.
+    complete: bool,
+    /// Whether the rest of a line longer than `MAX_LINE` is being skipped.
+    discarding: bool,
+}
+
+impl<T: SerialTransport> SerialDatalogger<T> {
+    ///
+    /// Open the port, retrying every second while the device is missing until
+    /// the application stops.
+    ///
+    pub async fn new(
+        port_name: &str,
+        config: &SerialConfig,
+        dry_run: bool,
+        shutdown: &Shutdown,
+    ) -> Result<Self> {
+        Self::open(port_name, config, dry_run, shutdown, true).await
+    }
+
+    ///
+    /// Open the port again, e.g. after the system was suspended, replacing
+    /// this one once the new one is open.
+    ///
+    pub async fn reopen(
+        &mut self,
+        port_name: &str,
+        config: &SerialConfig,
+        shutdown: &Shutdown,
+    ) -> Result<()> {
+        // The port is shared while both are open, and made exclusive again
+        // once this one is closed.
+        let _ = self.port.get_mut().set_exclusive(false);
+        *self = Self::open(port_name, config, self.dry_run, shutdown, false).await?;
+        if let Err(e) = self.port.get_mut().set_exclusive(true) {
+            warn!("{}", e);
+        }
+        // The first line may have been read in part by the old port.
+        let _ = self.read_serial_datapoint().await;
+        Ok(())
+    }
+
+    async fn open(
+        port_name: &str,
+        config: &SerialConfig,
+        dry_run: bool,
+        shutdown: &Shutdown,
+        exclusive: bool,
+    ) -> Result<Self> {
+        loop {
+            match T::open(port_name, config, exclusive) {
+                Ok(p) => {
+                    // Successfully opened the serial port
+                    return Ok(Self {
+                        dry_run,
+                        port: BufReader::new(p),
+                        read_timeout: Duration::from_millis(config.timeout_ms),
+                        line: Vec::with_capacity(LINE_CAPACITY),
+                        complete: false,
+                        discarding: false,
+                    });
+                }
+                Err(ref e) if is_missing(e) => {
+                    warn!("{}", e);
+                    tokio::select! {
+                        // Retry opening the port
+                        _ = sleep(Duration::from_secs(1)) => {}
+                        _ = shutdown.stopped() => {
+                            return Err(io::Error::from(io::ErrorKind::Interrupted).into())
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    ///
+    /// Read the next line, without its line ending. The returned bytes are
+    /// only valid until the next read. A line longer than `MAX_LINE` is an
+    /// invalid datapoint and skipped up to its line ending.
+    ///
+    pub async fn read_serial_datapoint(&mut self) -> Result<&[u8]> {
+        if self.complete {
+            self.line.clear();
+            self.complete = false;
+        }
+        loop {
+            // A line cut short by a timeout is continued, up to MAX_LINE in all.
+            let limit = (MAX_LINE - self.line.len()) as u64;
+            match timeout(
+                self.read_timeout,
+                (&mut self.port)
+                    .take(limit)
+                    .read_until(b'\n', &mut self.line),
+            )
+            .await
+            {
+                Ok(Ok(0)) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => {
+                    return Err(
+                        io::Error::new(io::ErrorKind::TimedOut, "Operation timed out").into(),
+                    )
+                }
+            }
+            let ended = self.line.last() == Some(&b'\n');
+            if self.line.len() >= MAX_LINE && !ended && !self.discarding {
+                let start = String::from_utf8_lossy(&self.line[..32]).into_owned();
+                self.line.clear();
+                self.discarding = true;
+                return Err(SolarError::InvalidDatapoint(format!(
+                    "{}... longer than {} bytes",
+                    start, MAX_LINE
+                )));
+            }
+            if !self.discarding {
+                break;
+            }
+            // More of the line that was too long.
+            self.line.clear();
+            self.discarding = !ended;
+        }
+        self.complete = true;
+        let end = self
+            .line
+            .iter()
+            .rposition(|&b| b != b'\r' && b != b'\n')
+            .map_or(0, |i| i + 1);
+        Ok(&self.line[..end])
+    }
+
+    ///
+    /// Read a line and parse it, an invalid line is an error like a failed read.
+    /// Both are timed for `TIMINGS`.
+    ///
+    pub async fn read_datapoint(&mut self) -> Result<DataPoint> {
+        let start = Instant::now();
+        loop {
+            match self.read_serial_datapoint().await {
+                Ok(line) => {
+                    TIMINGS.record(Stage::SerialRead, start.elapsed());
+                    return TIMINGS
+                        .time(Stage::Parse, || DataPoint::parse(line))
+                        .inspect_err(|e| warn!("{}", e));
+                }
+                Err(SolarError::Io(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    warn!("{}", e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    async fn write(&mut self, data: &str) -> usize {
+        let port = self.port.get_mut();
+        match port.write_all(data.as_bytes()).await {
+            Ok(()) => {
+                let _ = port.flush().await;
+                data.len()
+            }
+            Err(e) => {
+                warn!("{}", e);
+                0
+            }
+        }
+    }
+
+    ///Toggle the load on or off
+    pub async fn load_on(&mut self) {
+        if self.dry_run {
+            info!("Dry run, not sending LON.");
+            return;
+        }
+        let _ = self.read_serial_datapoint().await;
+        let x = self.write("LON\n").await;
+        info!("Wrote {} bytes over serial.", x);
+    }
+
+    pub async fn load_off(&mut self) {
+        if self.dry_run {
+            info!("Dry run, not sending LOFF.");
+            return;
+        }
+        let _ = self.read_serial_datapoint().await;
+        let x = self.write("LOFF\n").await;
+        info!("Wrote {} bytes over serial.", x);
+    }
+}
+
+///
+/// Whether opening a port failed because the device is not there (yet).
+///
+fn is_missing(e: &SolarError) -> bool {
+    match e {
+        SolarError::Serial(e) => e.kind() == tokio_serial::ErrorKind::NoDevice,
+        SolarError::Io(e) => e.kind() == io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
@@ -0,0 +1,47 @@
+use crate::config::SerialConfig;
+use crate::error::Result;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+//
+// Structs
+//
+/// The byte stream a `SerialDatalogger` reads lines from and writes load
+/// commands to, a serial port unless e.g. a scripted mock for tests.
+pub trait SerialTransport: AsyncRead + AsyncWrite + Unpin + Send + Sized {
+    ///
+    /// Open the port, `exclusive` keeping other processes from opening it
+    /// too where supported. A missing device fails with a `NoDevice` serial
+    /// error or an `io::ErrorKind::NotFound` error, opening is then retried.
+    ///
+    fn open(port_name: &str, config: &SerialConfig, exclusive: bool) -> Result<Self>;
+
+    ///
+    /// Allow or stop other processes opening the port while it is open.
+    ///
+    fn set_exclusive(&mut self, exclusive: bool) -> Result<()>;
+}
+
+//
+// implementations
+//
+impl SerialTransport for SerialStream {
+    fn open(port_name: &str, config: &SerialConfig, exclusive: bool) -> Result<Self> {
+        let builder = tokio_serial::new(port_name, config.baud_rate);
+        #[cfg(unix)]
+        let builder = builder.exclusive(exclusive);
+        #[cfg(not(unix))]
+        let _ = exclusive;
+        Ok(builder.open_native_async()?)
+    }
+
+    #[cfg(unix)]
+    fn set_exclusive(&mut self, exclusive: bool) -> Result<()> {
+        Ok(SerialStream::set_exclusive(self, exclusive)?)
+    }
+
+    #[cfg(not(unix))]
+    fn set_exclusive(&mut self, _exclusive: bool) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::sync::watch;
+
+//
+// Structs
+//
+/// Stops the application once. Threads check `running` between blocking
+/// calls, tasks await `stopped` and so stop right away.
+#[derive(Clone)]
+pub struct Shutdown {
+    running: Arc<AtomicBool>,
+    stopped: Arc<watch::Sender<bool>>,
+}
+
+//
+// implementations
+//
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            running: Arc::new(AtomicBool::new(true)),
+            stopped: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    ///
+    /// Clear the flag and wake every task waiting in `stopped`, callable
+    /// from any thread including a signal handler.
+    ///
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.stopped.send_replace(true);
+    }
+
+    ///
+    /// The flag, for threads which check it between blocking calls.
+    ///
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.running)
+    }
+
+    ///
+    /// Resolves once `stop` was called.
+    ///
+    pub async fn stopped(&self) {
+        let mut stopped = self.stopped.subscribe();
+        let _ = stopped.wait_for(|&stopped| stopped).await;
+    }
+}
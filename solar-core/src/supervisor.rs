@@ -0,0 +1,94 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Delay before the first restart of a worker, doubled with every further one.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// A worker running this long is restarted after the shortest delay again.
+const STABLE_AFTER: Duration = Duration::from_secs(5 * 60);
+/// How often a sleeping supervisor checks whether the worker should stop.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+//
+// Structs
+//
+/// Delays between restarts of a worker which keeps failing.
+pub struct Backoff {
+    delay: Duration,
+    started: Instant,
+}
+
+//
+// implementations
+//
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self {
+            delay: MIN_RESTART_DELAY,
+            started: Instant::now(),
+        }
+    }
+
+    ///
+    /// The delay before restarting a worker which just failed, doubling with
+    /// every failure unless it ran for a while since the last restart.
+    ///
+    pub fn failed(&mut self) -> Duration {
+        if self.started.elapsed() >= STABLE_AFTER {
+            self.delay = MIN_RESTART_DELAY;
+        }
+        let delay = self.delay;
+        self.delay = (delay * 2).min(MAX_RESTART_DELAY);
+        delay
+    }
+
+    ///
+    /// Note that the worker was started again.
+    ///
+    pub fn restarted(&mut self) {
+        self.started = Instant::now();
+    }
+}
+
+///
+/// Run the blocking worker `work` until it returns, restarting it with
+/// backoff whenever it panics. The delay is cut short once `stopped` returns
+/// true, the worker then runs again to finish its work. Every restart is
+/// counted in `restarts`.
+///
+pub fn run_supervised(
+    name: &str,
+    restarts: &AtomicU64,
+    stopped: impl Fn() -> bool,
+    mut work: impl FnMut(),
+) {
+    let mut backoff = Backoff::new();
+    while panic::catch_unwind(AssertUnwindSafe(&mut work)).is_err() {
+        let delay = backoff.failed();
+        error!(
+            "The {} panicked, restarting in {} s.",
+            name,
+            delay.as_secs()
+        );
+        let failed = Instant::now();
+        while failed.elapsed() < delay && !stopped() {
+            thread::sleep(STOP_CHECK_INTERVAL);
+        }
+        restarts.fetch_add(1, Ordering::Relaxed);
+        backoff.restarted();
+        info!("Restarting the {}.", name);
+    }
+}
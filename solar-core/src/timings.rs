@@ -0,0 +1,180 @@
+use serde::Serialize;
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+/// Timings of the whole process, recorded wherever the work happens.
+pub static TIMINGS: Timings = Timings::new();
+
+//
+// Structs
+//
+/// The measured stages of handling a datapoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// From polling the serial port to a complete line.
+    SerialRead,
+    /// Parsing a line into a datapoint.
+    Parse,
+    /// Writing the buffered datapoints to the database.
+    DbFlush,
+    /// Drawing a frame of the terminal UI.
+    Render,
+}
+
+/// Durations of the stages in microseconds, cheap enough to record on every
+/// datapoint and frame.
+pub struct Timings {
+    stages: [StageTimer; 4],
+}
+
+struct StageTimer {
+    last: AtomicU64,
+    max: AtomicU64,
+    total: AtomicU64,
+    count: AtomicU64,
+}
+
+/// What was measured of one stage so far, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct StageTiming {
+    pub last_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    /// Number of times the stage was measured.
+    pub count: u64,
+}
+
+/// A copy of the timings, e.g. for `/api/metrics`.
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct TimingsSnapshot {
+    pub serial_read: StageTiming,
+    pub parse: StageTiming,
+    pub db_flush: StageTiming,
+    pub render: StageTiming,
+}
+
+//
+// implementations
+//
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::SerialRead,
+        Stage::Parse,
+        Stage::DbFlush,
+        Stage::Render,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::SerialRead => "read",
+            Stage::Parse => "parse",
+            Stage::DbFlush => "flush",
+            Stage::Render => "render",
+        }
+    }
+}
+
+impl StageTimer {
+    const fn new() -> Self {
+        Self {
+            last: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn snapshot(&self) -> StageTiming {
+        let ms = |us: u64| us as f64 / 1000.0;
+        let count = self.count.load(Ordering::Relaxed);
+        StageTiming {
+            last_ms: ms(self.last.load(Ordering::Relaxed)),
+            avg_ms: ms(self.total.load(Ordering::Relaxed) / count.max(1)),
+            max_ms: ms(self.max.load(Ordering::Relaxed)),
+            count,
+        }
+    }
+}
+
+impl Timings {
+    const fn new() -> Self {
+        Self {
+            stages: [
+                StageTimer::new(),
+                StageTimer::new(),
+                StageTimer::new(),
+                StageTimer::new(),
+            ],
+        }
+    }
+
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        let us = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        let timer = &self.stages[stage as usize];
+        timer.last.store(us, Ordering::Relaxed);
+        timer.max.fetch_max(us, Ordering::Relaxed);
+        timer.total.fetch_add(us, Ordering::Relaxed);
+        timer.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    ///
+    /// Run `f` and record how long it took.
+    ///
+    pub fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(stage, start.elapsed());
+        result
+    }
+
+    pub fn stage(&self, stage: Stage) -> StageTiming {
+        self.stages[stage as usize].snapshot()
+    }
+
+    pub fn snapshot(&self) -> TimingsSnapshot {
+        TimingsSnapshot {
+            serial_read: self.stage(Stage::SerialRead),
+            parse: self.stage(Stage::Parse),
+            db_flush: self.stage(Stage::DbFlush),
+            render: self.stage(Stage::Render),
+        }
+    }
+}
+
+impl fmt::Display for Timings {
+    ///
+    /// The last, average and longest duration of every measured stage.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for stage in Stage::ALL {
+            let timing = self.stage(stage);
+            if timing.count == 0 {
+                continue;
+            }
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            write!(
+                f,
+                "{} {:.2} ms (avg {:.2}, max {:.2})",
+                stage.name(),
+                timing.last_ms,
+                timing.avg_ms,
+                timing.max_ms
+            )?;
+        }
+        if first {
+            write!(f, "nothing measured yet")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::Local;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+const WEATHER_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS Weather ",
+    "(hour INTEGER PRIMARY KEY NOT NULL, cloud_cover_percent DOUBLE,",
+    " irradiance_w_m2 DOUBLE, fetched INTEGER)"
+);
+const WEATHER_INSERT: &str = concat!(
+    "INSERT OR REPLACE INTO Weather(hour, cloud_cover_percent, irradiance_w_m2, fetched) ",
+    "VALUES(?,?,?,?)"
+);
+
+//
+// Structs
+//
+/// The weather observed at the panels' location in an hour.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WeatherHour {
+    /// Unix seconds starting the hour.
+    pub hour: i64,
+    /// Sky covered by clouds at the start of the hour, 0 to 100.
+    pub cloud_cover_percent: Option<f64>,
+    /// Mean global horizontal irradiance over the hour in W/m².
+    pub irradiance_w_m2: Option<f64>,
+}
+
+///
+/// Create the weather table.
+///
+pub fn init(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(WEATHER_CREATE_STMT, ())?;
+    Ok(())
+}
+
+///
+/// Store the observations, replacing those fetched before for the same hours.
+///
+pub fn store(connection: &mut Connection, hours: &[WeatherHour]) -> rusqlite::Result<()> {
+    let fetched = Local::now().timestamp();
+    let trans = connection.transaction()?;
+    for hour in hours {
+        trans.execute(
+            WEATHER_INSERT,
+            (
+                hour.hour,
+                hour.cloud_cover_percent,
+                hour.irradiance_w_m2,
+                fetched,
+            ),
+        )?;
+    }
+    trans.commit()
+}
+
+///
+/// The observations of the hours starting in `from..to`, the oldest first.
+///
+pub fn hours(connection: &Connection, from: i64, to: i64) -> rusqlite::Result<Vec<WeatherHour>> {
+    let mut stmt = connection.prepare(
+        "SELECT hour, cloud_cover_percent, irradiance_w_m2 FROM Weather \
+         WHERE hour >= ?1 AND hour < ?2 ORDER BY hour",
+    )?;
+    let rows = stmt.query_map((from, to), |row| {
+        Ok(WeatherHour {
+            hour: row.get(0)?,
+            cloud_cover_percent: row.get(1)?,
+            irradiance_w_m2: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,201 @@
+//!
+//! End-to-end tests of the serial datalogger against the scripted mock
+//! controller of the `mock` feature.
+//!
+use solar_core::config::SerialConfig;
+use solar_core::error::SolarError;
+use solar_core::mock_serial::{MockDevice, MockPort};
+use solar_core::serial_data_logger::SerialDatalogger;
+use solar_core::shutdown::Shutdown;
+use std::io;
+use std::time::{Duration, Instant};
+
+const LINE: &str = "12.64:17.90:0.80:11.10:14.40:0.00:1.00:22.50:2.50:1";
+
+fn config() -> SerialConfig {
+    SerialConfig {
+        timeout_ms: 100,
+        ..SerialConfig::default()
+    }
+}
+
+async fn open(port: &str, dry_run: bool) -> SerialDatalogger<MockPort> {
+    SerialDatalogger::new(port, &config(), dry_run, &Shutdown::new())
+        .await
+        .expect("open the mock port")
+}
+
+fn io_kind(result: Result<impl std::fmt::Debug, SolarError>) -> io::ErrorKind {
+    match result {
+        Err(SolarError::Io(e)) => e.kind(),
+        other => panic!("expected an io error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn reads_a_datapoint_per_line() {
+    let device = MockDevice::attach("mock-lines");
+    device.line(LINE).line(&LINE.replace("12.64", "12.70"));
+    let mut serial = open("mock-lines", false).await;
+    let first = serial.read_datapoint().await.unwrap();
+    let second = serial.read_datapoint().await.unwrap();
+    assert_eq!(first.get_battery_voltage(), 12.64);
+    assert_eq!(first.get_charge_current(), 2.5);
+    assert_eq!(first.get_load_onoff(), 1.0);
+    assert_eq!(second.get_battery_voltage(), 12.7);
+}
+
+#[tokio::test]
+async fn partial_frame_completes_after_a_timeout() {
+    let device = MockDevice::attach("mock-partial");
+    let (head, tail) = LINE.split_at(20);
+    device
+        .bytes(head.as_bytes())
+        .delay(Duration::from_millis(150))
+        .bytes(tail.as_bytes())
+        .bytes(b"\r\n");
+    let mut serial = open("mock-partial", false).await;
+    assert_eq!(
+        io_kind(serial.read_datapoint().await),
+        io::ErrorKind::TimedOut
+    );
+    // The part read before the timeout is kept for the next read.
+    let datapoint = serial.read_datapoint().await.unwrap();
+    assert_eq!(datapoint.get_battery_voltage(), 12.64);
+    assert_eq!(datapoint.get_load_onoff(), 1.0);
+}
+
+#[tokio::test]
+async fn invalid_frame_is_skipped() {
+    let device = MockDevice::attach("mock-invalid");
+    device.line("12.64:17.90:garbage").line(LINE);
+    let mut serial = open("mock-invalid", false).await;
+    assert!(matches!(
+        serial.read_datapoint().await,
+        Err(SolarError::InvalidDatapoint(_))
+    ));
+    assert_eq!(
+        serial.read_datapoint().await.unwrap().get_pv_voltage(),
+        17.9
+    );
+}
+
+#[tokio::test]
+async fn oversized_frame_is_skipped() {
+    let device = MockDevice::attach("mock-oversized");
+    device.line(&"9".repeat(3000)).line(LINE);
+    let mut serial = open("mock-oversized", false).await;
+    assert!(matches!(
+        serial.read_datapoint().await,
+        Err(SolarError::InvalidDatapoint(_))
+    ));
+    // The rest of the long line is skipped.
+    assert_eq!(
+        serial.read_datapoint().await.unwrap().get_battery_voltage(),
+        12.64
+    );
+}
+
+#[tokio::test]
+async fn silent_device_times_out() {
+    MockDevice::attach("mock-silent");
+    let mut serial = open("mock-silent", false).await;
+    assert_eq!(
+        io_kind(serial.read_datapoint().await),
+        io::ErrorKind::TimedOut
+    );
+}
+
+#[tokio::test]
+async fn unplugged_device_ends_the_stream() {
+    let device = MockDevice::attach("mock-eof");
+    device.eof().error(io::ErrorKind::BrokenPipe);
+    let mut serial = open("mock-eof", false).await;
+    assert_eq!(
+        io_kind(serial.read_datapoint().await),
+        io::ErrorKind::UnexpectedEof
+    );
+    assert_eq!(
+        io_kind(serial.read_datapoint().await),
+        io::ErrorKind::BrokenPipe
+    );
+}
+
+#[tokio::test]
+async fn waits_for_a_missing_device() {
+    let device = MockDevice::attach("mock-missing");
+    device.missing(1).line(LINE);
+    let start = Instant::now();
+    let mut serial = open("mock-missing", false).await;
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(device.opens(), 1);
+    assert!(serial.read_datapoint().await.is_ok());
+}
+
+#[tokio::test]
+async fn stops_waiting_on_shutdown() {
+    let device = MockDevice::attach("mock-shutdown");
+    device.missing(usize::MAX);
+    let shutdown = Shutdown::new();
+    shutdown.stop();
+    let result =
+        SerialDatalogger::<MockPort>::new("mock-shutdown", &config(), false, &shutdown).await;
+    assert_eq!(io_kind(result.map(|_| ())), io::ErrorKind::Interrupted);
+    assert_eq!(device.opens(), 0);
+}
+
+#[tokio::test]
+async fn port_is_held_exclusively() {
+    MockDevice::attach("mock-exclusive");
+    let _serial = open("mock-exclusive", false).await;
+    let second =
+        SerialDatalogger::<MockPort>::new("mock-exclusive", &config(), false, &Shutdown::new())
+            .await;
+    assert_eq!(io_kind(second.map(|_| ())), io::ErrorKind::ResourceBusy);
+}
+
+#[tokio::test]
+async fn reopen_discards_the_partial_line() {
+    let device = MockDevice::attach("mock-reopen");
+    device.bytes(b"2.50:1\r\n").line(LINE);
+    let mut serial = open("mock-reopen", false).await;
+    serial
+        .reopen("mock-reopen", &config(), &Shutdown::new())
+        .await
+        .unwrap();
+    assert_eq!(device.opens(), 2);
+    assert_eq!(device.pending(), 1);
+    assert_eq!(
+        serial.read_datapoint().await.unwrap().get_battery_voltage(),
+        12.64
+    );
+    // The new port holds the lock again.
+    let other =
+        SerialDatalogger::<MockPort>::new("mock-reopen", &config(), false, &Shutdown::new()).await;
+    assert_eq!(io_kind(other.map(|_| ())), io::ErrorKind::ResourceBusy);
+}
+
+#[tokio::test]
+async fn load_commands_follow_a_line() {
+    let device = MockDevice::attach("mock-load");
+    device.line(LINE).line(LINE).line(LINE);
+    let mut serial = open("mock-load", false).await;
+    serial.load_on().await;
+    assert_eq!(device.commands(), ["LON"]);
+    assert_eq!(device.pending(), 2);
+    serial.load_off().await;
+    serial.load_on().await;
+    assert_eq!(device.commands(), ["LON", "LOFF", "LON"]);
+    assert_eq!(device.pending(), 0);
+}
+
+#[tokio::test]
+async fn dry_run_sends_no_commands() {
+    let device = MockDevice::attach("mock-dry-run");
+    device.line(LINE);
+    let mut serial = open("mock-dry-run", true).await;
+    serial.load_on().await;
+    serial.load_off().await;
+    assert!(device.commands().is_empty());
+    assert_eq!(device.pending(), 1);
+}
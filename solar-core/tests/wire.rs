@@ -0,0 +1,132 @@
+//!
+//! Property tests of the formats datapoints travel in: the line sent by the
+//! controller and the JSON of the API, `tail` and the remote TUI.
+//!
+use proptest::prelude::*;
+use solar_core::datapoint::{DataPoint, DATA_POINT_VALUES};
+use solar_core::error::SolarError;
+
+/// Any finite value, the device never sends infinities or NaN.
+fn value() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        // Readings as sent by the controller, with two decimals.
+        (-10_000i32..10_000).prop_map(|v| v as f64 / 100.0),
+        prop::num::f64::NORMAL | prop::num::f64::SUBNORMAL | prop::num::f64::ZERO,
+    ]
+}
+
+fn datapoint() -> impl Strategy<Value = DataPoint> {
+    (any::<i64>(), prop::array::uniform10(value()))
+        .prop_map(|(time, values)| DataPoint::with_time(time, &values))
+}
+
+/// Fields which are no decimal number, each of them invalidating a line.
+fn invalid_field() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        Just(".".to_string()),
+        Just("-".to_string()),
+        Just("12.".to_string()),
+        Just("1.2.3".to_string()),
+        Just("--1".to_string()),
+        Just("1e5".to_string()),
+        Just("inf".to_string()),
+        Just("NaN".to_string()),
+        Just("0x1F".to_string()),
+        Just(" 12.5".to_string()),
+        "[a-zA-Z]{1,5}",
+        // A digit with a byte which is neither a digit nor a dot.
+        ("[0-9]{1,3}", "[^0-9.:]").prop_map(|(digits, c)| format!("{}{}", digits, c)),
+    ]
+}
+
+fn bits(values: [f64; DATA_POINT_VALUES]) -> [u64; DATA_POINT_VALUES] {
+    values.map(f64::to_bits)
+}
+
+fn assert_rejected(line: &str) -> Result<(), TestCaseError> {
+    match DataPoint::parse(line.as_bytes()) {
+        Err(SolarError::InvalidDatapoint(rejected)) => {
+            prop_assert_eq!(rejected, line);
+            Ok(())
+        }
+        other => Err(TestCaseError::fail(format!(
+            "{:?} was not rejected: {:?}",
+            line, other
+        ))),
+    }
+}
+
+proptest! {
+    #[test]
+    fn line_round_trips(datapoint in datapoint()) {
+        let line = datapoint.to_line();
+        let parsed = DataPoint::parse(line.as_bytes()).unwrap();
+        prop_assert_eq!(bits(parsed.values()), bits(datapoint.values()));
+        let from_str: DataPoint = line.parse().unwrap();
+        prop_assert_eq!(bits(from_str.values()), bits(datapoint.values()));
+    }
+
+    #[test]
+    fn json_round_trips(datapoint in datapoint()) {
+        let json = serde_json::to_string(&datapoint).unwrap();
+        let parsed: DataPoint = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(parsed.get_time(), datapoint.get_time());
+        prop_assert_eq!(bits(parsed.values()), bits(datapoint.values()));
+    }
+
+    #[test]
+    fn missing_field_is_rejected(datapoint in datapoint(), field in 0..DATA_POINT_VALUES) {
+        let mut fields: Vec<String> = datapoint.values().map(|v| v.to_string()).to_vec();
+        fields.remove(field);
+        assert_rejected(&fields.join(":"))?;
+    }
+
+    #[test]
+    fn extra_field_is_rejected(datapoint in datapoint(), extra in value(), at in 0..=DATA_POINT_VALUES) {
+        let mut fields: Vec<String> = datapoint.values().map(|v| v.to_string()).to_vec();
+        fields.insert(at, extra.to_string());
+        assert_rejected(&fields.join(":"))?;
+    }
+
+    #[test]
+    fn invalid_field_is_rejected(
+        datapoint in datapoint(),
+        field in 0..DATA_POINT_VALUES,
+        invalid in invalid_field(),
+    ) {
+        let mut fields: Vec<String> = datapoint.values().map(|v| v.to_string()).to_vec();
+        fields[field] = invalid;
+        assert_rejected(&fields.join(":"))?;
+    }
+
+    #[test]
+    fn line_cut_before_the_last_field_is_rejected(datapoint in datapoint(), cut in any::<prop::sample::Index>()) {
+        let line = datapoint.to_line();
+        let last = line.rfind(':').unwrap();
+        assert_rejected(&line[..cut.index(last + 1)])?;
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic(line in prop::collection::vec(any::<u8>(), 0..96)) {
+        if let Ok(datapoint) = DataPoint::parse(&line) {
+            prop_assert!(datapoint.values().iter().all(|v| v.is_finite()));
+        }
+    }
+
+    #[test]
+    fn mutated_line_parses_or_is_rejected(
+        datapoint in datapoint(),
+        at in any::<prop::sample::Index>(),
+        byte in any::<u8>(),
+    ) {
+        let mut line = datapoint.to_line().into_bytes();
+        let at = at.index(line.len());
+        line[at] = byte;
+        match DataPoint::parse(&line) {
+            Ok(parsed) => prop_assert!(parsed.values().iter().all(|v| v.is_finite())),
+            Err(SolarError::InvalidDatapoint(_)) => {}
+            Err(e) => prop_assert!(false, "unexpected error {}", e),
+        }
+    }
+}
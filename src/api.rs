@@ -0,0 +1,1144 @@
+use crate::cli::parse_timestamp;
+use crate::config::ApiConfig;
+use crate::control_auth::{ControlAuth, Credentials};
+use crate::datalogger::{CommandSource, ControlSender, LinkHealth, LoadCommand};
+use crate::grafana;
+use crate::rate_limit::RateLimiter;
+use crate::stats::period_bounds;
+use crate::tls::{self, TlsListener};
+use axum::{
+    extract::{
+        connect_info::Connected,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Path, Query, Request, State,
+    },
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    serve::IncomingStream,
+    Json, Router,
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use solar_core::config::parse_duration;
+use solar_core::cycles::CycleCounts;
+use solar_core::database::{AlertRecord, Database, HistoryCursor, DATA_FIELDS};
+use solar_core::datapoint::DataPoint;
+use solar_core::health::{self, HealthTrend, TREND_DAYS};
+use solar_core::recent::RecentData;
+use solar_core::records::Record;
+use solar_core::rollup::{HourlyEnergy, Summary};
+use solar_core::shutdown::Shutdown;
+use solar_core::timings::{TimingsSnapshot, TIMINGS};
+use solar_core::weather::WeatherHour;
+use std::{
+    io,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+use tokio::sync::watch;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    IntoParams, Modify, OpenApi, ToSchema,
+};
+
+//
+// Constants
+//
+/// Range returned by `/api/history` when `from` is omitted.
+const DEFAULT_HISTORY_RANGE: i64 = 24 * 60 * 60;
+/// Page size of `/api/history` when only `cursor` is given, and the largest allowed.
+const DEFAULT_PAGE_SIZE: usize = 1000;
+const MAX_PAGE_SIZE: usize = 10000;
+/// Range and number of alerts returned by `/api/alerts` by default, and the
+/// largest number allowed.
+const DEFAULT_ALERTS_RANGE: i64 = 7 * 24 * 60 * 60;
+const DEFAULT_ALERTS_LIMIT: usize = 100;
+const MAX_ALERTS_LIMIT: usize = 1000;
+/// Response header holding the cursor of the next page.
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+/// `/readyz` fails once the last datapoint is older than this many poll
+/// intervals, but never before `MIN_STALE_AGE` seconds.
+const STALE_SAMPLES: u32 = 3;
+const MIN_STALE_AGE: i64 = 30;
+
+//
+// Structs
+//
+/// The running datalogger as seen by the API.
+pub(crate) struct LiveData {
+    /// Changes with every datapoint, for the WebSocket stream.
+    pub(crate) latest: watch::Receiver<Option<DataPoint>>,
+    pub(crate) recent: RecentData,
+    pub(crate) health: Arc<LinkHealth>,
+    /// `None` unless `api.load_control` is enabled.
+    pub(crate) load_control: Option<ControlSender>,
+    pub(crate) control_auth: Arc<ControlAuth>,
+}
+
+#[derive(Clone)]
+struct ApiState {
+    latest: watch::Receiver<Option<DataPoint>>,
+    recent: RecentData,
+    health: Arc<LinkHealth>,
+    database: Arc<Mutex<Database>>,
+    device: Arc<DeviceInfo>,
+    load_control: Option<ControlSender>,
+    control_auth: Arc<ControlAuth>,
+}
+
+/// Address of the connected client, for rate limiting and request logs.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ClientAddr(SocketAddr);
+
+/// Body of `/healthz` and `/readyz`.
+#[derive(Debug, Serialize, ToSchema)]
+struct HealthStatus {
+    /// `ok` when the endpoint answers 200, `unavailable` otherwise.
+    status: &'static str,
+    /// Whether the datalogger thread is running.
+    datalogger: bool,
+    /// Failed serial reads since the last successful one.
+    serial_errors: u64,
+    /// Why the last serial read failed, until the next one succeeds.
+    last_error: Option<String>,
+    /// Times the datalogger, database writer or sinks panicked and were
+    /// restarted, and which one was restarted last.
+    restarts: u64,
+    last_restart: Option<String>,
+    /// Seconds since the last datapoint was read.
+    last_sample_age_s: Option<i64>,
+    database_writable: bool,
+}
+
+/// Static information about the connected controller served by `/api/device`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct DeviceInfo {
+    pub(crate) port: String,
+    pub(crate) baud_rate: u32,
+    pub(crate) version: &'static str,
+    pub(crate) started: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DeviceStatus {
+    #[serde(flatten)]
+    info: DeviceInfo,
+    last_datapoint: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum LoadState {
+    On,
+    Off,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct LoadRequest {
+    state: LoadState,
+    /// Required when `load.control_pin` or `load.control_secret` is set.
+    #[serde(flatten, skip_serializing)]
+    credentials: Credentials,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RecentParams {
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, only datapoints after it are
+    /// returned.
+    since: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryParams {
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default 24 hours before `to`.
+    from: Option<String>,
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default now.
+    to: Option<String>,
+    /// Average over buckets of this size, e.g. `15m`.
+    step: Option<String>,
+    /// Pick at most this many datapoints (up to 10000) which look like all of
+    /// them on a chart, instead of `step`.
+    points: Option<usize>,
+    /// Return at most this many datapoints (up to 10000) and the cursor of the
+    /// next page in the `X-Next-Cursor` header.
+    limit: Option<usize>,
+    /// `X-Next-Cursor` of the previous page.
+    cursor: Option<String>,
+    /// Comma separated fields to return besides `timestamp`.
+    fields: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SummaryParams {
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default the start of today.
+    from: Option<String>,
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default the end of today.
+    to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BatteryHealthParams {
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default 12 weeks before `to`.
+    from: Option<String>,
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default now.
+    to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct WeatherParams {
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default 24 hours before `to`.
+    from: Option<String>,
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default now.
+    to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AlertsParams {
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default 7 days before `to`.
+    from: Option<String>,
+    /// Unix seconds, RFC 3339 or `YYYY-MM-DD`, default now.
+    to: Option<String>,
+    /// Return at most this many alerts (up to 1000), default 100.
+    limit: Option<usize>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "rust-solar", description = "HTTP API of `rust-solar serve`."),
+    paths(
+        latest_datapoint,
+        recent_datapoints,
+        history,
+        summary,
+        harvest,
+        battery_health,
+        cycles,
+        weather,
+        records,
+        device_info,
+        metrics,
+        stream,
+        switch_load,
+        alerts,
+        acknowledge_alert,
+        healthz,
+        readyz
+    ),
+    modifiers(&ApiKeySecurity),
+    security((), ("bearer" = []), ("api_key" = []))
+)]
+struct ApiDoc;
+
+/// Documents the schemes accepted when `api.api_keys` is set.
+struct ApiKeySecurity;
+
+//
+// implementations
+//
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+    }
+}
+
+impl Connected<IncomingStream<'_, tokio::net::TcpListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, tokio::net::TcpListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+
+impl DeviceInfo {
+    pub(crate) fn new(port: &str, baud_rate: u32) -> Self {
+        Self {
+            port: port.to_string(),
+            baud_rate,
+            version: env!("CARGO_PKG_VERSION"),
+            started: Local::now().timestamp(),
+        }
+    }
+}
+
+///
+/// Serve the HTTP API on an already bound listener from a background thread
+/// until `running` is cleared. Binding and loading the TLS certificate happen
+/// up front so errors are reported to the caller. `/api/load` is refused
+/// unless `live.load_control` is given.
+///
+pub(crate) fn spawn_api_server(
+    listener: TcpListener,
+    config: &ApiConfig,
+    live: LiveData,
+    database: Database,
+    device: DeviceInfo,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    if config.api_keys.is_empty() && live.load_control.is_some() {
+        warn!("Load control is enabled without api.api_keys, anyone on the network can switch the load.");
+    }
+    let database = Arc::new(Mutex::new(database));
+    let state = ApiState {
+        latest: live.latest,
+        recent: live.recent,
+        health: live.health,
+        database: Arc::clone(&database),
+        device: Arc::new(device),
+        load_control: live.load_control,
+        control_auth: live.control_auth,
+    };
+    let mut app = Router::new()
+        .route("/api/latest", get(latest_datapoint))
+        .route("/api/recent", get(recent_datapoints))
+        .route("/api/history", get(history))
+        .route("/api/summary", get(summary))
+        .route("/api/harvest", get(harvest))
+        .route("/api/battery-health", get(battery_health))
+        .route("/api/cycles", get(cycles))
+        .route("/api/weather", get(weather))
+        .route("/api/records", get(records))
+        .route("/api/device", get(device_info))
+        .route("/api/metrics", get(metrics))
+        .route("/api/stream", get(stream))
+        .route("/api/load", post(switch_load))
+        .route("/api/alerts", get(alerts))
+        .route("/api/alerts/{id}/ack", post(acknowledge_alert))
+        .route("/api/openapi.json", get(openapi))
+        .with_state(state.clone())
+        .merge(grafana::router(database));
+    if !config.api_keys.is_empty() {
+        let keys = Arc::new(config.api_keys.clone());
+        app = app.layer(middleware::from_fn_with_state(keys, authorize));
+    }
+    if config.rate_limit > 0 {
+        let limiter = Arc::new(RateLimiter::new(config.rate_limit, config.rate_limit_burst));
+        app = app.layer(middleware::from_fn_with_state(limiter, rate_limit));
+    }
+    // Probes of orchestrators and uptime monitors need neither an API key nor
+    // to share the rate limit with dashboards on the same host.
+    let app = app.merge(
+        Router::new()
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .with_state(state),
+    );
+    spawn_router(listener, config, app, shutdown)
+}
+
+///
+/// Serve `app` on an already bound listener from a background thread until
+/// the application stops, over TLS when `api.tls_cert` and `api.tls_key` are
+/// set and logging requests with `api.log_requests`.
+///
+pub(crate) fn spawn_router(
+    listener: TcpListener,
+    config: &ApiConfig,
+    app: Router,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    listener.set_nonblocking(true)?;
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::server_config(cert, key)?),
+        (None, None) => None,
+        _ => {
+            return Err(io::Error::other(
+                "api.tls_cert and api.tls_key must be set together.",
+            ))
+        }
+    };
+    info!(
+        "HTTP API listening on {}://{}",
+        if tls.is_some() { "https" } else { "http" },
+        listener.local_addr()?
+    );
+    let app = if config.log_requests {
+        app.layer(middleware::from_fn(log_request))
+    } else {
+        app
+    }
+    .into_make_service_with_connect_info::<ClientAddr>();
+
+    thread::Builder::new().name("api".into()).spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(r) => r,
+            Err(e) => return error!("{}", e),
+        };
+        runtime.block_on(async move {
+            let listener = match tokio::net::TcpListener::from_std(listener) {
+                Ok(l) => l,
+                Err(e) => return error!("{}", e),
+            };
+            let shutdown = async move { shutdown.stopped().await };
+            let result = match tls {
+                Some(tls) => {
+                    axum::serve(TlsListener::new(listener, tls), app)
+                        .with_graceful_shutdown(shutdown)
+                        .await
+                }
+                None => {
+                    axum::serve(listener, app)
+                        .with_graceful_shutdown(shutdown)
+                        .await
+                }
+            };
+            if let Err(e) = result {
+                error!("{}", e);
+            }
+        });
+        info!("HTTP API stopped.");
+    })
+}
+
+///
+/// Reject requests without one of the configured API keys, given as
+/// `Authorization: Bearer <key>`, `X-API-Key: <key>` or, for WebSocket
+/// clients which cannot set headers, an `api_key` query parameter.
+///
+pub(crate) async fn authorize(
+    State(keys): State<Arc<Vec<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let key = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .or_else(|| {
+            request
+                .uri()
+                .query()
+                .and_then(|q| q.split('&').find_map(|p| p.strip_prefix("api_key=")))
+        });
+    let authorized = key.is_some_and(|key| keys.iter().any(|k| constant_time_eq(k, key)));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Missing or invalid API key.",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+///
+/// Answer 429 Too Many Requests to clients which exceed `api.rate_limit`.
+///
+pub(crate) async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(ClientAddr(client)): ConnectInfo<ClientAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(retry_after) = limiter.check(client.ip()) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                retry_after.as_secs().max(1).to_string(),
+            )],
+            "Too many requests.",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+///
+/// Log every request as `key=value` pairs once it has been answered.
+///
+async fn log_request(
+    ConnectInfo(ClientAddr(client)): ConnectInfo<ClientAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    info!(
+        "api request client={} method={} path={} status={} duration_ms={}",
+        client.ip(),
+        method,
+        path,
+        response.status().as_u16(),
+        started.elapsed().as_millis()
+    );
+    response
+}
+
+///
+/// Compare two keys without returning early, so the time taken does not
+/// reveal how much of a guessed key is correct.
+///
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/latest",
+    responses(
+        (status = 200, description = "The most recent datapoint.", body = DataPoint),
+        (status = 503, description = "No datapoint has been read yet."),
+    )
+)]
+async fn latest_datapoint(State(state): State<ApiState>) -> Result<Json<DataPoint>, StatusCode> {
+    state
+        .recent
+        .latest()
+        .map(Json)
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+///
+/// The datapoints of about the last hour kept in memory, newer than `since`
+/// if given, e.g. to fill a chart without querying the database.
+///
+#[utoipa::path(
+    get,
+    path = "/api/recent",
+    params(RecentParams),
+    responses(
+        (status = 200, description = "Recent datapoints, the oldest first.", body = Vec<DataPoint>),
+        (status = 400, description = "Invalid parameter."),
+    )
+)]
+async fn recent_datapoints(
+    State(state): State<ApiState>,
+    Query(params): Query<RecentParams>,
+) -> Result<Json<Vec<DataPoint>>, (StatusCode, String)> {
+    let since = match &params.since {
+        Some(since) => parse_timestamp(since).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => i64::MIN,
+    };
+    Ok(Json(state.recent.since(since)))
+}
+
+///
+/// Stored datapoints between `from` and `to` (default the last 24 hours),
+/// averaged over buckets of `step` (e.g. `5m`) when given, or downsampled to
+/// `points` for a chart. With `limit` or `cursor` the range is returned in
+/// pages, with `fields` only the given fields are included.
+///
+#[utoipa::path(
+    get,
+    path = "/api/history",
+    params(HistoryParams),
+    responses(
+        (status = 200, description = "Datapoints, without the fields left out by `fields`.",
+            body = Vec<DataPoint>,
+            headers(("x-next-cursor" = String, description = "Cursor of the next page, if any."))),
+        (status = 400, description = "Invalid parameter."),
+    )
+)]
+async fn history(
+    State(state): State<ApiState>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let to = match &params.to {
+        Some(to) => parse_timestamp(to).map_err(bad_request)?,
+        None => Local::now().timestamp(),
+    };
+    let from = match &params.from {
+        Some(from) => parse_timestamp(from).map_err(bad_request)?,
+        None => to - DEFAULT_HISTORY_RANGE,
+    };
+    let step = match &params.step {
+        Some(step) => Some(parse_duration(step).map_err(bad_request)? as i64),
+        None => None,
+    };
+    let fields = match &params.fields {
+        Some(fields) => Some(parse_fields(fields).map_err(bad_request)?),
+        None => None,
+    };
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let page = if let Some(points) = params.points {
+        if points == 0 || points > MAX_PAGE_SIZE {
+            return Err(bad_request(format!(
+                "points must be between 1 and {}.",
+                MAX_PAGE_SIZE
+            )));
+        }
+        if step.is_some() || params.limit.is_some() || params.cursor.is_some() {
+            return Err(bad_request(
+                "points can not be combined with step, limit or cursor.".to_string(),
+            ));
+        }
+        database
+            .downsampled(from, to, points)
+            .map(|datapoints| (datapoints, None))
+    } else if params.limit.is_some() || params.cursor.is_some() {
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            return Err(bad_request(format!(
+                "limit must be between 1 and {}.",
+                MAX_PAGE_SIZE
+            )));
+        }
+        let cursor = match &params.cursor {
+            Some(cursor) => cursor.parse().map_err(bad_request)?,
+            None => HistoryCursor::start(from),
+        };
+        database.history_page(cursor, to, step, limit)
+    } else {
+        match step {
+            Some(step) => database.history(from, to, step),
+            None => database.datapoints_between(from, to),
+        }
+        .map(|datapoints| (datapoints, None))
+    };
+    let (datapoints, next) = page.map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?;
+    let mut response = match fields {
+        Some(fields) => Json(select_fields(&datapoints, &fields)).into_response(),
+        None => Json(datapoints).into_response(),
+    };
+    if let Some(next) = next {
+        if let Ok(value) = HeaderValue::from_str(&next.to_string()) {
+            response.headers_mut().insert(NEXT_CURSOR_HEADER, value);
+        }
+    }
+    Ok(response)
+}
+
+///
+/// Split a comma separated list of field names, rejecting unknown ones.
+///
+fn parse_fields(fields: &str) -> Result<Vec<String>, String> {
+    fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| match DATA_FIELDS.contains(&field) {
+            true => Ok(field.to_string()),
+            false => Err(format!(
+                "unknown field {}, expected one of {}.",
+                field,
+                DATA_FIELDS.join(", ")
+            )),
+        })
+        .collect()
+}
+
+///
+/// The datapoints as JSON objects holding only the timestamp and `fields`.
+///
+fn select_fields(datapoints: &[DataPoint], fields: &[String]) -> Vec<serde_json::Value> {
+    datapoints
+        .iter()
+        .filter_map(|dp| match serde_json::to_value(dp) {
+            Ok(serde_json::Value::Object(mut values)) => {
+                values.retain(|field, _| field == "timestamp" || fields.contains(field));
+                Some(serde_json::Value::Object(values))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+///
+/// Summary statistics of the hours between `from` and `to`, by default today.
+///
+#[utoipa::path(
+    get,
+    path = "/api/summary",
+    params(SummaryParams),
+    responses(
+        (status = 200, description = "Summary of the range.", body = Summary),
+        (status = 400, description = "Invalid parameter."),
+    )
+)]
+async fn summary(
+    State(state): State<ApiState>,
+    Query(params): Query<SummaryParams>,
+) -> Result<Json<Summary>, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let (today, tomorrow) = period_bounds(false).map_err(|e| bad_request(e.to_string()))?;
+    let from = match &params.from {
+        Some(from) => parse_timestamp(from).map_err(bad_request)?,
+        None => today,
+    };
+    let to = match &params.to {
+        Some(to) => parse_timestamp(to).map_err(bad_request)?,
+        None => tomorrow,
+    };
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    database.summary(from, to).map(Json).map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })
+}
+
+///
+/// The energy harvested per hour between `from` and `to`, by default today.
+///
+#[utoipa::path(
+    get,
+    path = "/api/harvest",
+    params(SummaryParams),
+    responses(
+        (status = 200, description = "Hours of the range with data.", body = Vec<HourlyEnergy>),
+        (status = 400, description = "Invalid parameter."),
+    )
+)]
+async fn harvest(
+    State(state): State<ApiState>,
+    Query(params): Query<SummaryParams>,
+) -> Result<Json<Vec<HourlyEnergy>>, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let (today, tomorrow) = period_bounds(false).map_err(|e| bad_request(e.to_string()))?;
+    let from = match &params.from {
+        Some(from) => parse_timestamp(from).map_err(bad_request)?,
+        None => today,
+    };
+    let to = match &params.to {
+        Some(to) => parse_timestamp(to).map_err(bad_request)?,
+        None => tomorrow,
+    };
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    database.hourly_harvest(from, to).map(Json).map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })
+}
+
+///
+/// The battery health measurements of the days between `from` and `to`, by
+/// default the last 12 weeks, and the health score they give.
+///
+#[utoipa::path(
+    get,
+    path = "/api/battery-health",
+    params(BatteryHealthParams),
+    responses(
+        (status = 200, description = "Battery health trend.", body = HealthTrend),
+        (status = 400, description = "Invalid parameter."),
+    )
+)]
+async fn battery_health(
+    State(state): State<ApiState>,
+    Query(params): Query<BatteryHealthParams>,
+) -> Result<Json<HealthTrend>, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let to = match &params.to {
+        Some(to) => parse_timestamp(to).map_err(bad_request)?,
+        None => Local::now().timestamp(),
+    };
+    let from = match &params.from {
+        Some(from) => parse_timestamp(from).map_err(bad_request)?,
+        None => to - TREND_DAYS as i64 * 24 * 3600,
+    };
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    match database.battery_health(from, to) {
+        Ok(days) => Ok(Json(HealthTrend {
+            score: health::score(&days),
+            days,
+        })),
+        Err(e) => {
+            warn!("{}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    }
+}
+
+///
+/// The charge cycles counted per device by depth of discharge.
+///
+#[utoipa::path(
+    get,
+    path = "/api/cycles",
+    responses((status = 200, description = "Cycle counts per device.", body = Vec<CycleCounts>))
+)]
+async fn cycles(
+    State(state): State<ApiState>,
+) -> Result<Json<Vec<CycleCounts>>, (StatusCode, String)> {
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    database.cycle_counts().map(Json).map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })
+}
+
+///
+/// The weather observed per hour between `from` and `to`, by default the
+/// last 24 hours, if `[weather]` is enabled.
+///
+#[utoipa::path(
+    get,
+    path = "/api/weather",
+    params(WeatherParams),
+    responses(
+        (status = 200, description = "Hours of the range with observations.", body = Vec<WeatherHour>),
+        (status = 400, description = "Invalid parameter."),
+    )
+)]
+async fn weather(
+    State(state): State<ApiState>,
+    Query(params): Query<WeatherParams>,
+) -> Result<Json<Vec<WeatherHour>>, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let to = match &params.to {
+        Some(to) => parse_timestamp(to).map_err(bad_request)?,
+        None => Local::now().timestamp(),
+    };
+    let from = match &params.from {
+        Some(from) => parse_timestamp(from).map_err(bad_request)?,
+        None => to - 24 * 3600,
+    };
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    database.weather(from, to).map(Json).map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })
+}
+
+///
+/// The all-time records (period `all`) and those of every month (`YYYY-MM`)
+/// of the highest PV power and charge current and the lowest battery voltage.
+///
+#[utoipa::path(
+    get,
+    path = "/api/records",
+    responses((status = 200, description = "All-time records first, then the latest months.", body = Vec<Record>))
+)]
+async fn records(State(state): State<ApiState>) -> Result<Json<Vec<Record>>, (StatusCode, String)> {
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    database.records().map(Json).map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/device",
+    responses((status = 200, description = "The connected controller.", body = DeviceStatus))
+)]
+async fn device_info(State(state): State<ApiState>) -> Json<DeviceStatus> {
+    let last_datapoint = state.recent.latest().map(|dp| dp.get_time());
+    Json(DeviceStatus {
+        info: (*state.device).clone(),
+        last_datapoint,
+    })
+}
+
+///
+/// Switch the load, e.g. `{"state":"on"}`, with `"pin"`, or `"token"` and
+/// `"time"`, if required. The command goes through the same channel as the
+/// TUI switch, so it is applied right away.
+///
+#[utoipa::path(
+    post,
+    path = "/api/load",
+    request_body = LoadRequest,
+    responses(
+        (status = 202, description = "The command was queued.", body = LoadRequest),
+        (status = 403, description = "Load control is disabled, or the PIN or token is missing or wrong."),
+        (status = 503, description = "The datalogger is not running."),
+    )
+)]
+async fn switch_load(
+    State(state): State<ApiState>,
+    ConnectInfo(ClientAddr(client)): ConnectInfo<ClientAddr>,
+    Json(request): Json<LoadRequest>,
+) -> Result<(StatusCode, Json<LoadRequest>), (StatusCode, String)> {
+    let Some(load_control) = &state.load_control else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Load control is disabled, see api.load_control.".to_string(),
+        ));
+    };
+    let on = matches!(request.state, LoadState::On);
+    state
+        .control_auth
+        .check(on, &request.credentials, &client.ip().to_string())
+        .map_err(|e| (StatusCode::FORBIDDEN, e))?;
+    let command = LoadCommand {
+        on,
+        source: CommandSource::Api,
+    };
+    load_control.send(command.into()).map_err(|_| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The datalogger is not running.".to_string(),
+        )
+    })?;
+    Ok((StatusCode::ACCEPTED, Json(request)))
+}
+
+///
+/// Alerts active at some time between `from` and `to` (default the last 7
+/// days), the most recently raised first.
+///
+#[utoipa::path(
+    get,
+    path = "/api/alerts",
+    params(AlertsParams),
+    responses(
+        (status = 200, description = "Stored alerts.", body = Vec<AlertRecord>),
+        (status = 400, description = "Invalid parameter."),
+    )
+)]
+async fn alerts(
+    State(state): State<ApiState>,
+    Query(params): Query<AlertsParams>,
+) -> Result<Json<Vec<AlertRecord>>, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let to = match &params.to {
+        Some(to) => parse_timestamp(to).map_err(bad_request)?,
+        None => Local::now().timestamp(),
+    };
+    let from = match &params.from {
+        Some(from) => parse_timestamp(from).map_err(bad_request)?,
+        None => to - DEFAULT_ALERTS_RANGE,
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_ALERTS_LIMIT);
+    if limit == 0 || limit > MAX_ALERTS_LIMIT {
+        return Err(bad_request(format!(
+            "limit must be between 1 and {}.",
+            MAX_ALERTS_LIMIT
+        )));
+    }
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    database.alerts(from, to, limit).map(Json).map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })
+}
+
+///
+/// Acknowledge an alert, keeping the time of an earlier acknowledgement.
+///
+#[utoipa::path(
+    post,
+    path = "/api/alerts/{id}/ack",
+    params(("id" = i64, Path, description = "ID of the alert.")),
+    responses(
+        (status = 204, description = "The alert is acknowledged."),
+        (status = 404, description = "No alert has this ID."),
+    )
+)]
+async fn acknowledge_alert(
+    State(state): State<ApiState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let database = state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    match database.acknowledge_alert(id, Local::now().timestamp()) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((StatusCode::NOT_FOUND, format!("No alert with ID {}.", id))),
+        Err(e) => {
+            warn!("{}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    }
+}
+
+///
+/// How long reading the serial port, parsing, flushing to the database and
+/// drawing the UI take, to spot regressions on slow hardware.
+///
+#[utoipa::path(
+    get,
+    path = "/api/metrics",
+    responses((status = 200, description = "Stage timings since the start.", body = TimingsSnapshot))
+)]
+async fn metrics() -> Json<TimingsSnapshot> {
+    Json(TIMINGS.snapshot())
+}
+
+///
+/// WebSocket pushing every new datapoint as a JSON text message.
+///
+#[utoipa::path(
+    get,
+    path = "/api/stream",
+    responses((status = 101, description = "Switching to the WebSocket protocol."))
+)]
+async fn stream(ws: WebSocketUpgrade, State(state): State<ApiState>) -> Response {
+    ws.on_upgrade(move |socket| stream_datapoints(socket, state))
+}
+
+///
+/// Liveness: fails only when the datalogger thread has stopped, which a
+/// restart may fix.
+///
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    security(()),
+    responses(
+        (status = 200, description = "The datalogger is running.", body = HealthStatus),
+        (status = 503, description = "The datalogger has stopped.", body = HealthStatus),
+    )
+)]
+async fn healthz(State(state): State<ApiState>) -> (StatusCode, Json<HealthStatus>) {
+    let status = health_status(&state);
+    let alive = status.datalogger;
+    respond_health(status, alive)
+}
+
+///
+/// Readiness: fails while no recent datapoint was read from the serial port
+/// or the database cannot be written.
+///
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    security(()),
+    responses(
+        (status = 200, description = "Datapoints are read and stored.", body = HealthStatus),
+        (status = 503, description = "The serial link or database is failing.", body = HealthStatus),
+    )
+)]
+async fn readyz(State(state): State<ApiState>) -> (StatusCode, Json<HealthStatus>) {
+    let status = health_status(&state);
+    let stale_age = (state.health.poll_interval() * STALE_SAMPLES)
+        .as_secs()
+        .max(MIN_STALE_AGE as u64) as i64;
+    let ready = status.datalogger
+        && status.database_writable
+        && status.last_sample_age_s.is_some_and(|age| age <= stale_age);
+    respond_health(status, ready)
+}
+
+fn health_status(state: &ApiState) -> HealthStatus {
+    let database_writable = match state
+        .database
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .check_writable()
+    {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Database is not writable: {}", e);
+            false
+        }
+    };
+    HealthStatus {
+        status: "ok",
+        datalogger: state.health.alive(),
+        serial_errors: state.health.consecutive_errors(),
+        last_error: state.health.last_error(),
+        restarts: state.health.restarts(),
+        last_restart: state.health.last_restart(),
+        last_sample_age_s: state
+            .health
+            .last_sample()
+            .map(|time| (Local::now().timestamp() - time).max(0)),
+        database_writable,
+    }
+}
+
+fn respond_health(mut status: HealthStatus, ok: bool) -> (StatusCode, Json<HealthStatus>) {
+    if ok {
+        return (StatusCode::OK, Json(status));
+    }
+    status.status = "unavailable";
+    (StatusCode::SERVICE_UNAVAILABLE, Json(status))
+}
+
+///
+/// The OpenAPI document of this API.
+///
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+///
+/// Push the current and every following datapoint to the client as a JSON
+/// text message until the client disconnects. Open streams are dropped when
+/// the server shuts down.
+///
+async fn stream_datapoints(mut socket: WebSocket, state: ApiState) {
+    let mut latest = state.latest.clone();
+    latest.mark_changed();
+    loop {
+        tokio::select! {
+            changed = latest.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let Some(dp) = *latest.borrow_and_update() else {
+                    continue;
+                };
+                let json = match serde_json::to_string(&dp) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        warn!("{}", e);
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(json.into())).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("WebSocket client disconnected.");
+}
@@ -0,0 +1,93 @@
+use crate::config::Config;
+use crate::sun;
+use std::io;
+
+//
+// Constants
+//
+/// Irradiance outside the atmosphere in W/m², with the Meinel model's
+/// attenuation of 0.7 per air mass.
+const SOLAR_CONSTANT: f64 = 1353.0;
+/// Diffuse irradiance of a clear sky in parts of the direct normal one.
+const DIFFUSE_FRACTION: f64 = 0.1;
+/// Part of the irradiance reflected by the ground.
+const ALBEDO: f64 = 0.2;
+/// Irradiance panels are rated at in W/m².
+const RATED_IRRADIANCE: f64 = 1000.0;
+
+//
+// Structs
+//
+/// The panel array, to model the output it would have under a clear sky.
+#[derive(Debug, Clone)]
+pub(crate) struct ArrayModel {
+    watts: f64,
+    tilt: f64,
+    azimuth: f64,
+    latitude: f64,
+    longitude: f64,
+    /// Part of the output left after wiring, controller and temperature losses.
+    derate: f64,
+}
+
+//
+// implementations
+//
+impl ArrayModel {
+    ///
+    /// The array of the `[array]` section, `None` unless enabled, at the
+    /// site of the panels described in `[forecast]`.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Option<Self>> {
+        let (array, site) = (&config.array, &config.forecast);
+        if !array.enabled {
+            return Ok(None);
+        }
+        if site.latitude == 0.0 && site.longitude == 0.0 {
+            return Err(io::Error::other(
+                "forecast.latitude and forecast.longitude must be set for the array model.",
+            ));
+        }
+        if site.kwp <= 0.0
+            || !(0.0..=90.0).contains(&site.declination)
+            || !(0.0..100.0).contains(&array.losses_percent)
+        {
+            return Err(io::Error::other(
+                "forecast.kwp must be positive, forecast.declination between 0 and 90 and array.losses_percent between 0 and 100.",
+            ));
+        }
+        Ok(Some(Self {
+            watts: site.kwp * 1000.0,
+            tilt: site.declination,
+            azimuth: site.azimuth,
+            latitude: site.latitude,
+            longitude: site.longitude,
+            derate: 1.0 - array.losses_percent / 100.0,
+        }))
+    }
+
+    ///
+    /// The output in W under a clear sky at a unix time, from the sun's
+    /// position, the Meinel direct irradiance through the Kasten-Young air
+    /// mass and a fixed diffuse part, transposed onto the panels.
+    ///
+    pub(crate) fn clear_sky_watts(&self, time: i64) -> f64 {
+        let (elevation, azimuth) = sun::position(time, self.latitude, self.longitude);
+        if elevation <= 0.0 {
+            return 0.0;
+        }
+        let zenith = 90.0 - elevation;
+        let cos_zenith = zenith.to_radians().cos();
+        let air_mass = 1.0 / (cos_zenith + 0.50572 * (96.07995 - zenith).powf(-1.6364));
+        let direct = SOLAR_CONSTANT * 0.7_f64.powf(air_mass.powf(0.678));
+        let diffuse = DIFFUSE_FRACTION * direct;
+        let global = direct * cos_zenith + diffuse;
+        let tilt = self.tilt.to_radians();
+        let cos_incidence = cos_zenith * tilt.cos()
+            + zenith.to_radians().sin() * tilt.sin() * (azimuth - self.azimuth).to_radians().cos();
+        let irradiance = direct * cos_incidence.max(0.0)
+            + diffuse * (1.0 + tilt.cos()) / 2.0
+            + global * ALBEDO * (1.0 - tilt.cos()) / 2.0;
+        self.watts * irradiance / RATED_IRRADIANCE * self.derate
+    }
+}
@@ -0,0 +1,1189 @@
+use crate::config::{AutomationConfig, BatteryChemistry};
+use crate::script::ScriptRules;
+use crate::sun;
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeDelta, Weekday};
+use solar_core::alerts::{Event, EventKind, Severity};
+use solar_core::config::parse_duration;
+use solar_core::datapoint::DataPoint;
+use std::{
+    io,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+//
+// Structs
+//
+/// Switches the load in reaction to datapoints, through the same commands as
+/// the TUI switch.
+pub(crate) struct LoadAutomation {
+    config: AutomationConfig,
+    lvd: LvdState,
+    schedule: Vec<Window>,
+    /// Whether the schedule wanted the load on at the last datapoint.
+    scheduled: Option<bool>,
+    /// Latest datapoint time the schedule was checked at, so a clock stepped
+    /// back does not run a window again.
+    schedule_time: i64,
+    /// Whether the battery temperature cutoff holds the load off.
+    temp_tripped: bool,
+    overcurrent: OvercurrentState,
+    lighting: LightingState,
+    script: Option<ScriptRules>,
+}
+
+/// State of the low-voltage disconnect. Hold times are measured on the
+/// monotonic clock, so a clock step of the host does not cut them short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LvdState {
+    /// Waiting for the battery voltage to stay low, since the given time.
+    Armed { low_since: Option<Instant> },
+    /// The load was switched off, until the battery voltage recovers. With
+    /// the reconnect, the time since which it has been above the reconnect
+    /// voltage.
+    Tripped { high_since: Option<Instant> },
+}
+
+/// Local time window in which the load is on, e.g. `mon-fri 18:00-23:00` or
+/// `sunset+30m until sunrise`. It ends the next day when its end comes before
+/// its start.
+#[derive(Debug, Clone)]
+struct Window {
+    /// Days the window starts on, indexed from Monday.
+    days: [bool; 7],
+    start: Trigger,
+    end: Trigger,
+}
+
+/// State of the overcurrent protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OvercurrentState {
+    /// Waiting for the load current to stay high, since the given time.
+    Armed { high_since: Option<Instant> },
+    /// The load was switched off, until it is switched on by hand.
+    Tripped,
+}
+
+/// State of the dusk-to-dawn lighting. The times are since when a condition
+/// has held, so it only acts once it held for the hold time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LightingState {
+    /// Waiting for dusk, the panel voltage staying low.
+    Day { dark_since: Option<Instant> },
+    /// The load was switched on at dusk at the given time.
+    Lit {
+        on_since: Instant,
+        low_since: Option<Instant>,
+        light_since: Option<Instant>,
+    },
+    /// The load was switched off by the runtime or battery limit, until dawn.
+    Done { light_since: Option<Instant> },
+}
+
+/// Start or end of a schedule window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trigger {
+    At(NaiveTime),
+    /// Sunrise, offset by the given seconds.
+    Sunrise(i64),
+    /// Sunset, offset by the given seconds.
+    Sunset(i64),
+}
+
+//
+// implementations
+//
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        for (event, sunrise) in [("sunrise", true), ("sunset", false)] {
+            let Some(offset) = s.strip_prefix(event) else {
+                continue;
+            };
+            let offset = match offset.trim() {
+                "" => 0,
+                o if o.starts_with('+') => parse_duration(&o[1..])? as i64,
+                o if o.starts_with('-') => -(parse_duration(&o[1..])? as i64),
+                o => return Err(format!("invalid offset {:?}", o)),
+            };
+            return Ok(match sunrise {
+                true => Trigger::Sunrise(offset),
+                false => Trigger::Sunset(offset),
+            });
+        }
+        NaiveTime::parse_from_str(s, "%H:%M")
+            .map(Trigger::At)
+            .map_err(|_| format!("invalid time {:?}, expected HH:MM, sunrise or sunset", s))
+    }
+}
+
+impl Trigger {
+    ///
+    /// The time of the trigger on the given day, `None` if the sun does not
+    /// rise or set that day.
+    ///
+    fn resolve(self, day: NaiveDate, location: (f64, f64)) -> Option<DateTime<Local>> {
+        let time = match self {
+            Trigger::At(time) => return day.and_time(time).and_local_timezone(Local).earliest(),
+            Trigger::Sunrise(offset) => {
+                sun::sunrise_sunset(day, location.0, location.1)?.0 + TimeDelta::seconds(offset)
+            }
+            Trigger::Sunset(offset) => {
+                sun::sunrise_sunset(day, location.0, location.1)?.1 + TimeDelta::seconds(offset)
+            }
+        };
+        Some(time.with_timezone(&Local))
+    }
+
+    fn is_sun(self) -> bool {
+        !matches!(self, Trigger::At(_))
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (days, times) = match s.split_once(char::is_whitespace) {
+            Some((days, times)) if parse_days(days).is_ok() => (parse_days(days)?, times.trim()),
+            _ => ([true; 7], s),
+        };
+        let (start, end) = match times.split_once(" until ") {
+            Some((start, end)) => (start.trim().parse()?, end.trim().parse()?),
+            // Offsets contain dashes as well, e.g. `sunset-30m-sunrise`.
+            None => times
+                .match_indices('-')
+                .find_map(|(i, _)| {
+                    Some((
+                        times[..i].trim().parse().ok()?,
+                        times[i + 1..].trim().parse().ok()?,
+                    ))
+                })
+                .ok_or("expected [days] <start>-<end> or [days] <start> until <end>")?,
+        };
+        if start == end {
+            return Err("start and end must differ".into());
+        }
+        Ok(Self { days, start, end })
+    }
+}
+
+impl Window {
+    fn contains(&self, time: DateTime<Local>, location: (f64, f64)) -> bool {
+        let today = time.date_naive();
+        [today.pred_opt(), Some(today)]
+            .into_iter()
+            .flatten()
+            .filter(|day| self.days[day.weekday().num_days_from_monday() as usize])
+            .filter_map(|day| self.span(day, location))
+            .any(|(start, end)| start <= time && time < end)
+    }
+
+    ///
+    /// Start and end of the window starting on the given day.
+    ///
+    fn span(
+        &self,
+        day: NaiveDate,
+        location: (f64, f64),
+    ) -> Option<(DateTime<Local>, DateTime<Local>)> {
+        let start = self.start.resolve(day, location)?;
+        let mut end = self.end.resolve(day, location)?;
+        if end <= start {
+            end = self.end.resolve(day.succ_opt()?, location)?;
+        }
+        Some((start, end))
+    }
+}
+
+impl LoadAutomation {
+    pub(crate) fn new(config: &AutomationConfig) -> io::Result<Self> {
+        if config.lvd_enabled {
+            info!(
+                "Low-voltage disconnect below {} V held for {} s.",
+                config.lvd_voltage, config.lvd_hold_s
+            );
+        }
+        if config.lvd_enabled && config.reconnect_enabled {
+            if config.reconnect_voltage <= config.lvd_voltage {
+                return Err(io::Error::other(format!(
+                    "automation.reconnect_voltage ({} V) must be above lvd_voltage ({} V).",
+                    config.reconnect_voltage, config.lvd_voltage
+                )));
+            }
+            info!(
+                "Reconnecting the load above {} V held for {} s.",
+                config.reconnect_voltage, config.reconnect_hold_s
+            );
+        }
+        let schedule = config
+            .schedule
+            .iter()
+            .map(|window| {
+                window.parse().map_err(|e| {
+                    io::Error::other(format!(
+                        "automation.schedule: invalid window {:?}: {}.",
+                        window, e
+                    ))
+                })
+            })
+            .collect::<io::Result<Vec<Window>>>()?;
+        if !schedule.is_empty() {
+            info!("Load scheduled on during {}.", config.schedule.join(", "));
+        }
+        if schedule.iter().any(|w| w.start.is_sun() || w.end.is_sun()) {
+            if config.latitude == 0.0 && config.longitude == 0.0 {
+                return Err(io::Error::other(
+                    "automation.latitude and longitude must be set to schedule by sunrise or sunset.",
+                ));
+            }
+            match sun::sunrise_sunset(Local::now().date_naive(), config.latitude, config.longitude)
+            {
+                Some((sunrise, sunset)) => info!(
+                    "Sunrise at {}, sunset at {} today.",
+                    sunrise.with_timezone(&Local).format("%H:%M"),
+                    sunset.with_timezone(&Local).format("%H:%M")
+                ),
+                None => info!("The sun does not rise or set today."),
+            }
+        }
+        if config.temp_cutoff_enabled {
+            match config.battery_chemistry {
+                BatteryChemistry::Lithium => info!(
+                    "Battery temperature cutoff outside {} to {} C.",
+                    config.temp_cutoff_min, config.temp_cutoff_max
+                ),
+                BatteryChemistry::LeadAcid => info!(
+                    "Battery temperature cutoff above {} C.",
+                    config.temp_cutoff_max
+                ),
+            }
+        }
+        if config.overcurrent_enabled {
+            info!(
+                "Overcurrent protection above {} A held for {} s.",
+                config.overcurrent_limit, config.overcurrent_hold_s
+            );
+        }
+        if config.lighting_enabled {
+            if config.dawn_voltage <= config.dusk_voltage {
+                return Err(io::Error::other(format!(
+                    "automation.dawn_voltage ({} V) must be above dusk_voltage ({} V).",
+                    config.dawn_voltage, config.dusk_voltage
+                )));
+            }
+            info!(
+                "Lighting the load from dusk below {} V to dawn above {} V panel voltage{}, while the battery is above {} V.",
+                config.dusk_voltage,
+                config.dawn_voltage,
+                match config.lighting_hours > 0.0 {
+                    true => format!(" for at most {} h", config.lighting_hours),
+                    false => String::new(),
+                },
+                config.lighting_min_voltage
+            );
+        }
+        let script = match &config.script {
+            Some(path) => Some(ScriptRules::new(path, config.script_window)?),
+            None => None,
+        };
+        Ok(Self {
+            config: config.clone(),
+            lvd: LvdState::Armed { low_since: None },
+            schedule,
+            scheduled: None,
+            schedule_time: i64::MIN,
+            temp_tripped: false,
+            overcurrent: OvercurrentState::Armed { high_since: None },
+            lighting: LightingState::Day { dark_since: None },
+            script,
+        })
+    }
+
+    ///
+    /// Check a datapoint and return the load state to switch to, if any, with
+    /// the rule switching it, adding the alerts of the protections to
+    /// `events`. The schedule
+    /// switches at the start and end of its windows only, so the load can be
+    /// switched by hand in between, and so does the lighting at dusk, dawn
+    /// and its limits. A script overrides the schedule, which overrides the
+    /// lighting, but none switches the load on while a protection holds it
+    /// off. Hold times and the lighting runtime are measured from when the
+    /// datapoints arrive, the datapoint time only labels the alerts.
+    ///
+    pub(crate) fn check(
+        &mut self,
+        datapoint: &DataPoint,
+        events: &mut Vec<Event>,
+    ) -> Option<(bool, &'static str)> {
+        self.check_at(datapoint, Instant::now(), events)
+    }
+
+    ///
+    /// `check` with the datapoint arriving at `now`.
+    ///
+    fn check_at(
+        &mut self,
+        datapoint: &DataPoint,
+        now: Instant,
+        events: &mut Vec<Event>,
+    ) -> Option<(bool, &'static str)> {
+        let temp = self
+            .check_temperature(datapoint, events)
+            .map(|on| (on, "temp_cutoff"));
+        let overcurrent = self
+            .check_overcurrent(datapoint, now, events)
+            .map(|on| (on, "overcurrent"));
+        let lvd = self.check_lvd(datapoint, now);
+        let scheduled = self.check_schedule(datapoint).map(|on| (on, "schedule"));
+        let lighting = self
+            .check_lighting(datapoint, now)
+            .map(|on| (on, "lighting"));
+        let scripted = match &mut self.script {
+            Some(script) => script.check(datapoint).map(|on| (on, "script")),
+            None => None,
+        };
+        if temp.is_some() || overcurrent.is_some() {
+            return temp.or(overcurrent);
+        }
+        let held_off = match (self.temp_tripped, self.overcurrent) {
+            (true, _) => Some("the battery temperature is out of range"),
+            (false, OvercurrentState::Tripped) => Some("the overcurrent protection holds it off"),
+            _ => None,
+        };
+        match (lvd, scripted.or(scheduled).or(lighting)) {
+            (Some(false), _) => Some((false, "lvd")),
+            (Some(true), _) | (None, Some((true, _))) if held_off.is_some() => {
+                info!(
+                    "Not switching the load on while {}.",
+                    held_off.unwrap_or_default()
+                );
+                None
+            }
+            (Some(true), _) if self.scheduled == Some(false) => {
+                info!("Not reconnecting the load outside of the schedule.");
+                None
+            }
+            (Some(true), _) => Some((true, "reconnect")),
+            (None, Some((true, by))) if matches!(self.lvd, LvdState::Tripped { .. }) => {
+                info!(
+                    "Not switching the load on for the {} after a low-voltage disconnect.",
+                    by
+                );
+                None
+            }
+            (None, Some((on, by))) => {
+                info!(
+                    "The {} switches the load {}.",
+                    by,
+                    if on { "on" } else { "off" }
+                );
+                Some((on, by))
+            }
+            (None, None) => None,
+        }
+    }
+
+    ///
+    /// Switch the load off while the battery temperature is out of range,
+    /// raising a critical alert when it leaves the range and clearing it once
+    /// it is back within the range by the hysteresis.
+    ///
+    fn check_temperature(
+        &mut self,
+        datapoint: &DataPoint,
+        events: &mut Vec<Event>,
+    ) -> Option<bool> {
+        if !self.config.temp_cutoff_enabled {
+            return None;
+        }
+        let temp = datapoint.get_battery_temp();
+        let max = self.config.temp_cutoff_max;
+        let min = match self.config.battery_chemistry {
+            BatteryChemistry::Lithium => self.config.temp_cutoff_min,
+            BatteryChemistry::LeadAcid => f64::NEG_INFINITY,
+        };
+        let margin = match self.temp_tripped {
+            true => self.config.temp_cutoff_hysteresis,
+            false => 0.0,
+        };
+        let out_of_range = temp > max - margin || temp < min + margin;
+        let message = match (self.temp_tripped, out_of_range) {
+            (false, false) => return None,
+            // Held off, also when switched on by hand.
+            (true, true) => return (datapoint.get_load_onoff() > 0.0).then_some(false),
+            (false, true) if temp > max => {
+                format!(
+                    "Battery temperature above {} C: {} C, load switched off",
+                    max, temp
+                )
+            }
+            (false, true) => {
+                format!(
+                    "Battery temperature below {} C: {} C, load switched off",
+                    min, temp
+                )
+            }
+            (true, false) => format!("Battery temperature back in range: {} C", temp),
+        };
+        self.temp_tripped = out_of_range;
+        events.push(Event {
+            kind: match out_of_range {
+                true => EventKind::Alert,
+                false => EventKind::Cleared,
+            },
+            name: "battery_temp_cutoff".to_string(),
+            severity: Severity::Critical,
+            message,
+            value: temp,
+            timestamp: datapoint.get_time(),
+        });
+        self.temp_tripped.then_some(false)
+    }
+
+    ///
+    /// Switch the load off and raise a critical alert once the load current
+    /// stayed above the limit for the hold time. It stays off until switched
+    /// on by hand, see `load_switched`.
+    ///
+    fn check_overcurrent(
+        &mut self,
+        datapoint: &DataPoint,
+        now: Instant,
+        events: &mut Vec<Event>,
+    ) -> Option<bool> {
+        if !self.config.overcurrent_enabled {
+            return None;
+        }
+        let time = datapoint.get_time();
+        let current = datapoint.get_load_current();
+        let high_since = match self.overcurrent {
+            OvercurrentState::Tripped => return None,
+            OvercurrentState::Armed { .. } if current <= self.config.overcurrent_limit => {
+                self.overcurrent = OvercurrentState::Armed { high_since: None };
+                return None;
+            }
+            OvercurrentState::Armed { high_since } => high_since.unwrap_or(now),
+        };
+        let held = now.duration_since(high_since);
+        if held < Duration::from_secs(self.config.overcurrent_hold_s) {
+            self.overcurrent = OvercurrentState::Armed {
+                high_since: Some(high_since),
+            };
+            return None;
+        }
+        self.overcurrent = OvercurrentState::Tripped;
+        events.push(Event {
+            kind: EventKind::Alert,
+            name: "load_overcurrent".to_string(),
+            severity: Severity::Critical,
+            message: format!(
+                "Load current above {} A for {} s: {} A, load switched off until switched on by hand",
+                self.config.overcurrent_limit,
+                held.as_secs(),
+                current
+            ),
+            value: current,
+            timestamp: time,
+        });
+        Some(false)
+    }
+
+    ///
+    /// Note a load command from anywhere but the automation. Switching the
+    /// load on re-arms the overcurrent protection and clears its alert.
+    ///
+    pub(crate) fn load_switched(&mut self, on: bool, timestamp: i64, events: &mut Vec<Event>) {
+        if !on || self.overcurrent != OvercurrentState::Tripped {
+            return;
+        }
+        self.overcurrent = OvercurrentState::Armed { high_since: None };
+        events.push(Event {
+            kind: EventKind::Cleared,
+            name: "load_overcurrent".to_string(),
+            severity: Severity::Critical,
+            message: "Load switched on by hand after an overcurrent".to_string(),
+            value: 0.0,
+            timestamp,
+        });
+    }
+
+    ///
+    /// The load state to switch to when the schedule changes, or initially.
+    /// The schedule only moves forward in time: while the clock of the host
+    /// was stepped back it stays at the latest time seen, so a window it
+    /// already left is not entered again.
+    ///
+    fn check_schedule(&mut self, datapoint: &DataPoint) -> Option<bool> {
+        if self.schedule.is_empty() {
+            return None;
+        }
+        self.schedule_time = self.schedule_time.max(datapoint.get_time());
+        let time = DateTime::from_timestamp(self.schedule_time, 0)?.with_timezone(&Local);
+        let location = (self.config.latitude, self.config.longitude);
+        let on = self
+            .schedule
+            .iter()
+            .any(|window| window.contains(time, location));
+        if self.scheduled == Some(on) {
+            return None;
+        }
+        self.scheduled = Some(on);
+        Some(on)
+    }
+
+    ///
+    /// The load state to switch to at dusk, at dawn, or once the runtime or
+    /// the battery limit is reached, logging why.
+    ///
+    fn check_lighting(&mut self, datapoint: &DataPoint, now: Instant) -> Option<bool> {
+        if !self.config.lighting_enabled {
+            return None;
+        }
+        let pv = datapoint.get_pv_voltage();
+        let battery = datapoint.get_battery_voltage();
+        let hold = Duration::from_secs(self.config.lighting_hold_s);
+        // The time since when a condition has held, if it has for the hold time.
+        let held = |holds: bool, since: Option<Instant>| match holds {
+            true => {
+                let since = since.unwrap_or(now);
+                (Some(since), now.duration_since(since) >= hold)
+            }
+            false => (None, false),
+        };
+        let secs = |since: Option<Instant>| now.duration_since(since.unwrap_or(now)).as_secs();
+        match self.lighting {
+            LightingState::Day { dark_since } => {
+                let (dark_since, dusk) = held(pv < self.config.dusk_voltage, dark_since);
+                if !dusk {
+                    self.lighting = LightingState::Day { dark_since };
+                    return None;
+                }
+                if battery < self.config.lighting_min_voltage {
+                    info!(
+                        "Dusk with the panel at {} V, but not lighting as the battery is at {} V, below {} V.",
+                        pv, battery, self.config.lighting_min_voltage
+                    );
+                    self.lighting = LightingState::Done { light_since: None };
+                    return None;
+                }
+                info!(
+                    "Dusk with the panel below {} V for {} s, lighting the load.",
+                    self.config.dusk_voltage,
+                    secs(dark_since)
+                );
+                self.lighting = LightingState::Lit {
+                    on_since: now,
+                    low_since: None,
+                    light_since: None,
+                };
+                Some(true)
+            }
+            LightingState::Lit {
+                on_since,
+                low_since,
+                light_since,
+            } => {
+                let (light_since, dawn) = held(pv > self.config.dawn_voltage, light_since);
+                let (low_since, low) = held(battery < self.config.lighting_min_voltage, low_since);
+                let hours = now.duration_since(on_since).as_secs_f64() / 3600.0;
+                let expired =
+                    self.config.lighting_hours > 0.0 && hours >= self.config.lighting_hours;
+                if dawn {
+                    info!(
+                        "Dawn with the panel above {} V for {} s, the load was lit for {:.1} h.",
+                        self.config.dawn_voltage,
+                        secs(light_since),
+                        hours
+                    );
+                    self.lighting = LightingState::Day { dark_since: None };
+                    return Some(false);
+                }
+                if low || expired {
+                    match low {
+                        true => info!(
+                            "Battery below {} V for {} s, ending the lighting after {:.1} h.",
+                            self.config.lighting_min_voltage,
+                            secs(low_since),
+                            hours
+                        ),
+                        false => info!("The load was lit for {:.1} h, ending the lighting.", hours),
+                    }
+                    self.lighting = LightingState::Done { light_since: None };
+                    return Some(false);
+                }
+                self.lighting = LightingState::Lit {
+                    on_since,
+                    low_since,
+                    light_since,
+                };
+                None
+            }
+            LightingState::Done { light_since } => {
+                let (light_since, dawn) = held(pv > self.config.dawn_voltage, light_since);
+                self.lighting = match dawn {
+                    true => {
+                        info!("Dawn, lighting again at dusk.");
+                        LightingState::Day { dark_since: None }
+                    }
+                    false => LightingState::Done { light_since },
+                };
+                None
+            }
+        }
+    }
+
+    fn check_lvd(&mut self, datapoint: &DataPoint, now: Instant) -> Option<bool> {
+        if !self.config.lvd_enabled {
+            return None;
+        }
+        let voltage = datapoint.get_battery_voltage();
+        let low = voltage < self.config.lvd_voltage;
+        match self.lvd {
+            LvdState::Armed { .. } if !low => {
+                self.lvd = LvdState::Armed { low_since: None };
+                None
+            }
+            LvdState::Armed { low_since } => {
+                let since = low_since.unwrap_or(now);
+                let held = now.duration_since(since);
+                if held < Duration::from_secs(self.config.lvd_hold_s) {
+                    self.lvd = LvdState::Armed {
+                        low_since: Some(since),
+                    };
+                    return None;
+                }
+                warn!(
+                    "Battery at {} V, below {} V for {} s, switching the load off.",
+                    voltage,
+                    self.config.lvd_voltage,
+                    held.as_secs()
+                );
+                self.lvd = LvdState::Tripped { high_since: None };
+                Some(false)
+            }
+            LvdState::Tripped { high_since } if self.config.reconnect_enabled => {
+                if voltage < self.config.reconnect_voltage {
+                    self.lvd = LvdState::Tripped { high_since: None };
+                    return None;
+                }
+                let since = high_since.unwrap_or(now);
+                let held = now.duration_since(since);
+                if held < Duration::from_secs(self.config.reconnect_hold_s) {
+                    self.lvd = LvdState::Tripped {
+                        high_since: Some(since),
+                    };
+                    return None;
+                }
+                info!(
+                    "Battery at {} V, above {} V for {} s, switching the load on.",
+                    voltage,
+                    self.config.reconnect_voltage,
+                    held.as_secs()
+                );
+                self.lvd = LvdState::Armed { low_since: None };
+                Some(true)
+            }
+            // Re-armed once the voltage recovers, so switching the load back
+            // on by hand while it is still low is not overridden.
+            LvdState::Tripped { .. } if !low => {
+                self.lvd = LvdState::Armed { low_since: None };
+                None
+            }
+            LvdState::Tripped { .. } => None,
+        }
+    }
+}
+
+///
+/// Parse days such as `daily`, `mon-fri` or `sat,sun` into flags indexed
+/// from Monday.
+///
+fn parse_days(days: &str) -> Result<[bool; 7], String> {
+    if days == "daily" {
+        return Ok([true; 7]);
+    }
+    let mut flags = [false; 7];
+    for part in days.split(',') {
+        let day = |d: &str| {
+            Weekday::from_str(d.trim()).map_err(|_| format!("invalid day {:?}", d.trim()))
+        };
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (day(first)?, day(last)?),
+            None => (day(part)?, day(part)?),
+        };
+        let mut day = first;
+        flags[day.num_days_from_monday() as usize] = true;
+        while day != last {
+            day = day.succ();
+            flags[day.num_days_from_monday() as usize] = true;
+        }
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Values of the datapoints, in the order of `DataPoint::with_time`.
+    const VALUES: [f64; 10] = [12.8, 18.0, 1.0, 11.1, 14.4, 0.0, 1.0, 25.0, 2.0, 1.0];
+    const BATTERY_VOLTAGE: usize = 0;
+    const PV_VOLTAGE: usize = 1;
+    const LOAD_CURRENT: usize = 2;
+    const BATTERY_TEMP: usize = 7;
+    const LOAD_ONOFF: usize = 9;
+
+    /// Drives an automation with datapoints arriving at seconds since its start.
+    struct Driver {
+        automation: LoadAutomation,
+        start: Instant,
+        events: Vec<Event>,
+    }
+
+    impl Driver {
+        fn new(config: AutomationConfig) -> Self {
+            Self {
+                automation: LoadAutomation::new(&config).expect("a valid config"),
+                start: Instant::now(),
+                events: Vec::new(),
+            }
+        }
+
+        ///
+        /// Check a datapoint of time `time` with the given values changed,
+        /// arriving `secs` seconds after the start.
+        ///
+        fn check_at_time(
+            &mut self,
+            secs: u64,
+            time: i64,
+            values: &[(usize, f64)],
+        ) -> Option<(bool, &'static str)> {
+            let mut data = VALUES;
+            for &(index, value) in values {
+                data[index] = value;
+            }
+            let datapoint = DataPoint::with_time(time, &data);
+            let now = self.start + Duration::from_secs(secs);
+            self.automation.check_at(&datapoint, now, &mut self.events)
+        }
+
+        fn check(&mut self, secs: u64, values: &[(usize, f64)]) -> Option<(bool, &'static str)> {
+            self.check_at_time(secs, 1_700_000_000 + secs as i64, values)
+        }
+
+        fn events(&mut self) -> Vec<(EventKind, String)> {
+            self.events
+                .drain(..)
+                .map(|event| (event.kind, event.name))
+                .collect()
+        }
+    }
+
+    fn lvd(reconnect: bool) -> AutomationConfig {
+        AutomationConfig {
+            lvd_enabled: true,
+            lvd_voltage: 11.5,
+            lvd_hold_s: 60,
+            reconnect_enabled: reconnect,
+            reconnect_voltage: 12.6,
+            reconnect_hold_s: 600,
+            ..AutomationConfig::default()
+        }
+    }
+
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+            .single()
+            .expect("an unambiguous local time")
+    }
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").expect("a valid time")
+    }
+
+    #[test]
+    fn lvd_trips_once_the_voltage_stayed_low() {
+        let mut driver = Driver::new(lvd(false));
+        assert_eq!(driver.check(0, &[(BATTERY_VOLTAGE, 11.4)]), None);
+        // A dip which recovers within the hold time restarts it.
+        assert_eq!(driver.check(30, &[(BATTERY_VOLTAGE, 11.6)]), None);
+        assert_eq!(driver.check(40, &[(BATTERY_VOLTAGE, 11.4)]), None);
+        assert_eq!(driver.check(99, &[(BATTERY_VOLTAGE, 11.4)]), None);
+        assert_eq!(
+            driver.check(100, &[(BATTERY_VOLTAGE, 11.4)]),
+            Some((false, "lvd"))
+        );
+        assert_eq!(driver.check(200, &[(BATTERY_VOLTAGE, 11.3)]), None);
+        // Without the reconnect, a recovered battery only re-arms it.
+        assert_eq!(driver.check(300, &[(BATTERY_VOLTAGE, 13.0)]), None);
+        assert_eq!(driver.check(2000, &[(BATTERY_VOLTAGE, 13.0)]), None);
+        assert_eq!(driver.check(2010, &[(BATTERY_VOLTAGE, 11.4)]), None);
+        assert_eq!(
+            driver.check(2070, &[(BATTERY_VOLTAGE, 11.4)]),
+            Some((false, "lvd"))
+        );
+    }
+
+    #[test]
+    fn reconnect_needs_the_higher_voltage_for_its_hold_time() {
+        let mut driver = Driver::new(lvd(true));
+        assert_eq!(driver.check(0, &[(BATTERY_VOLTAGE, 11.4)]), None);
+        assert_eq!(
+            driver.check(60, &[(BATTERY_VOLTAGE, 11.4)]),
+            Some((false, "lvd"))
+        );
+        // Above the disconnect but below the reconnect voltage.
+        assert_eq!(driver.check(100, &[(BATTERY_VOLTAGE, 12.0)]), None);
+        assert_eq!(driver.check(1000, &[(BATTERY_VOLTAGE, 12.5)]), None);
+        assert_eq!(driver.check(1100, &[(BATTERY_VOLTAGE, 12.7)]), None);
+        // Dropping below the reconnect voltage restarts the hold time.
+        assert_eq!(driver.check(1600, &[(BATTERY_VOLTAGE, 12.5)]), None);
+        assert_eq!(driver.check(1700, &[(BATTERY_VOLTAGE, 12.7)]), None);
+        assert_eq!(driver.check(2299, &[(BATTERY_VOLTAGE, 12.7)]), None);
+        assert_eq!(
+            driver.check(2300, &[(BATTERY_VOLTAGE, 12.7)]),
+            Some((true, "reconnect"))
+        );
+        // Armed again.
+        assert_eq!(driver.check(2400, &[(BATTERY_VOLTAGE, 11.4)]), None);
+        assert_eq!(
+            driver.check(2460, &[(BATTERY_VOLTAGE, 11.4)]),
+            Some((false, "lvd"))
+        );
+    }
+
+    #[test]
+    fn reconnect_with_equal_voltages_is_refused() {
+        let config = AutomationConfig {
+            reconnect_voltage: 11.5,
+            ..lvd(true)
+        };
+        assert!(LoadAutomation::new(&config).is_err());
+    }
+
+    #[test]
+    fn lvd_overrides_the_schedule() {
+        let mut driver = Driver::new(AutomationConfig {
+            schedule: vec!["18:00-23:00".to_string()],
+            ..lvd(true)
+        });
+        let low = [(BATTERY_VOLTAGE, 11.4)];
+        let high = [(BATTERY_VOLTAGE, 12.7)];
+        let at = |hour, minute| local(1, hour, minute).timestamp();
+        assert_eq!(
+            driver.check_at_time(0, at(17, 59), &low),
+            Some((false, "schedule"))
+        );
+        // The window starts as the disconnect trips.
+        assert_eq!(
+            driver.check_at_time(60, at(18, 0), &low),
+            Some((false, "lvd"))
+        );
+        assert_eq!(driver.check_at_time(120, at(18, 1), &low), None);
+        // Reconnected within the window.
+        assert_eq!(driver.check_at_time(200, at(18, 3), &high), None);
+        assert_eq!(
+            driver.check_at_time(800, at(18, 13), &high),
+            Some((true, "reconnect"))
+        );
+        // Tripped again, the window ends before the battery recovers.
+        assert_eq!(driver.check_at_time(900, at(22, 58), &low), None);
+        assert_eq!(
+            driver.check_at_time(960, at(22, 59), &low),
+            Some((false, "lvd"))
+        );
+        assert_eq!(
+            driver.check_at_time(1020, at(23, 0), &low),
+            Some((false, "schedule"))
+        );
+        // Not reconnected outside of the window.
+        assert_eq!(driver.check_at_time(1100, at(23, 2), &high), None);
+        assert_eq!(driver.check_at_time(1700, at(23, 12), &high), None);
+    }
+
+    #[test]
+    fn lvd_holds_off_the_lighting() {
+        let mut driver = Driver::new(AutomationConfig {
+            lighting_enabled: true,
+            lighting_min_voltage: 11.0,
+            ..lvd(false)
+        });
+        let dusk = [(PV_VOLTAGE, 2.0), (BATTERY_VOLTAGE, 11.4)];
+        assert_eq!(driver.check(0, &dusk), None);
+        assert_eq!(driver.check(60, &dusk), Some((false, "lvd")));
+        // Dusk held for the hold time, but the load stays off.
+        assert_eq!(driver.check(600, &dusk), None);
+        assert_eq!(driver.check(700, &dusk), None);
+    }
+
+    #[test]
+    fn overcurrent_latches_until_switched_on_by_hand() {
+        let mut driver = Driver::new(AutomationConfig {
+            overcurrent_enabled: true,
+            schedule: vec!["18:00-23:00".to_string()],
+            ..AutomationConfig::default()
+        });
+        let high = [(LOAD_CURRENT, 12.0)];
+        let off = [(LOAD_CURRENT, 0.0), (LOAD_ONOFF, 0.0)];
+        let at = |hour, minute, second| local(1, hour, minute).timestamp() + second;
+        assert_eq!(
+            driver.check_at_time(0, at(17, 59, 0), &high),
+            Some((false, "schedule"))
+        );
+        assert_eq!(driver.check_at_time(4, at(17, 59, 4), &high), None);
+        assert_eq!(
+            driver.check_at_time(5, at(17, 59, 5), &high),
+            Some((false, "overcurrent"))
+        );
+        assert_eq!(
+            driver.events(),
+            vec![(EventKind::Alert, "load_overcurrent".to_string())]
+        );
+        // The schedule does not switch the load back on.
+        assert_eq!(driver.check_at_time(60, at(18, 0, 0), &off), None);
+        assert_eq!(driver.check_at_time(120, at(18, 1, 0), &off), None);
+        let mut events = Vec::new();
+        driver.automation.load_switched(false, 0, &mut events);
+        assert!(events.is_empty());
+        driver.automation.load_switched(true, 0, &mut events);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::Cleared);
+        // Armed again.
+        assert_eq!(driver.check_at_time(200, at(18, 2, 0), &high), None);
+        assert_eq!(
+            driver.check_at_time(205, at(18, 2, 5), &high),
+            Some((false, "overcurrent"))
+        );
+    }
+
+    #[test]
+    fn overcurrent_needs_its_hold_time() {
+        let mut driver = Driver::new(AutomationConfig {
+            overcurrent_enabled: true,
+            ..AutomationConfig::default()
+        });
+        assert_eq!(driver.check(0, &[(LOAD_CURRENT, 12.0)]), None);
+        assert_eq!(driver.check(4, &[(LOAD_CURRENT, 10.0)]), None);
+        assert_eq!(driver.check(5, &[(LOAD_CURRENT, 12.0)]), None);
+        assert_eq!(driver.check(9, &[(LOAD_CURRENT, 12.0)]), None);
+        assert_eq!(
+            driver.check(10, &[(LOAD_CURRENT, 12.0)]),
+            Some((false, "overcurrent"))
+        );
+    }
+
+    #[test]
+    fn temperature_cutoff_clears_with_hysteresis() {
+        let mut driver = Driver::new(AutomationConfig {
+            temp_cutoff_enabled: true,
+            ..lvd(true)
+        });
+        assert_eq!(driver.check(0, &[(BATTERY_TEMP, 50.0)]), None);
+        assert_eq!(
+            driver.check(10, &[(BATTERY_TEMP, 51.0)]),
+            Some((false, "temp_cutoff"))
+        );
+        assert_eq!(
+            driver.events(),
+            vec![(EventKind::Alert, "battery_temp_cutoff".to_string())]
+        );
+        // Within the hysteresis, switched on by hand and off again.
+        assert_eq!(
+            driver.check(20, &[(BATTERY_TEMP, 48.0)]),
+            Some((false, "temp_cutoff"))
+        );
+        let cooling = [(BATTERY_TEMP, 48.0), (LOAD_ONOFF, 0.0)];
+        assert_eq!(driver.check(30, &cooling), None);
+        // The reconnect does not switch the load on meanwhile.
+        let low = [
+            (BATTERY_TEMP, 48.0),
+            (LOAD_ONOFF, 0.0),
+            (BATTERY_VOLTAGE, 11.4),
+        ];
+        assert_eq!(driver.check(40, &low), None);
+        assert_eq!(driver.check(100, &low), Some((false, "lvd")));
+        let recovered = [(BATTERY_TEMP, 48.0), (LOAD_ONOFF, 0.0)];
+        assert_eq!(driver.check(200, &recovered), None);
+        assert_eq!(driver.check(800, &recovered), None);
+        assert!(driver.events().is_empty());
+        assert_eq!(driver.check(900, &[(BATTERY_TEMP, 46.0)]), None);
+        assert_eq!(
+            driver.events(),
+            vec![(EventKind::Cleared, "battery_temp_cutoff".to_string())]
+        );
+    }
+
+    #[test]
+    fn lithium_batteries_are_cut_off_when_cold() {
+        let mut driver = Driver::new(AutomationConfig {
+            temp_cutoff_enabled: true,
+            battery_chemistry: BatteryChemistry::Lithium,
+            ..AutomationConfig::default()
+        });
+        assert_eq!(
+            driver.check(0, &[(BATTERY_TEMP, -1.0)]),
+            Some((false, "temp_cutoff"))
+        );
+        assert_eq!(
+            driver.check(10, &[(BATTERY_TEMP, 2.0)]),
+            Some((false, "temp_cutoff"))
+        );
+        assert_eq!(driver.check(20, &[(BATTERY_TEMP, 3.0)]), None);
+        assert_eq!(
+            driver.events(),
+            vec![
+                (EventKind::Alert, "battery_temp_cutoff".to_string()),
+                (EventKind::Cleared, "battery_temp_cutoff".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn lighting_runs_from_dusk_to_dawn() {
+        let mut driver = Driver::new(AutomationConfig {
+            lighting_enabled: true,
+            ..AutomationConfig::default()
+        });
+        assert_eq!(driver.check(0, &[(PV_VOLTAGE, 18.0)]), None);
+        assert_eq!(driver.check(100, &[(PV_VOLTAGE, 4.0)]), None);
+        // A cloud is not dusk.
+        assert_eq!(driver.check(300, &[(PV_VOLTAGE, 6.0)]), None);
+        assert_eq!(driver.check(400, &[(PV_VOLTAGE, 4.0)]), None);
+        assert_eq!(driver.check(999, &[(PV_VOLTAGE, 4.0)]), None);
+        assert_eq!(
+            driver.check(1000, &[(PV_VOLTAGE, 4.0)]),
+            Some((true, "lighting"))
+        );
+        // Between the dusk and dawn voltages.
+        assert_eq!(driver.check(5000, &[(PV_VOLTAGE, 7.0)]), None);
+        assert_eq!(driver.check(6000, &[(PV_VOLTAGE, 9.0)]), None);
+        assert_eq!(
+            driver.check(6600, &[(PV_VOLTAGE, 9.0)]),
+            Some((false, "lighting"))
+        );
+    }
+
+    #[test]
+    fn lighting_ends_at_its_limits_until_dawn() {
+        let mut driver = Driver::new(AutomationConfig {
+            lighting_enabled: true,
+            lighting_hold_s: 60,
+            lighting_hours: 1.0,
+            ..AutomationConfig::default()
+        });
+        let dark = [(PV_VOLTAGE, 2.0)];
+        let light = [(PV_VOLTAGE, 9.0)];
+        assert_eq!(driver.check(0, &dark), None);
+        assert_eq!(driver.check(60, &dark), Some((true, "lighting")));
+        assert_eq!(driver.check(3659, &dark), None);
+        assert_eq!(driver.check(3660, &dark), Some((false, "lighting")));
+        // Done until dawn, not lit again while dark.
+        assert_eq!(driver.check(4000, &dark), None);
+        assert_eq!(driver.check(5000, &light), None);
+        assert_eq!(driver.check(5060, &light), None);
+        assert_eq!(driver.check(6000, &dark), None);
+        assert_eq!(driver.check(6060, &dark), Some((true, "lighting")));
+        // The battery running low ends it too.
+        let low = [(PV_VOLTAGE, 2.0), (BATTERY_VOLTAGE, 11.9)];
+        assert_eq!(driver.check(6100, &low), None);
+        assert_eq!(driver.check(6160, &low), Some((false, "lighting")));
+        assert_eq!(driver.check(6200, &dark), None);
+    }
+
+    #[test]
+    fn lighting_skips_a_night_with_a_low_battery() {
+        let mut driver = Driver::new(AutomationConfig {
+            lighting_enabled: true,
+            lighting_hold_s: 60,
+            ..AutomationConfig::default()
+        });
+        let low = [(PV_VOLTAGE, 2.0), (BATTERY_VOLTAGE, 11.9)];
+        assert_eq!(driver.check(0, &low), None);
+        assert_eq!(driver.check(60, &low), None);
+        assert_eq!(driver.check(120, &[(PV_VOLTAGE, 2.0)]), None);
+    }
+
+    #[test]
+    fn windows_parse() {
+        let window: Window = "mon-fri 18:00-23:00".parse().expect("parse");
+        assert_eq!(window.days, [true, true, true, true, true, false, false]);
+        assert_eq!(window.start, Trigger::At(time("18:00")));
+        assert_eq!(window.end, Trigger::At(time("23:00")));
+
+        let window: Window = "sat,sun 22:00-06:00".parse().expect("parse");
+        assert_eq!(window.days, [false, false, false, false, false, true, true]);
+        assert_eq!(window.end, Trigger::At(time("06:00")));
+
+        let window: Window = "sunset+30m until sunrise".parse().expect("parse");
+        assert_eq!(window.days, [true; 7]);
+        assert_eq!(window.start, Trigger::Sunset(1800));
+        assert_eq!(window.end, Trigger::Sunrise(0));
+
+        let window: Window = "daily sunset-30m-sunrise+1h".parse().expect("parse");
+        assert_eq!(window.start, Trigger::Sunset(-1800));
+        assert_eq!(window.end, Trigger::Sunrise(3600));
+
+        let window: Window = "fri-mon 07:30 until 08:00".parse().expect("parse");
+        assert_eq!(window.days, [true, false, false, false, true, true, true]);
+    }
+
+    #[test]
+    fn invalid_windows_are_refused() {
+        for window in [
+            "",
+            "18:00",
+            "mon-fri",
+            "18:00-18:00",
+            "sunset until sunset",
+            "18:00-24:00",
+            "6pm-11pm",
+            "funday 18:00-23:00",
+            "sunset+soon until sunrise",
+            "sunset*2 until sunrise",
+            "sunrise until",
+        ] {
+            assert!(window.parse::<Window>().is_err(), "{:?}", window);
+        }
+        let config = AutomationConfig {
+            schedule: vec!["sunset until sunrise".to_string()],
+            ..AutomationConfig::default()
+        };
+        assert!(LoadAutomation::new(&config).is_err());
+    }
+
+    #[test]
+    fn windows_wrap_around_midnight() {
+        // 2024-06-07 is a Friday.
+        let window: Window = "fri 22:00-06:00".parse().expect("parse");
+        let location = (0.0, 0.0);
+        assert!(!window.contains(local(7, 21, 59), location));
+        assert!(window.contains(local(7, 22, 0), location));
+        assert!(window.contains(local(8, 3, 0), location));
+        assert!(!window.contains(local(8, 6, 0), location));
+        // Windows starting on other days.
+        assert!(!window.contains(local(7, 3, 0), location));
+        assert!(!window.contains(local(8, 23, 0), location));
+    }
+
+    #[test]
+    fn sun_windows_follow_sunset_and_sunrise() {
+        let location = (52.0, 5.0);
+        let window: Window = "sunset+30m until sunrise".parse().expect("parse");
+        let day = local(1, 12, 0).date_naive();
+        let (_, sunset) = sun::sunrise_sunset(day, location.0, location.1).expect("a sunset");
+        let (sunrise, _) =
+            sun::sunrise_sunset(day.succ_opt().expect("a next day"), 52.0, 5.0).expect("a sunrise");
+        let sunset = sunset.with_timezone(&Local);
+        let sunrise = sunrise.with_timezone(&Local);
+        assert!(!window.contains(sunset + TimeDelta::minutes(29), location));
+        assert!(window.contains(sunset + TimeDelta::minutes(30), location));
+        assert!(window.contains(sunrise - TimeDelta::minutes(1), location));
+        assert!(!window.contains(sunrise, location));
+    }
+}
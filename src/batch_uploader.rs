@@ -0,0 +1,223 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Delay before retrying after a failed write, doubled up to `MAX_RETRY_DELAY`.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+/// Partial batches are written at least this often.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+//
+// Structs
+//
+/// Writes queued items in batches from a background thread, keeping a
+/// bounded backlog while the endpoint is unreachable and retrying with a
+/// growing delay. What a batch looks like and where it goes is up to the
+/// `BatchWriter`.
+pub(crate) struct BatchUploader<T> {
+    items: Sender<T>,
+}
+
+/// Sizes of the batches and the backlog.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BatchLimits {
+    pub(crate) batch_size: usize,
+    pub(crate) max_backlog: usize,
+}
+
+struct Backlog<W: BatchWriter> {
+    writer: W,
+    /// Used in log messages, e.g. `Influx write`.
+    label: &'static str,
+    limits: BatchLimits,
+    items: VecDeque<W::Item>,
+    retry_delay: Duration,
+    next_attempt: Instant,
+}
+
+//
+// Traits
+//
+/// Encodes and sends one batch for a `BatchUploader`.
+pub(crate) trait BatchWriter: Send + 'static {
+    type Item: Send + 'static;
+
+    ///
+    /// Encode the items and send them as one request.
+    ///
+    fn write(&mut self, batch: &[Self::Item]) -> Result<(), ureq::Error>;
+
+    ///
+    /// Whether a batch the endpoint rejected with this client error status
+    /// is dropped rather than retried. Retrying a rejected batch would block
+    /// the backlog forever, only rate limiting is retried by default.
+    ///
+    fn drops_rejected(&self, status: u16) -> bool {
+        status != 429
+    }
+
+    ///
+    /// Called with every item added to the backlog.
+    ///
+    fn queued(&mut self, _item: &Self::Item) {}
+
+    ///
+    /// Called with the backlog after a failed write.
+    ///
+    fn failed(&mut self, _backlog: &VecDeque<Self::Item>) {}
+
+    ///
+    /// Called once everything in the backlog has been written.
+    ///
+    fn emptied(&mut self) {}
+
+    ///
+    /// Whether items left unwritten on shutdown are kept for the next run,
+    /// e.g. in a write-ahead log.
+    ///
+    fn keeps_unwritten(&self) -> bool {
+        false
+    }
+}
+
+//
+// implementations
+//
+impl<T: Send + 'static> BatchUploader<T> {
+    ///
+    /// Start the thread writing batches with `writer`, beginning with the
+    /// items of `replayed`, e.g. left over by an earlier run.
+    ///
+    pub(crate) fn spawn<W: BatchWriter<Item = T>>(
+        name: &str,
+        label: &'static str,
+        limits: BatchLimits,
+        writer: W,
+        replayed: Vec<T>,
+    ) -> io::Result<Self> {
+        let (tx, rx) = mpsc::channel::<T>();
+        let mut backlog = Backlog {
+            writer,
+            label,
+            limits,
+            items: VecDeque::new(),
+            retry_delay: MIN_RETRY_DELAY,
+            next_attempt: Instant::now(),
+        };
+        for item in replayed {
+            backlog.push(item);
+        }
+        thread::Builder::new().name(name.into()).spawn(move || {
+            let mut last_flush = Instant::now();
+            loop {
+                match rx.recv_timeout(Duration::from_secs(1)) {
+                    Ok(item) => backlog.push(item),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        backlog.finish();
+                        break;
+                    }
+                }
+                let due = last_flush.elapsed() >= FLUSH_INTERVAL;
+                backlog.flush(due);
+                if due {
+                    last_flush = Instant::now();
+                }
+            }
+        })?;
+        Ok(Self { items: tx })
+    }
+
+    ///
+    /// Queue an item for the next batch.
+    ///
+    pub(crate) fn push(&self, item: T) {
+        let _ = self.items.send(item);
+    }
+}
+
+impl<W: BatchWriter> Backlog<W> {
+    fn push(&mut self, item: W::Item) {
+        if self.items.len() >= self.limits.max_backlog.max(1) {
+            self.items.pop_front();
+        }
+        self.writer.queued(&item);
+        self.items.push_back(item);
+    }
+
+    ///
+    /// Write full batches, or everything when `all` is set, unless waiting
+    /// for a retry.
+    ///
+    fn flush(&mut self, all: bool) {
+        let batch_size = self.limits.batch_size.max(1);
+        while !self.items.is_empty() && (all || self.items.len() >= batch_size) {
+            if Instant::now() < self.next_attempt {
+                return;
+            }
+            let count = batch_size.min(self.items.len());
+            match self.writer.write(&self.items.make_contiguous()[..count]) {
+                Ok(()) => {
+                    self.items.drain(..count);
+                    self.retry_delay = MIN_RETRY_DELAY;
+                }
+                Err(ureq::Error::StatusCode(code))
+                    if (400..500).contains(&code) && self.writer.drops_rejected(code) =>
+                {
+                    error!(
+                        "{} rejected {} datapoints with status {}.",
+                        self.label, count, code
+                    );
+                    self.items.drain(..count);
+                }
+                Err(e) => {
+                    warn!(
+                        "{} failed, {} datapoints backlogged: {}",
+                        self.label,
+                        self.items.len(),
+                        e
+                    );
+                    self.writer.failed(&self.items);
+                    self.next_attempt = Instant::now() + self.retry_delay;
+                    self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+                    return;
+                }
+            }
+        }
+        if self.items.is_empty() {
+            self.writer.emptied();
+        }
+    }
+
+    ///
+    /// Make one last attempt at writing everything on shutdown, also while
+    /// waiting for a retry, and log what is left.
+    ///
+    fn finish(&mut self) {
+        self.next_attempt = Instant::now();
+        self.flush(true);
+        if self.items.is_empty() {
+            return;
+        }
+        match self.writer.keeps_unwritten() {
+            true => info!(
+                "{} stopped, {} datapoints are kept for the next start.",
+                self.label,
+                self.items.len()
+            ),
+            false => warn!(
+                "{} stopped, dropping {} datapoints not written.",
+                self.label,
+                self.items.len()
+            ),
+        }
+    }
+}
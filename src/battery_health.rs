@@ -0,0 +1,164 @@
+use crate::config::{BatteryHealthConfig, Config};
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime, TimeZone};
+use solar_core::alerts::{parse_hours, Event, EventKind, Severity};
+use solar_core::database::Database;
+use solar_core::health::{self, TREND_DAYS};
+use std::{collections::HashSet, io};
+
+//
+// Structs
+//
+/// Measures the battery health indicators of every day once its night is
+/// over, and tracks the health score of the last weeks.
+pub(crate) struct BatteryHealthMonitor {
+    config: BatteryHealthConfig,
+    night: (NaiveTime, NaiveTime),
+    database: Database,
+    /// Day of the last check.
+    checked: Option<NaiveDate>,
+    /// Whether the battery_degraded alert is raised.
+    raised: bool,
+}
+
+//
+// implementations
+//
+impl BatteryHealthMonitor {
+    ///
+    /// Check the settings of `[battery_health]` and open a connection to
+    /// read the stored datapoints and store the measurements.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Self> {
+        let settings = &config.battery_health;
+        let night = parse_hours(&settings.night)
+            .map_err(|e| io::Error::other(format!("battery_health.night: {}.", e)))?;
+        if night.0 == night.1 || settings.min_load_step <= 0.0 || settings.rest_current < 0.0 {
+            return Err(io::Error::other(
+                "battery_health.night must not be empty, min_load_step must be positive and rest_current not negative.",
+            ));
+        }
+        info!(
+            "Measuring the battery voltage sag and overnight self-discharge ({}) once a day.",
+            settings.night
+        );
+        Ok(Self {
+            config: settings.clone(),
+            night,
+            database: Database::new(&config.database)?,
+            checked: None,
+            raised: false,
+        })
+    }
+
+    ///
+    /// Once a day at the end of the night window, measure the days before
+    /// which were not measured yet, and return the alert, or its clearing,
+    /// when the health score crosses `alert_score`.
+    ///
+    pub(crate) fn check(&mut self, timestamp: i64) -> Option<Event> {
+        let now = DateTime::from_timestamp(timestamp, 0)?.with_timezone(&Local);
+        let today = now.date_naive();
+        if self.checked == Some(today) || now.time() < self.night.1 {
+            return None;
+        }
+        self.checked = Some(today);
+        let first = today - Days::new(TREND_DAYS);
+        let (from, to) = (midnight(first)?, midnight(today)?);
+        let stored = self.database.battery_health(from, to).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        let measured: HashSet<i64> = stored.iter().map(|day| day.day).collect();
+        // Days are measured once their night is over, yesterday's this morning.
+        for ago in 1..=TREND_DAYS {
+            let day = today - Days::new(ago);
+            if !measured.contains(&midnight(day)?) {
+                self.measure(day);
+            }
+        }
+        let days = self.database.battery_health(from, to).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        let Some(score) = health::score(&days) else {
+            info!("Not enough battery health measurements yet for a score.");
+            return None;
+        };
+        let score = score.round();
+        info!("Battery health score {} of 100.", score);
+        let low = score < self.config.alert_score;
+        if low == self.raised {
+            return None;
+        }
+        self.raised = low;
+        let (kind, message) = match low {
+            true => (
+                EventKind::Alert,
+                format!(
+                    "Battery degrading: health score {} of 100, its voltage sags or self-discharges more than it used to",
+                    score
+                ),
+            ),
+            false => (
+                EventKind::Cleared,
+                format!("Battery health score back at {} of 100", score),
+            ),
+        };
+        Some(Event {
+            kind,
+            name: "battery_degraded".to_string(),
+            severity: Severity::Warning,
+            message,
+            value: score,
+            timestamp,
+        })
+    }
+
+    ///
+    /// Measure a day and the night after it, storing the indicators unless
+    /// there was no data to measure them.
+    ///
+    fn measure(&self, day: NaiveDate) -> Option<()> {
+        let next = day.succ_opt()?;
+        let night_start = match self.night.0 < self.night.1 {
+            true => next.and_time(self.night.0),
+            false => day.and_time(self.night.0),
+        };
+        let at = |time| Local.from_local_datetime(&time).earliest();
+        let night = (
+            at(night_start)?.timestamp(),
+            at(next.and_time(self.night.1))?.timestamp(),
+        );
+        let measured = self.database.measure_battery_health(
+            (midnight(day)?, midnight(next)?),
+            night,
+            self.config.rest_current,
+            self.config.min_load_step,
+        );
+        match measured {
+            Ok(health) if health.sag_mohm.is_some() || health.self_discharge_mv_h.is_some() => {
+                info!(
+                    "Battery on {}: sag {} mOhm, self-discharge {} mV/h.",
+                    day,
+                    format_value(health.sag_mohm),
+                    format_value(health.self_discharge_mv_h)
+                );
+                self.database.store_battery_health(&health);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("{}", e),
+        }
+        Some(())
+    }
+}
+
+fn midnight(day: NaiveDate) -> Option<i64> {
+    Local
+        .from_local_datetime(&day.and_time(NaiveTime::MIN))
+        .earliest()
+        .map(|t| t.timestamp())
+}
+
+fn format_value(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.1}", v))
+}
@@ -0,0 +1,190 @@
+use crate::config::{CapacityConfig, Config};
+use solar_core::alerts::{Event, EventKind, Severity};
+use solar_core::database::Database;
+use solar_core::datapoint::DataPoint;
+use std::{io, time::Duration};
+
+//
+// Constants
+//
+/// Longer intervals between datapoints end the measurement, the current in
+/// between is unknown.
+const MAX_INTERVAL: Duration = Duration::from_secs(60);
+/// Seconds the battery voltage has to stay at the empty voltage, so a
+/// short sag under a heavy load does not end a discharge.
+const EMPTY_SECONDS: i64 = 60;
+/// Discharges of less than this percentage of the nominal capacity are not
+/// measurements, the battery voltage sagged early or the count is off.
+const MIN_MEASURED_PERCENT: f64 = 20.0;
+
+//
+// Structs
+//
+/// Estimates the usable capacity of the battery by counting the amp hours
+/// taken out of it from full, as reported by the controller, until its
+/// voltage reaches the empty voltage.
+pub(crate) struct CapacityEstimator {
+    config: CapacityConfig,
+    device: String,
+    nominal_ah: f64,
+    database: Database,
+    /// Amp hours taken out since the battery was last full, unknown until
+    /// it is full.
+    since_full: Option<f64>,
+    /// Time the battery voltage reached the empty voltage.
+    empty_since: Option<i64>,
+    /// The latest estimate and whether it was checked against the limit.
+    estimate: Option<f64>,
+    checked: bool,
+    /// Whether the battery_capacity_low alert is raised.
+    raised: bool,
+}
+
+//
+// implementations
+//
+impl CapacityEstimator {
+    ///
+    /// Check the settings of `[capacity]` and load the estimate of the
+    /// device of `[cycles]`, whose capacity_ah is the nominal capacity.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Self> {
+        let settings = &config.capacity;
+        if settings.empty_voltage <= 0.0
+            || settings.min_capacity_percent <= 0.0
+            || settings.min_capacity_percent >= 100.0
+            || config.cycles.capacity_ah <= 0.0
+        {
+            return Err(io::Error::other(
+                "capacity.empty_voltage and cycles.capacity_ah must be positive and capacity.min_capacity_percent between 0 and 100.",
+            ));
+        }
+        let database = Database::new(&config.database)?;
+        let device = config.cycles.device.clone();
+        let (estimate, measurements) = database.capacity(&device).unwrap_or_else(|e| {
+            warn!("{}", e);
+            (None, 0)
+        });
+        match estimate {
+            Some(estimate) => info!(
+                "Usable capacity of {} estimated at {:.1} Ah of {} Ah from {} discharges.",
+                device, estimate, config.cycles.capacity_ah, measurements
+            ),
+            None => info!(
+                "Estimating the usable capacity of {} from discharges from full to {} V.",
+                device, settings.empty_voltage
+            ),
+        }
+        Ok(Self {
+            config: settings.clone(),
+            device,
+            nominal_ah: config.cycles.capacity_ah,
+            database,
+            since_full: None,
+            empty_since: None,
+            estimate,
+            checked: false,
+            raised: false,
+        })
+    }
+
+    ///
+    /// Count the amp hours since the datapoint before, `interval` ago, and
+    /// measure the capacity once a discharge from full reaches empty. Returns
+    /// the alert, or its clearing, when the estimate crosses the limit.
+    ///
+    pub(crate) fn check(
+        &mut self,
+        datapoint: &DataPoint,
+        interval: Option<Duration>,
+    ) -> Option<Event> {
+        let time = datapoint.get_time();
+        // An estimate below the limit from before a restart.
+        if !self.checked {
+            self.checked = true;
+            if let Some(event) = self.alert(time) {
+                return Some(event);
+            }
+        }
+        if datapoint.get_battery_full() > 0.0 {
+            self.since_full = Some(0.0);
+            self.empty_since = None;
+            return None;
+        }
+        let since_full = self.since_full.as_mut()?;
+        match interval {
+            Some(interval) if interval <= MAX_INTERVAL => {
+                let hours = interval.as_secs_f64() / 3600.0;
+                let net = datapoint.get_load_current() - datapoint.get_charge_current();
+                *since_full = (*since_full + net * hours).max(0.0);
+            }
+            _ => {
+                info!("Gap in the datapoints, the capacity is measured from the next full charge.");
+                self.since_full = None;
+                return None;
+            }
+        }
+        if datapoint.get_battery_voltage() > self.config.empty_voltage {
+            self.empty_since = None;
+            return None;
+        }
+        if time - *self.empty_since.get_or_insert(time) < EMPTY_SECONDS {
+            return None;
+        }
+        let measured = *since_full;
+        self.since_full = None;
+        self.empty_since = None;
+        if measured < self.nominal_ah * MIN_MEASURED_PERCENT / 100.0 {
+            info!(
+                "Battery empty after {:.1} Ah only, not counted as a capacity measurement.",
+                measured
+            );
+            return None;
+        }
+        info!(
+            "Battery empty after {:.1} Ah taken out from full.",
+            measured
+        );
+        self.database.store_capacity(&self.device, time, measured);
+        match self.database.capacity(&self.device) {
+            Ok((estimate, _)) => self.estimate = estimate,
+            Err(e) => warn!("{}", e),
+        }
+        self.alert(time)
+    }
+
+    ///
+    /// The alert when the estimate falls below min_capacity_percent of the
+    /// nominal capacity, or its clearing when it is back above.
+    ///
+    fn alert(&mut self, timestamp: i64) -> Option<Event> {
+        let estimate = self.estimate?;
+        let percent = 100.0 * estimate / self.nominal_ah;
+        let low = percent < self.config.min_capacity_percent;
+        if low == self.raised {
+            return None;
+        }
+        self.raised = low;
+        let (kind, message) = match low {
+            true => (
+                EventKind::Alert,
+                format!(
+                    "Battery capacity low: {:.1} Ah usable, {:.0}% of the nominal {} Ah",
+                    estimate, percent, self.nominal_ah
+                ),
+            ),
+            false => (
+                EventKind::Cleared,
+                format!("Battery capacity back at {:.1} Ah", estimate),
+            ),
+        };
+        Some(Event {
+            kind,
+            name: "battery_capacity_low".to_string(),
+            severity: Severity::Warning,
+            message,
+            value: estimate,
+            timestamp,
+        })
+    }
+}
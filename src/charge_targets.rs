@@ -0,0 +1,60 @@
+use crate::config::{BatteryChemistry, Config};
+use std::io;
+
+//
+// Structs
+//
+/// The absorption and float voltages a controller should charge the battery
+/// to, compensated for its temperature.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChargeTargets {
+    absorption_voltage: f64,
+    float_voltage: f64,
+    /// Volts the targets change by per degree away from `reference_temp`.
+    compensation_v_per_c: f64,
+    reference_temp: f64,
+}
+
+//
+// implementations
+//
+impl ChargeTargets {
+    ///
+    /// The targets of the `[charge_targets]` section, `None` unless enabled.
+    /// Unset voltages and the compensation default to those of the battery
+    /// chemistry of `[automation]`.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Option<Self>> {
+        let settings = &config.charge_targets;
+        if !settings.enabled {
+            return Ok(None);
+        }
+        let (absorption, float, compensation) = match config.automation.battery_chemistry {
+            BatteryChemistry::LeadAcid => (14.4, 13.6, -30.0),
+            BatteryChemistry::Lithium => (14.2, 13.5, 0.0),
+        };
+        let targets = Self {
+            absorption_voltage: settings.absorption_voltage.unwrap_or(absorption),
+            float_voltage: settings.float_voltage.unwrap_or(float),
+            compensation_v_per_c: settings.compensation_mv_per_c.unwrap_or(compensation) / 1000.0,
+            reference_temp: settings.reference_temp,
+        };
+        if targets.float_voltage <= 0.0 || targets.absorption_voltage < targets.float_voltage {
+            return Err(io::Error::other(
+                "charge_targets.float_voltage must be positive and absorption_voltage at least as high.",
+            ));
+        }
+        Ok(Some(targets))
+    }
+
+    ///
+    /// The absorption and float voltages at a battery temperature.
+    ///
+    pub(crate) fn at(&self, temperature: f64) -> (f64, f64) {
+        let compensation = (temperature - self.reference_temp) * self.compensation_v_per_c;
+        (
+            self.absorption_voltage + compensation,
+            self.float_voltage + compensation,
+        )
+    }
+}
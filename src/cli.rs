@@ -0,0 +1,267 @@
+use crate::report::ReportPeriod;
+use chrono::{DateTime, NaiveDate};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use log::LevelFilter;
+use solar_core::config::parse_duration;
+use solar_core::database::{Aggregation, DATA_FIELDS};
+use std::{net::SocketAddr, path::PathBuf};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "A simple solar tracer application.")]
+pub(crate) struct Cli {
+    /// Path to the config file, defaults to the platform config directory.
+    #[arg(short, long, value_name = "FILE", global = true)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Serial port to use, overrides the port set in the config file.
+    #[arg(short, long, value_name = "PORT", global = true)]
+    pub(crate) port: Option<String>,
+
+    /// Log file, overrides logging.file from the config.
+    #[arg(long, value_name = "FILE", global = true)]
+    pub(crate) log_file: Option<PathBuf>,
+
+    /// Log level of the log file (off, error, warn, info, debug, trace).
+    #[arg(long, value_name = "LEVEL", global = true)]
+    pub(crate) log_level: Option<LevelFilter>,
+
+    /// Read and display data without writing to the database or sending load commands.
+    #[arg(long, global = true)]
+    pub(crate) dry_run: bool,
+
+    /// Run the datalogger without the terminal UI, e.g. as a background service.
+    #[arg(long)]
+    pub(crate) headless: bool,
+
+    /// Browse the history and statistics of the database without a serial port.
+    #[arg(long, conflicts_with = "headless")]
+    pub(crate) offline: bool,
+
+    /// Collect uploads of remote loggers and serve the fleet API and dashboard,
+    /// without a serial port.
+    #[arg(long, conflicts_with_all = ["headless", "offline"])]
+    pub(crate) fleet_server: bool,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Export stored datapoints from the database to a file.
+    Export(ExportArgs),
+    /// Print aggregated values from the database, e.g. `query --avg 1h battery_voltage`.
+    Query(QueryArgs),
+    /// Run the datalogger headless and serve the HTTP API.
+    Serve(ServeArgs),
+    /// Show the TUI for a remote `serve` daemon, e.g. `connect pi:8080`.
+    Connect(ConnectArgs),
+    /// Stream each new datapoint to stdout as it arrives.
+    Tail(TailArgs),
+    /// Print summary statistics for today or the current month, or a daily,
+    /// weekly or monthly report.
+    Stats(StatsArgs),
+    /// Print the charge cycles counted per device by depth of discharge.
+    Cycles {
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// List the available serial ports with their USB metadata.
+    ListPorts {
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Update this binary to the latest GitHub release.
+    SelfUpdate(SelfUpdateArgs),
+    /// Print shell completions, e.g. `completions bash > /etc/bash_completion.d/rust-solar`.
+    Completions {
+        /// Shell to generate completions for.
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ExportArgs {
+    /// Start of the exported range, as unix seconds, RFC 3339 or YYYY-MM-DD.
+    #[arg(long, value_name = "TS", value_parser = parse_timestamp)]
+    pub(crate) from: Option<i64>,
+
+    /// End of the exported range (inclusive), same formats as --from.
+    #[arg(long, value_name = "TS", value_parser = parse_timestamp)]
+    pub(crate) to: Option<i64>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub(crate) format: ExportFormat,
+
+    /// Output file, stdout when omitted.
+    #[arg(short, long, value_name = "FILE")]
+    pub(crate) out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("aggregation").required(true)))]
+pub(crate) struct QueryArgs {
+    /// Start of the queried range, as unix seconds, RFC 3339 or YYYY-MM-DD.
+    #[arg(long, value_name = "TS", value_parser = parse_timestamp)]
+    pub(crate) from: Option<i64>,
+
+    /// End of the queried range (inclusive), same formats as --from.
+    #[arg(long, value_name = "TS", value_parser = parse_timestamp)]
+    pub(crate) to: Option<i64>,
+
+    /// Average of FIELD over buckets of INTERVAL (e.g. 15m, 1h, 1d).
+    #[arg(long, num_args = 2, value_names = ["INTERVAL", "FIELD"], group = "aggregation")]
+    pub(crate) avg: Option<Vec<String>>,
+
+    /// Minimum of FIELD over buckets of INTERVAL.
+    #[arg(long, num_args = 2, value_names = ["INTERVAL", "FIELD"], group = "aggregation")]
+    pub(crate) min: Option<Vec<String>>,
+
+    /// Maximum of FIELD over buckets of INTERVAL.
+    #[arg(long, num_args = 2, value_names = ["INTERVAL", "FIELD"], group = "aggregation")]
+    pub(crate) max: Option<Vec<String>>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub(crate) format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ServeArgs {
+    /// Address to listen on, overrides api.bind from the config.
+    #[arg(long, value_name = "ADDR")]
+    pub(crate) bind: Option<SocketAddr>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ConnectArgs {
+    /// Daemon address, `host:port` or an http(s) url.
+    pub(crate) host: String,
+
+    /// API key of the daemon, if it requires one.
+    #[arg(long)]
+    pub(crate) api_key: Option<String>,
+
+    /// PIN for switching the load, if the daemon requires one (load.control_pin).
+    #[arg(long)]
+    pub(crate) pin: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct SelfUpdateArgs {
+    /// Only check whether a newer release is available.
+    #[arg(long)]
+    pub(crate) check: bool,
+
+    /// Reinstall the latest release even if it is not newer.
+    #[arg(long, conflicts_with = "check")]
+    pub(crate) force: bool,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct StatsArgs {
+    /// Statistics for today (the default).
+    #[arg(long, conflicts_with = "month")]
+    pub(crate) today: bool,
+
+    /// Statistics for the current month.
+    #[arg(long)]
+    pub(crate) month: bool,
+
+    /// A report with a row per day, week or month instead, the most recent first.
+    #[arg(long, value_enum, value_name = "PERIOD", conflicts_with_all = ["today", "month"])]
+    pub(crate) report: Option<ReportPeriod>,
+
+    /// Rows of the report, by default 14 days, 8 weeks or 12 months.
+    #[arg(long, value_name = "N", requires = "report")]
+    pub(crate) count: Option<usize>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub(crate) format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct TailArgs {
+    /// Output format, one line per datapoint.
+    #[arg(long, value_enum, default_value_t = StreamFormat::Csv)]
+    pub(crate) format: StreamFormat,
+
+    /// Read from a running daemon (e.g. http://pi:8080) instead of the serial port.
+    #[arg(long, value_name = "URL")]
+    pub(crate) daemon: Option<String>,
+
+    /// API key of the daemon, if it requires one.
+    #[arg(long, requires = "daemon")]
+    pub(crate) api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum StreamFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    Csv,
+    Json,
+    Parquet,
+}
+
+///
+/// Parse a timestamp given on the command line into unix seconds.
+///
+pub(crate) fn parse_timestamp(value: &str) -> Result<i64, String> {
+    if let Ok(secs) = value.parse::<i64>() {
+        return Ok(secs);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.timestamp());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|d| d.and_local_timezone(chrono::Local).earliest())
+            .ok_or_else(|| format!("Invalid local date: {}", value))?;
+        return Ok(midnight.timestamp());
+    }
+    Err(format!(
+        "Invalid timestamp '{}', expected unix seconds, RFC 3339 or YYYY-MM-DD.",
+        value
+    ))
+}
+
+impl QueryArgs {
+    ///
+    /// The selected aggregation together with its bucket interval in seconds and field.
+    ///
+    pub(crate) fn aggregation(&self) -> Result<(Aggregation, u64, &str), String> {
+        let (aggregation, values) = match (&self.avg, &self.min, &self.max) {
+            (Some(v), _, _) => (Aggregation::Avg, v),
+            (_, Some(v), _) => (Aggregation::Min, v),
+            (_, _, Some(v)) => (Aggregation::Max, v),
+            _ => return Err("No aggregation given.".to_string()),
+        };
+        let field = values[1].as_str();
+        if !DATA_FIELDS.contains(&field) {
+            return Err(format!(
+                "Unknown field '{}', expected one of: {}.",
+                field,
+                DATA_FIELDS.join(", ")
+            ));
+        }
+        Ok((aggregation, parse_duration(&values[0])?, field))
+    }
+}
@@ -0,0 +1,1798 @@
+use crate::report::ReportPeriod;
+use log::LevelFilter;
+use serde::Deserialize;
+use solar_core::alerts::Severity;
+pub(crate) use solar_core::config::{AlertThresholds, DatabaseConfig, SerialConfig};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+//
+// Constants
+//
+const CONFIG_DIR_NAME: &str = "rust-solar";
+const CONFIG_FILENAME: &str = "config.toml";
+
+/// The commented configuration written out on first run, kept in sync with
+/// the `Default` implementations below.
+const DEFAULT_CONFIG: &str = r#"# rust-solar configuration file.
+# Every key is optional, missing keys fall back to the defaults shown here.
+
+[serial]
+# Serial port to use, e.g. "/dev/ttyUSB0" or "COM5".
+# When unset the port picker is shown at startup.
+#port = "/dev/ttyUSB0"
+baud_rate = 57600
+# Read timeout in milliseconds.
+timeout_ms = 2000
+# Delay between datapoint reads in milliseconds.
+poll_interval_ms = 1000
+
+[database]
+path = "solar_data.sql"
+# Number of datapoints buffered in memory before they are written.
+buffer_size = 256
+
+[ui]
+# Redraw interval of the terminal UI in milliseconds.
+tick_rate_ms = 25
+load_on_label = "ON"
+load_off_label = "OFF"
+# Ring the terminal bell when a critical alert is raised, so it is noticed with
+# the UI in a background window.
+bell = false
+# Also show critical alerts as desktop notifications (requires the `desktop`
+# feature).
+desktop_notifications = false
+
+[load]
+# How the load is switched: "serial" sends LON/LOFF to the controller, "gpio"
+# drives a relay wired to a GPIO pin of the host (requires the `gpio` feature).
+backend = "serial"
+# GPIO character device and line offset (BCM numbering on a Raspberry Pi).
+gpio_chip = "/dev/gpiochip0"
+gpio_pin = 17
+# Set when the relay switches on with the pin low.
+active_low = false
+# Require this PIN, or a token signed with control_secret, for load commands
+# over the network (the HTTP API, gRPC and MQTT), so a stray request cannot
+# switch the load. A token is the hex HMAC-SHA256 of "<on|off>:<unix time>"
+# with the secret, sent along with the time and valid for 5 minutes, once.
+# After control_max_failures failed attempts a client is locked out for
+# control_lockout_s seconds, after control_max_total_failures of all clients
+# within that time every client is.
+#control_pin = "1234"
+#control_secret = "secret"
+control_max_failures = 5
+control_max_total_failures = 20
+control_lockout_s = 300
+# Name of what is wired to the load terminals, e.g. "fridge", and its rated
+# power in watts. The reports then attribute the energy consumed to it, with
+# the hours at the rated power it lasts.
+#name = "fridge"
+#rated_watts = 60.0
+
+[automation]
+# Low-voltage disconnect: switch the load off once the battery voltage has
+# stayed below lvd_voltage for lvd_hold_s seconds, e.g. when the controller's
+# own cutoff is set too low. It acts again only after the voltage recovered.
+lvd_enabled = false
+lvd_voltage = 11.5
+lvd_hold_s = 60
+# Switch the load back on once the battery voltage has stayed at or above
+# reconnect_voltage for reconnect_hold_s seconds. The gap to lvd_voltage keeps
+# the load from flapping around the cutoff.
+reconnect_enabled = false
+reconnect_voltage = 12.6
+reconnect_hold_s = 600
+# Local time windows in which the load is switched on, e.g.
+# ["18:00-23:00", "mon-fri 05:30-07:00", "sat,sun 20:00-01:00"]. Windows may
+# start or end at sunrise or sunset with an optional offset, e.g.
+# "sunset+30m until sunrise" or "sunset-15m-23:00". The load is
+# switched at the start and end of each window only, so it can still be
+# toggled by hand in between. The low-voltage disconnect takes precedence.
+schedule = []
+# Location used to compute sunrise and sunset.
+latitude = 0.0
+longitude = 0.0
+# Switch the load off and raise a critical alert while the battery temperature
+# is above temp_cutoff_max, or below temp_cutoff_min for "lithium" batteries,
+# which must not be charged below freezing ("lead_acid" or "lithium"). The
+# load is held off, also against manual switching, until the temperature is
+# back within the limits by temp_cutoff_hysteresis degrees.
+temp_cutoff_enabled = false
+battery_chemistry = "lead_acid"
+temp_cutoff_max = 50.0
+temp_cutoff_min = 0.0
+temp_cutoff_hysteresis = 3.0
+# Switch the load off and raise a critical alert once the load current has
+# stayed above overcurrent_limit amperes for overcurrent_hold_s seconds, e.g.
+# to protect wiring sized below the controller's own limit. The load stays off
+# until it is switched on again by hand.
+overcurrent_enabled = false
+overcurrent_limit = 10.0
+overcurrent_hold_s = 5
+# Dusk-to-dawn lighting: switch the load on at dusk, once the panel voltage has
+# stayed below dusk_voltage for lighting_hold_s seconds, and off again at dawn
+# when it has stayed above dawn_voltage, after lighting_hours hours (0 for all
+# night) or when the battery voltage has stayed below lighting_min_voltage.
+# The controller reports no state of charge, so the floor is a voltage.
+lighting_enabled = false
+dusk_voltage = 5.0
+dawn_voltage = 8.0
+lighting_hold_s = 600
+lighting_hours = 0.0
+lighting_min_voltage = 12.0
+# Rhai script run on every datapoint (requires the `scripting` feature), with
+# `data` (the datapoint's fields and time), `stats` (min, max, avg and change
+# of each field over the last script_window datapoints), `load_on` and a
+# `state` map kept between runs. Calling load(true) or load(false) switches
+# the load, print() writes to the log.
+#script = "rules.rhai"
+script_window = 60
+
+[mqtt]
+# Publish every datapoint to an MQTT broker (requires the `mqtt` feature), one
+# topic per value: <topic_prefix>/<device>/battery_voltage, ...
+enabled = false
+host = "localhost"
+port = 1883
+client_id = "rust-solar"
+#username = "solar"
+#password = "secret"
+topic_prefix = "solar"
+device = "tracer"
+# 0 = at most once, 1 = at least once, 2 = exactly once.
+qos = 0
+# Let the broker keep the last value of each topic for new subscribers.
+retain = false
+# Switch the load with "on" or "off" messages on <topic_prefix>/<device>/<load_topic>.
+load_control = false
+load_topic = "load/set"
+# Change the serial poll interval with messages holding milliseconds (100 to
+# 3600000) on <topic_prefix>/<device>/<poll_interval_topic>.
+poll_interval_control = false
+poll_interval_topic = "poll_interval/set"
+# Connect over TLS (usually port 8883), verifying the broker with ca_cert or
+# the system's root certificates. client_cert and client_key (PEM) enable
+# mutual TLS, as required by AWS IoT Core together with its root CA:
+#tls = true
+#ca_cert = "/etc/rust-solar/AmazonRootCA1.pem"
+#client_cert = "/etc/rust-solar/device.pem.crt"
+#client_key = "/etc/rust-solar/private.pem.key"
+# Name of an AWS IoT thing whose device shadow gets the load state reported as
+# {"state":{"reported":{"load":"on"}}}. With load_control, a desired load
+# state set in the shadow switches the load.
+shadow_thing = ""
+
+[influx]
+# Push datapoints to InfluxDB or VictoriaMetrics using the line protocol.
+enabled = false
+# Write endpoint including the database/bucket and precision=s, e.g.
+# "http://localhost:8086/write?db=solar&precision=s" (InfluxDB 1.x, VictoriaMetrics) or
+# "http://localhost:8086/api/v2/write?org=home&bucket=solar&precision=s" (InfluxDB 2.x).
+url = "http://localhost:8086/write?db=solar&precision=s"
+# Sent as "Authorization: Token <token>" when set.
+#token = "secret"
+measurement = "solar"
+device = "tracer"
+# Number of datapoints per write request.
+batch_size = 10
+# Datapoints kept while the endpoint is unreachable, the oldest are dropped first.
+max_backlog = 100000
+
+[remote_write]
+# Push datapoints with the Prometheus remote-write protocol, e.g. to
+# Prometheus, Mimir, VictoriaMetrics or Grafana Cloud, as metrics named
+# <metric_prefix><field> with a device label.
+enabled = false
+url = "http://localhost:9090/api/v1/write"
+# Basic authentication (Grafana Cloud: instance id and API token), or a
+# bearer token.
+username = ""
+password = ""
+token = ""
+metric_prefix = "solar_"
+device = "tracer"
+# Number of datapoints per write request.
+batch_size = 60
+# Datapoints kept while the endpoint is unreachable, the oldest are dropped first.
+max_backlog = 100000
+# The backlog is also written to this file during an outage and resent after
+# a restart. Remove the line to keep it in memory only.
+wal_path = "solar-remote-write.wal"
+
+[graphite]
+# Send datapoints and counters using the Graphite plaintext protocol.
+enabled = false
+address = "localhost:2003"
+prefix = "solar.tracer"
+
+[statsd]
+# Send datapoints as StatsD gauges and counters over UDP.
+enabled = false
+address = "localhost:8125"
+prefix = "solar.tracer"
+
+[modbus]
+# Serve the latest values as a read-only Modbus TCP device (function codes 3
+# and 4, holding and input registers holding the same values):
+#  0 battery voltage, 1 PV voltage, 2 load current, 3 over discharge voltage,
+#  4 battery max voltage (0.01 V/A), 5 battery full, 6 charging (0/1),
+#  7 battery temperature (0.1 C, signed), 8 charge current (0.01 A),
+#  9 load on (0/1), 10-11 unix time (high word first).
+enabled = false
+# Port 502 is the standard but needs root on Linux.
+bind = "0.0.0.0:5020"
+# Unit id answered to, 0 answers every unit id.
+unit_id = 1
+
+[signalk]
+# Send datapoints as Signal K deltas, e.g. to signalk-server's WebSocket
+# stream (ws:// or wss://) or its TCP port (tcp://localhost:8375).
+enabled = false
+url = "ws://localhost:3000/signalk/v1/stream?subscribe=none"
+# Device access token, needed when the server has security enabled.
+token = ""
+# Ids used in the electrical.batteries.<id> and electrical.solar.<id> paths.
+battery_id = "house"
+solar_id = "tracer"
+
+[kafka]
+# Publish every datapoint as JSON to a Kafka topic (requires the `kafka`
+# feature), keyed by device so each site keeps its order within a partition.
+enabled = false
+# Comma separated host:port list of bootstrap brokers.
+brokers = "localhost:9092"
+topic = "solar"
+device = "tracer"
+client_id = "rust-solar"
+# Datapoints not delivered within this time are dropped.
+message_timeout_ms = 300000
+
+[kafka.properties]
+# Further librdkafka settings, e.g. for SASL or TLS:
+#"security.protocol" = "sasl_ssl"
+#"sasl.mechanisms" = "PLAIN"
+#"sasl.username" = "solar"
+#"sasl.password" = "secret"
+
+[nats]
+# Publish every datapoint to a NATS server, reconnecting in the background.
+enabled = false
+url = "nats://localhost:4222"
+# "json" sends each datapoint as one JSON message on the subject, "fields"
+# each value on <subject>.battery_voltage, ...
+subject = "solar.tracer"
+format = "json"
+# Credentials when the server requires them, either user and password or a token.
+user = ""
+password = ""
+token = ""
+
+[redis]
+# Add every value to a RedisTimeSeries key <key_prefix>:<device>:<field>,
+# e.g. solar:tracer:battery_voltage, labelled with device and field.
+enabled = false
+address = "localhost:6379"
+# Credentials for AUTH, username only with Redis 6 ACLs.
+username = ""
+password = ""
+key_prefix = "solar"
+device = "tracer"
+# How long samples are kept in newly created keys, 0 keeps them forever.
+retention_ms = 604800000
+
+[thingspeak]
+# Send datapoints to a ThingSpeak channel, averaged between updates.
+enabled = false
+# Write API key of the channel.
+api_key = ""
+url = "https://api.thingspeak.com/update"
+# Values sent as field1, field2, ... (at most 8).
+fields = ["battery_voltage", "pv_voltage", "load_current", "charge_current", "battery_temp"]
+# Seconds between updates, the free plan accepts one every 15 seconds.
+min_interval_s = 20
+
+# Webhooks called on alerts (a value outside of the [alerts] limits or back
+# within them) and load switching, e.g. for ntfy or IFTTT. Repeat the section
+# for more webhooks.
+#[[webhooks]]
+#url = "https://ntfy.sh/my-solar"
+# Only send these events (battery_low, battery_high, battery_temp,
+# load_current, load_on, load_off, link_lost), all when empty.
+#events = []
+# Only send events of at least this severity ("info", "warning" or
+# "critical"). Alerts below it are still sent once they have been active for
+# escalate_after_s seconds, if set, and then so is their clearing. E.g.
+# min_severity = "critical" with escalate_after_s = 1800 sends warnings only
+# when unresolved for 30 minutes.
+#min_severity = "info"
+#escalate_after_s = 0
+# Hold back events below critical during the quiet hours, e.g. "22:00-07:00",
+# an event repeated within repeat_after_s seconds, and any beyond max_per_hour
+# events an hour (0 for no limit). What was held back is sent as one summary
+# once the quiet hours are over, repeat_after_s after the first of it, and
+# within the hourly limit.
+#quiet_hours = "22:00-07:00"
+#repeat_after_s = 0
+#max_per_hour = 0
+# Request body, {{kind}}, {{name}}, {{severity}}, {{message}}, {{value}},
+# {{timestamp}} and {{time}} are replaced. Defaults to a JSON object of all of them.
+#template = '{"text": "{{message}}"}'
+#content_type = "application/json"
+# Attempts after the first failed one, and the delay between them in seconds.
+#retries = 3
+#retry_delay_s = 10
+
+# Local commands run on the same events, e.g. to switch a generator relay. The
+# event is passed in the environment as SOLAR_EVENT_KIND (alert, cleared or
+# load), SOLAR_EVENT_NAME, SOLAR_EVENT_SEVERITY, SOLAR_EVENT_MESSAGE,
+# SOLAR_EVENT_VALUE, SOLAR_EVENT_TIMESTAMP and SOLAR_EVENT_TIME (RFC 3339).
+# Repeat the section for more hooks.
+#[[hooks]]
+#command = "/usr/local/bin/generator"
+#args = []
+# Only run on these events, all when empty, and by severity as for webhooks.
+# Quiet hours and limits are also as for webhooks.
+#events = ["battery_low"]
+#min_severity = "info"
+#escalate_after_s = 0
+#quiet_hours = "22:00-07:00"
+#repeat_after_s = 0
+#max_per_hour = 0
+# Kill the command when it runs longer than this many seconds.
+#timeout_s = 30
+
+[email]
+# Mail alert and load events (requires the `email` feature).
+enabled = false
+server = "smtp.example.com"
+# 465 for "tls", 587 for "starttls" and 25 for "none".
+port = 587
+# "starttls", "tls" (implicit TLS) or "none" (plain text, local relays only).
+security = "starttls"
+#username = "solar@example.com"
+#password = "secret"
+from = "rust-solar <solar@example.com>"
+to = ["owner@example.com"]
+# Only mail these events, all when empty, and by severity as for webhooks.
+events = []
+min_severity = "info"
+escalate_after_s = 0
+# Quiet hours and limits as for webhooks.
+#quiet_hours = "22:00-07:00"
+repeat_after_s = 0
+max_per_hour = 0
+# {{time}} is the local time of the event, the other placeholders are as for webhooks.
+subject = "[rust-solar] {{message}}"
+body = "{{message}} at {{time}}."
+
+[telegram]
+# Send events as messages from a Telegram bot. Create the bot with @BotFather
+# and use the id of the chat (or group) it should write to.
+enabled = false
+bot_token = ""
+chat_id = ""
+# Only send these events, all when empty, e.g. ["battery_low", "battery_high"],
+# and by severity as for webhooks.
+events = []
+min_severity = "info"
+escalate_after_s = 0
+# Quiet hours and limits as for webhooks.
+#quiet_hours = "22:00-07:00"
+repeat_after_s = 0
+max_per_hour = 0
+template = "{{message}}"
+api_url = "https://api.telegram.org"
+
+[discord]
+# Post events to a Discord channel webhook (channel settings, Integrations).
+enabled = false
+webhook_url = ""
+username = "rust-solar"
+# Only send these events, all when empty, and by severity as for webhooks.
+events = []
+min_severity = "info"
+escalate_after_s = 0
+# Quiet hours and limits as for webhooks.
+#quiet_hours = "22:00-07:00"
+repeat_after_s = 0
+max_per_hour = 0
+template = "{{message}}"
+
+[forecast]
+# Fetch solar generation forecasts to compare with the actual harvest.
+enabled = false
+# "forecast_solar" (forecast.solar, free without a key) or "solcast".
+provider = "forecast_solar"
+latitude = 0.0
+longitude = 0.0
+# Panel tilt (0 = horizontal) and azimuth (0 = south, -90 = east, 90 = west).
+declination = 30.0
+azimuth = 0.0
+# Peak power of the panels in kW.
+kwp = 0.1
+# Personal forecast.solar key or Solcast API key, and the Solcast rooftop site id.
+#api_key = "secret"
+resource_id = ""
+# Solcast's free tier allows 10 requests a day, forecast.solar 12 an hour.
+refresh_interval_min = 180
+
+[array]
+# Model the output of the panel array under a clear sky and chart it on the
+# History tab over the measured charge power. The location, declination,
+# azimuth and kwp of the panels are those of [forecast], which need not be
+# enabled. losses_percent is what wiring, the controller and heat take off.
+enabled = false
+losses_percent = 14.0
+
+[weather]
+# Fetch the cloud cover and irradiance observed at the panels' location from
+# open-meteo.com every refresh_interval_min, for the last past_days days, and
+# store them per hour. The History tab then overlays the cloud cover on the
+# charge power.
+enabled = false
+latitude = 0.0
+longitude = 0.0
+past_days = 2
+refresh_interval_min = 60
+
+[performance]
+# Raise the panel_underperforming warning once the midday charge power has
+# stayed drop_percent below its baseline for the last `days` days, an early
+# sign of dirty or shaded panels. The baseline is learned from the preceding
+# baseline_days days as the power of their sunniest ones (the 90th
+# percentile), so cloudy days do not count. Power is only compared while the
+# battery is not full. Checked once a day at the end of the midday window.
+enabled = false
+midday = "10:00-14:00"
+days = 3
+baseline_days = 30
+drop_percent = 30.0
+
+[battery_health]
+# Once a day at the end of the night window, measure how far the battery
+# voltage sags per amp when the load switches (rising with the internal
+# resistance) and how much voltage it loses per hour overnight at rest, with
+# neither load nor charge current above rest_current. Only load steps of at
+# least min_load_step amps count. The Battery tab shows the trend and a health
+# score, and the battery_degraded warning is raised once the score drops below
+# alert_score, 0 to never raise it.
+enabled = true
+night = "01:00-05:00"
+rest_current = 0.1
+min_load_step = 1.0
+alert_score = 0.0
+
+[efficiency]
+# Raise the efficiency_dropping warning once the energy the load consumed per
+# energy harvested over the last `days` days is drop_percent below that of the
+# baseline_days days before, a sign of a failing battery, corroded wiring or
+# a controller problem. Checked once a day after midnight.
+enabled = false
+days = 7
+baseline_days = 30
+drop_percent = 15.0
+
+[records]
+# Keep all-time and monthly records of the highest PV power and charge current
+# and the lowest battery voltage, shown on the Stats tab and by /api/records.
+enabled = true
+
+[tariff]
+# Value the harvested energy at the price of grid power in the daily, weekly
+# and monthly reports. The price of an hour is that of the first time-of-use
+# band it starts in, else price_per_kwh. Repeat [[tariff.bands]] for more
+# bands.
+enabled = false
+currency = "USD"
+price_per_kwh = 0.15
+#[[tariff.bands]]
+#hours = "17:00-21:00"
+#price_per_kwh = 0.35
+
+[report_files]
+# Write a CSV file and an HTML page with charts for every completed period
+# ("day", "week" or "month") of `periods` to `directory`, e.g. to sync or mail
+# them elsewhere. The files of the period just completed are written shortly
+# after it ends, or on the next start if they are missing.
+enabled = false
+directory = "reports"
+periods = ["day", "week"]
+
+[cycles]
+# Count charge/discharge cycles from the amp hours going out of and into the
+# battery, as the controller reports no state of charge. A discharge of at
+# least min_depth_percent of capacity_ah counts as one cycle of its depth once
+# the battery is recharged by as much again, and the count starts over from
+# full whenever the controller reports the battery full. The counts are
+# stored per device and shown by `rust-solar cycles` and the Battery tab.
+enabled = false
+device = "tracer"
+capacity_ah = 100.0
+min_depth_percent = 5.0
+
+[capacity]
+# Estimate the usable capacity of the [cycles] device from the amp hours taken
+# out of the battery from full, as the controller reports it, until the
+# battery voltage stays at or below empty_voltage for a minute, e.g. the low
+# voltage disconnect of the controller. The estimate is the mean of the last
+# three such discharges, and the battery_capacity_low warning is raised once
+# it is below min_capacity_percent of capacity_ah of [cycles].
+enabled = false
+empty_voltage = 11.8
+min_capacity_percent = 80.0
+
+[charge_targets]
+# Show the absorption and float voltages at the measured battery temperature
+# on the Live tab, and how far the battery voltage is from them, to check the
+# controller's settings. They default to the battery_chemistry of
+# [automation]: 14.4 V absorption and 13.6 V float compensated by -30 mV per
+# degree above reference_temp for a 12 V lead-acid battery, 14.2 V and 13.5 V
+# without compensation for lithium. Uncomment to match other batteries.
+enabled = false
+#absorption_voltage = 14.4
+#float_voltage = 13.6
+#compensation_mv_per_c = -30.0
+reference_temp = 25.0
+
+[api]
+# Address the HTTP API listens on when running `serve`.
+bind = "0.0.0.0:8080"
+# Allow switching the load with POST /api/load. Set api_keys as well unless
+# the network is trusted.
+load_control = false
+# Serve HTTPS with this PEM certificate chain and private key.
+#tls_cert = "/etc/rust-solar/cert.pem"
+#tls_key = "/etc/rust-solar/key.pem"
+# When set, every request needs one of these keys as "Authorization: Bearer
+# <key>", "X-API-Key: <key>" or an api_key query parameter.
+api_keys = []
+# Requests per minute allowed from each client address, with bursts of up to
+# rate_limit_burst requests. Further requests are answered with 429, 0
+# disables the limit.
+rate_limit = 300
+rate_limit_burst = 30
+# Log every request (client, method, path, status and duration) at info level.
+log_requests = false
+
+[grpc]
+# gRPC API (proto/solar.proto) served next to the HTTP API by `serve`, needs
+# building with the `grpc` feature. Calls need one of api.api_keys if any.
+enabled = false
+bind = "0.0.0.0:50051"
+# Allow switching the load with the SetLoad call.
+load_control = false
+
+[socket]
+# Unix socket served by `serve`, streaming datapoints as JSON lines and
+# accepting "load on", "load off" and "poll_interval <ms>" commands.
+enabled = true
+path = "/run/solar-rust.sock"
+# File permissions of the socket.
+mode = 0o660
+# Accept the commands, from anyone who can connect, without the control_pin
+# of the [load] section.
+control = false
+
+[fleet]
+# Upload datapoints to a central instance started with --fleet-server.
+enabled = false
+# Base URL of the fleet server, datapoints are posted to <url>/api/fleet/upload.
+url = "http://localhost:8080"
+# Id of this logger in the fleet, and its key from the server's [fleet.devices].
+device_id = "tracer"
+upload_key = ""
+# Number of datapoints per upload.
+batch_size = 60
+# Datapoints kept while the server is unreachable, the oldest are dropped first.
+max_backlog = 100000
+# Database of the fleet server, holding the datapoints of every device.
+database = "solar-fleet.db"
+
+[fleet.devices]
+# Devices allowed to upload to the fleet server, as device id = upload key.
+#cabin = "secret"
+
+[threads]
+# Linux only: scheduling of the thread reading the serial port and of the
+# terminal UI, so database flushes and chart rendering on a single-core board
+# do not delay the reads. Nice values go from -20 (highest) to 19 (lowest),
+# raising the priority needs root or CAP_SYS_NICE. A realtime priority from 1
+# to 99 runs the serial thread with SCHED_FIFO instead, 0 leaves it alone.
+# The cpus pin a thread to the given cores, e.g. [0], empty for any core.
+serial_nice = 0
+serial_realtime_priority = 0
+serial_cpus = []
+ui_nice = 0
+ui_cpus = []
+
+[logging]
+# Log file, written to the working directory unless an absolute path is given.
+file = "solar-rust.log"
+# Levels are one of "off", "error", "warn", "info", "debug" or "trace".
+file_level = "info"
+# Rotate the log file once it exceeds this size in megabytes (0 disables),
+# and/or at the first write of each new day.
+max_size_mb = 10
+rotate_daily = false
+# Number of rotated files to keep (solar-rust.log.1 is the newest).
+keep_files = 5
+# Whether to log to the terminal at all, and at which level.
+terminal = true
+terminal_level = "error"
+# Also send log messages (RFC 5424) to a syslog daemon, either a local socket
+# such as "/dev/log" or "host:514" over UDP.
+syslog = false
+syslog_level = "info"
+syslog_address = "/dev/log"
+# One of "user", "daemon", "local0" ... "local7".
+syslog_facility = "daemon"
+
+[alerts]
+# Values outside of these limits are highlighted in the UI and raise alerts.
+battery_low_voltage = 11.8
+battery_high_voltage = 14.8
+battery_max_temp = 45.0
+load_max_current = 10.0
+# Raise the critical data_stale alert once no valid datapoint was read for this
+# many seconds, but never less than two poll intervals, e.g. with the serial
+# cable unplugged or the controller hung. It is cleared by the next datapoint.
+# 0 disables it.
+stale_after_s = 120
+# Further rules as "[name:] <field> <op> <threshold> [for <duration>] [=> <severity>]",
+# <op> being <, <=, > or >= and <severity> info, warning (the default) or
+# critical. The alert is raised once the condition held for the duration and
+# named <field>_below_<threshold> or <field>_above_<threshold> unless named.
+# <field>/s, <field>/min or <field>/h compares the change of the field over the
+# last second, minute or hour instead, catching failing connections and shading
+# which stay within the limits. "between <HH:MM>-<HH:MM>" limits a rule to
+# those hours.
+rules = [
+    #"battery_voltage < 11.8 for 60s => critical",
+    #"pv_dark: pv_voltage < 1 for 30m => info",
+    #"battery_voltage/min < -0.2 for 2m => critical",
+    #"shading: charge_current/min < -2 between 10:00-15:00",
+]
+"#;
+
+//
+// Structs
+//
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    pub(crate) serial: SerialConfig,
+    pub(crate) database: DatabaseConfig,
+    pub(crate) ui: UiConfig,
+    pub(crate) load: LoadConfig,
+    pub(crate) automation: AutomationConfig,
+    pub(crate) mqtt: MqttConfig,
+    pub(crate) influx: InfluxConfig,
+    pub(crate) remote_write: RemoteWriteConfig,
+    pub(crate) graphite: GraphiteConfig,
+    pub(crate) statsd: StatsdConfig,
+    pub(crate) modbus: ModbusConfig,
+    pub(crate) thingspeak: ThingSpeakConfig,
+    pub(crate) signalk: SignalKConfig,
+    pub(crate) kafka: KafkaConfig,
+    pub(crate) nats: NatsConfig,
+    pub(crate) redis: RedisConfig,
+    pub(crate) webhooks: Vec<WebhookConfig>,
+    pub(crate) hooks: Vec<HookConfig>,
+    pub(crate) email: EmailConfig,
+    pub(crate) telegram: TelegramConfig,
+    pub(crate) discord: DiscordConfig,
+    pub(crate) forecast: ForecastConfig,
+    pub(crate) array: ArrayConfig,
+    pub(crate) weather: WeatherConfig,
+    pub(crate) performance: PerformanceConfig,
+    pub(crate) battery_health: BatteryHealthConfig,
+    pub(crate) efficiency: EfficiencyConfig,
+    pub(crate) records: RecordsConfig,
+    pub(crate) tariff: TariffConfig,
+    pub(crate) report_files: ReportFilesConfig,
+    pub(crate) cycles: CyclesConfig,
+    pub(crate) capacity: CapacityConfig,
+    pub(crate) charge_targets: ChargeTargetsConfig,
+    pub(crate) api: ApiConfig,
+    pub(crate) grpc: GrpcConfig,
+    pub(crate) socket: SocketConfig,
+    pub(crate) fleet: FleetConfig,
+    pub(crate) threads: ThreadsConfig,
+    pub(crate) logging: LoggingConfig,
+    pub(crate) alerts: AlertThresholds,
+    /// Set by `--dry-run`: read and display data without database writes or load commands.
+    #[serde(skip)]
+    pub(crate) dry_run: bool,
+    /// The file the config was loaded from.
+    #[serde(skip)]
+    pub(crate) path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct UiConfig {
+    pub(crate) tick_rate_ms: u64,
+    pub(crate) load_on_label: String,
+    pub(crate) load_off_label: String,
+    pub(crate) bell: bool,
+    pub(crate) desktop_notifications: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LoadBackend {
+    Serial,
+    Gpio,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct LoadConfig {
+    pub(crate) backend: LoadBackend,
+    pub(crate) gpio_chip: PathBuf,
+    pub(crate) gpio_pin: u32,
+    pub(crate) active_low: bool,
+    pub(crate) control_pin: Option<String>,
+    pub(crate) control_secret: Option<String>,
+    pub(crate) control_max_failures: u32,
+    pub(crate) control_max_total_failures: u32,
+    pub(crate) control_lockout_s: u64,
+    pub(crate) name: Option<String>,
+    pub(crate) rated_watts: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct AutomationConfig {
+    pub(crate) lvd_enabled: bool,
+    pub(crate) lvd_voltage: f64,
+    pub(crate) lvd_hold_s: u64,
+    pub(crate) reconnect_enabled: bool,
+    pub(crate) reconnect_voltage: f64,
+    pub(crate) reconnect_hold_s: u64,
+    pub(crate) schedule: Vec<String>,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+    pub(crate) temp_cutoff_enabled: bool,
+    pub(crate) battery_chemistry: BatteryChemistry,
+    pub(crate) temp_cutoff_max: f64,
+    pub(crate) temp_cutoff_min: f64,
+    pub(crate) temp_cutoff_hysteresis: f64,
+    pub(crate) overcurrent_enabled: bool,
+    pub(crate) overcurrent_limit: f64,
+    pub(crate) overcurrent_hold_s: u64,
+    pub(crate) lighting_enabled: bool,
+    pub(crate) dusk_voltage: f64,
+    pub(crate) dawn_voltage: f64,
+    pub(crate) lighting_hold_s: u64,
+    pub(crate) lighting_hours: f64,
+    pub(crate) lighting_min_voltage: f64,
+    pub(crate) script: Option<PathBuf>,
+    pub(crate) script_window: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BatteryChemistry {
+    LeadAcid,
+    Lithium,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct MqttConfig {
+    pub(crate) enabled: bool,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) client_id: String,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    pub(crate) topic_prefix: String,
+    pub(crate) device: String,
+    pub(crate) qos: u8,
+    pub(crate) retain: bool,
+    pub(crate) load_control: bool,
+    pub(crate) load_topic: String,
+    pub(crate) poll_interval_control: bool,
+    pub(crate) poll_interval_topic: String,
+    pub(crate) tls: bool,
+    pub(crate) ca_cert: Option<PathBuf>,
+    pub(crate) client_cert: Option<PathBuf>,
+    pub(crate) client_key: Option<PathBuf>,
+    pub(crate) shadow_thing: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct InfluxConfig {
+    pub(crate) enabled: bool,
+    pub(crate) url: String,
+    pub(crate) token: Option<String>,
+    pub(crate) measurement: String,
+    pub(crate) device: String,
+    pub(crate) batch_size: usize,
+    pub(crate) max_backlog: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct RemoteWriteConfig {
+    pub(crate) enabled: bool,
+    pub(crate) url: String,
+    pub(crate) username: String,
+    pub(crate) password: String,
+    pub(crate) token: String,
+    pub(crate) metric_prefix: String,
+    pub(crate) device: String,
+    pub(crate) batch_size: usize,
+    pub(crate) max_backlog: usize,
+    pub(crate) wal_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct GraphiteConfig {
+    pub(crate) enabled: bool,
+    pub(crate) address: String,
+    pub(crate) prefix: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct StatsdConfig {
+    pub(crate) enabled: bool,
+    pub(crate) address: String,
+    pub(crate) prefix: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ModbusConfig {
+    pub(crate) enabled: bool,
+    pub(crate) bind: SocketAddr,
+    pub(crate) unit_id: u8,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct KafkaConfig {
+    pub(crate) enabled: bool,
+    pub(crate) brokers: String,
+    pub(crate) topic: String,
+    pub(crate) device: String,
+    pub(crate) client_id: String,
+    pub(crate) message_timeout_ms: u64,
+    pub(crate) properties: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct NatsConfig {
+    pub(crate) enabled: bool,
+    pub(crate) url: String,
+    pub(crate) subject: String,
+    pub(crate) format: NatsFormat,
+    pub(crate) user: String,
+    pub(crate) password: String,
+    pub(crate) token: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum NatsFormat {
+    /// The whole datapoint as JSON on `subject`.
+    Json,
+    /// Each value on `<subject>.<field>`.
+    Fields,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct RedisConfig {
+    pub(crate) enabled: bool,
+    pub(crate) address: String,
+    pub(crate) username: String,
+    pub(crate) password: String,
+    pub(crate) key_prefix: String,
+    pub(crate) device: String,
+    pub(crate) retention_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct SignalKConfig {
+    pub(crate) enabled: bool,
+    pub(crate) url: String,
+    pub(crate) token: String,
+    pub(crate) battery_id: String,
+    pub(crate) solar_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ThingSpeakConfig {
+    pub(crate) enabled: bool,
+    pub(crate) api_key: String,
+    pub(crate) url: String,
+    pub(crate) fields: Vec<String>,
+    pub(crate) min_interval_s: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct WebhookConfig {
+    pub(crate) url: String,
+    pub(crate) events: Vec<String>,
+    pub(crate) min_severity: Severity,
+    pub(crate) escalate_after_s: u64,
+    pub(crate) quiet_hours: Option<String>,
+    pub(crate) repeat_after_s: u64,
+    pub(crate) max_per_hour: u32,
+    pub(crate) template: Option<String>,
+    pub(crate) content_type: String,
+    pub(crate) retries: u32,
+    pub(crate) retry_delay_s: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct HookConfig {
+    pub(crate) command: String,
+    pub(crate) args: Vec<String>,
+    pub(crate) events: Vec<String>,
+    pub(crate) min_severity: Severity,
+    pub(crate) escalate_after_s: u64,
+    pub(crate) quiet_hours: Option<String>,
+    pub(crate) repeat_after_s: u64,
+    pub(crate) max_per_hour: u32,
+    pub(crate) timeout_s: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SmtpSecurity {
+    None,
+    Starttls,
+    Tls,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct EmailConfig {
+    pub(crate) enabled: bool,
+    pub(crate) server: String,
+    pub(crate) port: u16,
+    pub(crate) security: SmtpSecurity,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    pub(crate) from: String,
+    pub(crate) to: Vec<String>,
+    pub(crate) events: Vec<String>,
+    pub(crate) min_severity: Severity,
+    pub(crate) escalate_after_s: u64,
+    pub(crate) quiet_hours: Option<String>,
+    pub(crate) repeat_after_s: u64,
+    pub(crate) max_per_hour: u32,
+    pub(crate) subject: String,
+    pub(crate) body: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct TelegramConfig {
+    pub(crate) enabled: bool,
+    pub(crate) bot_token: String,
+    pub(crate) chat_id: String,
+    pub(crate) events: Vec<String>,
+    pub(crate) min_severity: Severity,
+    pub(crate) escalate_after_s: u64,
+    pub(crate) quiet_hours: Option<String>,
+    pub(crate) repeat_after_s: u64,
+    pub(crate) max_per_hour: u32,
+    pub(crate) template: String,
+    pub(crate) api_url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct DiscordConfig {
+    pub(crate) enabled: bool,
+    pub(crate) webhook_url: String,
+    pub(crate) username: String,
+    pub(crate) events: Vec<String>,
+    pub(crate) min_severity: Severity,
+    pub(crate) escalate_after_s: u64,
+    pub(crate) quiet_hours: Option<String>,
+    pub(crate) repeat_after_s: u64,
+    pub(crate) max_per_hour: u32,
+    pub(crate) template: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ForecastProvider {
+    ForecastSolar,
+    Solcast,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ForecastConfig {
+    pub(crate) enabled: bool,
+    pub(crate) provider: ForecastProvider,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+    pub(crate) declination: f64,
+    pub(crate) azimuth: f64,
+    pub(crate) kwp: f64,
+    pub(crate) api_key: Option<String>,
+    pub(crate) resource_id: String,
+    pub(crate) refresh_interval_min: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ArrayConfig {
+    pub(crate) enabled: bool,
+    pub(crate) losses_percent: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct WeatherConfig {
+    pub(crate) enabled: bool,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+    pub(crate) past_days: u32,
+    pub(crate) refresh_interval_min: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct PerformanceConfig {
+    pub(crate) enabled: bool,
+    pub(crate) midday: String,
+    pub(crate) days: u32,
+    pub(crate) baseline_days: u32,
+    pub(crate) drop_percent: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct BatteryHealthConfig {
+    pub(crate) enabled: bool,
+    pub(crate) night: String,
+    pub(crate) rest_current: f64,
+    pub(crate) min_load_step: f64,
+    pub(crate) alert_score: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct EfficiencyConfig {
+    pub(crate) enabled: bool,
+    pub(crate) days: u32,
+    pub(crate) baseline_days: u32,
+    pub(crate) drop_percent: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct RecordsConfig {
+    pub(crate) enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct TariffConfig {
+    pub(crate) enabled: bool,
+    pub(crate) currency: String,
+    pub(crate) price_per_kwh: f64,
+    pub(crate) bands: Vec<TariffBand>,
+}
+
+/// A time-of-use band of a tariff.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct TariffBand {
+    /// `<HH:MM>-<HH:MM>`, may span midnight.
+    pub(crate) hours: String,
+    pub(crate) price_per_kwh: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ReportFilesConfig {
+    pub(crate) enabled: bool,
+    pub(crate) directory: PathBuf,
+    pub(crate) periods: Vec<ReportPeriod>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct CyclesConfig {
+    pub(crate) enabled: bool,
+    pub(crate) device: String,
+    pub(crate) capacity_ah: f64,
+    pub(crate) min_depth_percent: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct CapacityConfig {
+    pub(crate) enabled: bool,
+    pub(crate) empty_voltage: f64,
+    pub(crate) min_capacity_percent: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ChargeTargetsConfig {
+    pub(crate) enabled: bool,
+    pub(crate) absorption_voltage: Option<f64>,
+    pub(crate) float_voltage: Option<f64>,
+    pub(crate) compensation_mv_per_c: Option<f64>,
+    pub(crate) reference_temp: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct ApiConfig {
+    pub(crate) bind: SocketAddr,
+    pub(crate) load_control: bool,
+    pub(crate) tls_cert: Option<PathBuf>,
+    pub(crate) tls_key: Option<PathBuf>,
+    pub(crate) api_keys: Vec<String>,
+    pub(crate) rate_limit: u32,
+    pub(crate) rate_limit_burst: u32,
+    pub(crate) log_requests: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct GrpcConfig {
+    pub(crate) enabled: bool,
+    pub(crate) bind: SocketAddr,
+    pub(crate) load_control: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct SocketConfig {
+    pub(crate) enabled: bool,
+    pub(crate) path: PathBuf,
+    pub(crate) mode: u32,
+    pub(crate) control: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct FleetConfig {
+    pub(crate) enabled: bool,
+    pub(crate) url: String,
+    pub(crate) device_id: String,
+    pub(crate) upload_key: String,
+    pub(crate) batch_size: usize,
+    pub(crate) max_backlog: usize,
+    pub(crate) database: PathBuf,
+    pub(crate) devices: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ThreadsConfig {
+    pub(crate) serial_nice: i32,
+    pub(crate) serial_realtime_priority: u32,
+    pub(crate) serial_cpus: Vec<usize>,
+    pub(crate) ui_nice: i32,
+    pub(crate) ui_cpus: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct LoggingConfig {
+    pub(crate) file: PathBuf,
+    pub(crate) file_level: LevelFilter,
+    pub(crate) max_size_mb: u64,
+    pub(crate) rotate_daily: bool,
+    pub(crate) keep_files: usize,
+    pub(crate) terminal: bool,
+    pub(crate) terminal_level: LevelFilter,
+    pub(crate) syslog: bool,
+    pub(crate) syslog_level: LevelFilter,
+    pub(crate) syslog_address: String,
+    pub(crate) syslog_facility: SyslogFacility,
+}
+
+/// Syslog facilities which make sense for an application, with their codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SyslogFacility {
+    User = 1,
+    Daemon = 3,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+//
+// implementations
+//
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate_ms: 25,
+            load_on_label: String::from("ON"),
+            load_off_label: String::from("OFF"),
+            bell: false,
+            desktop_notifications: false,
+        }
+    }
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            backend: LoadBackend::Serial,
+            gpio_chip: PathBuf::from("/dev/gpiochip0"),
+            gpio_pin: 17,
+            active_low: false,
+            control_pin: None,
+            control_secret: None,
+            control_max_failures: 5,
+            control_max_total_failures: 20,
+            control_lockout_s: 300,
+            name: None,
+            rated_watts: None,
+        }
+    }
+}
+
+impl Default for AutomationConfig {
+    fn default() -> Self {
+        Self {
+            lvd_enabled: false,
+            lvd_voltage: 11.5,
+            lvd_hold_s: 60,
+            reconnect_enabled: false,
+            reconnect_voltage: 12.6,
+            reconnect_hold_s: 600,
+            schedule: Vec::new(),
+            latitude: 0.0,
+            longitude: 0.0,
+            temp_cutoff_enabled: false,
+            battery_chemistry: BatteryChemistry::LeadAcid,
+            temp_cutoff_max: 50.0,
+            temp_cutoff_min: 0.0,
+            temp_cutoff_hysteresis: 3.0,
+            overcurrent_enabled: false,
+            overcurrent_limit: 10.0,
+            overcurrent_hold_s: 5,
+            lighting_enabled: false,
+            dusk_voltage: 5.0,
+            dawn_voltage: 8.0,
+            lighting_hold_s: 600,
+            lighting_hours: 0.0,
+            lighting_min_voltage: 12.0,
+            script: None,
+            script_window: 60,
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::from("localhost"),
+            port: 1883,
+            client_id: String::from("rust-solar"),
+            username: None,
+            password: None,
+            topic_prefix: String::from("solar"),
+            device: String::from("tracer"),
+            qos: 0,
+            retain: false,
+            load_control: false,
+            load_topic: String::from("load/set"),
+            poll_interval_control: false,
+            poll_interval_topic: String::from("poll_interval/set"),
+            tls: false,
+            ca_cert: None,
+            client_cert: None,
+            client_key: None,
+            shadow_thing: String::new(),
+        }
+    }
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::from("http://localhost:8086/write?db=solar&precision=s"),
+            token: None,
+            measurement: String::from("solar"),
+            device: String::from("tracer"),
+            batch_size: 10,
+            max_backlog: 100_000,
+        }
+    }
+}
+
+impl Default for RemoteWriteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::from("http://localhost:9090/api/v1/write"),
+            username: String::new(),
+            password: String::new(),
+            token: String::new(),
+            metric_prefix: String::from("solar_"),
+            device: String::from("tracer"),
+            batch_size: 60,
+            max_backlog: 100_000,
+            wal_path: Some(PathBuf::from("solar-remote-write.wal")),
+        }
+    }
+}
+
+impl Default for GraphiteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::from("localhost:2003"),
+            prefix: String::from("solar.tracer"),
+        }
+    }
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::from("localhost:8125"),
+            prefix: String::from("solar.tracer"),
+        }
+    }
+}
+
+impl Default for ModbusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: SocketAddr::from(([0, 0, 0, 0], 5020)),
+            unit_id: 1,
+        }
+    }
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            brokers: String::from("localhost:9092"),
+            topic: String::from("solar"),
+            device: String::from("tracer"),
+            client_id: String::from("rust-solar"),
+            message_timeout_ms: 300_000,
+            properties: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for NatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::from("nats://localhost:4222"),
+            subject: String::from("solar.tracer"),
+            format: NatsFormat::Json,
+            user: String::new(),
+            password: String::new(),
+            token: String::new(),
+        }
+    }
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: String::from("localhost:6379"),
+            username: String::new(),
+            password: String::new(),
+            key_prefix: String::from("solar"),
+            device: String::from("tracer"),
+            retention_ms: 7 * 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+impl Default for SignalKConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::from("ws://localhost:3000/signalk/v1/stream?subscribe=none"),
+            token: String::new(),
+            battery_id: String::from("house"),
+            solar_id: String::from("tracer"),
+        }
+    }
+}
+
+impl Default for ThingSpeakConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api_key: String::new(),
+            url: String::from("https://api.thingspeak.com/update"),
+            fields: [
+                "battery_voltage",
+                "pv_voltage",
+                "load_current",
+                "charge_current",
+                "battery_temp",
+            ]
+            .map(String::from)
+            .to_vec(),
+            min_interval_s: 20,
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            events: Vec::new(),
+            min_severity: Severity::Info,
+            escalate_after_s: 0,
+            quiet_hours: None,
+            repeat_after_s: 0,
+            max_per_hour: 0,
+            template: None,
+            content_type: String::from("application/json"),
+            retries: 3,
+            retry_delay_s: 10,
+        }
+    }
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self {
+            command: String::new(),
+            args: Vec::new(),
+            events: Vec::new(),
+            min_severity: Severity::Info,
+            escalate_after_s: 0,
+            quiet_hours: None,
+            repeat_after_s: 0,
+            max_per_hour: 0,
+            timeout_s: 30,
+        }
+    }
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            server: String::from("smtp.example.com"),
+            port: 587,
+            security: SmtpSecurity::Starttls,
+            username: None,
+            password: None,
+            from: String::from("rust-solar <solar@example.com>"),
+            to: Vec::new(),
+            events: Vec::new(),
+            min_severity: Severity::Info,
+            escalate_after_s: 0,
+            quiet_hours: None,
+            repeat_after_s: 0,
+            max_per_hour: 0,
+            subject: String::from("[rust-solar] {{message}}"),
+            body: String::from("{{message}} at {{time}}."),
+        }
+    }
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bot_token: String::new(),
+            chat_id: String::new(),
+            events: Vec::new(),
+            min_severity: Severity::Info,
+            escalate_after_s: 0,
+            quiet_hours: None,
+            repeat_after_s: 0,
+            max_per_hour: 0,
+            template: String::from("{{message}}"),
+            api_url: String::from("https://api.telegram.org"),
+        }
+    }
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            webhook_url: String::new(),
+            username: String::from("rust-solar"),
+            events: Vec::new(),
+            min_severity: Severity::Info,
+            escalate_after_s: 0,
+            quiet_hours: None,
+            repeat_after_s: 0,
+            max_per_hour: 0,
+            template: String::from("{{message}}"),
+        }
+    }
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: ForecastProvider::ForecastSolar,
+            latitude: 0.0,
+            longitude: 0.0,
+            declination: 30.0,
+            azimuth: 0.0,
+            kwp: 0.1,
+            api_key: None,
+            resource_id: String::new(),
+            refresh_interval_min: 180,
+        }
+    }
+}
+
+impl Default for ArrayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            losses_percent: 14.0,
+        }
+    }
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            latitude: 0.0,
+            longitude: 0.0,
+            past_days: 2,
+            refresh_interval_min: 60,
+        }
+    }
+}
+
+impl Default for PerformanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            midday: String::from("10:00-14:00"),
+            days: 3,
+            baseline_days: 30,
+            drop_percent: 30.0,
+        }
+    }
+}
+
+impl Default for BatteryHealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            night: String::from("01:00-05:00"),
+            rest_current: 0.1,
+            min_load_step: 1.0,
+            alert_score: 0.0,
+        }
+    }
+}
+
+impl Default for EfficiencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            days: 7,
+            baseline_days: 30,
+            drop_percent: 15.0,
+        }
+    }
+}
+
+impl Default for RecordsConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+impl Default for TariffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            currency: String::from("USD"),
+            price_per_kwh: 0.15,
+            bands: Vec::new(),
+        }
+    }
+}
+
+impl Default for ReportFilesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: PathBuf::from("reports"),
+            periods: vec![ReportPeriod::Day, ReportPeriod::Week],
+        }
+    }
+}
+
+impl Default for CyclesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: String::from("tracer"),
+            capacity_ah: 100.0,
+            min_depth_percent: 5.0,
+        }
+    }
+}
+
+impl Default for CapacityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            empty_voltage: 11.8,
+            min_capacity_percent: 80.0,
+        }
+    }
+}
+
+impl Default for ChargeTargetsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            absorption_voltage: None,
+            float_voltage: None,
+            compensation_mv_per_c: None,
+            reference_temp: 25.0,
+        }
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            load_control: false,
+            tls_cert: None,
+            tls_key: None,
+            api_keys: Vec::new(),
+            rate_limit: 300,
+            rate_limit_burst: 30,
+            log_requests: false,
+        }
+    }
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: SocketAddr::from(([0, 0, 0, 0], 50051)),
+            load_control: false,
+        }
+    }
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from("/run/solar-rust.sock"),
+            mode: 0o660,
+            control: false,
+        }
+    }
+}
+
+impl Default for FleetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::from("http://localhost:8080"),
+            device_id: String::from("tracer"),
+            upload_key: String::new(),
+            batch_size: 60,
+            max_backlog: 100_000,
+            database: PathBuf::from("solar-fleet.db"),
+            devices: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("solar-rust.log"),
+            file_level: LevelFilter::Info,
+            max_size_mb: 10,
+            rotate_daily: false,
+            keep_files: 5,
+            terminal: true,
+            terminal_level: LevelFilter::Error,
+            syslog: false,
+            syslog_level: LevelFilter::Info,
+            syslog_address: String::from("/dev/log"),
+            syslog_facility: SyslogFacility::Daemon,
+        }
+    }
+}
+
+impl Config {
+    ///
+    /// The default location of the config file, `$XDG_CONFIG_HOME/rust-solar/config.toml`
+    /// on Linux and `%APPDATA%\rust-solar\config.toml` on Windows.
+    ///
+    pub(crate) fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(CONFIG_DIR_NAME)
+            .join(CONFIG_FILENAME)
+    }
+
+    ///
+    /// Load the config from the given path. A commented default config is
+    /// written out if the file does not exist yet.
+    ///
+    /// This runs before logging is set up, so it must not log.
+    ///
+    pub(crate) fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, DEFAULT_CONFIG)?;
+        }
+        let contents = fs::read_to_string(path)?;
+        let mut config: Config = toml::from_str(&contents)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        config.path = path.to_path_buf();
+        Ok(config)
+    }
+}
+
+///
+/// Write the fixed limits to the `[alerts]` section of a config file,
+/// keeping its comments and other settings.
+///
+pub(crate) fn save_alert_limits(
+    thresholds: &AlertThresholds,
+    path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut document: toml_edit::DocumentMut = fs::read_to_string(path)?.parse()?;
+    let alerts = &mut document["alerts"];
+    alerts["battery_low_voltage"] = toml_edit::value(thresholds.battery_low_voltage);
+    alerts["battery_high_voltage"] = toml_edit::value(thresholds.battery_high_voltage);
+    alerts["battery_max_temp"] = toml_edit::value(thresholds.battery_max_temp);
+    alerts["load_max_current"] = toml_edit::value(thresholds.load_max_current);
+    fs::write(path, document.to_string())?;
+    Ok(())
+}
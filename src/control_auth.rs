@@ -0,0 +1,345 @@
+use crate::api::constant_time_eq;
+use crate::config::LoadConfig;
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use utoipa::ToSchema;
+
+//
+// Constants
+//
+/// Seconds a signed token is valid before and after its time.
+const TOKEN_VALIDITY: i64 = 5 * 60;
+
+//
+// Structs
+//
+/// PIN or signed token sent with a load command over the network.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub(crate) struct Credentials {
+    /// The `load.control_pin`.
+    pub(crate) pin: Option<String>,
+    /// Hex HMAC-SHA256 of `<on|off>:<time>` with `load.control_secret`.
+    pub(crate) token: Option<String>,
+    /// Unix seconds the token was made at.
+    pub(crate) time: Option<i64>,
+}
+
+/// Checks the PIN or signed token of load commands arriving over the
+/// network, locking a client out after repeated failures, and every client
+/// after too many failures of all of them.
+pub(crate) struct ControlAuth {
+    pin: Option<String>,
+    secret: Option<String>,
+    max_failures: u32,
+    max_total_failures: u32,
+    lockout: Duration,
+    failures: Mutex<FailureLog>,
+    /// Tokens accepted within their validity, which must not be replayed.
+    used_tokens: Mutex<VecDeque<(i64, String)>>,
+}
+
+#[derive(Debug, Default)]
+struct FailureLog {
+    /// Clients which failed or are locked out within the lockout duration.
+    clients: HashMap<String, Failures>,
+    /// Times of the failures of all clients within the lockout duration.
+    recent: VecDeque<Instant>,
+    /// End of the lockout of every client.
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Failures {
+    count: u32,
+    last: Option<Instant>,
+    locked_until: Option<Instant>,
+}
+
+//
+// implementations
+//
+impl ControlAuth {
+    pub(crate) fn new(config: &LoadConfig) -> Self {
+        let nonempty = |s: &Option<String>| s.clone().filter(|s| !s.is_empty());
+        let auth = Self {
+            pin: nonempty(&config.control_pin),
+            secret: nonempty(&config.control_secret),
+            max_failures: config.control_max_failures.max(1),
+            max_total_failures: config.control_max_total_failures.max(1),
+            lockout: Duration::from_secs(config.control_lockout_s),
+            failures: Mutex::new(FailureLog::default()),
+            used_tokens: Mutex::new(VecDeque::new()),
+        };
+        if auth.required() {
+            info!("Load commands over the network need the control PIN or a signed token.");
+        }
+        auth
+    }
+
+    ///
+    /// Whether load commands need credentials.
+    ///
+    pub(crate) fn required(&self) -> bool {
+        self.pin.is_some() || self.secret.is_some()
+    }
+
+    ///
+    /// Check the credentials of a command switching the load `on` or off
+    /// from `client`, logging failures. The error is the reason to refuse it.
+    ///
+    pub(crate) fn check(
+        &self,
+        on: bool,
+        credentials: &Credentials,
+        client: &str,
+    ) -> Result<(), String> {
+        if !self.required() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut log = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        log.prune(now, self.lockout);
+        if let Some(until) = log.locked_until {
+            warn!(
+                "Refused load command from {} during the lockout of all clients.",
+                client
+            );
+            return Err(format!(
+                "Too many failed attempts, retry in {} s.",
+                (until - now).as_secs() + 1
+            ));
+        }
+        let entry = log.clients.entry(client.to_string()).or_default();
+        if let Some(until) = entry.locked_until {
+            warn!("Refused load command from {} during its lockout.", client);
+            return Err(format!(
+                "Too many failed attempts, retry in {} s.",
+                (until - now).as_secs() + 1
+            ));
+        }
+        let result = self.verify(on, credentials);
+        match &result {
+            Ok(()) => entry.count = 0,
+            Err(e) => {
+                entry.count += 1;
+                entry.last = Some(now);
+                warn!(
+                    "Refused load command from {}: {} ({} of {} attempts).",
+                    client, e, entry.count, self.max_failures
+                );
+                if entry.count >= self.max_failures {
+                    entry.count = 0;
+                    entry.locked_until = Some(now + self.lockout);
+                    warn!(
+                        "Locking {} out of load control for {} s.",
+                        client,
+                        self.lockout.as_secs()
+                    );
+                }
+                log.recent.push_back(now);
+                if log.recent.len() >= self.max_total_failures as usize {
+                    log.recent.clear();
+                    log.locked_until = Some(now + self.lockout);
+                    warn!(
+                        "{} failed load commands within {} s, locking every client out of load control.",
+                        self.max_total_failures,
+                        self.lockout.as_secs()
+                    );
+                }
+            }
+        }
+        log.prune(now, self.lockout);
+        result
+    }
+
+    fn verify(&self, on: bool, credentials: &Credentials) -> Result<(), String> {
+        if let (Some(pin), Some(given)) = (&self.pin, &credentials.pin) {
+            return match constant_time_eq(pin, given) {
+                true => Ok(()),
+                false => Err("wrong PIN".to_string()),
+            };
+        }
+        let (Some(secret), Some(token), Some(time)) =
+            (&self.secret, &credentials.token, credentials.time)
+        else {
+            return Err(match self.secret {
+                Some(_) => "missing PIN or signed token and time".to_string(),
+                None => "missing PIN".to_string(),
+            });
+        };
+        let now = Local::now().timestamp();
+        if (now - time).abs() > TOKEN_VALIDITY {
+            return Err("expired token".to_string());
+        }
+        let state = if on { "on" } else { "off" };
+        let expected = sign(secret.as_bytes(), &format!("{}:{}", state, time))?;
+        if !constant_time_eq(&expected, &token.to_lowercase()) {
+            return Err("invalid token".to_string());
+        }
+        let mut used = self
+            .used_tokens
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        used.retain(|(time, _)| (now - time).abs() <= TOKEN_VALIDITY);
+        if used.iter().any(|(_, t)| *t == expected) {
+            return Err("replayed token".to_string());
+        }
+        used.push_back((time, expected));
+        Ok(())
+    }
+}
+
+impl FailureLog {
+    ///
+    /// Forget failures older than the lockout duration and lockouts which
+    /// have expired, so clients which stopped trying are not kept.
+    ///
+    fn prune(&mut self, now: Instant, lockout: Duration) {
+        let recent = |time: Instant| now.duration_since(time) < lockout;
+        self.locked_until = self.locked_until.filter(|until| now < *until);
+        while self.recent.front().is_some_and(|time| !recent(*time)) {
+            self.recent.pop_front();
+        }
+        self.clients.retain(|_, failures| {
+            failures.locked_until = failures.locked_until.filter(|until| now < *until);
+            if !failures.last.is_some_and(recent) {
+                failures.count = 0;
+            }
+            failures.count > 0 || failures.locked_until.is_some()
+        });
+    }
+}
+
+///
+/// The hex HMAC-SHA256 of `message` with `key`.
+///
+fn sign(key: &[u8], message: &str) -> Result<String, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| e.to_string())?;
+    mac.update(message.as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control_auth(lockout_s: u64) -> ControlAuth {
+        ControlAuth::new(&LoadConfig {
+            control_pin: Some("1234".to_string()),
+            control_secret: Some("secret".to_string()),
+            control_max_failures: 3,
+            control_max_total_failures: 5,
+            control_lockout_s: lockout_s,
+            ..LoadConfig::default()
+        })
+    }
+
+    fn pin(pin: &str) -> Credentials {
+        Credentials {
+            pin: Some(pin.to_string()),
+            ..Credentials::default()
+        }
+    }
+
+    fn token(on: bool, time: i64) -> Credentials {
+        let state = if on { "on" } else { "off" };
+        Credentials {
+            token: sign(b"secret", &format!("{}:{}", state, time)).ok(),
+            time: Some(time),
+            ..Credentials::default()
+        }
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        let long_key = [0xaa; 131];
+        let vectors: [(&[u8], &str, &str); 4] = [
+            (
+                &[0x0b; 20],
+                "Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                "what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &long_key,
+                "Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &long_key,
+                "This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, message, expected) in vectors {
+            assert_eq!(sign(key, message).as_deref(), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn pin_is_checked() {
+        let auth = control_auth(300);
+        assert!(auth.check(true, &pin("1234"), "a").is_ok());
+        assert!(auth.check(true, &pin("4321"), "a").is_err());
+        assert!(auth.check(true, &Credentials::default(), "a").is_err());
+    }
+
+    #[test]
+    fn token_is_accepted_once_for_its_state() {
+        let auth = control_auth(300);
+        let now = Local::now().timestamp();
+        assert!(auth.check(false, &token(true, now), "a").is_err());
+        assert!(auth.check(true, &token(true, now), "a").is_ok());
+        assert!(auth.check(true, &token(true, now), "a").is_err());
+        let old = now - TOKEN_VALIDITY - 1;
+        assert!(auth.check(true, &token(true, old), "a").is_err());
+    }
+
+    #[test]
+    fn client_is_locked_out_after_max_failures() {
+        let auth = control_auth(300);
+        for _ in 0..3 {
+            assert!(auth.check(true, &pin("0000"), "a").is_err());
+        }
+        assert!(auth.check(true, &pin("1234"), "a").is_err());
+        assert!(auth.check(true, &pin("1234"), "b").is_ok());
+    }
+
+    #[test]
+    fn every_client_is_locked_out_after_max_total_failures() {
+        let auth = control_auth(300);
+        for client in ["a", "b", "c", "d", "e"] {
+            assert!(auth.check(true, &pin("0000"), client).is_err());
+        }
+        assert!(auth.check(true, &pin("1234"), "f").is_err());
+    }
+
+    #[test]
+    fn failures_are_forgotten() {
+        let auth = control_auth(300);
+        assert!(auth.check(true, &pin("0000"), "a").is_err());
+        assert!(auth.check(true, &pin("1234"), "a").is_ok());
+        assert!(auth.failures.lock().unwrap().clients.is_empty());
+
+        // Failures and lockouts which expire at once are not kept.
+        let auth = control_auth(0);
+        for client in ["a", "b", "c", "d", "e", "f"] {
+            for _ in 0..3 {
+                let _ = auth.check(true, &pin("0000"), client);
+            }
+        }
+        let log = auth.failures.lock().unwrap();
+        assert!(log.clients.is_empty() && log.recent.is_empty() && log.locked_until.is_none());
+    }
+}
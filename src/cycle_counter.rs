@@ -0,0 +1,134 @@
+use crate::config::{Config, CyclesConfig};
+use chrono::Local;
+use solar_core::cycles::CycleState;
+use solar_core::database::Database;
+use solar_core::datapoint::DataPoint;
+use std::{io, time::Duration};
+
+//
+// Constants
+//
+/// Longer intervals between datapoints are not counted, the current in
+/// between is unknown.
+const MAX_INTERVAL: Duration = Duration::from_secs(60);
+/// Seconds between stores of the state while no cycle completes.
+const STORE_INTERVAL: i64 = 300;
+
+//
+// Structs
+//
+/// Counts the charge/discharge cycles of the battery from the amp hours going
+/// out of and into it. A discharge of at least `min_depth_percent` of the
+/// capacity counts as a cycle of its depth once the battery is recharged by
+/// as much again.
+pub(crate) struct CycleCounter {
+    config: CyclesConfig,
+    database: Database,
+    state: CycleState,
+    /// Time the state was last stored.
+    stored: i64,
+}
+
+//
+// implementations
+//
+impl CycleCounter {
+    ///
+    /// Check the settings of `[cycles]` and continue from the state stored
+    /// for the device, if any.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Self> {
+        let settings = &config.cycles;
+        if settings.capacity_ah <= 0.0
+            || settings.min_depth_percent <= 0.0
+            || settings.min_depth_percent >= 100.0
+        {
+            return Err(io::Error::other(
+                "cycles.capacity_ah must be positive and cycles.min_depth_percent between 0 and 100.",
+            ));
+        }
+        let database = Database::new(&config.database)?;
+        let now = Local::now().timestamp();
+        let state = match database.cycle_state(&settings.device) {
+            Ok(Some(state)) => state,
+            Ok(None) => CycleState::new(now),
+            Err(e) => {
+                warn!("{}", e);
+                CycleState::new(now)
+            }
+        };
+        info!(
+            "Counting charge cycles of {} against {} Ah, {:.1} Ah below full.",
+            settings.device, settings.capacity_ah, state.deficit_ah
+        );
+        Ok(Self {
+            config: settings.clone(),
+            database,
+            state,
+            stored: now,
+        })
+    }
+
+    ///
+    /// Count the amp hours since the datapoint before, `interval` ago, and
+    /// store the cycle once a discharge is over.
+    ///
+    pub(crate) fn check(&mut self, datapoint: &DataPoint, interval: Option<Duration>) {
+        let time = datapoint.get_time();
+        let state = &mut self.state;
+        if let Some(interval) = interval.filter(|i| *i <= MAX_INTERVAL) {
+            let hours = interval.as_secs_f64() / 3600.0;
+            // Positive while the load draws more than the panels charge.
+            let net = datapoint.get_load_current() - datapoint.get_charge_current();
+            state.discharged_ah += net.max(0.0) * hours;
+            state.deficit_ah = (state.deficit_ah + net * hours).max(0.0);
+        }
+        if datapoint.get_battery_full() > 0.0 {
+            state.deficit_ah = 0.0;
+        }
+        let capacity = self.config.capacity_ah;
+        let hysteresis = capacity * self.config.min_depth_percent / 100.0;
+        let mut cycle = None;
+        if state.discharging {
+            state.bottom_ah = state.bottom_ah.max(state.deficit_ah);
+            if state.deficit_ah < state.bottom_ah - hysteresis {
+                let depth = (state.bottom_ah - state.top_ah) / capacity * 100.0;
+                info!(
+                    "Charge cycle of {:.0}% depth of discharge ({:.1} Ah).",
+                    depth,
+                    state.bottom_ah - state.top_ah
+                );
+                cycle = Some((state.since, time, depth));
+                state.discharging = false;
+                state.top_ah = state.deficit_ah;
+            }
+        } else {
+            state.top_ah = state.top_ah.min(state.deficit_ah);
+            if state.deficit_ah > state.top_ah + hysteresis {
+                state.discharging = true;
+                state.bottom_ah = state.deficit_ah;
+                state.since = time;
+            }
+        }
+        if cycle.is_some() || time - self.stored >= STORE_INTERVAL {
+            self.save(cycle, time);
+        }
+    }
+
+    fn save(&mut self, cycle: Option<(i64, i64, f64)>, time: i64) {
+        self.stored = time;
+        self.database.store_cycle_state(
+            &self.config.device,
+            self.config.capacity_ah,
+            &self.state,
+            cycle,
+            time,
+        );
+    }
+}
+
+impl Drop for CycleCounter {
+    fn drop(&mut self) {
+        self.save(None, Local::now().timestamp());
+    }
+}
@@ -0,0 +1,1114 @@
+use crate::automation::LoadAutomation;
+use crate::battery_health::BatteryHealthMonitor;
+use crate::capacity::CapacityEstimator;
+use crate::config::{AlertThresholds, Config, LoadBackend};
+use crate::control_auth::ControlAuth;
+use crate::cycle_counter::CycleCounter;
+use crate::efficiency::EfficiencyMonitor;
+use crate::fleet::FleetUploader;
+use crate::forecast;
+use crate::gpio_relay::GpioRelay;
+use crate::graphite::{GraphiteWriter, StatsdWriter};
+use crate::influx::InfluxWriter;
+use crate::instance_lock::InstanceLock;
+use crate::kafka::KafkaProducer;
+use crate::modbus::ModbusServer;
+use crate::mqtt::MqttPublisher;
+use crate::nats::NatsPublisher;
+use crate::notify::Notifier;
+use crate::performance::PerformanceMonitor;
+use crate::priority;
+use crate::records::RecordTracker;
+use crate::redis::RedisWriter;
+use crate::remote_write::RemoteWriteWriter;
+use crate::report_files;
+use crate::signalk::SignalKWriter;
+use crate::thingspeak::ThingSpeakWriter;
+use crate::weather;
+use chrono::Local;
+use solar_core::alerts::{AlertMonitor, Event, EventKind};
+use solar_core::datapoint::DataPoint;
+use solar_core::db_writer::DbWriter;
+use solar_core::queue::{self, QueueReceiver, QueueSender};
+use solar_core::recent::{RecentData, RECENT_SAMPLES};
+use solar_core::serial_data_logger::SerialDatalogger;
+use solar_core::shutdown::Shutdown;
+use solar_core::supervisor::{self, Backoff};
+use solar_core::timings::TIMINGS;
+use std::{
+    fmt, io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    time::{interval_at, sleep, Instant, Interval, MissedTickBehavior},
+};
+
+//
+// Constants
+//
+/// Bounds for poll intervals set at runtime.
+pub(crate) const MIN_POLL_INTERVAL_MS: u64 = 100;
+pub(crate) const MAX_POLL_INTERVAL_MS: u64 = 60 * 60 * 1000;
+/// Datapoints and events queued per subscriber of the event bus, one falling
+/// behind misses the oldest.
+const DATAPOINT_QUEUE_SIZE: usize = 64;
+const EVENT_QUEUE_SIZE: usize = 64;
+/// Readings queued for the sinks, the oldest are dropped when they fall behind.
+const SINK_QUEUE_SIZE: usize = 64;
+/// Seconds a load command waits for the controller to report the new state,
+/// so the change is attributed to it.
+const COMMAND_TIMEOUT_S: i64 = 60;
+/// How often the stage timings are logged.
+const TIMINGS_LOG_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// The system having been suspended this many seconds between two polls is
+/// a gap in the data.
+const SUSPEND_GAP_S: i64 = 30;
+
+//
+// Structs
+//
+/// Sends commands to the datalogger, from any thread.
+pub(crate) type ControlSender = UnboundedSender<ControlCommand>;
+
+/// Commands applied by the datalogger as they arrive.
+#[derive(Debug, Clone)]
+pub(crate) enum ControlCommand {
+    Load(LoadCommand),
+    /// New limits of the built-in alert rules, edited in the TUI.
+    AlertLimits(AlertThresholds),
+    #[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+    PollInterval {
+        interval_ms: u64,
+        source: CommandSource,
+    },
+}
+
+/// Request to switch the load, from the TUI switch, the HTTP API, gRPC, MQTT,
+/// the Unix socket or an automation.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LoadCommand {
+    pub(crate) on: bool,
+    pub(crate) source: CommandSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CommandSource {
+    Ui,
+    Api,
+    #[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+    Mqtt,
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    Grpc,
+    Socket,
+    /// An automation rule, e.g. `schedule` or `lvd`.
+    Automation(&'static str),
+}
+
+/// State of the serial link as seen by the datalogger thread, for the health
+/// endpoints.
+#[derive(Debug)]
+pub(crate) struct LinkHealth {
+    /// Cleared when the datalogger thread exits, even by panicking.
+    alive: AtomicBool,
+    /// Time of the last datapoint read, 0 before the first one.
+    last_sample: AtomicI64,
+    consecutive_errors: AtomicU64,
+    poll_interval_ms: AtomicU64,
+    /// The last failed read, cleared by the next datapoint.
+    last_error: Mutex<Option<String>>,
+    /// Times a worker panicked and was restarted, and the last one.
+    restarts: AtomicU64,
+    last_restart: Mutex<Option<String>>,
+}
+
+/// Clears `LinkHealth::alive` when dropped with the datalogger thread.
+struct AliveGuard(Arc<LinkHealth>);
+
+/// The thread behind a `DataloggerHandle`. Dropping it, also while unwinding
+/// from a panic, stops the application and waits for the thread, so the
+/// datalogger stores what it buffers before the process exits.
+pub(crate) struct DataloggerThread {
+    shutdown: Shutdown,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Publishes the datapoints and events of the datalogger to any number of
+/// subscribers. Receivers see the channel closed once the datalogger stops.
+#[derive(Clone)]
+pub(crate) struct EventBus {
+    datapoints: broadcast::Sender<DataPoint>,
+    events: broadcast::Sender<Event>,
+}
+
+/// The sinks datapoints are pushed to, on a task of their own so a slow
+/// server does not delay reading the serial port.
+struct Sinks {
+    mqtt: Option<MqttPublisher>,
+    influx: Option<InfluxWriter>,
+    graphite: Option<GraphiteWriter>,
+    statsd: Option<StatsdWriter>,
+    modbus: Option<ModbusServer>,
+    thingspeak: Option<ThingSpeakWriter>,
+    signalk: Option<SignalKWriter>,
+    kafka: Option<KafkaProducer>,
+    nats: Option<NatsPublisher>,
+    redis: Option<RedisWriter>,
+    remote_write: Option<RemoteWriteWriter>,
+    fleet: Option<FleetUploader>,
+}
+
+/// State of the datalogger task.
+struct Datalogger {
+    config: Config,
+    port_name: String,
+    serial: SerialDatalogger,
+    store: DbWriter,
+    bus: EventBus,
+    recent: RecentData,
+    /// Datapoints for the sinks, `None` for a failed read.
+    sinks: QueueSender<Option<DataPoint>>,
+    /// Times the sinks panicked and were restarted.
+    sinks_restarts: Arc<AtomicU64>,
+    /// Restarts of the database writer and the sinks already reported.
+    restarts_seen: (u64, u64),
+    notifier: Option<Notifier>,
+    automation: LoadAutomation,
+    alert_monitor: AlertMonitor,
+    performance: Option<PerformanceMonitor>,
+    battery_health: Option<BatteryHealthMonitor>,
+    efficiency: Option<EfficiencyMonitor>,
+    cycles: Option<CycleCounter>,
+    capacity: Option<CapacityEstimator>,
+    records: Option<RecordTracker>,
+    relay: Option<GpioRelay>,
+    /// Commands of the automations, applied like any other.
+    control: ControlSender,
+    health: Arc<LinkHealth>,
+    poll_interval: Duration,
+    /// The clocks at the last poll, to detect a suspend.
+    last_poll: PollClocks,
+    /// Monotonic time of the last datapoint, the energy of the next one is
+    /// counted over the interval since.
+    last_sample: Option<Instant>,
+    error_count: u64,
+    link_lost: bool,
+    /// Time of the last datapoint, and whether the data_stale alert is raised.
+    last_valid: i64,
+    stale: bool,
+    /// The last load command until the controller reports its state.
+    commanded: Option<(LoadCommand, i64)>,
+    /// The load state shown by the UI, the last command or a change reported
+    /// by the controller.
+    load: watch::Sender<bool>,
+}
+
+/// The clocks at a poll, to tell whether the system was suspended since.
+#[derive(Debug, Clone, Copy)]
+struct PollClocks {
+    instant: Instant,
+    /// Wall clock time in unix seconds.
+    time: i64,
+    /// Time the system spent suspended since boot, where known.
+    suspended: Option<Duration>,
+}
+
+///
+/// Handle to the background thread which reads datapoints from the serial
+/// port, stores them in the database and applies control commands.
+///
+pub(crate) struct DataloggerHandle {
+    pub(crate) initial: DataPoint,
+    /// Whether the load is on, according to the load backend.
+    pub(crate) load: watch::Receiver<bool>,
+    pub(crate) datapoints: broadcast::Receiver<DataPoint>,
+    /// Alert and load events, for the alerts panel of the UI.
+    pub(crate) events: broadcast::Receiver<Event>,
+    /// The latest datapoints read.
+    pub(crate) recent: RecentData,
+    pub(crate) control: ControlSender,
+    /// Checks load commands arriving over the network.
+    pub(crate) control_auth: Arc<ControlAuth>,
+    pub(crate) health: Arc<LinkHealth>,
+    pub(crate) thread: DataloggerThread,
+}
+
+//
+// implementations
+//
+impl From<LoadCommand> for ControlCommand {
+    fn from(command: LoadCommand) -> Self {
+        ControlCommand::Load(command)
+    }
+}
+
+impl LinkHealth {
+    pub(crate) fn new(last_sample: i64, poll_interval: Duration) -> Self {
+        Self {
+            alive: AtomicBool::new(true),
+            last_sample: AtomicI64::new(last_sample),
+            consecutive_errors: AtomicU64::new(0),
+            poll_interval_ms: AtomicU64::new(poll_interval.as_millis() as u64),
+            last_error: Mutex::new(None),
+            restarts: AtomicU64::new(0),
+            last_restart: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn last_sample(&self) -> Option<i64> {
+        Some(self.last_sample.load(Ordering::Relaxed)).filter(|&time| time > 0)
+    }
+
+    pub(crate) fn consecutive_errors(&self) -> u64 {
+        self.consecutive_errors.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn last_error(&self) -> Option<String> {
+        self.last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Which worker was restarted last and when.
+    ///
+    pub(crate) fn last_restart(&self) -> Option<String> {
+        self.last_restart
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn record_sample(&self, time: i64) {
+        self.last_sample.store(time, Ordering::Relaxed);
+        self.consecutive_errors.store(0, Ordering::Relaxed);
+        *self
+            .last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    pub(crate) fn record_error(&self, error: impl fmt::Display) {
+        self.consecutive_errors.fetch_add(1, Ordering::Relaxed);
+        *self
+            .last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(error.to_string());
+    }
+
+    pub(crate) fn record_restart(&self, worker: &str) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        *self
+            .last_restart
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(format!(
+            "{} restarted at {}",
+            worker,
+            Local::now().format("%H:%M:%S")
+        ));
+    }
+}
+
+impl Drop for AliveGuard {
+    fn drop(&mut self) {
+        self.0.alive.store(false, Ordering::Relaxed);
+    }
+}
+
+impl DataloggerThread {
+    pub(crate) fn new(shutdown: Shutdown, thread: JoinHandle<()>) -> Self {
+        Self {
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    ///
+    /// Stop the application and wait for the thread to finish.
+    ///
+    pub(crate) fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for DataloggerThread {
+    fn drop(&mut self) {
+        self.shutdown.stop();
+        if let Some(thread) = self.thread.take() {
+            let name = thread.thread().name().unwrap_or("datalogger").to_string();
+            if thread.join().is_err() {
+                error!("Thread {} panicked.", name);
+            }
+        }
+    }
+}
+
+impl CommandSource {
+    ///
+    /// Name stored in the `source` column of the load events table and logged,
+    /// for an automation the rule switching the load.
+    ///
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CommandSource::Ui => "ui",
+            CommandSource::Api => "api",
+            CommandSource::Mqtt => "mqtt",
+            CommandSource::Grpc => "grpc",
+            CommandSource::Socket => "socket",
+            CommandSource::Automation(rule) => rule,
+        }
+    }
+}
+
+impl EventBus {
+    pub(crate) fn new() -> Self {
+        Self {
+            datapoints: broadcast::channel(DATAPOINT_QUEUE_SIZE).0,
+            events: broadcast::channel(EVENT_QUEUE_SIZE).0,
+        }
+    }
+
+    ///
+    /// Receivers of the datapoints and events published from now on.
+    ///
+    pub(crate) fn subscribe(&self) -> (broadcast::Receiver<DataPoint>, broadcast::Receiver<Event>) {
+        (self.datapoints.subscribe(), self.events.subscribe())
+    }
+
+    pub(crate) fn publish(&self, datapoint: DataPoint) {
+        // Nobody may be subscribed.
+        let _ = self.datapoints.send(datapoint);
+    }
+
+    pub(crate) fn publish_event(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+}
+
+impl Sinks {
+    fn new(
+        config: &Config,
+        control_auth: &Arc<ControlAuth>,
+        control: &ControlSender,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            mqtt: match config.mqtt.enabled {
+                true => Some(
+                    MqttPublisher::new(&config.mqtt, Arc::clone(control_auth), control.clone())
+                        .inspect_err(|e| error!("{}", e))?,
+                ),
+                false => None,
+            },
+            influx: match config.influx.enabled {
+                true => Some(InfluxWriter::new(&config.influx)?),
+                false => None,
+            },
+            graphite: match config.graphite.enabled {
+                true => Some(GraphiteWriter::new(&config.graphite)?),
+                false => None,
+            },
+            statsd: match config.statsd.enabled {
+                true => Some(StatsdWriter::new(&config.statsd).inspect_err(|e| error!("{}", e))?),
+                false => None,
+            },
+            modbus: match config.modbus.enabled {
+                true => Some(ModbusServer::new(&config.modbus).inspect_err(|e| error!("{}", e))?),
+                false => None,
+            },
+            thingspeak: match config.thingspeak.enabled {
+                true => Some(
+                    ThingSpeakWriter::new(&config.thingspeak).inspect_err(|e| error!("{}", e))?,
+                ),
+                false => None,
+            },
+            signalk: match config.signalk.enabled {
+                true => Some(SignalKWriter::new(&config.signalk).inspect_err(|e| error!("{}", e))?),
+                false => None,
+            },
+            kafka: match config.kafka.enabled {
+                true => Some(KafkaProducer::new(&config.kafka).inspect_err(|e| error!("{}", e))?),
+                false => None,
+            },
+            nats: match config.nats.enabled {
+                true => Some(NatsPublisher::new(&config.nats).inspect_err(|e| error!("{}", e))?),
+                false => None,
+            },
+            redis: match config.redis.enabled {
+                true => Some(RedisWriter::new(&config.redis)?),
+                false => None,
+            },
+            remote_write: match config.remote_write.enabled {
+                true => Some(
+                    RemoteWriteWriter::new(&config.remote_write)
+                        .inspect_err(|e| error!("{}", e))?,
+                ),
+                false => None,
+            },
+            fleet: match config.fleet.enabled {
+                true => Some(FleetUploader::new(&config.fleet).inspect_err(|e| error!("{}", e))?),
+                false => None,
+            },
+        })
+    }
+
+    ///
+    /// Push datapoints to the sinks until the datalogger stops, then drop
+    /// them so they flush what they buffer.
+    ///
+    fn run(&mut self, readings: &QueueReceiver<Option<DataPoint>>) {
+        while let Some(reading) = readings.recv() {
+            match reading {
+                Some(d) => self.push(&d),
+                None => self.read_error(),
+            }
+        }
+    }
+
+    fn push(&mut self, d: &DataPoint) {
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(d);
+        }
+        if let Some(influx) = &self.influx {
+            influx.push(d);
+        }
+        if let Some(graphite) = &mut self.graphite {
+            graphite.push(d);
+        }
+        if let Some(statsd) = &self.statsd {
+            statsd.push(d);
+        }
+        if let Some(modbus) = &self.modbus {
+            modbus.update(d);
+        }
+        if let Some(thingspeak) = &self.thingspeak {
+            thingspeak.push(d);
+        }
+        if let Some(signalk) = &self.signalk {
+            signalk.push(d);
+        }
+        if let Some(kafka) = &self.kafka {
+            kafka.publish(d);
+        }
+        if let Some(nats) = &self.nats {
+            nats.publish(d);
+        }
+        if let Some(redis) = &self.redis {
+            redis.push(d);
+        }
+        if let Some(remote_write) = &self.remote_write {
+            remote_write.push(d);
+        }
+        if let Some(fleet) = &self.fleet {
+            fleet.push(d);
+        }
+    }
+
+    fn read_error(&mut self) {
+        if let Some(graphite) = &mut self.graphite {
+            graphite.read_error();
+        }
+        if let Some(statsd) = &self.statsd {
+            statsd.read_error();
+        }
+    }
+}
+
+///
+/// Open the given port, read an initial datapoint and start the datalogger
+/// thread. The thread runs a tokio runtime with the serial reader, the
+/// database writer and the sinks as tasks until the application stops, and
+/// holds the instance lock on the port and database until it exits.
+///
+pub(crate) fn spawn_datalogger(
+    port_name: &str,
+    config: &Config,
+    shutdown: Shutdown,
+) -> io::Result<DataloggerHandle> {
+    let instance_lock = InstanceLock::acquire(
+        port_name,
+        (!config.dry_run).then_some(config.database.path.as_path()),
+    )
+    .inspect_err(|e| error!("{}", e))?;
+    let relay = match config.load.backend {
+        LoadBackend::Serial => None,
+        LoadBackend::Gpio => Some(GpioRelay::new(&config.load).inspect_err(|e| error!("{}", e))?),
+    };
+    let (control_tx, control_rx) = mpsc::unbounded_channel::<ControlCommand>();
+    let control_auth = Arc::new(ControlAuth::new(&config.load));
+    let sinks = Sinks::new(config, &control_auth, &control_tx)?;
+    if config.forecast.enabled && !config.dry_run {
+        forecast::spawn_fetcher(config, shutdown.flag())?;
+    }
+    if config.weather.enabled && !config.dry_run {
+        weather::spawn_fetcher(config, shutdown.flag())?;
+    }
+    if config.report_files.enabled {
+        report_files::spawn_writer(config, shutdown.flag())?;
+    }
+    let notifier = Notifier::new(config)?;
+    let automation = LoadAutomation::new(&config.automation).inspect_err(|e| error!("{}", e))?;
+    let alert_monitor = AlertMonitor::new(&config.alerts).inspect_err(|e| error!("{}", e))?;
+    let performance = match config.performance.enabled {
+        true => Some(PerformanceMonitor::new(config).inspect_err(|e| error!("{}", e))?),
+        false => None,
+    };
+    let battery_health = match config.battery_health.enabled {
+        true => Some(BatteryHealthMonitor::new(config).inspect_err(|e| error!("{}", e))?),
+        false => None,
+    };
+    let efficiency = match config.efficiency.enabled {
+        true => Some(EfficiencyMonitor::new(config).inspect_err(|e| error!("{}", e))?),
+        false => None,
+    };
+    let records = match config.records.enabled {
+        true => Some(RecordTracker::new(config).inspect_err(|e| error!("{}", e))?),
+        false => None,
+    };
+    let cycles = match config.cycles.enabled {
+        true => Some(CycleCounter::new(config).inspect_err(|e| error!("{}", e))?),
+        false => None,
+    };
+    let capacity = match config.capacity.enabled {
+        true => Some(CapacityEstimator::new(config).inspect_err(|e| error!("{}", e))?),
+        false => None,
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let bus = EventBus::new();
+    let (datapoints, events) = bus.subscribe();
+    let (mut datalogger, initial, sinks_task) = runtime.block_on(async {
+        let mut serial =
+            SerialDatalogger::new(port_name, &config.serial, config.dry_run, &shutdown).await?;
+        let _ = serial.read_serial_datapoint().await; //throw away read to ensure device is ready
+        let initial = serial.read_datapoint().await?;
+        let (sinks_tx, sinks_rx) = queue::bounded(SINK_QUEUE_SIZE);
+        let sinks_restarts = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&sinks_restarts);
+        let sinks_task = tokio::task::spawn_blocking(move || {
+            let mut sinks = sinks;
+            supervisor::run_supervised(
+                "sinks",
+                &counter,
+                || sinks_rx.is_closed(),
+                || sinks.run(&sinks_rx),
+            );
+        });
+        let poll_interval = Duration::from_millis(config.serial.poll_interval_ms);
+        // A GPIO relay is always switched off when its line is requested.
+        let load_on = relay.is_none() && initial.get_load_onoff() > 0.0;
+        let datalogger = Datalogger {
+            config: config.clone(),
+            port_name: port_name.to_string(),
+            serial,
+            store: DbWriter::spawn(&config.database, config.dry_run),
+            bus,
+            recent: RecentData::new(RECENT_SAMPLES),
+            sinks: sinks_tx,
+            sinks_restarts,
+            restarts_seen: (0, 0),
+            notifier,
+            automation,
+            alert_monitor,
+            performance,
+            battery_health,
+            efficiency,
+            cycles,
+            capacity,
+            records,
+            relay,
+            control: control_tx.clone(),
+            health: Arc::new(LinkHealth::new(initial.get_time(), poll_interval)),
+            poll_interval,
+            last_poll: PollClocks::now(),
+            last_sample: Some(Instant::now()),
+            error_count: 0,
+            link_lost: false,
+            last_valid: initial.get_time(),
+            stale: false,
+            commanded: None,
+            load: watch::channel(load_on).0,
+        };
+        io::Result::Ok((datalogger, initial, sinks_task))
+    })?;
+    datalogger.store.datapoint(initial, None);
+    datalogger.recent.push(initial);
+    let recent = datalogger.recent.clone();
+    let events_now = datalogger.alert_monitor.check(&initial);
+    datalogger.dispatch(events_now);
+    let load = datalogger.load.subscribe();
+    let health = Arc::clone(&datalogger.health);
+    let builder = thread::Builder::new()
+        .name("datalogger".into())
+        .stack_size(1024 * 1024); //1MB
+    let stop = shutdown.clone();
+    let thread = builder.spawn(move || {
+        let threads = &datalogger.config.threads;
+        priority::apply(
+            "serial",
+            threads.serial_nice,
+            threads.serial_realtime_priority,
+            &threads.serial_cpus,
+        );
+        let _instance_lock = instance_lock;
+        let _alive = AliveGuard(Arc::clone(&datalogger.health));
+        let mut commands = control_rx;
+        let mut backoff = Backoff::new();
+        // After a panic the datalogger carries on with the state and the
+        // serial port it had, the datapoint being handled is lost.
+        while panic::catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(datalogger.run(&mut commands, &shutdown))
+        }))
+        .is_err()
+        {
+            let delay = backoff.failed();
+            runtime.block_on(datalogger.restart(delay, &shutdown));
+            backoff.restarted();
+        }
+        runtime.block_on(async move {
+            let Datalogger {
+                store, sinks, bus, ..
+            } = datalogger;
+            // Receivers of the bus see it closed, and the writer and the
+            // sinks finish what is queued.
+            drop(bus);
+            drop(sinks);
+            if sinks_task.await.is_err() {
+                error!("Sinks panicked.");
+            }
+            store.close().await;
+        });
+    })?;
+    Ok(DataloggerHandle {
+        initial,
+        load,
+        datapoints,
+        events,
+        recent,
+        control: control_tx,
+        control_auth,
+        health,
+        thread: DataloggerThread::new(stop, thread),
+    })
+}
+
+impl PollClocks {
+    fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            time: Local::now().timestamp(),
+            suspended: suspended_time(),
+        }
+    }
+
+    ///
+    /// Seconds the system was suspended between `earlier` and these clocks.
+    /// Without the suspended time the wall clock is compared with the
+    /// monotonic clock, so a step of the wall clock looks like a suspend.
+    ///
+    fn suspended_since(&self, earlier: &Self) -> i64 {
+        match (self.suspended, earlier.suspended) {
+            (Some(now), Some(before)) => now.saturating_sub(before).as_secs() as i64,
+            _ => {
+                let elapsed = self.instant.duration_since(earlier.instant).as_secs() as i64;
+                (self.time - earlier.time) - elapsed
+            }
+        }
+    }
+}
+
+impl Datalogger {
+    ///
+    /// Wait for whichever comes first of the next poll, a command or the
+    /// application stopping, until it stops.
+    ///
+    async fn run(&mut self, commands: &mut UnboundedReceiver<ControlCommand>, shutdown: &Shutdown) {
+        let mut poll = poll_timer(self.poll_interval);
+        let mut timings_log = poll_timer(TIMINGS_LOG_INTERVAL);
+        loop {
+            tokio::select! {
+                biased;
+                _ = shutdown.stopped() => break,
+                // A read waits for up to serial.timeout_ms, and reconnecting
+                // as long as the device is missing.
+                _ = poll.tick() => tokio::select! {
+                    _ = self.poll(shutdown) => {}
+                    _ = shutdown.stopped() => break,
+                },
+                Some(command) = commands.recv() => {
+                    if let Some(interval) = self.apply(command).await {
+                        poll = poll_timer(interval);
+                    }
+                }
+                _ = timings_log.tick() => info!("Timings: {}.", TIMINGS),
+            }
+        }
+        info!("Timings: {}.", TIMINGS);
+    }
+
+    ///
+    /// Wait `delay` after the datalogger panicked, showing it as the serial
+    /// error meanwhile, before it runs again.
+    ///
+    async fn restart(&mut self, delay: Duration, shutdown: &Shutdown) {
+        error!(
+            "The datalogger panicked, restarting in {} s.",
+            delay.as_secs()
+        );
+        self.health.record_error(format!(
+            "Datalogger panicked, restarting in {} s",
+            delay.as_secs()
+        ));
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = shutdown.stopped() => return,
+        }
+        info!("Restarting the datalogger.");
+        self.health.record_restart("datalogger");
+    }
+
+    ///
+    /// Report restarts of the database writer and the sinks to the UI and
+    /// the health endpoints.
+    ///
+    fn check_workers(&mut self) {
+        let seen = (
+            self.store.restarts(),
+            self.sinks_restarts.load(Ordering::Relaxed),
+        );
+        if seen.0 > self.restarts_seen.0 {
+            self.health.record_restart("database writer");
+        }
+        if seen.1 > self.restarts_seen.1 {
+            self.health.record_restart("sinks");
+        }
+        self.restarts_seen = seen;
+    }
+
+    ///
+    /// After the system was suspended, record the time without data as a gap,
+    /// so no energy is counted across it and no stale data alert is raised,
+    /// and reopen the serial port which may not have survived it.
+    ///
+    async fn check_suspend(&mut self, shutdown: &Shutdown) {
+        let clocks = PollClocks::now();
+        let suspended = clocks.suspended_since(&self.last_poll);
+        let now = clocks.time;
+        self.last_poll = clocks;
+        if suspended < SUSPEND_GAP_S {
+            return;
+        }
+        warn!(
+            "The system was suspended for about {} s, reopening the serial port.",
+            suspended
+        );
+        self.store.data_gap(self.last_valid, now, "suspend");
+        self.last_valid = now;
+        self.last_sample = None;
+        if let Err(e) = self
+            .serial
+            .reopen(&self.port_name, &self.config.serial, shutdown)
+            .await
+        {
+            warn!("Reopening {} failed: {}", self.port_name, e);
+        }
+    }
+
+    ///
+    /// Read one datapoint and pass it on, or count the failure and reopen the
+    /// port after 5 of them.
+    ///
+    async fn poll(&mut self, shutdown: &Shutdown) {
+        self.check_workers();
+        self.check_suspend(shutdown).await;
+        let datapoint = match self.serial.read_datapoint().await {
+            Ok(d) => {
+                self.health.record_sample(d.get_time());
+                let now = Instant::now();
+                let interval = self.last_sample.replace(now).map(|last| now - last);
+                self.store.datapoint(d, interval);
+                if let Some(cycles) = &mut self.cycles {
+                    cycles.check(&d, interval);
+                }
+                let capacity = self
+                    .capacity
+                    .as_mut()
+                    .and_then(|capacity| capacity.check(&d, interval));
+                if let Some(records) = &mut self.records {
+                    records.check(&d);
+                }
+                self.recent.push(d);
+                if self.link_lost {
+                    self.link_lost = false;
+                    self.dispatch(vec![Event::link(false, d.get_time())]);
+                }
+                if self.stale {
+                    self.stale = false;
+                    self.dispatch(vec![Event::stale(
+                        false,
+                        d.get_time() - self.last_valid,
+                        d.get_time(),
+                    )]);
+                }
+                self.last_valid = d.get_time();
+                self.to_sinks(Some(d));
+                let mut events = self.alert_monitor.check(&d);
+                attribute_load_changes(&mut events, &mut self.commanded, &d, &self.store);
+                if self.relay.is_none() && events.iter().any(|e| e.kind == EventKind::Load) {
+                    self.load.send_replace(d.get_load_onoff() > 0.0);
+                }
+                events.extend(capacity);
+                if let Some(performance) = &mut self.performance {
+                    events.extend(performance.check(d.get_time()));
+                }
+                if let Some(battery_health) = &mut self.battery_health {
+                    events.extend(battery_health.check(d.get_time()));
+                }
+                if let Some(efficiency) = &mut self.efficiency {
+                    events.extend(efficiency.check(d.get_time()));
+                }
+                let load = self.automation.check(&d, &mut events);
+                self.dispatch(events);
+                if let Some((on, rule)) = load {
+                    let command = LoadCommand {
+                        on,
+                        source: CommandSource::Automation(rule),
+                    };
+                    let _ = self.control.send(command.into());
+                }
+                d
+            }
+            Err(e) => {
+                self.health.record_error(e);
+                self.to_sinks(None);
+                let now = Local::now().timestamp();
+                let stale_after = self
+                    .config
+                    .alerts
+                    .stale_after_s
+                    .max(2 * self.poll_interval.as_secs()) as i64;
+                if self.config.alerts.stale_after_s > 0
+                    && !self.stale
+                    && now - self.last_valid >= stale_after
+                {
+                    self.stale = true;
+                    self.dispatch(vec![Event::stale(true, now - self.last_valid, now)]);
+                }
+                self.error_count += 1;
+                if self.error_count >= 5 {
+                    self.error_count = 0;
+                    if !self.link_lost {
+                        self.link_lost = true;
+                        self.dispatch(vec![Event::link(true, Local::now().timestamp())]);
+                    }
+                    info!("Failed to read 5 datapoints, attempting to reconnect in 1 second.");
+                    sleep(Duration::from_secs(1)).await;
+                    // Keep the old port when reopening fails, to retry after
+                    // the next 5 failures.
+                    let _ = self
+                        .serial
+                        .reopen(&self.port_name, &self.config.serial, shutdown)
+                        .await;
+                }
+                DataPoint::default()
+            }
+        };
+        self.bus.publish(datapoint);
+    }
+
+    ///
+    /// Apply a command, returning the new poll interval if it changed.
+    ///
+    async fn apply(&mut self, command: ControlCommand) -> Option<Duration> {
+        match command {
+            ControlCommand::PollInterval {
+                interval_ms,
+                source,
+            } => {
+                if (MIN_POLL_INTERVAL_MS..=MAX_POLL_INTERVAL_MS).contains(&interval_ms) {
+                    info!(
+                        "Poll interval set to {} ms ({}).",
+                        interval_ms,
+                        source.name()
+                    );
+                    self.poll_interval = Duration::from_millis(interval_ms);
+                    self.health
+                        .poll_interval_ms
+                        .store(interval_ms, Ordering::Relaxed);
+                    return Some(self.poll_interval);
+                }
+                warn!(
+                    "Ignoring poll interval of {} ms ({}), expected {} to {} ms.",
+                    interval_ms,
+                    source.name(),
+                    MIN_POLL_INTERVAL_MS,
+                    MAX_POLL_INTERVAL_MS
+                );
+            }
+            ControlCommand::AlertLimits(thresholds) => {
+                info!("Alert limits changed.");
+                self.alert_monitor.set_limits(&thresholds);
+            }
+            ControlCommand::Load(command) => self.switch_load(command).await,
+        }
+        None
+    }
+
+    async fn switch_load(&mut self, command: LoadCommand) {
+        let now = Local::now().timestamp();
+        if !matches!(command.source, CommandSource::Automation(_)) {
+            let mut events = Vec::new();
+            self.automation.load_switched(command.on, now, &mut events);
+            self.dispatch(events);
+        }
+        info!(
+            "Switching the load {} ({}).",
+            if command.on { "on" } else { "off" },
+            command.source.name()
+        );
+        match &self.relay {
+            _ if self.config.dry_run => info!("Dry run, not switching the load."),
+            Some(relay) => match relay.set(command.on) {
+                Ok(()) => {
+                    self.store.load_event(command.on, command.source.name());
+                    // The controller does not see the relay, so the command
+                    // is the change.
+                    if self.load.send_replace(command.on) != command.on {
+                        self.dispatch(vec![Event::load(command.on, command.source.name(), now)]);
+                    }
+                }
+                Err(e) => warn!("{}", e),
+            },
+            None => {
+                if command.on {
+                    self.serial.load_on().await;
+                } else {
+                    self.serial.load_off().await;
+                }
+                self.store.load_event(command.on, command.source.name());
+                self.commanded = Some((command, now));
+                self.load.send_replace(command.on);
+            }
+        }
+    }
+
+    fn to_sinks(&self, reading: Option<DataPoint>) {
+        if self.sinks.send(reading) {
+            warn!("Sinks are falling behind, dropping the oldest datapoints.");
+        }
+    }
+
+    ///
+    /// Log alert events and store them, then publish every event on the bus
+    /// and pass it to the notifiers.
+    ///
+    fn dispatch(&self, events: Vec<Event>) {
+        for event in events {
+            match event.kind {
+                EventKind::Alert => warn!(
+                    "Alert {} ({}): {}",
+                    event.name,
+                    event.severity.name(),
+                    event.message
+                ),
+                EventKind::Cleared => info!("Alert {} cleared: {}", event.name, event.message),
+                EventKind::Load => info!("Load event {}: {}", event.name, event.message),
+                EventKind::Summary => {}
+            }
+            if event.kind == EventKind::Alert || event.kind == EventKind::Cleared {
+                self.store.alert_event(&event);
+            }
+            self.bus.publish_event(event.clone());
+            if let Some(notifier) = &self.notifier {
+                notifier.notify(event);
+            }
+        }
+    }
+}
+
+///
+/// The time the system spent suspended since boot, the difference of
+/// CLOCK_BOOTTIME, which counts suspends, and CLOCK_MONOTONIC, which does
+/// not. Unlike the wall clock neither is stepped, e.g. by NTP on a
+/// Raspberry Pi without a real-time clock.
+///
+#[cfg(target_os = "linux")]
+fn suspended_time() -> Option<Duration> {
+    let read = |clock| {
+        let mut time = unsafe { std::mem::zeroed::<libc::timespec>() };
+        match unsafe { libc::clock_gettime(clock, &mut time) } {
+            0 => Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32)),
+            _ => None,
+        }
+    };
+    Some(read(libc::CLOCK_BOOTTIME)?.saturating_sub(read(libc::CLOCK_MONOTONIC)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn suspended_time() -> Option<Duration> {
+    None
+}
+
+///
+/// A timer ticking every `period`, the first tick after one period as the
+/// initial datapoint was just read.
+///
+fn poll_timer(period: Duration) -> Interval {
+    let mut timer = interval_at(Instant::now() + period, period);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    timer
+}
+
+///
+/// Attribute the load state change among `events` to the load command which
+/// caused it. Changes without one were made by the controller itself, e.g. by
+/// its own low-voltage disconnect, and are stored as such.
+///
+fn attribute_load_changes(
+    events: &mut [Event],
+    commanded: &mut Option<(LoadCommand, i64)>,
+    datapoint: &DataPoint,
+    store: &DbWriter,
+) {
+    let load_on = datapoint.get_load_onoff() > 0.0;
+    let source = match *commanded {
+        Some((_, time)) if datapoint.get_time() - time > COMMAND_TIMEOUT_S => {
+            *commanded = None;
+            None
+        }
+        Some((command, _)) if command.on == load_on => {
+            *commanded = None;
+            Some(command.source)
+        }
+        _ => None,
+    };
+    for event in events.iter_mut().filter(|e| e.kind == EventKind::Load) {
+        match source {
+            Some(source) => *event = Event::load(load_on, source.name(), event.timestamp),
+            None => store.load_event(load_on, "controller"),
+        }
+    }
+}
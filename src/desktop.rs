@@ -0,0 +1,58 @@
+use solar_core::alerts::Event;
+use std::io;
+
+//
+// Structs
+//
+/// Shows alerts as notifications of the desktop the terminal UI runs on.
+pub(crate) struct DesktopNotifier;
+
+//
+// implementations
+//
+#[cfg(feature = "desktop")]
+impl DesktopNotifier {
+    pub(crate) fn new() -> io::Result<Self> {
+        info!("Showing critical alerts as desktop notifications.");
+        Ok(Self)
+    }
+
+    ///
+    /// Show a notification from a new thread, as talking to the notification
+    /// service must not stall the UI. Failures are logged.
+    ///
+    pub(crate) fn send(&self, event: &Event) {
+        let owned_event = event.clone();
+        let spawned = std::thread::Builder::new()
+            .name("desktop".into())
+            .spawn(move || show(&owned_event));
+        if let Err(e) = spawned {
+            warn!("Desktop notification for {} failed: {}", event.name, e);
+        }
+    }
+}
+
+#[cfg(feature = "desktop")]
+fn show(event: &Event) {
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .appname("rust-solar")
+        .summary(&format!("Solar alert: {}", event.name))
+        .body(&event.message);
+    #[cfg(all(unix, not(target_os = "macos")))]
+    notification.urgency(notify_rust::Urgency::Critical);
+    if let Err(e) = notification.show() {
+        warn!("Desktop notification for {} failed: {}", event.name, e);
+    }
+}
+
+#[cfg(not(feature = "desktop"))]
+impl DesktopNotifier {
+    pub(crate) fn new() -> io::Result<Self> {
+        Err(io::Error::other(
+            "ui.desktop_notifications requires building with the `desktop` feature.",
+        ))
+    }
+
+    pub(crate) fn send(&self, _event: &Event) {}
+}
@@ -0,0 +1,127 @@
+use crate::config::{Config, EfficiencyConfig};
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime, TimeZone};
+use solar_core::alerts::{Event, EventKind, Severity};
+use solar_core::database::Database;
+use solar_core::rollup::efficiency;
+use std::io;
+
+//
+// Structs
+//
+/// Compares the system efficiency, the energy consumed per energy harvested,
+/// of the last days with the weeks before, as a dropping efficiency points to
+/// a failing battery, corroded wiring or a controller problem.
+pub(crate) struct EfficiencyMonitor {
+    config: EfficiencyConfig,
+    database: Database,
+    /// Day of the last check.
+    checked: Option<NaiveDate>,
+    /// Whether the efficiency_dropping alert is raised.
+    raised: bool,
+}
+
+//
+// implementations
+//
+impl EfficiencyMonitor {
+    ///
+    /// Check the settings of `[efficiency]` and open a connection to read the
+    /// hourly rollups.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Self> {
+        let settings = &config.efficiency;
+        if settings.days == 0 || settings.baseline_days == 0 || settings.drop_percent <= 0.0 {
+            return Err(io::Error::other(
+                "efficiency.days and efficiency.baseline_days must be at least 1 and drop_percent positive.",
+            ));
+        }
+        info!(
+            "Checking the system efficiency of the last {} days against the {} days before.",
+            settings.days, settings.baseline_days
+        );
+        Ok(Self {
+            config: settings.clone(),
+            database: Database::new(&config.database)?,
+            checked: None,
+            raised: false,
+        })
+    }
+
+    ///
+    /// Once a day after midnight, compare the efficiency of the last days up
+    /// to yesterday with the baseline before and return the alert, or its
+    /// clearing, when that changes.
+    ///
+    pub(crate) fn check(&mut self, timestamp: i64) -> Option<Event> {
+        let today = DateTime::from_timestamp(timestamp, 0)?
+            .with_timezone(&Local)
+            .date_naive();
+        if self.checked == Some(today) {
+            return None;
+        }
+        self.checked = Some(today);
+        let days = self.config.days.into();
+        let start = today - Days::new(days);
+        let baseline_start = start - Days::new(self.config.baseline_days.into());
+        let recent = self.efficiency(start, today);
+        let baseline = self.efficiency(baseline_start, start);
+        let (Some(recent), Some(baseline)) = (recent, baseline) else {
+            info!("Not enough data yet to check the system efficiency.");
+            return None;
+        };
+        if baseline <= 0.0 {
+            return None;
+        }
+        let percent = (recent / baseline * 100.0).round();
+        info!(
+            "System efficiency of the last {} days {:.0}%, {}% of the baseline of {:.0}%.",
+            days, recent, percent, baseline
+        );
+        let low = percent < 100.0 - self.config.drop_percent;
+        if low == self.raised {
+            return None;
+        }
+        self.raised = low;
+        let (kind, message) = match low {
+            true => (
+                EventKind::Alert,
+                format!(
+                    "System efficiency dropping: {:.0}% of the energy harvested reached the load in the last {} days, down from {:.0}%, check the wiring and controller",
+                    recent, days, baseline
+                ),
+            ),
+            false => (
+                EventKind::Cleared,
+                format!("System efficiency back at {:.0}%", recent),
+            ),
+        };
+        Some(Event {
+            kind,
+            name: "efficiency_dropping".to_string(),
+            severity: Severity::Warning,
+            message,
+            value: recent,
+            timestamp,
+        })
+    }
+
+    ///
+    /// The efficiency of the days from `first` up to `end`, if enough energy
+    /// was harvested then.
+    ///
+    fn efficiency(&self, first: NaiveDate, end: NaiveDate) -> Option<f64> {
+        let summary = self
+            .database
+            .summary(midnight(first)?, midnight(end)?)
+            .inspect_err(|e| warn!("{}", e))
+            .ok()?;
+        efficiency(summary.energy_harvested_wh, summary.energy_consumed_wh)
+    }
+}
+
+fn midnight(day: NaiveDate) -> Option<i64> {
+    Local
+        .from_local_datetime(&day.and_time(NaiveTime::MIN))
+        .earliest()
+        .map(|t| t.timestamp())
+}
@@ -0,0 +1,120 @@
+use crate::config::EmailConfig;
+use solar_core::alerts::Event;
+use std::io;
+
+//
+// Structs
+//
+/// Mails alert and load events through an SMTP server.
+#[cfg(feature = "email")]
+pub(crate) struct EmailNotifier {
+    transport: lettre::SmtpTransport,
+    from: lettre::message::Mailbox,
+    to: Vec<lettre::message::Mailbox>,
+    config: EmailConfig,
+}
+
+#[cfg(not(feature = "email"))]
+pub(crate) struct EmailNotifier {
+    config: EmailConfig,
+}
+
+//
+// implementations
+//
+#[cfg(feature = "email")]
+impl EmailNotifier {
+    ///
+    /// Check the addresses and set up the SMTP transport, the server is only
+    /// contacted when an event is sent.
+    ///
+    pub(crate) fn new(config: &EmailConfig) -> io::Result<Self> {
+        use crate::config::SmtpSecurity;
+        use lettre::SmtpTransport;
+        use lettre::{message::Mailbox, transport::smtp::authentication::Credentials};
+        use std::time::Duration;
+
+        let parse = |field: &str, address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| io::Error::other(format!("email.{}: {}: {}", field, address, e)))
+        };
+        let from = parse("from", &config.from)?;
+        let to = config
+            .to
+            .iter()
+            .map(|address| parse("to", address))
+            .collect::<io::Result<Vec<_>>>()?;
+        if to.is_empty() {
+            return Err(io::Error::other("email.to: no recipients configured."));
+        }
+
+        let builder = match config.security {
+            SmtpSecurity::Tls => SmtpTransport::relay(&config.server),
+            SmtpSecurity::Starttls => SmtpTransport::starttls_relay(&config.server),
+            SmtpSecurity::None => Ok(SmtpTransport::builder_dangerous(&config.server)),
+        }
+        .map_err(|e| io::Error::other(format!("email.server: {}", e)))?;
+        let mut builder = builder
+            .port(config.port)
+            .timeout(Some(Duration::from_secs(30)));
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                config.password.clone().unwrap_or_default(),
+            ));
+        }
+        info!(
+            "Mailing events to {} through {}:{}",
+            config.to.join(", "),
+            config.server,
+            config.port
+        );
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            to,
+            config: config.clone(),
+        })
+    }
+
+    ///
+    /// Send one mail to all recipients, failures are logged.
+    ///
+    pub(crate) fn send(&self, event: &Event) {
+        use crate::notify::render;
+        use lettre::{message::header::ContentType, Message, Transport};
+
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(render(&self.config.subject, event, false))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let result = message
+            .body(render(&self.config.body, event, false))
+            .map_err(|e| e.to_string())
+            .and_then(|m| self.transport.send(&m).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Mailing {} failed: {}", event.name, e);
+        }
+    }
+}
+
+#[cfg(not(feature = "email"))]
+impl EmailNotifier {
+    pub(crate) fn new(_config: &EmailConfig) -> io::Result<Self> {
+        Err(io::Error::other(
+            "Email alerts require building with the `email` feature.",
+        ))
+    }
+
+    pub(crate) fn send(&self, _event: &Event) {}
+}
+
+impl EmailNotifier {
+    pub(crate) fn config(&self) -> &EmailConfig {
+        &self.config
+    }
+}
@@ -0,0 +1,121 @@
+use crate::cli::{ExportArgs, ExportFormat};
+use crate::config::Config;
+use solar_core::database::Database;
+use solar_core::datapoint::DataPoint;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+///
+/// Export the datapoints in the requested range to a file or stdout.
+///
+pub(crate) fn run_export(config: &Config, args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let database = Database::new(&config.database)?;
+    let from = args.from.unwrap_or(0);
+    let to = args.to.unwrap_or(i64::MAX);
+    let datapoints = database.datapoints_between(from, to)?;
+    info!(
+        "Exporting {} datapoints as {:?}.",
+        datapoints.len(),
+        args.format
+    );
+
+    match args.format {
+        ExportFormat::Csv => write_csv(open_output(args)?, &datapoints),
+        ExportFormat::Json => write_json(open_output(args)?, &datapoints),
+        ExportFormat::Parquet => write_parquet(args, &datapoints),
+    }
+}
+
+fn open_output(args: &ExportArgs) -> io::Result<Box<dyn Write + Send>> {
+    Ok(match &args.out {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    })
+}
+
+fn write_csv(out: Box<dyn Write + Send>, datapoints: &[DataPoint]) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(out);
+    for dp in datapoints {
+        writer.serialize(dp)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_json(
+    mut out: Box<dyn Write + Send>,
+    datapoints: &[DataPoint],
+) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(&mut out, datapoints)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(args: &ExportArgs, datapoints: &[DataPoint]) -> Result<(), Box<dyn Error>> {
+    use parquet::{
+        data_type::{DoubleType, Int64Type},
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+    use std::sync::Arc;
+
+    const SCHEMA: &str = "message datapoint {
+        REQUIRED INT64 timestamp;
+        REQUIRED DOUBLE battery_voltage;
+        REQUIRED DOUBLE pv_voltage;
+        REQUIRED DOUBLE load_current;
+        REQUIRED DOUBLE over_discharge;
+        REQUIRED DOUBLE battery_max;
+        REQUIRED DOUBLE battery_full;
+        REQUIRED DOUBLE charging;
+        REQUIRED DOUBLE battery_temp;
+        REQUIRED DOUBLE charge_current;
+        REQUIRED DOUBLE load_onoff;
+    }";
+    let columns: [fn(&DataPoint) -> f64; 10] = [
+        DataPoint::get_battery_voltage,
+        DataPoint::get_pv_voltage,
+        DataPoint::get_load_current,
+        DataPoint::get_over_discharge,
+        DataPoint::get_battery_max,
+        DataPoint::get_battery_full,
+        DataPoint::get_charging,
+        DataPoint::get_battery_temp,
+        DataPoint::get_charge_current,
+        DataPoint::get_load_onoff,
+    ];
+
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let props = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(open_output(args)?, schema, props)?;
+    let mut row_group = writer.next_row_group()?;
+    if let Some(mut column) = row_group.next_column()? {
+        let values: Vec<i64> = datapoints.iter().map(DataPoint::get_time).collect();
+        column
+            .typed::<Int64Type>()
+            .write_batch(&values, None, None)?;
+        column.close()?;
+    }
+    for getter in columns {
+        if let Some(mut column) = row_group.next_column()? {
+            let values: Vec<f64> = datapoints.iter().map(getter).collect();
+            column
+                .typed::<DoubleType>()
+                .write_batch(&values, None, None)?;
+            column.close()?;
+        }
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_args: &ExportArgs, _datapoints: &[DataPoint]) -> Result<(), Box<dyn Error>> {
+    Err("Parquet export requires building with the `parquet` feature.".into())
+}
@@ -0,0 +1,90 @@
+use crate::batch_uploader::{BatchLimits, BatchUploader, BatchWriter};
+use crate::config::FleetConfig;
+use serde::{Deserialize, Serialize};
+use solar_core::datapoint::DataPoint;
+use std::io;
+
+//
+// Constants
+//
+/// Path of the upload endpoint below the fleet server's URL.
+pub(crate) const UPLOAD_PATH: &str = "/api/fleet/upload";
+
+//
+// Structs
+//
+/// Body of an upload, the datapoints of one device.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Upload {
+    pub(crate) device: String,
+    pub(crate) datapoints: Vec<DataPoint>,
+}
+
+/// Uploads datapoints to a fleet server from a background thread, keeping a
+/// bounded backlog while the server is unreachable.
+pub(crate) struct FleetUploader {
+    uploader: BatchUploader<DataPoint>,
+}
+
+/// Writes batches as the uploads of this device.
+struct UploadWriter {
+    url: String,
+    device: String,
+    upload_key: String,
+}
+
+//
+// implementations
+//
+impl FleetUploader {
+    pub(crate) fn new(config: &FleetConfig) -> io::Result<Self> {
+        if config.device_id.is_empty() {
+            return Err(io::Error::other("fleet.device_id must be set."));
+        }
+        info!(
+            "Uploading datapoints to fleet server {} as {}",
+            config.url, config.device_id
+        );
+        let limits = BatchLimits {
+            batch_size: config.batch_size,
+            max_backlog: config.max_backlog,
+        };
+        let writer = UploadWriter {
+            url: format!("{}{}", config.url.trim_end_matches('/'), UPLOAD_PATH),
+            device: config.device_id.clone(),
+            upload_key: config.upload_key.clone(),
+        };
+        let uploader = BatchUploader::spawn("fleet", "Fleet upload", limits, writer, Vec::new())?;
+        Ok(Self { uploader })
+    }
+
+    ///
+    /// Queue a datapoint for the next upload.
+    ///
+    pub(crate) fn push(&self, datapoint: &DataPoint) {
+        self.uploader.push(*datapoint);
+    }
+}
+
+impl BatchWriter for UploadWriter {
+    type Item = DataPoint;
+
+    fn write(&mut self, batch: &[DataPoint]) -> Result<(), ureq::Error> {
+        let upload = Upload {
+            device: self.device.clone(),
+            datapoints: batch.to_vec(),
+        };
+        ureq::post(&self.url)
+            .header("Authorization", &format!("Bearer {}", self.upload_key))
+            .send_json(&upload)?;
+        Ok(())
+    }
+
+    ///
+    /// A wrong key is kept retrying so nothing is lost until it is fixed on
+    /// either side, other rejected batches are dropped.
+    ///
+    fn drops_rejected(&self, status: u16) -> bool {
+        !matches!(status, 401 | 403 | 429)
+    }
+}
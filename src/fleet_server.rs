@@ -0,0 +1,396 @@
+use crate::api::{authorize, constant_time_eq, rate_limit, spawn_router};
+use crate::cli::parse_timestamp;
+use crate::config::Config;
+use crate::fleet::{Upload, UPLOAD_PATH};
+use crate::rate_limit::RateLimiter;
+use crate::systemd;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::Html,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Local;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use solar_core::config::parse_duration;
+use solar_core::database::DATA_FIELDS;
+use solar_core::datapoint::DataPoint;
+use solar_core::shutdown::Shutdown;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    net::{SocketAddr, TcpListener},
+    path::Path as FsPath,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+//
+// Constants
+//
+const FLEET_CREATE_STMT: &str = concat!(
+    "CREATE TABLE IF NOT EXISTS FleetData ",
+    "(device TEXT NOT NULL, time INTEGER NOT NULL, battery_voltage DOUBLE,",
+    " pv_voltage DOUBLE, load_current DOUBLE, over_discharge DOUBLE,",
+    " battery_max DOUBLE, battery_full BOOLEAN, charging BOOLEAN,",
+    " battery_temp DOUBLE, charge_current DOUBLE, load_onoff BOOLEAN,",
+    " PRIMARY KEY (device, time))"
+);
+/// Retried uploads replace the datapoints stored by the first attempt.
+const FLEET_INSERT: &str = concat!(
+    "INSERT OR REPLACE INTO FleetData(device, time, battery_voltage, pv_voltage,",
+    " load_current, over_discharge, battery_max, battery_full, charging,",
+    " battery_temp, charge_current, load_onoff) VALUES(?,?,?,?,?,?,?,?,?,?,?,?)"
+);
+/// History returned when `from` is not given, in seconds.
+const DEFAULT_HISTORY_RANGE: i64 = 24 * 60 * 60;
+const DASHBOARD_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Solar fleet</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ccc; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+tr.stale td { color: #b00; }
+</style>
+</head>
+<body>
+<h1>Solar fleet</h1>
+<table>
+<thead><tr><th>Device</th><th>Last seen</th><th>Battery V</th><th>PV V</th>
+<th>Charge A</th><th>Load A</th><th>Temp &deg;C</th><th>Load</th></tr></thead>
+<tbody id="devices"></tbody>
+</table>
+<script>
+// The api_key query parameter of this page is passed on to the API.
+async function refresh() {
+  const response = await fetch("/api/fleet/devices" + location.search);
+  const devices = response.ok ? await response.json() : [];
+  const now = Date.now() / 1000;
+  document.getElementById("devices").innerHTML = devices.map(d => {
+    const l = d.latest;
+    const age = d.last_seen === null ? null : Math.round(now - d.last_seen);
+    const cells = l === null ? ["never", "", "", "", "", "", ""] : [
+      age + " s ago", l.battery_voltage.toFixed(2), l.pv_voltage.toFixed(2),
+      l.charge_current.toFixed(2), l.load_current.toFixed(2),
+      l.battery_temp.toFixed(1), l.load_onoff ? "on" : "off"];
+    const row = document.createElement("tr");
+    row.className = age === null || age > 300 ? "stale" : "";
+    for (const text of [d.device, ...cells]) {
+      row.appendChild(document.createElement("td")).textContent = text;
+    }
+    return row.outerHTML;
+  }).join("");
+}
+refresh();
+setInterval(refresh, 10000);
+</script>
+</body>
+</html>
+"#;
+
+//
+// Structs
+//
+/// Datapoints of every device of the fleet, in their own database next to
+/// the one of the local logger.
+struct FleetStore {
+    connection: Connection,
+}
+
+#[derive(Clone)]
+struct FleetState {
+    store: Arc<Mutex<FleetStore>>,
+    /// Upload key of each device.
+    devices: Arc<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Serialize)]
+struct FleetDevice {
+    device: String,
+    /// Time of the newest datapoint, null if none was uploaded yet.
+    last_seen: Option<i64>,
+    datapoints: i64,
+    latest: Option<DataPoint>,
+}
+
+#[derive(Debug, Serialize)]
+struct UploadResult {
+    stored: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryParams {
+    from: Option<String>,
+    to: Option<String>,
+    step: Option<String>,
+}
+
+//
+// implementations
+//
+impl FleetStore {
+    fn open(path: &FsPath) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute(FLEET_CREATE_STMT, ())?;
+        Ok(Self { connection })
+    }
+
+    fn insert(&mut self, device: &str, datapoints: &[DataPoint]) -> rusqlite::Result<usize> {
+        let trans = self.connection.transaction()?;
+        for dp in datapoints {
+            trans.execute(
+                FLEET_INSERT,
+                (
+                    device,
+                    dp.get_time(),
+                    dp.get_battery_voltage(),
+                    dp.get_pv_voltage(),
+                    dp.get_load_current(),
+                    dp.get_over_discharge(),
+                    dp.get_battery_max(),
+                    dp.get_battery_full(),
+                    dp.get_charging(),
+                    dp.get_battery_temp(),
+                    dp.get_charge_current(),
+                    dp.get_load_onoff(),
+                ),
+            )?;
+        }
+        trans.commit()?;
+        Ok(datapoints.len())
+    }
+
+    ///
+    /// Number of datapoints and the newest one of each device which uploaded any.
+    ///
+    fn devices(&self) -> rusqlite::Result<BTreeMap<String, (i64, DataPoint)>> {
+        // SQLite takes the bare columns from the row holding the MAX.
+        let sql = format!(
+            "SELECT device, COUNT(*), MAX(time), {} FROM FleetData GROUP BY device",
+            DATA_FIELDS.join(", ")
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map((), |row| {
+            let mut data = [0.0; 10];
+            for (i, value) in data.iter_mut().enumerate() {
+                *value = row.get(i + 3)?;
+            }
+            Ok((
+                row.get(0)?,
+                (row.get(1)?, DataPoint::with_time(row.get(2)?, &data)),
+            ))
+        })?;
+        rows.collect()
+    }
+
+    ///
+    /// Datapoints of a device in the inclusive range `from..=to`, averaged over
+    /// buckets of `step` seconds if given.
+    ///
+    fn history(
+        &self,
+        device: &str,
+        from: i64,
+        to: i64,
+        step: Option<i64>,
+    ) -> rusqlite::Result<Vec<DataPoint>> {
+        // Times are unique per device, so buckets of a second are the raw datapoints.
+        let sql = format!(
+            "SELECT (time / ?4) * ?4 AS bucket, {} FROM FleetData \
+             WHERE device = ?1 AND time >= ?2 AND time <= ?3 GROUP BY bucket ORDER BY bucket",
+            DATA_FIELDS.map(|f| format!("AVG({})", f)).join(", ")
+        );
+        let mut stmt = self.connection.prepare(&sql)?;
+        let rows = stmt.query_map((device, from, to, step.unwrap_or(1).max(1)), |row| {
+            let mut data = [0.0; 10];
+            for (i, value) in data.iter_mut().enumerate() {
+                *value = row.get(i + 1)?;
+            }
+            Ok(DataPoint::with_time(row.get(0)?, &data))
+        })?;
+        rows.collect()
+    }
+}
+
+///
+/// Run as the central collector of a fleet: accept uploads of the devices in
+/// `fleet.devices` and serve their datapoints and a dashboard on `bind` until
+/// the process receives Ctrl-C or SIGTERM. Reading needs one of
+/// `api.api_keys` if any, like the API of a single logger.
+///
+pub(crate) fn run_fleet_server(config: &Config, bind: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let shutdown = Shutdown::new();
+    {
+        let shutdown = shutdown.clone();
+        ctrlc::set_handler(move || {
+            info!("Shutdown requested.");
+            shutdown.stop();
+        })?;
+    }
+    let listener = match systemd::activated_listener() {
+        Some(listener) => listener,
+        None => TcpListener::bind(bind)?,
+    };
+    let store = FleetStore::open(&config.fleet.database).inspect_err(|e| {
+        error!("{}: {}", config.fleet.database.display(), e);
+    })?;
+    if config.fleet.devices.is_empty() {
+        warn!("No devices in fleet.devices, every upload will be refused.");
+    }
+    let state = FleetState {
+        store: Arc::new(Mutex::new(store)),
+        devices: Arc::new(config.fleet.devices.clone()),
+    };
+    let mut app = Router::new()
+        .route("/api/fleet/devices", get(devices))
+        .route("/api/fleet/devices/{device}/history", get(history))
+        .with_state(state.clone());
+    if !config.api.api_keys.is_empty() {
+        let keys = Arc::new(config.api.api_keys.clone());
+        app = app.layer(middleware::from_fn_with_state(keys, authorize));
+    }
+    // Uploads are authorized by their device key, which is rate limited
+    // like the API keys so it cannot be guessed.
+    app = app.merge(
+        Router::new()
+            .route(UPLOAD_PATH, post(upload))
+            .with_state(state),
+    );
+    if config.api.rate_limit > 0 {
+        let limiter = Arc::new(RateLimiter::new(
+            config.api.rate_limit,
+            config.api.rate_limit_burst,
+        ));
+        app = app.layer(middleware::from_fn_with_state(limiter, rate_limit));
+    }
+    // The dashboard holds no data.
+    let app = app.merge(Router::new().route("/fleet", get(|| async { Html(DASHBOARD_HTML) })));
+    info!(
+        "Fleet server collecting {} devices into {}",
+        config.fleet.devices.len(),
+        config.fleet.database.display()
+    );
+    let server = spawn_router(listener, &config.api, app, shutdown)?;
+    systemd::notify_ready();
+    if server.join().is_err() {
+        error!("API thread panicked.");
+    }
+    systemd::notify_stopping();
+    info!("Fleet server stopped.");
+    Ok(())
+}
+
+///
+/// Store the datapoints of a device, e.g. `{"device":"cabin","datapoints":[...]}`
+/// with the device's key as `Authorization: Bearer <key>` or `X-API-Key: <key>`.
+///
+async fn upload(
+    State(state): State<FleetState>,
+    headers: HeaderMap,
+    Json(upload): Json<Upload>,
+) -> Result<Json<UploadResult>, (StatusCode, String)> {
+    let key = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()));
+    let authorized = match (state.devices.get(&upload.device), key) {
+        (Some(expected), Some(key)) => constant_time_eq(expected, key),
+        _ => false,
+    };
+    if !authorized {
+        warn!("Refused fleet upload for device {:?}.", upload.device);
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Unknown device or invalid upload key.".into(),
+        ));
+    }
+    let mut store = state.store.lock().unwrap_or_else(PoisonError::into_inner);
+    let stored = store
+        .insert(&upload.device, &upload.datapoints)
+        .map_err(|e| {
+            warn!("{}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+    debug!("Stored {} datapoints of {}.", stored, upload.device);
+    Ok(Json(UploadResult { stored }))
+}
+
+///
+/// Every configured device and any other which uploaded datapoints, with its
+/// newest datapoint.
+///
+async fn devices(
+    State(state): State<FleetState>,
+) -> Result<Json<Vec<FleetDevice>>, (StatusCode, String)> {
+    let stored = state
+        .store
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .devices()
+        .map_err(|e| {
+            warn!("{}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })?;
+    let mut devices: Vec<FleetDevice> = state
+        .devices
+        .keys()
+        .filter(|device| !stored.contains_key(*device))
+        .map(|device| FleetDevice {
+            device: device.clone(),
+            last_seen: None,
+            datapoints: 0,
+            latest: None,
+        })
+        .collect();
+    devices.extend(
+        stored
+            .into_iter()
+            .map(|(device, (datapoints, latest))| FleetDevice {
+                device,
+                last_seen: Some(latest.get_time()),
+                datapoints,
+                latest: Some(latest),
+            }),
+    );
+    devices.sort_by(|a, b| a.device.cmp(&b.device));
+    Ok(Json(devices))
+}
+
+///
+/// Datapoints of one device, with `from`, `to` and `step` as for `/api/history`.
+///
+async fn history(
+    State(state): State<FleetState>,
+    Path(device): Path<String>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Json<Vec<DataPoint>>, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let to = match &params.to {
+        Some(to) => parse_timestamp(to).map_err(bad_request)?,
+        None => Local::now().timestamp(),
+    };
+    let from = match &params.from {
+        Some(from) => parse_timestamp(from).map_err(bad_request)?,
+        None => to - DEFAULT_HISTORY_RANGE,
+    };
+    let step = match &params.step {
+        Some(step) => Some(parse_duration(step).map_err(bad_request)? as i64),
+        None => None,
+    };
+    let store = state.store.lock().unwrap_or_else(PoisonError::into_inner);
+    let datapoints = store.history(&device, from, to, step).map_err(|e| {
+        warn!("{}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?;
+    if datapoints.is_empty() && !state.devices.contains_key(&device) {
+        return Err((StatusCode::NOT_FOUND, format!("Unknown device {}.", device)));
+    }
+    Ok(Json(datapoints))
+}
@@ -0,0 +1,180 @@
+use crate::config::{Config, ForecastConfig, ForecastProvider};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use solar_core::database::Database;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Delay before fetching again after a failed request.
+const RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+//
+// Structs
+//
+#[derive(Debug, Deserialize)]
+struct ForecastSolarResponse {
+    result: ForecastSolarResult,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForecastSolarResult {
+    /// Energy of the period ending at the local time `YYYY-MM-DD HH:MM:SS`.
+    watt_hours_period: HashMap<String, f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SolcastResponse {
+    forecasts: Vec<SolcastPeriod>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SolcastPeriod {
+    /// Average power over the period in kW.
+    pv_estimate: f64,
+    period_end: String,
+    /// ISO 8601 duration, e.g. `PT30M`.
+    period: String,
+}
+
+///
+/// Fetch the forecast every `refresh_interval_min` from a background thread
+/// until `running` is cleared.
+///
+pub(crate) fn spawn_fetcher(
+    config: &Config,
+    running: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    let forecast = config.forecast.clone();
+    let database_config = config.database.clone();
+    info!("Fetching solar forecasts from {:?}", forecast.provider);
+    thread::Builder::new()
+        .name("forecast".into())
+        .spawn(move || {
+            let mut database = match Database::new(&database_config) {
+                Ok(database) => database,
+                Err(e) => return error!("Not fetching solar forecasts: {}", e),
+            };
+            let interval = Duration::from_secs(forecast.refresh_interval_min.max(1) * 60);
+            while running.load(Ordering::SeqCst) {
+                let delay = match fetch(&forecast) {
+                    Ok(hours) => {
+                        let total: f64 = hours.values().sum();
+                        info!(
+                            "Stored a forecast of {} hours, {:.0} Wh.",
+                            hours.len(),
+                            total
+                        );
+                        database.store_forecast(&hours);
+                        interval
+                    }
+                    Err(e) => {
+                        warn!("Forecast: {}", e);
+                        RETRY_DELAY.min(interval)
+                    }
+                };
+                let start = Instant::now();
+                while running.load(Ordering::SeqCst) && start.elapsed() < delay {
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        })
+}
+
+///
+/// Request a forecast and sum it up per hour as (hour start, Wh).
+///
+fn fetch(config: &ForecastConfig) -> Result<BTreeMap<i64, f64>, String> {
+    let periods = match config.provider {
+        ForecastProvider::ForecastSolar => fetch_forecast_solar(config)?,
+        ForecastProvider::Solcast => fetch_solcast(config)?,
+    };
+    let mut hours = BTreeMap::new();
+    for (end, wh) in periods {
+        // A period ending on the hour belongs to the hour before.
+        *hours
+            .entry((end - 1).div_euclid(3600) * 3600)
+            .or_insert(0.0) += wh;
+    }
+    Ok(hours)
+}
+
+///
+/// forecast.solar reports local times of the location, which is assumed to
+/// share the time zone of this machine.
+///
+fn fetch_forecast_solar(config: &ForecastConfig) -> Result<Vec<(i64, f64)>, String> {
+    let key = config
+        .api_key
+        .as_ref()
+        .map_or(String::new(), |key| format!("/{}", key));
+    let url = format!(
+        "https://api.forecast.solar{}/estimate/{}/{}/{}/{}/{}",
+        key, config.latitude, config.longitude, config.declination, config.azimuth, config.kwp
+    );
+    let response: ForecastSolarResponse = ureq::get(&url)
+        .call()
+        .and_then(|mut r| r.body_mut().read_json())
+        .map_err(|e| e.to_string())?;
+    response
+        .result
+        .watt_hours_period
+        .iter()
+        .map(|(time, wh)| {
+            let end = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .and_then(|t| Local.from_local_datetime(&t).earliest())
+                .ok_or_else(|| format!("Invalid time {}", time))?;
+            Ok((end.timestamp(), *wh))
+        })
+        .collect()
+}
+
+fn fetch_solcast(config: &ForecastConfig) -> Result<Vec<(i64, f64)>, String> {
+    let api_key = config
+        .api_key
+        .as_ref()
+        .ok_or("Solcast requires forecast.api_key.")?;
+    let url = format!(
+        "https://api.solcast.com.au/rooftop_sites/{}/forecasts?format=json",
+        config.resource_id
+    );
+    let response: SolcastResponse = ureq::get(&url)
+        .header("Authorization", &format!("Bearer {}", api_key))
+        .call()
+        .and_then(|mut r| r.body_mut().read_json())
+        .map_err(|e| e.to_string())?;
+    response
+        .forecasts
+        .iter()
+        .map(|p| {
+            let end = DateTime::parse_from_rfc3339(&p.period_end)
+                .map_err(|e| format!("{}: {}", p.period_end, e))?;
+            let hours =
+                period_hours(&p.period).ok_or_else(|| format!("Invalid period {}", p.period))?;
+            Ok((end.timestamp(), p.pv_estimate * 1000.0 * hours))
+        })
+        .collect()
+}
+
+///
+/// Length of an ISO 8601 duration like `PT30M` or `PT1H` in hours.
+///
+fn period_hours(period: &str) -> Option<f64> {
+    let value = period.strip_prefix("PT")?;
+    if let Some(minutes) = value.strip_suffix('M') {
+        minutes.parse::<f64>().ok().map(|m| m / 60.0)
+    } else {
+        value.strip_suffix('H')?.parse().ok()
+    }
+}
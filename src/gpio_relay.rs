@@ -0,0 +1,77 @@
+use crate::config::LoadConfig;
+use std::io;
+
+//
+// Structs
+//
+/// A load relay driven by a GPIO line of the host, e.g. a Raspberry Pi, used
+/// instead of the LON/LOFF serial commands when `load.backend = "gpio"`.
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+pub(crate) struct GpioRelay {
+    line: gpio_cdev::LineHandle,
+    active_low: bool,
+}
+
+#[cfg(not(all(feature = "gpio", target_os = "linux")))]
+pub(crate) struct GpioRelay;
+
+//
+// implementations
+//
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+impl GpioRelay {
+    ///
+    /// Request the configured line as an output. The relay starts switched off.
+    ///
+    pub(crate) fn new(config: &LoadConfig) -> io::Result<Self> {
+        use gpio_cdev::{Chip, LineRequestFlags};
+
+        let context = |e: gpio_cdev::Error| {
+            io::Error::other(format!(
+                "GPIO line {} of {}: {}",
+                config.gpio_pin,
+                config.gpio_chip.display(),
+                e
+            ))
+        };
+        let mut chip = Chip::new(&config.gpio_chip).map_err(context)?;
+        let line = chip
+            .get_line(config.gpio_pin)
+            .and_then(|line| {
+                line.request(
+                    LineRequestFlags::OUTPUT,
+                    u8::from(config.active_low),
+                    "rust-solar",
+                )
+            })
+            .map_err(context)?;
+        info!(
+            "Using GPIO line {} of {} for load control.",
+            config.gpio_pin,
+            config.gpio_chip.display()
+        );
+        Ok(Self {
+            line,
+            active_low: config.active_low,
+        })
+    }
+
+    pub(crate) fn set(&self, on: bool) -> io::Result<()> {
+        self.line
+            .set_value(u8::from(on != self.active_low))
+            .map_err(io::Error::other)
+    }
+}
+
+#[cfg(not(all(feature = "gpio", target_os = "linux")))]
+impl GpioRelay {
+    pub(crate) fn new(_config: &LoadConfig) -> io::Result<Self> {
+        Err(io::Error::other(
+            "GPIO load control requires building with the `gpio` feature on Linux.",
+        ))
+    }
+
+    pub(crate) fn set(&self, _on: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use axum::{extract::State, http::StatusCode, routing::get, routing::post, Json, Router};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use solar_core::database::{Aggregation, Database, DATA_FIELDS};
+use solar_core::forecast::FORECAST_METRIC;
+use solar_core::rollup::ROLLUP_METRICS;
+use std::sync::{Arc, Mutex, PoisonError};
+
+//
+// Structs
+//
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryRequest {
+    range: Range,
+    #[serde(default)]
+    interval_ms: Option<i64>,
+    #[serde(default)]
+    max_data_points: Option<i64>,
+    targets: Vec<Target>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    target: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TimeSeries {
+    target: String,
+    /// Pairs of [value, unix milliseconds].
+    datapoints: Vec<(f64, i64)>,
+}
+
+///
+/// Routes implementing the Grafana SimpleJSON / JSON datasource contract below
+/// `/grafana`: `GET /` as connection test, `POST /search` listing the metrics
+/// and `POST /query` returning time series.
+///
+pub(crate) fn router(database: Arc<Mutex<Database>>) -> Router {
+    Router::new()
+        .route("/grafana", get(|| async { StatusCode::OK }))
+        .route("/grafana/", get(|| async { StatusCode::OK }))
+        .route("/grafana/search", post(search))
+        .route("/grafana/query", post(query))
+        .with_state(database)
+}
+
+async fn search() -> Json<Vec<&'static str>> {
+    Json(
+        DATA_FIELDS
+            .iter()
+            .copied()
+            .chain(ROLLUP_METRICS.iter().map(|(name, _)| *name))
+            .chain([FORECAST_METRIC])
+            .collect(),
+    )
+}
+
+///
+/// Data fields are averaged over the requested interval, rollup metrics are
+/// read from the hourly rollups and so have a resolution of at least an hour.
+///
+async fn query(
+    State(database): State<Arc<Mutex<Database>>>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<Vec<TimeSeries>>, (StatusCode, String)> {
+    let parse = |value: &str| {
+        DateTime::parse_from_rfc3339(value)
+            .map(|t| t.timestamp())
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("{}: {}", value, e)))
+    };
+    let from = parse(&request.range.from)?;
+    let to = parse(&request.range.to)?;
+    let mut interval = request.interval_ms.unwrap_or(60_000) / 1000;
+    if let Some(max_points) = request.max_data_points.filter(|m| *m > 0) {
+        interval = interval.max((to - from) / max_points);
+    }
+    let interval = interval.max(1);
+
+    let database = database.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut series = Vec::new();
+    for target in request.targets.iter().filter_map(|t| t.target.as_deref()) {
+        let points = if DATA_FIELDS.contains(&target) {
+            database
+                .aggregate(Aggregation::Avg, target, interval, from, to)
+                .map(|buckets| buckets.iter().map(|b| (b.time, b.value)).collect())
+        } else {
+            database.rollup_series(target, interval, from, to)
+        };
+        let points = points.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        series.push(TimeSeries {
+            target: target.to_string(),
+            datapoints: points
+                .into_iter()
+                .map(|(time, value)| (value, time * 1000))
+                .collect(),
+        });
+    }
+    Ok(Json(series))
+}
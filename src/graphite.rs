@@ -0,0 +1,147 @@
+use crate::config::{GraphiteConfig, StatsdConfig};
+use solar_core::datapoint::DataPoint;
+use std::{
+    io::{self, Write},
+    net::{TcpStream, UdpSocket},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Delay before reconnecting to Graphite, lines are dropped meanwhile.
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+//
+// Structs
+//
+/// Sends datapoints and the internal counters to Graphite using the plaintext
+/// protocol, `<prefix>.<field> <value> <timestamp>`, from a background thread.
+pub(crate) struct GraphiteWriter {
+    lines: Sender<String>,
+    prefix: String,
+    datapoints: u64,
+    read_errors: u64,
+}
+
+/// Sends datapoints as StatsD gauges and the internal counters as counters.
+pub(crate) struct StatsdWriter {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+//
+// implementations
+//
+impl GraphiteWriter {
+    pub(crate) fn new(config: &GraphiteConfig) -> io::Result<Self> {
+        let (tx, rx) = mpsc::channel::<String>();
+        let address = config.address.clone();
+        thread::Builder::new()
+            .name("graphite".into())
+            .spawn(move || {
+                let mut stream: Option<TcpStream> = None;
+                let mut next_attempt = Instant::now();
+                for lines in rx {
+                    if stream.is_none() && Instant::now() >= next_attempt {
+                        match TcpStream::connect(&address) {
+                            Ok(s) => stream = Some(s),
+                            Err(e) => {
+                                warn!("Graphite {}: {}", address, e);
+                                next_attempt = Instant::now() + RECONNECT_DELAY;
+                            }
+                        }
+                    }
+                    if let Some(s) = &mut stream {
+                        if let Err(e) = s.write_all(lines.as_bytes()) {
+                            warn!("Graphite {}: {}", address, e);
+                            stream = None;
+                            next_attempt = Instant::now() + RECONNECT_DELAY;
+                        }
+                    }
+                }
+            })?;
+        info!("Sending metrics to Graphite at {}", config.address);
+        Ok(Self {
+            lines: tx,
+            prefix: config.prefix.clone(),
+            datapoints: 0,
+            read_errors: 0,
+        })
+    }
+
+    pub(crate) fn push(&mut self, datapoint: &DataPoint) {
+        self.datapoints += 1;
+        let time = datapoint.get_time();
+        let mut lines = String::new();
+        for (field, value) in fields(datapoint) {
+            lines += &format!("{}.{} {} {}\n", self.prefix, field, value, time);
+        }
+        lines += &self.counters(time);
+        let _ = self.lines.send(lines);
+    }
+
+    pub(crate) fn read_error(&mut self) {
+        self.read_errors += 1;
+        let _ = self
+            .lines
+            .send(self.counters(chrono::Local::now().timestamp()));
+    }
+
+    fn counters(&self, time: i64) -> String {
+        format!(
+            "{0}.datapoints {1} {3}\n{0}.read_errors {2} {3}\n",
+            self.prefix, self.datapoints, self.read_errors, time
+        )
+    }
+}
+
+impl StatsdWriter {
+    pub(crate) fn new(config: &StatsdConfig) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(&config.address)?;
+        socket.set_nonblocking(true)?;
+        info!("Sending metrics to StatsD at {}", config.address);
+        Ok(Self {
+            socket,
+            prefix: config.prefix.clone(),
+        })
+    }
+
+    pub(crate) fn push(&self, datapoint: &DataPoint) {
+        let mut packet = String::new();
+        for (field, value) in fields(datapoint) {
+            packet += &format!("{}.{}:{}|g\n", self.prefix, field, value);
+        }
+        packet += &format!("{}.datapoints:1|c", self.prefix);
+        self.send(&packet);
+    }
+
+    pub(crate) fn read_error(&self) {
+        self.send(&format!("{}.read_errors:1|c", self.prefix));
+    }
+
+    ///
+    /// Send a packet, losing it when the daemon is unreachable as usual for StatsD.
+    ///
+    fn send(&self, packet: &str) {
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            debug!("StatsD: {}", e);
+        }
+    }
+}
+
+///
+/// The values of a datapoint by field name, without the timestamp.
+///
+fn fields(datapoint: &DataPoint) -> Vec<(String, serde_json::Value)> {
+    match serde_json::to_value(datapoint) {
+        Ok(serde_json::Value::Object(values)) => values
+            .into_iter()
+            .filter(|(field, _)| field != "timestamp")
+            .collect(),
+        _ => Vec::new(),
+    }
+}
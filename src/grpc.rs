@@ -0,0 +1,231 @@
+use crate::config::GrpcConfig;
+use crate::control_auth::ControlAuth;
+use crate::datalogger::ControlSender;
+use solar_core::database::Database;
+use solar_core::datapoint::DataPoint;
+use solar_core::shutdown::Shutdown;
+use std::{io, sync::Arc, thread::JoinHandle};
+use tokio::sync::watch;
+
+#[cfg(feature = "grpc")]
+mod proto {
+    tonic::include_proto!("solar.v1");
+}
+
+#[cfg(feature = "grpc")]
+use {
+    crate::api::constant_time_eq,
+    crate::control_auth::Credentials,
+    crate::datalogger::{CommandSource, LoadCommand},
+    chrono::Local,
+    proto::solar_server::{Solar, SolarServer},
+    std::{
+        net::TcpListener,
+        pin::Pin,
+        sync::{Mutex, PoisonError},
+        thread,
+    },
+    tokio_stream::{wrappers::WatchStream, Stream, StreamExt},
+    tonic::{Request, Response, Status},
+};
+
+//
+// Constants
+//
+/// Range returned by `GetHistory` when `from` is omitted.
+#[cfg(feature = "grpc")]
+const DEFAULT_HISTORY_RANGE: i64 = 24 * 60 * 60;
+
+//
+// Structs
+//
+#[cfg(feature = "grpc")]
+struct SolarService {
+    latest: watch::Receiver<Option<DataPoint>>,
+    database: Mutex<Database>,
+    /// `None` unless `grpc.load_control` is enabled.
+    load_control: Option<ControlSender>,
+    control_auth: Arc<ControlAuth>,
+}
+
+//
+// implementations
+//
+#[cfg(feature = "grpc")]
+impl From<DataPoint> for proto::DataPoint {
+    fn from(datapoint: DataPoint) -> Self {
+        Self {
+            timestamp: datapoint.get_time(),
+            battery_voltage: datapoint.get_battery_voltage(),
+            pv_voltage: datapoint.get_pv_voltage(),
+            load_current: datapoint.get_load_current(),
+            over_discharge: datapoint.get_over_discharge(),
+            battery_max: datapoint.get_battery_max(),
+            battery_full: datapoint.get_battery_full(),
+            charging: datapoint.get_charging(),
+            battery_temp: datapoint.get_battery_temp(),
+            charge_current: datapoint.get_charge_current(),
+            load_onoff: datapoint.get_load_onoff(),
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+#[tonic::async_trait]
+impl Solar for SolarService {
+    type SubscribeStream = Pin<Box<dyn Stream<Item = Result<proto::DataPoint, Status>> + Send>>;
+
+    async fn subscribe(
+        &self,
+        _request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let datapoints = WatchStream::new(self.latest.clone())
+            .filter_map(|latest| latest.map(|dp| Ok(proto::DataPoint::from(dp))));
+        Ok(Response::new(Box::pin(datapoints)))
+    }
+
+    async fn get_history(
+        &self,
+        request: Request<proto::HistoryRequest>,
+    ) -> Result<Response<proto::HistoryResponse>, Status> {
+        let request = request.into_inner();
+        let to = request.to.unwrap_or_else(|| Local::now().timestamp());
+        let from = request.from.unwrap_or(to - DEFAULT_HISTORY_RANGE);
+        let database = self.database.lock().unwrap_or_else(PoisonError::into_inner);
+        let datapoints = match request.step_s {
+            Some(step) if step <= 0 => {
+                return Err(Status::invalid_argument("step_s must be positive."))
+            }
+            Some(step) => database.history(from, to, step),
+            None => database.datapoints_between(from, to),
+        };
+        let datapoints = datapoints.map_err(|e| {
+            warn!("{}", e);
+            Status::internal(e.to_string())
+        })?;
+        Ok(Response::new(proto::HistoryResponse {
+            datapoints: datapoints.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn set_load(
+        &self,
+        request: Request<proto::SetLoadRequest>,
+    ) -> Result<Response<proto::SetLoadResponse>, Status> {
+        let Some(load_control) = &self.load_control else {
+            return Err(Status::permission_denied(
+                "Load control is disabled, set load_control = true in the [grpc] section.",
+            ));
+        };
+        let client = request
+            .remote_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+        let request = request.into_inner();
+        let on = request.on;
+        let credentials = Credentials {
+            pin: Some(request.pin).filter(|pin| !pin.is_empty()),
+            token: Some(request.token).filter(|token| !token.is_empty()),
+            time: Some(request.time).filter(|&time| time != 0),
+        };
+        self.control_auth
+            .check(on, &credentials, &client)
+            .map_err(Status::permission_denied)?;
+        load_control
+            .send(
+                LoadCommand {
+                    on,
+                    source: CommandSource::Grpc,
+                }
+                .into(),
+            )
+            .map_err(|_| Status::unavailable("The datalogger has stopped."))?;
+        info!("Load switched {} over gRPC.", if on { "on" } else { "off" });
+        Ok(Response::new(proto::SetLoadResponse {}))
+    }
+}
+
+///
+/// Serve the gRPC API from a background thread until `running` is cleared.
+/// With `api_keys` set, calls need one of them as `authorization: Bearer <key>`
+/// or `x-api-key` metadata.
+///
+#[cfg(feature = "grpc")]
+pub(crate) fn spawn_grpc_server(
+    config: &GrpcConfig,
+    api_keys: &[String],
+    latest: watch::Receiver<Option<DataPoint>>,
+    database: Database,
+    load_control: Option<ControlSender>,
+    control_auth: Arc<ControlAuth>,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(config.bind)?;
+    listener.set_nonblocking(true)?;
+    info!("gRPC API listening on {}", listener.local_addr()?);
+    if api_keys.is_empty() && load_control.is_some() {
+        warn!("gRPC load control is enabled without api.api_keys, anyone on the network can switch the load.");
+    }
+    let service = SolarService {
+        latest,
+        database: Mutex::new(database),
+        load_control: config.load_control.then_some(load_control).flatten(),
+        control_auth,
+    };
+    let keys = api_keys.to_vec();
+    let authorize = move |request: Request<()>| {
+        if keys.is_empty() {
+            return Ok(request);
+        }
+        let metadata = request.metadata();
+        let key = metadata
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .or_else(|| metadata.get("x-api-key").and_then(|v| v.to_str().ok()));
+        match key {
+            Some(key) if keys.iter().any(|k| constant_time_eq(k, key)) => Ok(request),
+            _ => Err(Status::unauthenticated("Missing or invalid API key.")),
+        }
+    };
+
+    thread::Builder::new().name("grpc".into()).spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(r) => r,
+            Err(e) => return error!("{}", e),
+        };
+        runtime.block_on(async move {
+            let listener = match tokio::net::TcpListener::from_std(listener) {
+                Ok(l) => tokio_stream::wrappers::TcpListenerStream::new(l),
+                Err(e) => return error!("{}", e),
+            };
+            let shutdown = async move { shutdown.stopped().await };
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(SolarServer::with_interceptor(service, authorize))
+                .serve_with_incoming_shutdown(listener, shutdown)
+                .await
+            {
+                error!("{}", e);
+            }
+        });
+        info!("gRPC API stopped.");
+    })
+}
+
+#[cfg(not(feature = "grpc"))]
+pub(crate) fn spawn_grpc_server(
+    _config: &GrpcConfig,
+    _api_keys: &[String],
+    _latest: watch::Receiver<Option<DataPoint>>,
+    _database: Database,
+    _load_control: Option<ControlSender>,
+    _control_auth: Arc<ControlAuth>,
+    _shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    Err(io::Error::other(
+        "The gRPC API requires building with the `grpc` feature.",
+    ))
+}
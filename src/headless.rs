@@ -0,0 +1,132 @@
+use crate::api::{spawn_api_server, DeviceInfo, LiveData};
+use crate::config::Config;
+use crate::datalogger::spawn_datalogger;
+use crate::grpc::spawn_grpc_server;
+use crate::socket::spawn_socket_server;
+use crate::systemd;
+use solar_core::database::Database;
+use solar_core::shutdown::Shutdown;
+use std::{
+    error::Error,
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+};
+use tokio::sync::{broadcast::error::RecvError, watch};
+
+///
+/// Run the serial reader and database without a terminal UI until the
+/// process receives Ctrl-C or SIGTERM, optionally serving the HTTP API.
+///
+pub(crate) fn run_headless(
+    port: &str,
+    config: &Config,
+    api_bind: Option<SocketAddr>,
+) -> Result<(), Box<dyn Error>> {
+    let shutdown = Shutdown::new();
+    {
+        let shutdown = shutdown.clone();
+        ctrlc::set_handler(move || {
+            info!("Shutdown requested.");
+            shutdown.stop();
+        })?;
+    }
+
+    // Bind before opening the port so address errors are reported right away.
+    let listener = match api_bind {
+        Some(bind) => match systemd::activated_listener() {
+            Some(listener) => Some(listener),
+            None => Some(TcpListener::bind(bind)?),
+        },
+        None => None,
+    };
+
+    info!("Starting headless datalogger on {}", port);
+    let datalogger = spawn_datalogger(port, config, shutdown.clone())?;
+    let (latest_tx, latest_rx) = watch::channel(None);
+    let api = match listener {
+        Some(listener) => Some(spawn_api_server(
+            listener,
+            &config.api,
+            LiveData {
+                latest: latest_rx,
+                recent: datalogger.recent.clone(),
+                health: Arc::clone(&datalogger.health),
+                load_control: config.api.load_control.then(|| datalogger.control.clone()),
+                control_auth: Arc::clone(&datalogger.control_auth),
+            },
+            Database::new(&config.database)?,
+            DeviceInfo::new(port, config.serial.baud_rate),
+            shutdown.clone(),
+        )?),
+        None => None,
+    };
+    let grpc = if config.grpc.enabled {
+        Some(spawn_grpc_server(
+            &config.grpc,
+            &config.api.api_keys,
+            latest_tx.subscribe(),
+            Database::new(&config.database)?,
+            Some(datalogger.control.clone()),
+            Arc::clone(&datalogger.control_auth),
+            shutdown.clone(),
+        )?)
+    } else {
+        None
+    };
+    // The socket is on by default, so a missing /run directory or permission
+    // must not prevent running as an unprivileged user.
+    let socket = if config.socket.enabled {
+        let load = &config.load;
+        if config.socket.control && (load.control_pin.is_some() || load.control_secret.is_some()) {
+            warn!(
+                "Load commands on {} need no control_pin, anyone who can connect can switch the load.",
+                config.socket.path.display()
+            );
+        }
+        spawn_socket_server(
+            &config.socket,
+            latest_tx.subscribe(),
+            datalogger.control.clone(),
+            shutdown.clone(),
+        )
+        .inspect_err(|e| warn!("Unix socket disabled: {}", e))
+        .ok()
+    } else {
+        None
+    };
+    latest_tx.send_replace(Some(datalogger.initial));
+    systemd::notify_ready();
+    // The bus closes once the datalogger stops on Ctrl-C or SIGTERM.
+    let mut datapoints = datalogger.datapoints;
+    loop {
+        match datapoints.blocking_recv() {
+            Ok(dp) => {
+                debug!("{}", dp);
+                systemd::notify_watchdog();
+                latest_tx.send_replace(Some(dp));
+            }
+            Err(RecvError::Lagged(missed)) => warn!("Missed {} datapoints.", missed),
+            Err(RecvError::Closed) => break,
+        }
+    }
+    shutdown.stop();
+    systemd::notify_stopping();
+    datalogger.thread.stop();
+    if let Some(api) = api {
+        if api.join().is_err() {
+            error!("API thread panicked.");
+        }
+    }
+    if let Some(grpc) = grpc {
+        if grpc.join().is_err() {
+            error!("gRPC thread panicked.");
+        }
+    }
+    if let Some(socket) = socket {
+        if socket.join().is_err() {
+            error!("Unix socket thread panicked.");
+        }
+    }
+    info!("Headless datalogger stopped.");
+    Ok(())
+}
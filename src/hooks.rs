@@ -0,0 +1,78 @@
+use crate::config::HookConfig;
+use chrono::{DateTime, Local};
+use solar_core::alerts::Event;
+use std::{
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// How often a running command is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+///
+/// Run the command of a hook with the event in its environment from a new
+/// thread, so a slow command delays neither the other hooks nor the
+/// notifiers. It is killed after `timeout_s` seconds.
+///
+pub(crate) fn run_hook(hook: &HookConfig, event: &Event) {
+    let (owned_hook, event) = (hook.clone(), event.clone());
+    let spawned = thread::Builder::new()
+        .name("hook".into())
+        .spawn(move || run(&owned_hook, &event));
+    if let Err(e) = spawned {
+        warn!("Hook {}: {}", hook_label(hook), e);
+    }
+}
+
+fn run(hook: &HookConfig, event: &Event) {
+    let label = hook_label(hook);
+    let time = DateTime::from_timestamp(event.timestamp, 0)
+        .map(|t| t.with_timezone(&Local).to_rfc3339())
+        .unwrap_or_default();
+    let child = Command::new(&hook.command)
+        .args(&hook.args)
+        .env("SOLAR_EVENT_KIND", event.kind.name())
+        .env("SOLAR_EVENT_NAME", &event.name)
+        .env("SOLAR_EVENT_SEVERITY", event.severity.name())
+        .env("SOLAR_EVENT_MESSAGE", &event.message)
+        .env("SOLAR_EVENT_VALUE", event.value.to_string())
+        .env("SOLAR_EVENT_TIMESTAMP", event.timestamp.to_string())
+        .env("SOLAR_EVENT_TIME", time)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => return error!("Hook {} for {}: {}", label, event.name, e),
+    };
+    let deadline = Instant::now() + Duration::from_secs(hook.timeout_s);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => {
+                return info!("Hook {} ran for {}.", label, event.name)
+            }
+            Ok(Some(status)) => {
+                return warn!("Hook {} for {} failed: {}", label, event.name, status)
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return warn!(
+                    "Hook {} for {} killed after {} s.",
+                    label, event.name, hook.timeout_s
+                );
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return warn!("Hook {} for {}: {}", label, event.name, e),
+        }
+    }
+}
+
+fn hook_label(hook: &HookConfig) -> &str {
+    hook.command.rsplit('/').next().unwrap_or(&hook.command)
+}
@@ -0,0 +1,98 @@
+use crate::batch_uploader::{BatchLimits, BatchUploader, BatchWriter};
+use crate::config::InfluxConfig;
+use solar_core::datapoint::DataPoint;
+use std::{fmt::Write, io};
+
+//
+// Structs
+//
+/// Pushes datapoints to an InfluxDB compatible endpoint from a background
+/// thread, keeping a bounded backlog while the endpoint is unreachable.
+pub(crate) struct InfluxWriter {
+    uploader: BatchUploader<String>,
+    measurement: String,
+    device: String,
+}
+
+/// Writes batches of line protocol lines.
+struct LineWriter {
+    config: InfluxConfig,
+}
+
+//
+// implementations
+//
+impl InfluxWriter {
+    pub(crate) fn new(config: &InfluxConfig) -> io::Result<Self> {
+        let limits = BatchLimits {
+            batch_size: config.batch_size,
+            max_backlog: config.max_backlog,
+        };
+        let writer = LineWriter {
+            config: config.clone(),
+        };
+        let uploader = BatchUploader::spawn("influx", "Influx write", limits, writer, Vec::new())?;
+        info!("Pushing datapoints to {}", config.url);
+        Ok(Self {
+            uploader,
+            measurement: escape(&config.measurement),
+            device: escape(&config.device),
+        })
+    }
+
+    ///
+    /// Queue a datapoint for the next batch.
+    ///
+    pub(crate) fn push(&self, datapoint: &DataPoint) {
+        if let Some(line) = self.line_protocol(datapoint) {
+            self.uploader.push(line);
+        }
+    }
+
+    ///
+    /// Format a datapoint as a line protocol line with second precision, e.g.
+    /// `solar,device=tracer battery_voltage=12.5,... 1700000000`.
+    ///
+    fn line_protocol(&self, datapoint: &DataPoint) -> Option<String> {
+        let serde_json::Value::Object(values) = serde_json::to_value(datapoint).ok()? else {
+            return None;
+        };
+        let mut line = format!("{},device={} ", self.measurement, self.device);
+        let fields = values.iter().filter(|(field, _)| *field != "timestamp");
+        for (i, (field, value)) in fields.enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let _ = write!(line, "{}{}={}", separator, field, value);
+        }
+        let _ = write!(line, " {}", datapoint.get_time());
+        Some(line)
+    }
+}
+
+impl BatchWriter for LineWriter {
+    type Item = String;
+
+    fn write(&mut self, batch: &[String]) -> Result<(), ureq::Error> {
+        let body = batch
+            .iter()
+            .fold(String::new(), |body, line| body + line.as_str() + "\n");
+        let mut request = ureq::post(&self.config.url).header("Content-Type", "text/plain");
+        if let Some(token) = &self.config.token {
+            request = request.header("Authorization", &format!("Token {}", token));
+        }
+        request.send(body)?;
+        Ok(())
+    }
+}
+
+///
+/// Escape commas, spaces and equal signs in measurement and tag names.
+///
+fn escape(value: &str) -> String {
+    value.chars().fold(String::new(), |mut out, c| {
+        if matches!(c, ',' | ' ' | '=') {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
@@ -0,0 +1,74 @@
+use std::{
+    env,
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+//
+// Structs
+//
+/// Exclusive locks on the serial port and database held for the lifetime of a
+/// datalogger. The locks are released by the OS when the files are closed,
+/// so a crashed instance never leaves a stale lock behind.
+pub(crate) struct InstanceLock {
+    _files: Vec<File>,
+}
+
+//
+// implementations
+//
+impl InstanceLock {
+    ///
+    /// Lock the given serial port and, unless `None`, the database, failing with
+    /// a descriptive error when another instance already holds either of them.
+    ///
+    pub(crate) fn acquire(port_name: &str, database: Option<&Path>) -> io::Result<Self> {
+        let port_lock = env::temp_dir().join(format!(
+            "rust-solar-{}.lock",
+            port_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        ));
+        let mut files = vec![lock(&port_lock, &format!("serial port {}", port_name))?];
+        if let Some(database) = database {
+            let mut database_lock = database.as_os_str().to_owned();
+            database_lock.push(".lock");
+            files.push(lock(
+                &PathBuf::from(database_lock),
+                &format!("database {}", database.display()),
+            )?);
+        }
+        Ok(Self { _files: files })
+    }
+}
+
+fn lock(path: &Path, resource: &str) -> io::Result<File> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut pid = String::new();
+            let _ = file.read_to_string(&mut pid);
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!(
+                    "Another instance (pid {}) is already using the {}, lock file {}.",
+                    pid.trim(),
+                    resource,
+                    path.display()
+                ),
+            ));
+        }
+        Err(TryLockError::Error(e)) => return Err(e),
+    }
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", process::id())?;
+    file.flush()?;
+    Ok(file)
+}
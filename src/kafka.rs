@@ -0,0 +1,80 @@
+use crate::config::KafkaConfig;
+use solar_core::datapoint::DataPoint;
+use std::io;
+
+//
+// Structs
+//
+/// Publishes each datapoint as JSON to a Kafka topic, keyed by the device id
+/// so that all datapoints of a site land in the same partition, in order.
+#[cfg(feature = "kafka")]
+pub(crate) struct KafkaProducer {
+    producer: rdkafka::producer::ThreadedProducer<rdkafka::producer::DefaultProducerContext>,
+    topic: String,
+    key: String,
+}
+
+#[cfg(not(feature = "kafka"))]
+pub(crate) struct KafkaProducer;
+
+//
+// implementations
+//
+#[cfg(feature = "kafka")]
+impl KafkaProducer {
+    ///
+    /// Create the producer, which connects to the brokers and retries sending
+    /// from librdkafka's own threads. `properties` are passed to librdkafka
+    /// as is, e.g. for SASL or TLS settings.
+    ///
+    pub(crate) fn new(config: &KafkaConfig) -> io::Result<Self> {
+        let mut client_config = rdkafka::ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("client.id", &config.client_id)
+            .set("message.timeout.ms", config.message_timeout_ms.to_string());
+        for (name, value) in &config.properties {
+            client_config.set(name, value);
+        }
+        let producer = client_config
+            .create()
+            .map_err(|e| io::Error::other(format!("kafka: {}", e)))?;
+        info!(
+            "Publishing datapoints to Kafka topic {} on {}",
+            config.topic, config.brokers
+        );
+        Ok(Self {
+            producer,
+            topic: config.topic.clone(),
+            key: config.device.clone(),
+        })
+    }
+
+    ///
+    /// Queue a datapoint without blocking, it is dropped while the queue is
+    /// full or if it cannot be delivered within `message_timeout_ms`.
+    ///
+    pub(crate) fn publish(&self, datapoint: &DataPoint) {
+        let Ok(payload) = serde_json::to_string(datapoint) else {
+            return;
+        };
+        let record = rdkafka::producer::BaseRecord::to(&self.topic)
+            .key(&self.key)
+            .payload(&payload)
+            .timestamp(datapoint.get_time() * 1000);
+        if let Err((e, _)) = self.producer.send(record) {
+            debug!("Kafka: {}", e);
+        }
+    }
+}
+
+#[cfg(not(feature = "kafka"))]
+impl KafkaProducer {
+    pub(crate) fn new(_config: &KafkaConfig) -> io::Result<Self> {
+        Err(io::Error::other(
+            "Kafka publishing requires building with the `kafka` feature.",
+        ))
+    }
+
+    pub(crate) fn publish(&self, _datapoint: &DataPoint) {}
+}
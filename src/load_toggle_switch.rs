@@ -7,8 +7,8 @@ use tui::{
     widgets::{Block, Borders},
 };
 
-#[derive(Debug, Clone)]
-/// A custom widget for a toggle switch.
+#[derive(Debug, Clone, Copy)]
+/// A custom widget for a toggle switch, built each frame from the load state.
 pub(crate) struct LoadToggleSwitch<'a> {
     pub(crate) is_on: bool,
     labels: (&'a str, &'a str),
@@ -20,7 +20,7 @@ impl<'a> LoadToggleSwitch<'a> {
     }
 }
 
-impl<'a> tui::widgets::Widget for LoadToggleSwitch<'a> {
+impl tui::widgets::Widget for LoadToggleSwitch<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let on_label = Span::styled(
             self.labels.0,
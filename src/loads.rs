@@ -0,0 +1,81 @@
+use crate::config::LoadConfig;
+use crate::report::ReportRow;
+use serde::Serialize;
+use solar_core::rollup::Summary;
+use std::fmt;
+
+//
+// Structs
+//
+/// A load channel known by name, e.g. a fridge, whose consumption the
+/// reports attribute to it.
+#[derive(Debug, Clone)]
+pub(crate) struct NamedLoad {
+    name: String,
+    rated_watts: Option<f64>,
+}
+
+/// The energy a named load consumed in a period.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LoadEnergy {
+    pub(crate) name: String,
+    pub(crate) energy_wh: f64,
+    /// Hours at the rated power the energy lasts, with a rated power.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) rated_hours: Option<f64>,
+}
+
+//
+// implementations
+//
+impl NamedLoad {
+    ///
+    /// The loads named in the `[load]` section, none unless it has a name.
+    ///
+    pub(crate) fn from_config(config: &LoadConfig) -> Vec<Self> {
+        config
+            .name
+            .iter()
+            .map(|name| Self {
+                name: name.clone(),
+                rated_watts: config.rated_watts.filter(|watts| *watts > 0.0),
+            })
+            .collect()
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    ///
+    /// The energy this load consumed in a summary. The controller has a
+    /// single load channel, which draws all the energy consumed.
+    ///
+    pub(crate) fn energy(&self, summary: &Summary) -> LoadEnergy {
+        let energy_wh = summary.energy_consumed_wh;
+        LoadEnergy {
+            name: self.name.clone(),
+            energy_wh,
+            rated_hours: self.rated_watts.map(|watts| energy_wh / watts),
+        }
+    }
+}
+
+impl fmt::Display for LoadEnergy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:.1} Wh", self.name, self.energy_wh)?;
+        if let Some(hours) = self.rated_hours {
+            write!(f, ", {:.1} h at rated power", hours)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Attribute the energy consumed in each row of a report to the loads.
+///
+pub(crate) fn attribute(loads: &[NamedLoad], rows: &mut [ReportRow]) {
+    for row in rows {
+        row.loads = loads.iter().map(|load| load.energy(&row.summary)).collect();
+    }
+}
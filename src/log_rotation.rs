@@ -0,0 +1,103 @@
+use chrono::{Local, NaiveDate};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+//
+// Structs
+//
+/// A log file which is rotated by size and/or day, keeping a fixed number of
+/// old files named `<file>.1` (newest) to `<file>.<keep>` (oldest).
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened: NaiveDate,
+    max_size: u64,
+    daily: bool,
+    keep: usize,
+}
+
+//
+// implementations
+//
+impl RotatingFile {
+    ///
+    /// Open the log file for appending. A `max_size` of 0 disables size based rotation.
+    ///
+    pub(crate) fn new(path: &Path, max_size: u64, daily: bool, keep: usize) -> io::Result<Self> {
+        let (file, size, opened) = Self::open(path)?;
+        let mut rotating = Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            opened,
+            max_size,
+            daily,
+            keep,
+        };
+        if rotating.needs_rotation() {
+            rotating.rotate()?;
+        }
+        Ok(rotating)
+    }
+
+    fn open(path: &Path) -> io::Result<(File, u64, NaiveDate)> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        let opened = metadata
+            .modified()
+            .map(|m| chrono::DateTime::<Local>::from(m).date_naive())
+            .unwrap_or_else(|_| Local::now().date_naive());
+        Ok((file, metadata.len(), opened))
+    }
+
+    fn needs_rotation(&self) -> bool {
+        (self.max_size > 0 && self.size >= self.max_size)
+            || (self.daily && self.size > 0 && self.opened != Local::now().date_naive())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.keep));
+            for index in (1..self.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        let (file, size, _) = Self::open(&self.path)?;
+        self.file = file;
+        self.size = size;
+        self.opened = Local::now().date_naive();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation() {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
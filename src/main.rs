@@ -1,78 +1,231 @@
-mod database;
-mod datapoint;
+mod api;
+mod array;
+mod automation;
+mod batch_uploader;
+mod battery_health;
+mod capacity;
+mod charge_targets;
+mod cli;
+mod config;
+mod control_auth;
+mod cycle_counter;
+mod datalogger;
+mod desktop;
+mod efficiency;
+mod email;
+mod export;
+mod fleet;
+mod fleet_server;
+mod forecast;
+mod gpio_relay;
+mod grafana;
+mod graphite;
+mod grpc;
+mod headless;
+mod hooks;
+mod influx;
+mod instance_lock;
+mod kafka;
 mod load_toggle_switch;
-mod serial_data_logger;
+mod loads;
+mod log_rotation;
+mod modbus;
+mod mqtt;
+mod nats;
+mod notify;
+mod performance;
+mod ports;
+mod priority;
+mod query;
+mod rate_limit;
+mod records;
+mod redis;
+mod remote;
+mod remote_write;
+mod report;
+mod report_files;
+mod script;
+mod self_update;
+mod signalk;
+mod socket;
+mod stats;
+mod sun;
+mod syslog;
+mod systemd;
+mod tail;
+mod tariff;
+mod thingspeak;
+mod throttle;
+mod tls;
+mod ui;
+mod weather;
 
 #[macro_use]
 extern crate log;
 extern crate simplelog;
 use simplelog::{
-    ColorChoice, CombinedLogger, Config, ConfigBuilder, LevelFilter, TermLogger, TerminalMode,
-    WriteLogger,
+    ColorChoice, CombinedLogger, ConfigBuilder, LevelFilter, SharedLogger, TermLogger,
+    TerminalMode, WriteLogger,
 };
 
-use datapoint::DataPoint;
+use array::ArrayModel;
+use charge_targets::ChargeTargets;
+use clap::{CommandFactory, Parser};
+use cli::{Cli, Command, ConnectArgs};
+use config::{save_alert_limits, AlertThresholds, Config, LoggingConfig};
+use datalogger::{spawn_datalogger, CommandSource, ControlCommand, DataloggerHandle, LoadCommand};
+use desktop::DesktopNotifier;
 use load_toggle_switch::LoadToggleSwitch;
-use serial_data_logger::SerialDatalogger;
+use loads::NamedLoad;
+use log_rotation::RotatingFile;
+use ports::PortInfo;
+use remote::RemoteClient;
+use solar_core::alerts::{EventKind, Severity};
+use solar_core::database::Database;
+use solar_core::shutdown::Shutdown;
+use solar_core::timings::{Stage, TIMINGS};
+use syslog::SyslogLogger;
+use tariff::Tariff;
+use ui::{HistorySource, LazyDatabase, Tab, ViewState};
 
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseEventKind},
+    cursor,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent,
+        KeyModifiers, MouseEventKind,
+    },
     execute,
+    style::Print,
     terminal::{
         disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
     },
 };
+use futures_util::StreamExt;
 use std::{
     error::Error,
-    fs::File,
     io,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc, Arc, Mutex,
-    },
-    thread::{self, sleep},
-    time::Duration,
+    net::SocketAddr,
+    sync::mpsc::TryRecvError,
+    time::{Duration, Instant},
 };
+use tokio::time::MissedTickBehavior;
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    layout::Alignment,
     style::{Color, Modifier, Style},
-    widgets::{Block, BorderType, Borders, Cell, List, ListItem, ListState, Row, Table},
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState},
     Frame, Terminal,
 };
 
 type TermType = Terminal<CrosstermBackend<std::io::Stdout>>;
 type TermResult = Result<Terminal<CrosstermBackend<std::io::Stdout>>, Box<dyn Error>>;
 
-const LOGFILE_PATH: &str = "solar-rust.log";
 const APP_NAME: &str = "Solar Tracer";
+/// Frames of the spinner shown while looking for serial ports.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const SPINNER_INTERVAL_MS: u128 = 80;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    setup_logging()?;
+    let cli = Cli::parse();
+    // Completions only print to stdout, so skip the config and logging setup.
+    if let Some(Command::Completions { shell }) = cli.command {
+        clap_complete::generate(shell, &mut Cli::command(), "rust-solar", &mut io::stdout());
+        return Ok(());
+    }
+    let config_path = cli.config.clone().unwrap_or_else(Config::default_path);
+    let mut config = Config::load(&config_path)?;
+    if cli.port.is_some() {
+        config.serial.port = cli.port;
+    }
+    if let Some(log_file) = cli.log_file {
+        config.logging.file = log_file;
+    }
+    if let Some(log_level) = cli.log_level {
+        config.logging.file_level = log_level;
+    }
+    config.dry_run = cli.dry_run;
+    config.database.read_only = cli.dry_run || cli.offline;
+    setup_logging(&config.logging)?;
+    log_panics();
     info!("Application Start");
+    info!("Using config file {}", config_path.display());
+    if config.dry_run {
+        info!("Dry run, no database writes or load commands.");
+    }
 
-    let ports = SerialDatalogger::get_comms();
-    let mut terminal = setup_terminal()?;
-
-    let mut port_list_state = ListState::default();
-    port_list_state.select(Some(0));
+    let res = match &cli.command {
+        Some(Command::Export(args)) => Some(export::run_export(&config, args)),
+        Some(Command::Query(args)) => Some(query::run_query(&config, args)),
+        Some(Command::Tail(args)) => Some(tail::run_tail(&config, args)),
+        Some(Command::Stats(args)) => Some(stats::run_stats(&config, args)),
+        Some(Command::Cycles { format }) => Some(stats::run_cycles(&config, *format)),
+        Some(Command::ListPorts { format }) => Some(ports::run_list_ports(*format)),
+        Some(Command::SelfUpdate(args)) => Some(self_update::run_self_update(args)),
+        // Already printed before the config was loaded.
+        Some(Command::Completions { .. }) => Some(Ok(())),
+        Some(Command::Connect(_)) => None,
+        Some(Command::Serve(args)) => {
+            let bind = args.bind.unwrap_or(config.api.bind);
+            Some(run_headless(&config, Some(bind)))
+        }
+        None if cli.headless => Some(run_headless(&config, None)),
+        None if cli.fleet_server => Some(fleet_server::run_fleet_server(&config, config.api.bind)),
+        None if cli.offline && !config.database.path.exists() => {
+            let msg = format!(
+                "Database {} does not exist.",
+                config.database.path.display()
+            );
+            error!("{}", msg);
+            Some(Err(msg.into()))
+        }
+        None => None,
+    };
+    if let Some(res) = res {
+        info!("Application End");
+        return res;
+    }
 
-    info!("Displaying serial ports.");
-    let should_continue = display_ports(&mut terminal, &ports, &mut port_list_state)?;
+    // SIGTERM and SIGHUP end the UI like `q`, so the terminal is restored.
+    let shutdown = Shutdown::new();
+    {
+        let shutdown = shutdown.clone();
+        ctrlc::set_handler(move || shutdown.stop())?;
+    }
+    restore_terminal_on_panic();
+    let mut terminal = setup_terminal()?;
 
-    if should_continue {
-        let port = match port_list_state.selected() {
-            Some(p) => p,
-            None => {
-                error!("Not a valid port.");
-                panic!("Not a valid port.")
+    let selected_port = match &config.serial.port {
+        _ if matches!(cli.command, Some(Command::Connect(_))) => Ok(None),
+        _ if cli.offline => {
+            info!("Browsing {} offline.", config.database.path.display());
+            if let Err(err) = run_offline(&mut terminal, &config, &shutdown) {
+                error!("{:?}", err);
             }
-        };
-        let selected_port = &ports[port];
-        let res = run_app(&mut terminal, selected_port);
-        if let Err(err) = res {
-            error!("{:?}", err);
+            Ok(None)
+        }
+        Some(port) => {
+            ports::log_configured(port);
+            Ok(Some(port.clone()))
+        }
+        None => {
+            info!("Displaying serial ports.");
+            let tick_rate = Duration::from_millis(config.ui.tick_rate_ms);
+            display_ports(&mut terminal, tick_rate, &shutdown).map(|port| {
+                port.map(|port| {
+                    info!("Selected port: {}", port.label());
+                    port.name
+                })
+            })
         }
+    };
+
+    let res = selected_port.and_then(|selected_port| match (selected_port, &cli.command) {
+        (_, Some(Command::Connect(args))) => run_remote(&mut terminal, args, &config, &shutdown),
+        (Some(selected_port), _) => run_app(&mut terminal, &selected_port, &config, &shutdown),
+        (None, _) => Ok(()),
+    });
+    if let Err(err) = &res {
+        error!("{:?}", err);
     }
 
     info!("Cleaning up Terminal.");
@@ -81,7 +234,47 @@ fn main() -> Result<(), Box<dyn Error>> {
         error!("{:?}", err);
     }
     info!("Application End");
-    Ok(())
+    // Reported after the alternate screen is left so the message stays visible.
+    res.map_err(std::convert::Into::into)
+}
+
+fn run_headless(config: &Config, api_bind: Option<SocketAddr>) -> Result<(), Box<dyn Error>> {
+    let Some(port) = &config.serial.port else {
+        error!("Headless mode requires a serial port, use --port or set serial.port.");
+        return Err("No serial port configured.".into());
+    };
+    headless::run_headless(port, config, api_bind)
+}
+
+///
+/// Log panics of any thread, so a crash of a background service is recorded
+/// in the log file and not only on a terminal nobody watches.
+///
+fn log_panics() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        error!("Thread {} {}", thread.name().unwrap_or("unnamed"), info);
+        default_hook(info);
+    }));
+}
+
+///
+/// Leave the alternate screen before a panic message is printed, so it is
+/// readable and the shell is usable afterwards.
+///
+fn restore_terminal_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            cursor::Show
+        );
+        default_hook(info);
+    }));
 }
 
 fn setup_terminal() -> TermResult {
@@ -108,44 +301,103 @@ fn cleanup_terminal(terminal: &mut TermType) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn setup_logging() -> Result<(), Box<dyn Error>> {
+fn setup_logging(config: &LoggingConfig) -> Result<(), Box<dyn Error>> {
     let mut conf = ConfigBuilder::new();
     //conf.set_line_ending(LineEnding::Crlf);
-    CombinedLogger::init(vec![
-        TermLogger::new(
-            LevelFilter::Error,
-            Config::default(),
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+    if config.terminal && config.terminal_level != LevelFilter::Off {
+        loggers.push(TermLogger::new(
+            config.terminal_level,
+            simplelog::Config::default(),
             TerminalMode::Mixed,
             ColorChoice::Auto,
-        ),
-        WriteLogger::new(
-            LevelFilter::Info,
+        ));
+    }
+    if config.file_level != LevelFilter::Off {
+        loggers.push(WriteLogger::new(
+            config.file_level,
             conf.build(),
-            File::create(LOGFILE_PATH).unwrap(),
-        ),
-    ])
-    .map_err(std::convert::Into::into)
+            RotatingFile::new(
+                &config.file,
+                config.max_size_mb * 1024 * 1024,
+                config.rotate_daily,
+                config.keep_files,
+            )?,
+        ));
+    }
+    if config.syslog && config.syslog_level != LevelFilter::Off {
+        loggers.push(Box::new(SyslogLogger::new(config)?));
+    }
+    CombinedLogger::init(loggers).map_err(std::convert::Into::into)
 }
 
+///
+/// Show the port picker until a port is chosen, `None` if the user quits.
+/// The ports are enumerated in the background, with a spinner meanwhile.
+///
 fn display_ports<B: Backend>(
     terminal: &mut Terminal<B>,
-    ports: &[String],
-    port_list_state: &mut ListState,
-) -> io::Result<bool> {
-    for (i, p) in ports.iter().enumerate() {
-        info!("{i}: {p:?}");
-    }
+    tick_rate: Duration,
+    shutdown: &Shutdown,
+) -> io::Result<Option<PortInfo>> {
+    let enumerated = ports::enumerate_in_background()?;
+    let started = Instant::now();
+    let mut found: Option<Vec<PortInfo>> = None;
+    let mut ports: Vec<String> = Vec::new();
+    let mut title = String::new();
+    let mut port_list_state = ListState::default();
+    port_list_state.select(Some(0));
 
-    loop {
-        let _ = terminal.draw(|f| init_ui(f, ports.to_vec(), port_list_state));
-        if crossterm::event::poll(Duration::from_micros(100))? {
+    while shutdown.running() {
+        if found.is_none() {
+            let (enumerated, message) = match enumerated.try_recv() {
+                Err(TryRecvError::Empty) => {
+                    let frame = started.elapsed().as_millis() / SPINNER_INTERVAL_MS;
+                    let spinner = SPINNER[frame as usize % SPINNER.len()];
+                    title = format!("{} Looking for serial ports (q to exit)", spinner);
+                    (None, None)
+                }
+                Ok(Ok(list)) if list.is_empty() => {
+                    (Some(list), Some("No serial ports found".into()))
+                }
+                Ok(Ok(list)) => (Some(list), None),
+                Ok(Err(e)) => {
+                    error!("Error reading ports: {}", e);
+                    (
+                        Some(Vec::new()),
+                        Some(format!("Error reading ports: {}", e)),
+                    )
+                }
+                Err(TryRecvError::Disconnected) => {
+                    (Some(Vec::new()), Some("Error reading ports".into()))
+                }
+            };
+            if let Some(list) = enumerated {
+                ports = list.iter().map(PortInfo::label).collect();
+                for (i, p) in ports.iter().enumerate() {
+                    info!("{i}: {p:?}");
+                }
+                title = format!(
+                    "{} (q to exit)",
+                    message.unwrap_or_else(|| "Port Selection".into())
+                );
+                found = Some(list);
+            }
+        }
+        let _ = terminal.draw(|f| init_ui(f, ports.to_vec(), &title, &mut port_list_state));
+        if event::poll(tick_rate)? {
             if let Event::Key(key) = event::read()? {
-                if let KeyCode::Enter = key.code {
-                    info!("User selected: {}", port_list_state.selected().unwrap());
-                    return Ok(true);
+                let selected = port_list_state.selected().filter(|&i| i < ports.len());
+                if let (KeyCode::Enter, Some(selected), Some(found)) = (key.code, selected, &found)
+                {
+                    info!("User selected: {}", selected);
+                    return Ok(Some(found[selected].clone()));
+                }
+                if is_quit(&key) {
+                    return Ok(None);
                 }
-                if let KeyCode::Char('q') = key.code {
-                    return Ok(false);
+                if ports.is_empty() {
+                    continue;
                 }
                 if let KeyCode::Up = key.code {
                     info!("User action: {:?}", key.code);
@@ -172,115 +424,252 @@ fn display_ports<B: Backend>(
             }
         }
     }
+    Ok(None)
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, selected_port: &String) -> io::Result<()> {
-    let (rx, tx) = mpsc::channel();
-    let (bg_tx_input, bg_rx_input) = mpsc::channel();
-    let mut data_logger = SerialDatalogger::new(selected_port.to_string());
-    let _ = data_logger.read_serial_datapoint(); //throw away read to ensure device is ready
-    let initial_dp = data_logger.read_datapoint()?;
-    let load_switch = Arc::new(Mutex::new(LoadToggleSwitch::new(
-        initial_dp.get_load_onoff() > 0.0,
-        ("ON", "OFF"),
-    )));
-    let running = Arc::new(AtomicBool::new(true));
-    let builder = thread::Builder::new()
-        .name("datalogger".into())
-        .stack_size(1024 * 1024); //1MB
-    let task = {
-        let mut error_count: u64 = 0;
-        let running = Arc::clone(&running);
-        let selected_port_copy = String::from(selected_port);
-        move || {
-            while running.load(Ordering::SeqCst) {
-                let datapoint = match data_logger.read_datapoint() {
-                    Ok(d) => d,
-                    Err(_e) => {
-                        error_count += 1;
-                        if error_count >= 5 {
-                            error_count = 0;
-                            info!(
-                                "Failed to read 5 datapoints, attempting to reconnect in 1 second."
-                            );
-                            std::thread::sleep(Duration::from_secs(1));
-                            data_logger = SerialDatalogger::new(selected_port_copy.clone());
-                        }
-                        DataPoint::default()
-                    }
-                };
-                match rx.send(datapoint) {
-                    Ok(_) => {}
-                    Err(e) => warn!("{}", e),
-                }
-                sleep(Duration::from_secs(1));
-                match bg_rx_input.recv_timeout(Duration::from_micros(1000)) {
-                    Ok(msg) => {
-                        if msg {
-                            data_logger.load_on();
-                        } else {
-                            data_logger.load_off();
-                        }
-                    }
-                    Err(_e) => {}
-                };
-            }
-        }
+///
+/// `q` or Ctrl-C, which arrives as a key while the terminal is in raw mode.
+///
+fn is_quit(key: &KeyEvent) -> bool {
+    matches!(key.code, KeyCode::Char('q'))
+        || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL))
+}
+
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    selected_port: &str,
+    config: &Config,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    let mut datalogger = spawn_datalogger(selected_port, config, shutdown.clone())?;
+    // The datalogger thread owns the writing connection, history is read
+    // through a second one opened once a tab needs it.
+    let database = LazyDatabase::new(&config.database);
+    let title = format!("{}, q to quit", APP_NAME);
+    let res = run_live(
+        terminal,
+        &title,
+        &mut datalogger,
+        &database,
+        config,
+        shutdown,
+        true,
+    );
+    // Wait for the datalogger to store what it buffers.
+    datalogger.thread.stop();
+    res
+}
+
+///
+/// Show the TUI for a remote daemon, datapoints arriving over its WebSocket
+/// and the load being switched through its API.
+///
+fn run_remote<B: Backend>(
+    terminal: &mut Terminal<B>,
+    args: &ConnectArgs,
+    config: &Config,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    let client = RemoteClient::new(&args.host, args.api_key.clone(), args.pin.clone());
+    let mut datalogger = client.connect(shutdown)?;
+    let title = format!("{} ({}), q to quit", APP_NAME, client.url());
+    let res = run_live(
+        terminal,
+        &title,
+        &mut datalogger,
+        &client,
+        config,
+        shutdown,
+        false,
+    );
+    datalogger.thread.stop();
+    res
+}
+
+///
+/// Run the tabbed UI on live datapoints until `q` is pressed. Edited alert
+/// limits are applied and saved to the config if `limits_editable`.
+///
+fn run_live<B: Backend>(
+    terminal: &mut Terminal<B>,
+    title: &str,
+    datalogger: &mut DataloggerHandle,
+    history: &dyn HistorySource,
+    config: &Config,
+    shutdown: &Shutdown,
+    limits_editable: bool,
+) -> io::Result<()> {
+    let labels = (
+        config.ui.load_on_label.as_str(),
+        config.ui.load_off_label.as_str(),
+    );
+    let desktop = match config.ui.desktop_notifications {
+        true => Some(DesktopNotifier::new()?),
+        false => None,
     };
-    let _handle = builder
-        .spawn(task)
-        .expect("Error: creating data logging thread failed.");
-    let mut current_dp = DataPoint::default();
-    let input_thread = {
-        let running = Arc::clone(&running);
-        let load_switch = Arc::clone(&load_switch);
-        let bg_tx = bg_tx_input.clone();
-        move || {
-            while running.load(Ordering::SeqCst) {
-                match event::read().unwrap() {
-                    Event::Key(q) => {
-                        if let KeyCode::Char('q') = q.code {
-                            running.store(false, Ordering::SeqCst);
-                        }
-                    }
-                    Event::Mouse(me) => {
+    let threads = &config.threads;
+    priority::apply("UI", threads.ui_nice, 0, &threads.ui_cpus);
+    let mut state = ViewState::new(
+        vec![
+            Tab::Live,
+            Tab::History,
+            Tab::Stats,
+            Tab::Reports,
+            Tab::Forecast,
+            Tab::Battery,
+            Tab::Alarms,
+        ],
+        Tariff::new(&config.tariff).inspect_err(|e| error!("{}", e))?,
+        NamedLoad::from_config(&config.load),
+        ChargeTargets::new(config).inspect_err(|e| error!("{}", e))?,
+        ArrayModel::new(config).inspect_err(|e| error!("{}", e))?,
+        config.alerts.clone(),
+        limits_editable,
+    );
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let mut input = EventStream::new();
+        let mut redraw = tokio::time::interval(Duration::from_millis(config.ui.tick_rate_ms));
+        redraw.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Input, datapoints and events are handled as they arrive, the tick
+        // only redraws.
+        loop {
+            tokio::select! {
+                _ = shutdown.stopped() => return Ok(()),
+                _ = redraw.tick() => {}
+                input = input.next() => match input {
+                    Some(Ok(Event::Key(key))) if is_quit(&key) => shutdown.stop(),
+                    Some(Ok(Event::Key(key))) => state.handle_key(key.code),
+                    Some(Ok(Event::Mouse(me))) => {
                         if let MouseEventKind::Down(_) = me.kind {
                             if me.row == 1 && me.column <= 10 {
-                                if load_switch.lock().unwrap().is_on {
-                                    load_switch.lock().unwrap().is_on = false;
-                                    bg_tx.send(load_switch.lock().unwrap().is_on).unwrap();
-                                } else {
-                                    load_switch.lock().unwrap().is_on = true;
-                                    bg_tx.send(load_switch.lock().unwrap().is_on).unwrap();
-                                }
+                                let on = !*datalogger.load.borrow();
+                                let _ = datalogger.control.send(
+                                    LoadCommand {
+                                        on,
+                                        source: CommandSource::Ui,
+                                    }
+                                    .into(),
+                                );
                             }
                         }
                     }
-                    Event::FocusGained => {}
-                    Event::FocusLost => {}
-                    Event::Paste(_) => {}
-                    Event::Resize(_, _) => {}
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                },
+                // The datapoint is read from the recent ones and the load
+                // state from its channel when drawing.
+                Ok(_) = datalogger.datapoints.recv() => {}
+                Ok(()) = datalogger.load.changed() => {}
+                Ok(event) = datalogger.events.recv() => {
+                    if event.kind == EventKind::Alert && event.severity == Severity::Critical {
+                        if config.ui.bell {
+                            execute!(io::stdout(), Print('\x07'))?;
+                        }
+                        if let Some(desktop) = &desktop {
+                            desktop.send(&event);
+                        }
+                    }
+                    state.record_event(event);
                 }
             }
+            if let Some(thresholds) = state.take_changed_limits() {
+                let _ = datalogger
+                    .control
+                    .send(ControlCommand::AlertLimits(thresholds.clone()));
+                save_limits(thresholds, config);
+            }
+            state.refresh(history);
+            let load_switch = LoadToggleSwitch::new(*datalogger.load.borrow(), labels);
+            TIMINGS.time(Stage::Render, || {
+                terminal.draw(|f| {
+                    ui::draw(
+                        f,
+                        title,
+                        &state,
+                        Some((&datalogger.recent, &datalogger.health, load_switch)),
+                    )
+                })
+            })?;
+        }
+    })
+}
+
+///
+/// Save edited alert limits to the config file they were loaded from.
+///
+fn save_limits(thresholds: &AlertThresholds, config: &Config) {
+    match save_alert_limits(thresholds, &config.path) {
+        Ok(()) => info!("Alert limits saved to {}.", config.path.display()),
+        Err(e) => error!(
+            "Saving the alert limits to {} failed: {}",
+            config.path.display(),
+            e
+        ),
+    }
+}
+
+///
+/// Browse the history and statistics of an existing database without
+/// opening a serial port.
+///
+fn run_offline<B: Backend>(
+    terminal: &mut Terminal<B>,
+    config: &Config,
+    shutdown: &Shutdown,
+) -> io::Result<()> {
+    let tick_rate = Duration::from_millis(config.ui.tick_rate_ms);
+    let database = Database::new(&config.database)?;
+    let mut state = ViewState::new(
+        vec![
+            Tab::History,
+            Tab::Stats,
+            Tab::Reports,
+            Tab::Forecast,
+            Tab::Battery,
+            Tab::Alarms,
+        ],
+        Tariff::new(&config.tariff).inspect_err(|e| error!("{}", e))?,
+        NamedLoad::from_config(&config.load),
+        None,
+        ArrayModel::new(config).inspect_err(|e| error!("{}", e))?,
+        config.alerts.clone(),
+        true,
+    );
+    let title = format!(
+        "{} (offline: {}), q to quit",
+        APP_NAME,
+        config.database.path.display()
+    );
+    while shutdown.running() {
+        state.refresh(&database);
+        TIMINGS.time(Stage::Render, || {
+            terminal.draw(|f| ui::draw(f, &title, &state, None))
+        })?;
+        if event::poll(tick_rate)? {
+            if let Event::Key(key) = event::read()? {
+                if is_quit(&key) {
+                    return Ok(());
+                }
+                state.handle_key(key.code);
+            }
+        }
+        if let Some(thresholds) = state.take_changed_limits() {
+            save_limits(thresholds, config);
         }
-    };
-    let input_builder = thread::Builder::new()
-        .name("input".into())
-        .stack_size(1024 * 1024); //1MB
-    let _handle = input_builder
-        .spawn(input_thread)
-        .expect("Error: creating input thread failed.");
-    while running.load(Ordering::SeqCst) {
-        current_dp = match tx.recv_timeout(Duration::from_millis(25)) {
-            Ok(v) => v,
-            Err(_e) => current_dp,
-        };
-        terminal.draw(|f| ui(f, current_dp, Arc::clone(&load_switch)))?;
     }
     Ok(())
 }
 
-fn init_ui<B: Backend>(f: &mut Frame<B>, ports: Vec<String>, port_list_state: &mut ListState) {
+fn init_ui<B: Backend>(
+    f: &mut Frame<B>,
+    ports: Vec<String>,
+    title: &str,
+    port_list_state: &mut ListState,
+) {
     let size = f.size();
     let block = Block::default()
         .borders(Borders::ALL)
@@ -292,7 +681,7 @@ fn init_ui<B: Backend>(f: &mut Frame<B>, ports: Vec<String>, port_list_state: &m
     let port_list = List::new(port_items)
         .block(
             Block::default()
-                .title("Port Selection (q to exit)")
+                .title(title)
                 .title_alignment(Alignment::Center)
                 .borders(Borders::ALL),
         )
@@ -301,108 +690,3 @@ fn init_ui<B: Backend>(f: &mut Frame<B>, ports: Vec<String>, port_list_state: &m
         .highlight_symbol(">>");
     f.render_stateful_widget(port_list, size, port_list_state);
 }
-
-fn ui<B: Backend>(
-    f: &mut Frame<B>,
-    datapoint: DataPoint,
-    load_switch: Arc<Mutex<LoadToggleSwitch>>,
-) {
-    let size = f.size();
-    let block = Block::default()
-        .borders(Borders::ALL)
-        .title(format!("{}{}", APP_NAME, ", q to quit"))
-        .title_alignment(Alignment::Center)
-        .border_type(BorderType::Rounded);
-    f.render_widget(block, size);
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .margin(4)
-        .constraints([Constraint::Percentage(100), Constraint::Percentage(100)].as_ref())
-        .split(f.size());
-
-    let top_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(100), Constraint::Percentage(100)].as_ref())
-        .split(chunks[0]);
-    let load = if datapoint.get_load_onoff() < 1.0 {
-        "Off"
-    } else {
-        "On"
-    };
-    let load_current = datapoint.get_load_current().to_string();
-    let battery_voltage = datapoint.get_battery_voltage().to_string();
-    let battery_temp = datapoint.get_battery_temp().to_string();
-    let pv_voltage = datapoint.get_pv_voltage().to_string();
-    let charging = if datapoint.get_charging() < 1.0 {
-        "No"
-    } else {
-        "Yes"
-    };
-    let charge_current = datapoint.get_charge_current().to_string();
-    let over_discharge = datapoint.get_over_discharge().to_string();
-    let battery_max = datapoint.get_battery_max().to_string();
-    let battery_full = if datapoint.get_battery_full() < 1.0 {
-        "No"
-    } else {
-        "Yes"
-    };
-    let time = datapoint.get_time_formatted();
-    let table = Table::new(vec![
-        Row::new(vec![
-            Cell::from("Load: ").style(Style::default().fg(Color::Green)),
-            Cell::from(load),
-        ]),
-        Row::new(vec![
-            Cell::from("Load Current: ").style(Style::default().fg(Color::Green)),
-            Cell::from(load_current),
-        ]),
-        Row::new(vec![
-            Cell::from("Battery Voltage: ").style(Style::default().fg(Color::Green)),
-            Cell::from(battery_voltage),
-        ]),
-        Row::new(vec![
-            Cell::from("Battery Full: ").style(Style::default().fg(Color::Green)),
-            Cell::from(battery_full),
-        ]),
-        Row::new(vec![
-            Cell::from("Battery Temp: ").style(Style::default().fg(Color::Green)),
-            Cell::from(battery_temp),
-        ]),
-        Row::new(vec![
-            Cell::from("PV Voltage: ").style(Style::default().fg(Color::Green)),
-            Cell::from(pv_voltage),
-        ]),
-        Row::new(vec![
-            Cell::from("Charging: ").style(Style::default().fg(Color::Green)),
-            Cell::from(charging),
-        ]),
-        Row::new(vec![
-            Cell::from("Charge Current: ").style(Style::default().fg(Color::Green)),
-            Cell::from(charge_current),
-        ]),
-        Row::new(vec![
-            Cell::from("Over Discharge: ").style(Style::default().fg(Color::Green)),
-            Cell::from(over_discharge),
-        ]),
-        Row::new(vec![
-            Cell::from("Battery Max: ").style(Style::default().fg(Color::Green)),
-            Cell::from(battery_max),
-        ]),
-        Row::new(vec![
-            Cell::from("Timestamp: ").style(Style::default().fg(Color::Green)),
-            Cell::from(time),
-        ]),
-    ])
-    .style(Style::default().fg(Color::White))
-    .block(Block::default().title("MPPT Data"))
-    .widths(&[
-        Constraint::Length(25),
-        Constraint::Length(50),
-        Constraint::Length(10),
-    ])
-    .column_spacing(1);
-    f.render_widget(table, top_chunks[0]);
-    let area = Rect::new(size.x, size.y, 10, 2);
-    let button = load_switch.lock().unwrap().clone();
-    f.render_widget(button, area);
-}
@@ -0,0 +1,171 @@
+use crate::config::ModbusConfig;
+use solar_core::datapoint::DataPoint;
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+
+//
+// Constants
+//
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+const SERVER_DEVICE_BUSY: u8 = 0x06;
+/// Number of registers in the map, see `registers`.
+const REGISTER_COUNT: u16 = 12;
+/// Clients which stay silent this long are disconnected.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+//
+// Structs
+//
+/// A read-only Modbus TCP server answering function codes 3 and 4 with the
+/// latest datapoint. Holding and input registers hold the same values.
+pub(crate) struct ModbusServer {
+    latest: Arc<Mutex<Option<DataPoint>>>,
+}
+
+//
+// implementations
+//
+impl ModbusServer {
+    ///
+    /// Bind the listener and serve every client from its own thread.
+    ///
+    pub(crate) fn new(config: &ModbusConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(config.bind)?;
+        info!("Modbus TCP server listening on {}", listener.local_addr()?);
+        let latest = Arc::new(Mutex::new(None));
+        let unit_id = config.unit_id;
+        {
+            let latest = Arc::clone(&latest);
+            thread::Builder::new()
+                .name("modbus".into())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        let stream = match stream {
+                            Ok(s) => s,
+                            Err(e) => {
+                                warn!("Modbus: {}", e);
+                                continue;
+                            }
+                        };
+                        let latest = Arc::clone(&latest);
+                        let client = move || {
+                            if let Err(e) = serve_client(stream, &latest, unit_id) {
+                                debug!("Modbus client: {}", e);
+                            }
+                        };
+                        if let Err(e) = thread::Builder::new()
+                            .name("modbus-client".into())
+                            .spawn(client)
+                        {
+                            warn!("Modbus: {}", e);
+                        }
+                    }
+                })?;
+        }
+        Ok(Self { latest })
+    }
+
+    pub(crate) fn update(&self, datapoint: &DataPoint) {
+        *self.latest.lock().unwrap_or_else(PoisonError::into_inner) = Some(*datapoint);
+    }
+}
+
+///
+/// Answer requests until the client disconnects. Requests for another unit
+/// id are ignored unless `unit_id` is 0.
+///
+fn serve_client(
+    mut stream: TcpStream,
+    latest: &Mutex<Option<DataPoint>>,
+    unit_id: u8,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    loop {
+        // MBAP header: transaction id, protocol id, length, unit id.
+        let mut header = [0u8; 7];
+        stream.read_exact(&mut header)?;
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if !(2..=254).contains(&length) {
+            return Err(io::Error::other("invalid MBAP length"));
+        }
+        let mut pdu = vec![0u8; length - 1];
+        stream.read_exact(&mut pdu)?;
+        if header[2..4] != [0, 0] || (unit_id != 0 && header[6] != unit_id) {
+            continue;
+        }
+        let response = match respond(&pdu, *latest.lock().unwrap_or_else(PoisonError::into_inner)) {
+            Ok(data) => data,
+            Err(code) => vec![pdu[0] | 0x80, code],
+        };
+        let mut frame = Vec::with_capacity(7 + response.len());
+        frame.extend_from_slice(&header[..4]);
+        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend_from_slice(&response);
+        stream.write_all(&frame)?;
+    }
+}
+
+///
+/// The response PDU for a request PDU, or the exception code.
+///
+fn respond(pdu: &[u8], latest: Option<DataPoint>) -> Result<Vec<u8>, u8> {
+    let function = pdu[0];
+    if function != READ_HOLDING_REGISTERS && function != READ_INPUT_REGISTERS {
+        return Err(ILLEGAL_FUNCTION);
+    }
+    if pdu.len() != 5 {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    let start = u16::from_be_bytes([pdu[1], pdu[2]]);
+    let count = u16::from_be_bytes([pdu[3], pdu[4]]);
+    if count == 0 || count > 125 {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    if start
+        .checked_add(count)
+        .is_none_or(|end| end > REGISTER_COUNT)
+    {
+        return Err(ILLEGAL_DATA_ADDRESS);
+    }
+    let datapoint = latest.ok_or(SERVER_DEVICE_BUSY)?;
+    let registers = registers(&datapoint);
+    let mut response = vec![function, (count * 2) as u8];
+    for register in &registers[start as usize..(start + count) as usize] {
+        response.extend_from_slice(&register.to_be_bytes());
+    }
+    Ok(response)
+}
+
+///
+/// The register map: voltages and currents in 0.01 V/A, the temperature in
+/// 0.1 °C (signed), flags as 0/1 and the unix timestamp as two registers,
+/// high word first.
+///
+fn registers(datapoint: &DataPoint) -> [u16; REGISTER_COUNT as usize] {
+    let scaled = |value: f64, scale: f64| (value * scale).round() as i64 as u16;
+    let time = datapoint.get_time() as u32;
+    [
+        scaled(datapoint.get_battery_voltage(), 100.0),
+        scaled(datapoint.get_pv_voltage(), 100.0),
+        scaled(datapoint.get_load_current(), 100.0),
+        scaled(datapoint.get_over_discharge(), 100.0),
+        scaled(datapoint.get_battery_max(), 100.0),
+        scaled(datapoint.get_battery_full(), 1.0),
+        scaled(datapoint.get_charging(), 1.0),
+        scaled(datapoint.get_battery_temp(), 10.0),
+        scaled(datapoint.get_charge_current(), 100.0),
+        scaled(datapoint.get_load_onoff(), 1.0),
+        (time >> 16) as u16,
+        time as u16,
+    ]
+}
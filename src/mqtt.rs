@@ -0,0 +1,306 @@
+use crate::config::MqttConfig;
+use crate::control_auth::ControlAuth;
+#[cfg(feature = "mqtt")]
+use crate::control_auth::Credentials;
+use crate::datalogger::ControlSender;
+#[cfg(feature = "mqtt")]
+use crate::datalogger::{CommandSource, ControlCommand, LoadCommand};
+use solar_core::datapoint::DataPoint;
+use std::{io, sync::Arc};
+
+//
+// Structs
+//
+/// Parses the load state and credentials of a load command.
+#[cfg(feature = "mqtt")]
+type LoadParser = fn(&str) -> Option<(bool, Credentials)>;
+
+/// Publishes each datapoint to an MQTT broker, one topic per value.
+#[cfg(feature = "mqtt")]
+pub(crate) struct MqttPublisher {
+    client: rumqttc::Client,
+    topic_base: String,
+    qos: rumqttc::QoS,
+    retain: bool,
+    /// Update topic of the AWS IoT device shadow, if any.
+    shadow_topic: Option<String>,
+    /// Load state last reported to the shadow.
+    reported_load: std::sync::Mutex<Option<bool>>,
+}
+
+#[cfg(not(feature = "mqtt"))]
+pub(crate) struct MqttPublisher;
+
+//
+// implementations
+//
+#[cfg(feature = "mqtt")]
+impl MqttPublisher {
+    ///
+    /// Connect to the broker in the background. The connection is driven by
+    /// its own thread which keeps reconnecting while the broker is down and,
+    /// with `load_control` or `poll_interval_control`, forwards messages on the
+    /// command topics below `<prefix>/<device>` to the datalogger. With
+    /// `load_control` and `shadow_thing`, so are shadow deltas for the load.
+    /// Load commands are checked by `control_auth`.
+    ///
+    pub(crate) fn new(
+        config: &MqttConfig,
+        control_auth: Arc<ControlAuth>,
+        control: ControlSender,
+    ) -> io::Result<Self> {
+        use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+        use std::time::Duration;
+
+        let qos =
+            rumqttc::qos(config.qos).map_err(|e| io::Error::other(format!("mqtt.qos: {:?}", e)))?;
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+        if config.tls {
+            options.set_transport(tls_transport(config)?);
+        }
+        let (client, mut connection) = Client::new(options, 64);
+        let topic_base = format!("{}/{}", config.topic_prefix, config.device);
+        let load_topic = config
+            .load_control
+            .then(|| format!("{}/{}", topic_base, config.load_topic));
+        let poll_interval_topic = config
+            .poll_interval_control
+            .then(|| format!("{}/{}", topic_base, config.poll_interval_topic));
+        let shadow_topic = (!config.shadow_thing.is_empty())
+            .then(|| format!("$aws/things/{}/shadow/update", config.shadow_thing));
+        let delta_topic = shadow_topic
+            .as_ref()
+            .filter(|_| config.load_control)
+            .map(|topic| format!("{}/delta", topic));
+        let subscriber = client.clone();
+        std::thread::Builder::new()
+            .name("mqtt".into())
+            .spawn(move || {
+                for event in connection.iter() {
+                    match event {
+                        // Subscriptions do not survive a reconnect.
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            for topic in load_topic
+                                .iter()
+                                .chain(&poll_interval_topic)
+                                .chain(&delta_topic)
+                            {
+                                if let Err(e) = subscriber.try_subscribe(topic, QoS::AtLeastOnce) {
+                                    warn!("MQTT: {}", e);
+                                }
+                            }
+                        }
+                        Ok(Event::Incoming(Packet::Publish(message))) => {
+                            let topic = Some(&message.topic);
+                            let parse_load: Option<LoadParser> = if topic == load_topic.as_ref() {
+                                Some(parse_load_command)
+                            } else if topic == delta_topic.as_ref() {
+                                Some(parse_shadow_delta)
+                            } else if topic == poll_interval_topic.as_ref() {
+                                None
+                            } else {
+                                continue;
+                            };
+                            // A retained command would be replayed on every reconnect.
+                            if message.retain {
+                                warn!("MQTT: ignoring retained command on {}.", message.topic);
+                                continue;
+                            }
+                            let payload = String::from_utf8_lossy(&message.payload);
+                            let command = match parse_load {
+                                Some(parse) => match parse(payload.trim()) {
+                                    Some((on, credentials)) => {
+                                        if control_auth.check(on, &credentials, "MQTT").is_err() {
+                                            continue;
+                                        }
+                                        Some(ControlCommand::Load(LoadCommand {
+                                            on,
+                                            source: CommandSource::Mqtt,
+                                        }))
+                                    }
+                                    None => None,
+                                },
+                                None => parse_poll_interval(payload.trim()),
+                            };
+                            match command {
+                                Some(command) => {
+                                    let _ = control.send(command);
+                                }
+                                None => warn!(
+                                    "MQTT: invalid command {:?} on {}.",
+                                    payload, message.topic
+                                ),
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn!("MQTT: {}", e);
+                            std::thread::sleep(Duration::from_secs(5));
+                        }
+                    }
+                }
+            })?;
+        info!(
+            "Publishing datapoints to {}://{}:{}/{}/{}",
+            if config.tls { "mqtts" } else { "mqtt" },
+            config.host,
+            config.port,
+            config.topic_prefix,
+            config.device
+        );
+        Ok(Self {
+            client,
+            topic_base,
+            qos,
+            retain: config.retain,
+            shadow_topic,
+            reported_load: std::sync::Mutex::new(None),
+        })
+    }
+
+    ///
+    /// Queue one message per value without blocking, datapoints are dropped
+    /// while the outgoing queue is full.
+    ///
+    pub(crate) fn publish(&self, datapoint: &DataPoint) {
+        if let Some(topic) = &self.shadow_topic {
+            let on = datapoint.get_load_onoff() > 0.0;
+            let mut reported = self
+                .reported_load
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if *reported != Some(on) {
+                let state = if on { "on" } else { "off" };
+                let payload = serde_json::json!({ "state": { "reported": { "load": state } } });
+                // Retried with the next datapoint while the queue is full.
+                if self
+                    .client
+                    .try_publish(topic, rumqttc::QoS::AtLeastOnce, false, payload.to_string())
+                    .is_ok()
+                {
+                    *reported = Some(on);
+                }
+            }
+        }
+        let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(datapoint) else {
+            return;
+        };
+        for (field, value) in fields {
+            let topic = format!("{}/{}", self.topic_base, field);
+            if let Err(e) = self
+                .client
+                .try_publish(topic, self.qos, self.retain, value.to_string())
+            {
+                debug!("MQTT: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+///
+/// `on`/`off` (or `1`/`0`, `true`/`false`) as a load state, or a JSON object
+/// with the state and credentials, e.g. `{"state":"on","pin":"1234"}`.
+///
+#[cfg(feature = "mqtt")]
+fn parse_load_command(payload: &str) -> Option<(bool, Credentials)> {
+    match payload.starts_with('{') {
+        true => load_with_credentials(&serde_json::from_str(payload).ok()?, "state"),
+        false => Some((parse_load_state(payload)?, Credentials::default())),
+    }
+}
+
+#[cfg(feature = "mqtt")]
+fn parse_load_state(state: &str) -> Option<bool> {
+    match state.to_lowercase().as_str() {
+        "on" | "1" | "true" => Some(true),
+        "off" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+///
+/// The load state under `key` of a JSON object, and the `pin`, `token` and
+/// `time` next to it.
+///
+#[cfg(feature = "mqtt")]
+fn load_with_credentials(object: &serde_json::Value, key: &str) -> Option<(bool, Credentials)> {
+    let on = match object.get(key)? {
+        serde_json::Value::String(state) => parse_load_state(state),
+        state => parse_load_state(&state.to_string()),
+    }?;
+    let credentials = serde_json::from_value(object.clone()).unwrap_or_default();
+    Some((on, credentials))
+}
+
+///
+/// A poll interval in milliseconds, the range is checked by the datalogger.
+///
+#[cfg(feature = "mqtt")]
+fn parse_poll_interval(payload: &str) -> Option<ControlCommand> {
+    Some(ControlCommand::PollInterval {
+        interval_ms: payload.parse().ok()?,
+        source: CommandSource::Mqtt,
+    })
+}
+
+///
+/// The desired load state from a shadow delta document,
+/// `{"state":{"load":"on"},...}`, with credentials next to `load`.
+///
+#[cfg(feature = "mqtt")]
+fn parse_shadow_delta(payload: &str) -> Option<(bool, Credentials)> {
+    let delta: serde_json::Value = serde_json::from_str(payload).ok()?;
+    load_with_credentials(delta.get("state")?, "load")
+}
+
+///
+/// A TLS transport verifying the broker with `ca_cert`, or the system's root
+/// certificates without one, and authenticating with the client certificate
+/// if configured.
+///
+#[cfg(feature = "mqtt")]
+fn tls_transport(config: &MqttConfig) -> io::Result<rumqttc::Transport> {
+    use rumqttc::Transport;
+    use std::path::Path;
+
+    let read = |key: &str, path: &Path| {
+        std::fs::read(path)
+            .map_err(|e| io::Error::other(format!("mqtt.{}: {}: {}", key, path.display(), e)))
+    };
+    let client_auth = match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => Some((read("client_cert", cert)?, read("client_key", key)?)),
+        (None, None) => None,
+        _ => {
+            return Err(io::Error::other(
+                "mqtt.client_cert and mqtt.client_key must be set together.",
+            ))
+        }
+    };
+    match (&config.ca_cert, client_auth) {
+        (Some(ca), client_auth) => Ok(Transport::tls(read("ca_cert", ca)?, client_auth, None)),
+        (None, None) => Ok(Transport::tls_with_default_config()),
+        (None, Some(_)) => Err(io::Error::other(
+            "mqtt.ca_cert is required with a client certificate.",
+        )),
+    }
+}
+
+#[cfg(not(feature = "mqtt"))]
+impl MqttPublisher {
+    pub(crate) fn new(
+        _config: &MqttConfig,
+        _control_auth: Arc<ControlAuth>,
+        _control: ControlSender,
+    ) -> io::Result<Self> {
+        Err(io::Error::other(
+            "MQTT publishing requires building with the `mqtt` feature.",
+        ))
+    }
+
+    pub(crate) fn publish(&self, _datapoint: &DataPoint) {}
+}
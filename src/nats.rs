@@ -0,0 +1,204 @@
+use crate::config::{NatsConfig, NatsFormat};
+use serde_json::{json, Map, Value};
+use solar_core::datapoint::DataPoint;
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Delay before reconnecting to the server, datapoints are dropped meanwhile.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// Time allowed for the server's INFO and the reply to our CONNECT.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for messages from the server after each publish.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(10);
+
+//
+// Structs
+//
+/// Publishes datapoints to a NATS server from a background thread, either as
+/// one JSON message on `subject` or one message per value on
+/// `<subject>.<field>`.
+pub(crate) struct NatsPublisher {
+    messages: Sender<Vec<(String, String)>>,
+    subject: String,
+    format: NatsFormat,
+}
+
+/// A connection speaking the NATS client protocol, `PUB <subject> <bytes>`.
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    /// Part of a server message read before a timeout.
+    pending: String,
+}
+
+//
+// implementations
+//
+impl NatsPublisher {
+    pub(crate) fn new(config: &NatsConfig) -> io::Result<Self> {
+        let Some(address) = config.url.strip_prefix("nats://") else {
+            return Err(io::Error::other(format!(
+                "nats.url: {} must start with nats://.",
+                config.url
+            )));
+        };
+        if config.subject.is_empty() || config.subject.contains(char::is_whitespace) {
+            return Err(io::Error::other(format!(
+                "nats.subject: {:?} must be non-empty and without spaces.",
+                config.subject
+            )));
+        }
+        info!(
+            "Publishing datapoints to NATS subject {} at {}",
+            config.subject, config.url
+        );
+        let (tx, rx) = mpsc::channel::<Vec<(String, String)>>();
+        let subject = config.subject.clone();
+        let format = config.format;
+        let address = address.to_string();
+        let config = config.clone();
+        thread::Builder::new().name("nats".into()).spawn(move || {
+            let mut connection: Option<Connection> = None;
+            let mut next_attempt = Instant::now();
+            for messages in rx {
+                if connection.is_none() && Instant::now() >= next_attempt {
+                    match Connection::open(&address, &config) {
+                        Ok(c) => {
+                            info!("Connected to NATS server {}", config.url);
+                            connection = Some(c);
+                        }
+                        Err(e) => {
+                            warn!("NATS {}: {}", config.url, e);
+                            next_attempt = Instant::now() + RECONNECT_DELAY;
+                        }
+                    }
+                }
+                if let Some(c) = &mut connection {
+                    if let Err(e) = c.publish(&messages) {
+                        warn!("NATS {}: {}", config.url, e);
+                        connection = None;
+                        next_attempt = Instant::now() + RECONNECT_DELAY;
+                    }
+                }
+            }
+        })?;
+        Ok(Self {
+            messages: tx,
+            subject,
+            format,
+        })
+    }
+
+    pub(crate) fn publish(&self, datapoint: &DataPoint) {
+        let messages = match self.format {
+            NatsFormat::Json => match serde_json::to_string(datapoint) {
+                Ok(payload) => vec![(self.subject.clone(), payload)],
+                Err(_) => return,
+            },
+            NatsFormat::Fields => match serde_json::to_value(datapoint) {
+                Ok(Value::Object(fields)) => fields
+                    .into_iter()
+                    .map(|(field, value)| {
+                        (format!("{}.{}", self.subject, field), value.to_string())
+                    })
+                    .collect(),
+                _ => return,
+            },
+        };
+        let _ = self.messages.send(messages);
+    }
+}
+
+impl Connection {
+    ///
+    /// Connect, wait for the server's INFO, then send CONNECT followed by a
+    /// PING whose PONG confirms that the credentials were accepted.
+    ///
+    fn open(address: &str, config: &NatsConfig) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut connection = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+            pending: String::new(),
+        };
+        let info = connection.read_message()?;
+        if !info.starts_with("INFO ") {
+            return Err(io::Error::other(format!("unexpected greeting {:?}", info)));
+        }
+        let mut options = Map::new();
+        options.insert("verbose".into(), json!(false));
+        options.insert("pedantic".into(), json!(false));
+        options.insert("name".into(), json!("rust-solar"));
+        options.insert("lang".into(), json!("rust"));
+        options.insert("version".into(), json!(env!("CARGO_PKG_VERSION")));
+        for (key, value) in [
+            ("user", &config.user),
+            ("pass", &config.password),
+            ("auth_token", &config.token),
+        ] {
+            if !value.is_empty() {
+                options.insert(key.into(), json!(value));
+            }
+        }
+        let connect = format!("CONNECT {}\r\nPING\r\n", Value::Object(options));
+        connection.stream.write_all(connect.as_bytes())?;
+        loop {
+            let message = connection.read_message()?;
+            match message.as_str() {
+                "PONG" => break,
+                "PING" => connection.stream.write_all(b"PONG\r\n")?,
+                _ if message.starts_with("-ERR") => return Err(io::Error::other(message)),
+                _ => {}
+            }
+        }
+        connection.stream.set_read_timeout(Some(DRAIN_TIMEOUT))?;
+        Ok(connection)
+    }
+
+    fn publish(&mut self, messages: &[(String, String)]) -> io::Result<()> {
+        let mut buffer = String::new();
+        for (subject, payload) in messages {
+            buffer += &format!("PUB {} {}\r\n{}\r\n", subject, payload.len(), payload);
+        }
+        self.stream.write_all(buffer.as_bytes())?;
+        // Answer the server's keep-alive pings, it disconnects clients which
+        // miss two of them.
+        loop {
+            match self.read_message() {
+                Ok(message) if message == "PING" => self.stream.write_all(b"PONG\r\n")?,
+                Ok(message) if message.starts_with("-ERR") => warn!("NATS: {}", message),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(())
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    ///
+    /// The next line from the server without its line ending.
+    ///
+    fn read_message(&mut self) -> io::Result<String> {
+        // A timeout leaves the partial line in `pending` for the next call.
+        self.reader.read_line(&mut self.pending)?;
+        if !self.pending.ends_with('\n') {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "connection closed by the server",
+            ));
+        }
+        let message = self.pending.trim_end().to_string();
+        self.pending.clear();
+        Ok(message)
+    }
+}
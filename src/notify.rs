@@ -0,0 +1,385 @@
+use crate::config::{Config, DiscordConfig, HookConfig, TelegramConfig, WebhookConfig};
+use crate::email::EmailNotifier;
+use crate::hooks;
+use crate::throttle::Throttle;
+use crate::ui::format_duration;
+use chrono::{DateTime, Local};
+use solar_core::alerts::{Event, EventKind, Severity};
+use std::{
+    io,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+//
+// Constants
+//
+/// Retries of failed Telegram and Discord messages and the delay between them.
+const CHAT_RETRIES: u32 = 3;
+const CHAT_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How often active alerts are checked for escalation.
+const ESCALATION_CHECK: Duration = Duration::from_secs(1);
+
+//
+// Structs
+//
+/// Delivers alert and load events to the configured notification backends
+/// and command hooks from a background thread, so slow endpoints never stall
+/// the datalogger.
+pub(crate) struct Notifier {
+    events: Sender<Event>,
+}
+
+/// A notification backend and its event routing settings.
+enum Backend {
+    Hook(HookConfig),
+    Webhook(WebhookConfig),
+    Email(Box<EmailNotifier>),
+    Telegram(TelegramConfig),
+    Discord(DiscordConfig),
+}
+
+/// An active alert below the minimum severity of some backends, which is
+/// sent to them once it has been active for their escalation time.
+struct Escalation {
+    event: Event,
+    /// Whether it was sent to each backend.
+    escalated: Vec<bool>,
+}
+
+//
+// implementations
+//
+impl Notifier {
+    ///
+    /// Start the delivery thread, or return `None` when no backend is configured.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Option<Self>> {
+        let mut backends: Vec<Backend> = config
+            .hooks
+            .iter()
+            .filter(|h| !h.command.is_empty())
+            .cloned()
+            .map(Backend::Hook)
+            .collect();
+        backends.extend(
+            config
+                .webhooks
+                .iter()
+                .filter(|w| !w.url.is_empty())
+                .cloned()
+                .map(Backend::Webhook),
+        );
+        if config.email.enabled {
+            backends.push(Backend::Email(Box::new(EmailNotifier::new(&config.email)?)));
+        }
+        if config.telegram.enabled {
+            backends.push(Backend::Telegram(config.telegram.clone()));
+        }
+        if config.discord.enabled {
+            backends.push(Backend::Discord(config.discord.clone()));
+        }
+        if backends.is_empty() {
+            return Ok(None);
+        }
+        let mut throttles = backends
+            .iter()
+            .map(|backend| {
+                let (quiet_hours, repeat_after_s, max_per_hour) = backend.limits();
+                Throttle::new(quiet_hours, repeat_after_s, max_per_hour)
+                    .map_err(|e| io::Error::other(format!("quiet_hours: {}.", e)))
+            })
+            .collect::<io::Result<Vec<Throttle>>>()?;
+        let (tx, rx) = mpsc::channel::<Event>();
+        thread::Builder::new()
+            .name("notify".into())
+            .spawn(move || {
+                let mut pending = Vec::new();
+                loop {
+                    match rx.recv_timeout(ESCALATION_CHECK) {
+                        Ok(event) => deliver(&backends, &mut throttles, &mut pending, event),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    let now = Local::now().timestamp();
+                    escalate(&backends, &mut throttles, &mut pending, now);
+                    for (backend, throttle) in backends.iter().zip(&mut throttles) {
+                        if let Some(summary) = throttle.summary(now) {
+                            backend.send(&summary);
+                        }
+                    }
+                }
+            })?;
+        Ok(Some(Self { events: tx }))
+    }
+
+    pub(crate) fn notify(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+}
+
+impl Backend {
+    ///
+    /// The event name filter, minimum severity and escalation time.
+    ///
+    fn routing(&self) -> (&[String], Severity, u64) {
+        match self {
+            Backend::Hook(c) => (&c.events, c.min_severity, c.escalate_after_s),
+            Backend::Webhook(c) => (&c.events, c.min_severity, c.escalate_after_s),
+            Backend::Email(email) => {
+                let c = email.config();
+                (&c.events, c.min_severity, c.escalate_after_s)
+            }
+            Backend::Telegram(c) => (&c.events, c.min_severity, c.escalate_after_s),
+            Backend::Discord(c) => (&c.events, c.min_severity, c.escalate_after_s),
+        }
+    }
+
+    ///
+    /// The quiet hours, repeat time and hourly limit of the throttle.
+    ///
+    fn limits(&self) -> (Option<&str>, u64, u32) {
+        match self {
+            Backend::Hook(c) => (c.quiet_hours.as_deref(), c.repeat_after_s, c.max_per_hour),
+            Backend::Webhook(c) => (c.quiet_hours.as_deref(), c.repeat_after_s, c.max_per_hour),
+            Backend::Email(email) => {
+                let c = email.config();
+                (c.quiet_hours.as_deref(), c.repeat_after_s, c.max_per_hour)
+            }
+            Backend::Telegram(c) => (c.quiet_hours.as_deref(), c.repeat_after_s, c.max_per_hour),
+            Backend::Discord(c) => (c.quiet_hours.as_deref(), c.repeat_after_s, c.max_per_hour),
+        }
+    }
+
+    ///
+    /// Whether the event is sent right away, an empty filter passing all names.
+    ///
+    fn wants(&self, event: &Event) -> bool {
+        let (filter, min_severity, _) = self.routing();
+        (filter.is_empty() || filter.contains(&event.name)) && event.severity >= min_severity
+    }
+
+    ///
+    /// Seconds after which the alert is sent while still active, if it is
+    /// below the minimum severity and the backend escalates.
+    ///
+    fn escalates(&self, event: &Event) -> Option<i64> {
+        let (filter, min_severity, escalate_after_s) = self.routing();
+        let matches = filter.is_empty() || filter.contains(&event.name);
+        (matches && event.severity < min_severity && escalate_after_s > 0)
+            .then_some(escalate_after_s as i64)
+    }
+
+    fn send(&self, event: &Event) {
+        match self {
+            Backend::Hook(hook) => hooks::run_hook(hook, event),
+            Backend::Webhook(webhook) => send_webhook(webhook, event),
+            Backend::Email(email) => email.send(event),
+            Backend::Telegram(telegram) => send_telegram(telegram, event),
+            Backend::Discord(discord) => send_discord(discord, event),
+        }
+    }
+}
+
+///
+/// Send an event to the backends routing it, unless their throttle holds it
+/// back, and track alerts which may be escalated. Clearing an alert is also
+/// sent where it was escalated to.
+///
+fn deliver(
+    backends: &[Backend],
+    throttles: &mut [Throttle],
+    pending: &mut Vec<Escalation>,
+    event: Event,
+) {
+    info!("{}", event.message);
+    let now = Local::now().timestamp();
+    for (backend, throttle) in backends.iter().zip(throttles.iter_mut()) {
+        if backend.wants(&event) && throttle.admit(&event, now) {
+            backend.send(&event);
+        }
+    }
+    let active = pending.iter().position(|p| p.event.name == event.name);
+    let escalation = active.map(|i| pending.remove(i));
+    match event.kind {
+        EventKind::Alert if backends.iter().any(|b| b.escalates(&event).is_some()) => {
+            pending.push(Escalation {
+                event,
+                escalated: vec![false; backends.len()],
+            });
+        }
+        EventKind::Cleared => {
+            let Some(escalation) = escalation else {
+                return;
+            };
+            for ((backend, throttle), _) in backends
+                .iter()
+                .zip(throttles.iter_mut())
+                .zip(escalation.escalated)
+                .filter(|(_, escalated)| *escalated)
+            {
+                if throttle.admit(&event, now) {
+                    backend.send(&event);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+///
+/// Send the alerts which have been active for the escalation time of a
+/// backend to it, once, through its throttle.
+///
+fn escalate(
+    backends: &[Backend],
+    throttles: &mut [Throttle],
+    pending: &mut [Escalation],
+    now: i64,
+) {
+    for escalation in pending {
+        let age = now - escalation.event.timestamp;
+        for ((backend, throttle), escalated) in backends
+            .iter()
+            .zip(throttles.iter_mut())
+            .zip(&mut escalation.escalated)
+        {
+            let due = backend
+                .escalates(&escalation.event)
+                .is_some_and(|after| age >= after);
+            if !*escalated && due {
+                *escalated = true;
+                let mut event = escalation.event.clone();
+                event.message = format!(
+                    "{} (unresolved for {})",
+                    event.message,
+                    format_duration(age)
+                );
+                if throttle.admit(&event, now) {
+                    backend.send(&event);
+                }
+            }
+        }
+    }
+}
+
+///
+/// Fill in a template, `{{field}}` being replaced by the event fields and
+/// `{{time}}` by the local time. With `json` strings are JSON escaped (without
+/// quotes) so they can be embedded in JSON templates.
+///
+pub(crate) fn render(template: &str, event: &Event, json: bool) -> String {
+    let escape = |s: &str| {
+        if !json {
+            return s.to_string();
+        }
+        let quoted = serde_json::to_string(s).unwrap_or_default();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let time = DateTime::from_timestamp(event.timestamp, 0)
+        .map(|t| {
+            t.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default();
+    template
+        .replace("{{kind}}", event.kind.name())
+        .replace("{{name}}", &event.name)
+        .replace("{{severity}}", event.severity.name())
+        .replace("{{message}}", &escape(&event.message))
+        .replace("{{value}}", &event.value.to_string())
+        .replace("{{timestamp}}", &event.timestamp.to_string())
+        .replace("{{time}}", &time)
+}
+
+fn send_webhook(webhook: &WebhookConfig, event: &Event) {
+    let body = match &webhook.template {
+        Some(template) => render(template, event, true),
+        None => serde_json::to_string(event).unwrap_or_default(),
+    };
+    let delivered = post(
+        &format!("Webhook {}", webhook.url),
+        &webhook.url,
+        &body,
+        &webhook.content_type,
+        webhook.retries,
+        Duration::from_secs(webhook.retry_delay_s),
+    );
+    if !delivered {
+        error!("Giving up on webhook {} for {}.", webhook.url, event.name);
+    }
+}
+
+///
+/// Send the event as a Telegram bot message.
+///
+fn send_telegram(telegram: &TelegramConfig, event: &Event) {
+    let url = format!(
+        "{}/bot{}/sendMessage",
+        telegram.api_url.trim_end_matches('/'),
+        telegram.bot_token
+    );
+    let body = serde_json::json!({
+        "chat_id": telegram.chat_id,
+        "text": render(&telegram.template, event, false),
+    });
+    if !post(
+        "Telegram message",
+        &url,
+        &body.to_string(),
+        "application/json",
+        CHAT_RETRIES,
+        CHAT_RETRY_DELAY,
+    ) {
+        error!("Giving up on Telegram message for {}.", event.name);
+    }
+}
+
+///
+/// Send the event to a Discord channel webhook.
+///
+fn send_discord(discord: &DiscordConfig, event: &Event) {
+    let body = serde_json::json!({
+        "username": discord.username,
+        "content": render(&discord.template, event, false),
+    });
+    if !post(
+        "Discord message",
+        &discord.webhook_url,
+        &body.to_string(),
+        "application/json",
+        CHAT_RETRIES,
+        CHAT_RETRY_DELAY,
+    ) {
+        error!("Giving up on Discord message for {}.", event.name);
+    }
+}
+
+///
+/// POST a body, retrying failed requests. Returns whether it was delivered,
+/// `label` names the endpoint in the log instead of a url that may hold a token.
+///
+fn post(
+    label: &str,
+    url: &str,
+    body: &str,
+    content_type: &str,
+    retries: u32,
+    delay: Duration,
+) -> bool {
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            thread::sleep(delay);
+        }
+        match ureq::post(url)
+            .header("Content-Type", content_type)
+            .send(body)
+        {
+            Ok(_) => return true,
+            Err(e) => warn!("{} failed (attempt {}): {}", label, attempt + 1, e),
+        }
+    }
+    false
+}
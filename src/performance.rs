@@ -0,0 +1,140 @@
+use crate::config::{Config, PerformanceConfig};
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime, TimeZone};
+use solar_core::alerts::{parse_hours, Event, EventKind, Severity};
+use solar_core::database::Database;
+use std::io;
+
+//
+// Constants
+//
+/// Seconds the charge power is averaged over, so a brief peak between clouds
+/// does not count as a sunny day.
+const POWER_BUCKET: i64 = 15 * 60;
+/// Days with data needed before the baseline is trusted.
+const MIN_BASELINE_DAYS: usize = 7;
+/// Percentile of the daily peak powers taken as the baseline.
+const BASELINE_PERCENTILE: f64 = 0.9;
+
+//
+// Structs
+//
+/// Compares the midday charge power of the last days with the power learned
+/// from the weeks before, to spot dirty or shaded panels.
+pub(crate) struct PerformanceMonitor {
+    config: PerformanceConfig,
+    midday: (NaiveTime, NaiveTime),
+    database: Database,
+    /// Day of the last check.
+    checked: Option<NaiveDate>,
+    /// Whether the panel_underperforming alert is raised.
+    raised: bool,
+}
+
+//
+// implementations
+//
+impl PerformanceMonitor {
+    ///
+    /// Check the settings of `[performance]` and open a connection to read
+    /// the stored datapoints.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Self> {
+        let settings = &config.performance;
+        let midday = parse_hours(&settings.midday)
+            .map_err(|e| io::Error::other(format!("performance.midday: {}.", e)))?;
+        if midday.0 >= midday.1 || settings.days == 0 || settings.baseline_days == 0 {
+            return Err(io::Error::other(
+                "performance.midday must not span midnight, and days and baseline_days must be at least 1.",
+            ));
+        }
+        info!(
+            "Checking the midday charge power of the last {} days against the {} days before.",
+            settings.days, settings.baseline_days
+        );
+        Ok(Self {
+            config: settings.clone(),
+            midday,
+            database: Database::new(&config.database)?,
+            checked: None,
+            raised: false,
+        })
+    }
+
+    ///
+    /// Once a day at the end of the midday window, compare the best midday
+    /// power of the last days with the baseline and return the alert, or its
+    /// clearing, when that changes.
+    ///
+    pub(crate) fn check(&mut self, timestamp: i64) -> Option<Event> {
+        let now = DateTime::from_timestamp(timestamp, 0)?.with_timezone(&Local);
+        let today = now.date_naive();
+        if self.checked == Some(today) || now.time() < self.midday.1 {
+            return None;
+        }
+        self.checked = Some(today);
+        let recent: Vec<f64> = (0..self.config.days)
+            .filter_map(|ago| self.peak_power(today - Days::new(ago.into())))
+            .collect();
+        let mut baseline: Vec<f64> = (self.config.days
+            ..self.config.days + self.config.baseline_days)
+            .filter_map(|ago| self.peak_power(today - Days::new(ago.into())))
+            .collect();
+        if recent.len() < self.config.days as usize || baseline.len() < MIN_BASELINE_DAYS {
+            info!("Not enough midday data yet to check the panel performance.");
+            return None;
+        }
+        baseline.sort_by(f64::total_cmp);
+        let index = ((baseline.len() - 1) as f64 * BASELINE_PERCENTILE).round() as usize;
+        let expected = baseline[index];
+        if expected <= 0.0 {
+            return None;
+        }
+        let best = recent.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let percent = (best / expected * 100.0).round();
+        info!(
+            "Best midday charge power of the last {} days {:.0} W, {}% of the baseline of {:.0} W.",
+            self.config.days, best, percent, expected
+        );
+        let low = percent < 100.0 - self.config.drop_percent;
+        if low == self.raised {
+            return None;
+        }
+        self.raised = low;
+        let (kind, message) = match low {
+            true => (
+                EventKind::Alert,
+                format!(
+                    "Panels underperforming: midday charge power at most {}% of the usual {:.0} W for {} days",
+                    percent, expected, self.config.days
+                ),
+            ),
+            false => (
+                EventKind::Cleared,
+                format!("Panels back at {}% of the usual midday charge power", percent),
+            ),
+        };
+        Some(Event {
+            kind,
+            name: "panel_underperforming".to_string(),
+            severity: Severity::Warning,
+            message,
+            value: percent,
+            timestamp,
+        })
+    }
+
+    ///
+    /// The peak charge power within the midday window of a day, if there is
+    /// data for it.
+    ///
+    fn peak_power(&self, day: NaiveDate) -> Option<f64> {
+        let at = |time| Local.from_local_datetime(&day.and_time(time)).earliest();
+        let (from, to) = (at(self.midday.0)?, at(self.midday.1)?);
+        self.database
+            .peak_charge_power(from.timestamp(), to.timestamp(), POWER_BUCKET)
+            .unwrap_or_else(|e| {
+                warn!("{}", e);
+                None
+            })
+    }
+}
@@ -0,0 +1,164 @@
+use crate::cli::OutputFormat;
+use serde::Serialize;
+use serialport::SerialPortType;
+use std::{
+    error::Error,
+    io,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+//
+// Structs
+//
+/// A serial port together with the metadata reported by the OS.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PortInfo {
+    pub(crate) name: String,
+    pub(crate) kind: &'static str,
+    pub(crate) vid: Option<u16>,
+    pub(crate) pid: Option<u16>,
+    pub(crate) serial_number: Option<String>,
+    pub(crate) manufacturer: Option<String>,
+    pub(crate) product: Option<String>,
+}
+
+//
+// implementations
+//
+impl From<serialport::SerialPortInfo> for PortInfo {
+    fn from(port: serialport::SerialPortInfo) -> Self {
+        let mut info = Self {
+            name: port.port_name,
+            kind: "unknown",
+            vid: None,
+            pid: None,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+        };
+        match port.port_type {
+            SerialPortType::UsbPort(usb) => {
+                info.kind = "usb";
+                info.vid = Some(usb.vid);
+                info.pid = Some(usb.pid);
+                info.serial_number = usb.serial_number;
+                info.manufacturer = usb.manufacturer;
+                info.product = usb.product;
+            }
+            SerialPortType::PciPort => info.kind = "pci",
+            SerialPortType::BluetoothPort => info.kind = "bluetooth",
+            SerialPortType::Unknown => {}
+        }
+        info
+    }
+}
+
+impl PortInfo {
+    ///
+    /// Name shown in the port picker and logs. On Windows `COM5` alone says
+    /// little, so the device description is used, e.g. `USB-SERIAL CH340 (COM5)`.
+    ///
+    pub(crate) fn label(&self) -> String {
+        match &self.product {
+            Some(product) if cfg!(windows) => {
+                if product.contains(&self.name) {
+                    product.clone()
+                } else {
+                    format!("{} ({})", product, self.name)
+                }
+            }
+            _ => self.name.clone(),
+        }
+    }
+}
+
+///
+/// Label of the named port as given by [`PortInfo::label`], or the name itself
+/// when the port is not currently enumerated.
+///
+pub(crate) fn label_of(name: &str) -> String {
+    available_ports()
+        .ok()
+        .and_then(|ports| ports.into_iter().find(|p| p.name == name))
+        .map_or_else(|| name.to_string(), |p| p.label())
+}
+
+///
+/// Enumerate the serial ports available on this machine.
+///
+pub(crate) fn available_ports() -> serialport::Result<Vec<PortInfo>> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .map(PortInfo::from)
+        .collect())
+}
+
+///
+/// Enumerate the serial ports on a thread of its own, as it takes seconds on
+/// some systems, e.g. with many Bluetooth or virtual ports.
+///
+pub(crate) fn enumerate_in_background() -> io::Result<Receiver<serialport::Result<Vec<PortInfo>>>> {
+    let (tx, rx) = mpsc::channel();
+    thread::Builder::new().name("ports".into()).spawn(move || {
+        let _ = tx.send(available_ports());
+    })?;
+    Ok(rx)
+}
+
+///
+/// Log the label of the configured port, looked up aside so enumerating the
+/// ports does not hold up the UI.
+///
+pub(crate) fn log_configured(name: &str) {
+    let name = name.to_string();
+    let spawned = thread::Builder::new()
+        .name("ports".into())
+        .spawn(move || info!("Using configured serial port: {}", label_of(&name)));
+    if let Err(e) = spawned {
+        warn!("{}", e);
+    }
+}
+
+///
+/// Print the available serial ports to stdout, for scripts and udev debugging.
+///
+pub(crate) fn run_list_ports(format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let ports = available_ports()?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&ports)?),
+        OutputFormat::Table => {
+            print_row([
+                "port",
+                "type",
+                "vid:pid",
+                "serial",
+                "manufacturer",
+                "product",
+            ]);
+            for port in ports {
+                let ids = match (port.vid, port.pid) {
+                    (Some(vid), Some(pid)) => format!("{:04x}:{:04x}", vid, pid),
+                    _ => String::from("-"),
+                };
+                print_row([
+                    &port.name,
+                    port.kind,
+                    &ids,
+                    port.serial_number.as_deref().unwrap_or("-"),
+                    port.manufacturer.as_deref().unwrap_or("-"),
+                    port.product.as_deref().unwrap_or("-"),
+                ]);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_row(columns: [&str; 6]) {
+    let [port, kind, ids, serial, manufacturer, product] = columns;
+    println!(
+        "{:<24} {:<10} {:<9} {:<20} {:<24} {}",
+        port, kind, ids, serial, manufacturer, product
+    );
+}
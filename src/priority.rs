@@ -0,0 +1,91 @@
+use std::io;
+
+///
+/// Set the nice value, realtime priority and CPU affinity of the calling
+/// thread as configured in `[threads]`, leaving whatever is 0 or empty alone.
+/// Failures, e.g. raising the priority without CAP_SYS_NICE, are logged and
+/// the thread runs on as it was.
+///
+pub(crate) fn apply(thread: &str, nice: i32, realtime_priority: u32, cpus: &[usize]) {
+    if nice == 0 && realtime_priority == 0 && cpus.is_empty() {
+        return;
+    }
+    let result = set_nice(nice)
+        .and_then(|_| set_realtime(realtime_priority))
+        .and_then(|_| set_affinity(cpus));
+    match result {
+        Ok(()) => info!(
+            "Running the {} thread with nice {}, realtime priority {} on cpus {:?}.",
+            thread, nice, realtime_priority, cpus
+        ),
+        Err(e) => warn!(
+            "Setting the priority of the {} thread failed: {}",
+            thread, e
+        ),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_nice(nice: i32) -> io::Result<()> {
+    if nice == 0 {
+        return Ok(());
+    }
+    // The nice value of a thread id only applies to that thread on Linux.
+    let tid = unsafe { libc::gettid() };
+    match unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_realtime(priority: u32) -> io::Result<()> {
+    if priority == 0 {
+        return Ok(());
+    }
+    let param = libc::sched_param {
+        sched_priority: priority.min(99) as i32,
+    };
+    match unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    if cpus.is_empty() {
+        return Ok(());
+    }
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::other(format!("no cpu {}", cpu)));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    match unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_nice(_nice: i32) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_realtime(_priority: u32) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+    io::Error::other("thread priorities are only supported on Linux")
+}
@@ -0,0 +1,35 @@
+use crate::cli::{OutputFormat, QueryArgs};
+use crate::config::Config;
+use chrono::{DateTime, Local};
+use solar_core::database::Database;
+use std::error::Error;
+
+///
+/// Run an aggregation query against the database and print the result to stdout.
+///
+pub(crate) fn run_query(config: &Config, args: &QueryArgs) -> Result<(), Box<dyn Error>> {
+    let (aggregation, interval, field) = args.aggregation()?;
+    let database = Database::new(&config.database)?;
+    let buckets = database.aggregate(
+        aggregation,
+        field,
+        i64::try_from(interval)?,
+        args.from.unwrap_or(0),
+        args.to.unwrap_or(i64::MAX),
+    )?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&buckets)?),
+        OutputFormat::Table => {
+            let header = format!("{:?}({})", aggregation, field).to_lowercase();
+            println!("{:<32} {:>16} {:>8}", "time", header, "samples");
+            for bucket in buckets {
+                let time = DateTime::from_timestamp(bucket.time, 0)
+                    .map(|t| t.with_timezone(&Local).to_rfc3339())
+                    .unwrap_or_else(|| bucket.time.to_string());
+                println!("{:<32} {:>16.3} {:>8}", time, bucket.value, bucket.samples);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Clients are forgotten once their bucket has been full for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Idle clients are pruned whenever this many are tracked.
+const PRUNE_THRESHOLD: usize = 1024;
+
+//
+// Structs
+//
+/// Token bucket per client address: each client may send `burst` requests at
+/// once, refilled at `per_minute` requests per minute.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    per_minute: u32,
+    burst: u32,
+    clients: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+//
+// implementations
+//
+impl RateLimiter {
+    pub(crate) fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            per_minute,
+            burst: burst.max(1),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    ///
+    /// Take a token for `client`, or return how long until the next one is
+    /// available.
+    ///
+    pub(crate) fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let rate = f64::from(self.per_minute) / 60.0;
+        let burst = f64::from(self.burst);
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        if clients.len() >= PRUNE_THRESHOLD {
+            clients.retain(|_, bucket| now.duration_since(bucket.updated) < IDLE_TIMEOUT);
+        }
+        let bucket = clients.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
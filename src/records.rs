@@ -0,0 +1,47 @@
+use crate::config::Config;
+use solar_core::database::Database;
+use solar_core::datapoint::DataPoint;
+use solar_core::records::{self, Record, ALL_TIME, RECORD_KINDS};
+use std::io;
+
+//
+// Structs
+//
+/// Keeps the all-time and monthly records up to date with every datapoint.
+pub(crate) struct RecordTracker {
+    database: Database,
+    records: Vec<Record>,
+}
+
+//
+// implementations
+//
+impl RecordTracker {
+    ///
+    /// Continue from the records stored so far.
+    ///
+    pub(crate) fn new(config: &Config) -> io::Result<Self> {
+        let database = Database::new(&config.database)?;
+        let records = database.records().map_err(io::Error::other)?;
+        Ok(Self { database, records })
+    }
+
+    ///
+    /// Store the records the datapoint beats.
+    ///
+    pub(crate) fn check(&mut self, datapoint: &DataPoint) {
+        let beaten = records::update(&mut self.records, datapoint);
+        if beaten.is_empty() {
+            return;
+        }
+        for record in beaten.iter().filter(|r| r.period == ALL_TIME) {
+            if let Some(kind) = RECORD_KINDS.iter().find(|k| k.name == record.name) {
+                debug!(
+                    "New all-time record: {} {:.2} {}.",
+                    kind.label, record.value, kind.unit
+                );
+            }
+        }
+        self.database.store_records(&beaten);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::config::RedisConfig;
+use serde_json::Value;
+use solar_core::datapoint::DataPoint;
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Delay before reconnecting to Redis, datapoints are dropped meanwhile.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// Time allowed for Redis to answer a batch of commands.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+//
+// Structs
+//
+/// Adds each value of a datapoint to a RedisTimeSeries key,
+/// `<key_prefix>:<device>:<field>`, from a background thread. Missing keys
+/// are created by `TS.ADD` with the configured retention and labels.
+pub(crate) struct RedisWriter {
+    commands: Sender<Vec<Vec<String>>>,
+    key_base: String,
+    device: String,
+    retention_ms: u64,
+}
+
+/// A connection speaking RESP, the Redis protocol.
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+//
+// implementations
+//
+impl RedisWriter {
+    pub(crate) fn new(config: &RedisConfig) -> io::Result<Self> {
+        info!(
+            "Adding datapoints to RedisTimeSeries at {} under {}:{}",
+            config.address, config.key_prefix, config.device
+        );
+        let (tx, rx) = mpsc::channel::<Vec<Vec<String>>>();
+        let key_base = format!("{}:{}", config.key_prefix, config.device);
+        let device = config.device.clone();
+        let retention_ms = config.retention_ms;
+        let config = config.clone();
+        thread::Builder::new().name("redis".into()).spawn(move || {
+            let mut connection: Option<Connection> = None;
+            let mut next_attempt = Instant::now();
+            // Errors such as a missing TimeSeries module repeat with every
+            // datapoint, so each is only logged once in a row.
+            let mut last_error: Option<String> = None;
+            for commands in rx {
+                if connection.is_none() && Instant::now() >= next_attempt {
+                    match Connection::open(&config) {
+                        Ok(c) => {
+                            info!("Connected to Redis at {}", config.address);
+                            connection = Some(c);
+                        }
+                        Err(e) => {
+                            warn!("Redis {}: {}", config.address, e);
+                            next_attempt = Instant::now() + RECONNECT_DELAY;
+                        }
+                    }
+                }
+                if let Some(c) = &mut connection {
+                    match c.execute(&commands) {
+                        Ok(mut errors) => {
+                            let error = errors.pop();
+                            if let Some(e) =
+                                error.as_ref().filter(|&e| Some(e) != last_error.as_ref())
+                            {
+                                warn!("Redis {}: {}", config.address, e);
+                            }
+                            last_error = error;
+                        }
+                        Err(e) => {
+                            warn!("Redis {}: {}", config.address, e);
+                            connection = None;
+                            next_attempt = Instant::now() + RECONNECT_DELAY;
+                        }
+                    }
+                }
+            }
+        })?;
+        Ok(Self {
+            commands: tx,
+            key_base,
+            device,
+            retention_ms,
+        })
+    }
+
+    pub(crate) fn push(&self, datapoint: &DataPoint) {
+        let Ok(Value::Object(fields)) = serde_json::to_value(datapoint) else {
+            return;
+        };
+        let timestamp = (datapoint.get_time() * 1000).to_string();
+        let commands = fields
+            .into_iter()
+            .filter(|(field, _)| field != "timestamp")
+            .map(|(field, value)| {
+                vec![
+                    String::from("TS.ADD"),
+                    format!("{}:{}", self.key_base, field),
+                    timestamp.clone(),
+                    value.to_string(),
+                    String::from("RETENTION"),
+                    self.retention_ms.to_string(),
+                    // Two datapoints can be read within the same second.
+                    String::from("ON_DUPLICATE"),
+                    String::from("LAST"),
+                    String::from("LABELS"),
+                    String::from("device"),
+                    self.device.clone(),
+                    String::from("field"),
+                    field,
+                ]
+            })
+            .collect();
+        let _ = self.commands.send(commands);
+    }
+}
+
+impl Connection {
+    fn open(config: &RedisConfig) -> io::Result<Self> {
+        let stream = TcpStream::connect(&config.address)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        let mut connection = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        };
+        if !config.password.is_empty() {
+            let mut auth = vec![String::from("AUTH")];
+            if !config.username.is_empty() {
+                auth.push(config.username.clone());
+            }
+            auth.push(config.password.clone());
+            if let Some(error) = connection.execute(&[auth])?.pop() {
+                return Err(io::Error::other(error));
+            }
+        }
+        Ok(connection)
+    }
+
+    ///
+    /// Send the commands in one pipeline and wait for their replies, returning
+    /// the error replies. I/O errors mean the connection is lost.
+    ///
+    fn execute(&mut self, commands: &[Vec<String>]) -> io::Result<Vec<String>> {
+        let mut request = Vec::new();
+        for command in commands {
+            request.extend_from_slice(format!("*{}\r\n", command.len()).as_bytes());
+            for arg in command {
+                request.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+            }
+        }
+        self.stream.write_all(&request)?;
+        let mut errors = Vec::new();
+        for _ in commands {
+            let mut reply = String::new();
+            if self.reader.read_line(&mut reply)? == 0 {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "connection closed by the server",
+                ));
+            }
+            // AUTH and TS.ADD only answer with simple strings, integers or errors.
+            match reply.trim_end().split_at_checked(1) {
+                Some(("-", error)) => errors.push(error.to_string()),
+                Some(("+" | ":", _)) => {}
+                _ => {
+                    return Err(io::Error::other(format!(
+                        "unexpected reply {:?}",
+                        reply.trim_end()
+                    )))
+                }
+            }
+        }
+        Ok(errors)
+    }
+}
@@ -0,0 +1,280 @@
+use crate::config::LoadConfig;
+use crate::control_auth::ControlAuth;
+use crate::datalogger::{ControlCommand, DataloggerHandle, DataloggerThread, EventBus, LinkHealth};
+use crate::ui::HistorySource;
+use solar_core::cycles::CycleCounts;
+use solar_core::database::AlertRecord;
+use solar_core::datapoint::DataPoint;
+use solar_core::health::{HealthDay, HealthTrend};
+use solar_core::recent::{RecentData, RECENT_SAMPLES};
+use solar_core::records::Record;
+use solar_core::rollup::{HourlyEnergy, Summary};
+use solar_core::shutdown::Shutdown;
+use solar_core::weather::WeatherHour;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tungstenite::{client::IntoClientRequest, http::HeaderValue, stream::MaybeTlsStream, Message};
+
+//
+// Constants
+//
+/// Delay before reconnecting after the stream was lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long the stream thread blocks before checking whether to stop.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+//
+// Structs
+//
+/// Client for the HTTP API of a `serve` daemon, used by `connect` in place
+/// of the serial port and the local database.
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteClient {
+    /// Base url without trailing slash, e.g. `http://pi:8080`.
+    url: String,
+    api_key: Option<String>,
+    /// Sent with load commands, see `load.control_pin`.
+    pin: Option<String>,
+}
+
+//
+// implementations
+//
+impl RemoteClient {
+    ///
+    /// `host` is a url or just `host:port`, in which case http is assumed.
+    ///
+    pub(crate) fn new(host: &str, api_key: Option<String>, pin: Option<String>) -> Self {
+        let host = host.trim_end_matches('/');
+        let url = if host.contains("://") {
+            host.to_string()
+        } else {
+            format!("http://{}", host)
+        };
+        Self { url, api_key, pin }
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    ///
+    /// Fetch the latest datapoint and start streaming the following ones, the
+    /// returned handle behaving like a local datalogger. Load commands are
+    /// forwarded to `POST /api/load`.
+    ///
+    pub(crate) fn connect(&self, shutdown: &Shutdown) -> io::Result<DataloggerHandle> {
+        let running = shutdown.flag();
+        let initial: DataPoint = self.get("/api/latest", &[])?;
+        let bus = EventBus::new();
+        let (datapoints, events) = bus.subscribe();
+        let recent = RecentData::new(RECENT_SAMPLES);
+        recent.push(initial);
+        let (load_tx, mut load_rx) = tokio::sync::mpsc::unbounded_channel::<ControlCommand>();
+        let health = Arc::new(LinkHealth::new(initial.get_time(), RECONNECT_DELAY));
+        let (load, load_state) = watch::channel(initial.get_load_onoff() > 0.0);
+        let load = Arc::new(load);
+        let stream = {
+            let client = self.clone();
+            let health = Arc::clone(&health);
+            let recent = recent.clone();
+            let load = Arc::clone(&load);
+            move || {
+                while running.load(Ordering::SeqCst) {
+                    if let Err(e) = client.stream(&bus, &recent, &health, &load, &running) {
+                        health.record_error(&e);
+                        warn!("{}/api/stream: {}", client.url, e);
+                    }
+                    let start = Instant::now();
+                    while running.load(Ordering::SeqCst) && start.elapsed() < RECONNECT_DELAY {
+                        thread::sleep(STOP_CHECK_INTERVAL);
+                    }
+                }
+            }
+        };
+        let thread = thread::Builder::new().name("remote".into()).spawn(stream)?;
+        let client = self.clone();
+        thread::Builder::new()
+            .name("remote-load".into())
+            .spawn(move || {
+                while let Some(command) = load_rx.blocking_recv() {
+                    // Only the load can be switched remotely.
+                    let ControlCommand::Load(command) = command else {
+                        continue;
+                    };
+                    let state = if command.on { "on" } else { "off" };
+                    match client
+                        .request(ureq::post(format!("{}/api/load", client.url)))
+                        .send_json(serde_json::json!({ "state": state, "pin": client.pin }))
+                    {
+                        Ok(_) => {
+                            load.send_replace(command.on);
+                        }
+                        Err(e) => warn!("Switching the remote load failed: {}", e),
+                    }
+                }
+            })?;
+        info!("Connected to {}", self.url);
+        Ok(DataloggerHandle {
+            initial,
+            load: load_state,
+            datapoints,
+            // The daemon's alerts reach its own notifiers, not this TUI.
+            events,
+            recent,
+            control: load_tx,
+            // Load commands are checked by the daemon.
+            control_auth: Arc::new(ControlAuth::new(&LoadConfig::default())),
+            health,
+            thread: DataloggerThread::new(shutdown.clone(), thread),
+        })
+    }
+
+    ///
+    /// Forward datapoints from the WebSocket until it closes or `running` is
+    /// cleared. A change of the reported load state updates `load`.
+    ///
+    fn stream(
+        &self,
+        bus: &EventBus,
+        recent: &RecentData,
+        health: &LinkHealth,
+        load: &watch::Sender<bool>,
+        running: &AtomicBool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let url = format!("{}/api/stream", self.url.replacen("http", "ws", 1));
+        let mut request = url.into_client_request()?;
+        if let Some(key) = &self.api_key {
+            let value = HeaderValue::from_str(&format!("Bearer {}", key))?;
+            request.headers_mut().insert("Authorization", value);
+        }
+        let (mut socket, _) = tungstenite::connect(request)?;
+        // Wake up now and then to notice `running` being cleared.
+        let stream = match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream,
+            MaybeTlsStream::Rustls(stream) => &stream.sock,
+            _ => return Err(io::Error::other("unsupported WebSocket stream type").into()),
+        };
+        stream.set_read_timeout(Some(STOP_CHECK_INTERVAL))?;
+        while running.load(Ordering::SeqCst) {
+            let message = match socket.read() {
+                Ok(message) => message,
+                Err(tungstenite::Error::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+            match message {
+                Message::Text(text) => {
+                    let datapoint: DataPoint = serde_json::from_str(&text)?;
+                    let load_on = datapoint.get_load_onoff() > 0.0;
+                    if recent
+                        .latest()
+                        .is_some_and(|latest| (latest.get_load_onoff() > 0.0) != load_on)
+                    {
+                        load.send_replace(load_on);
+                    }
+                    health.record_sample(datapoint.get_time());
+                    recent.push(datapoint);
+                    bus.publish(datapoint);
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> io::Result<T> {
+        let mut request = self.request(ureq::get(format!("{}{}", self.url, path)));
+        for (name, value) in query {
+            request = request.query(*name, value);
+        }
+        request
+            .call()
+            .and_then(|mut r| r.body_mut().read_json())
+            .map_err(|e| io::Error::other(format!("{}{}: {}", self.url, path, e)))
+    }
+
+    fn request<B>(&self, request: ureq::RequestBuilder<B>) -> ureq::RequestBuilder<B> {
+        match &self.api_key {
+            Some(key) => request.header("Authorization", &format!("Bearer {}", key)),
+            None => request,
+        }
+    }
+}
+
+impl HistorySource for RemoteClient {
+    fn history(&self, from: i64, to: i64, points: usize) -> Result<Vec<DataPoint>, String> {
+        let query = [
+            ("from", from.to_string()),
+            ("to", to.to_string()),
+            ("points", points.to_string()),
+        ];
+        self.get("/api/history", &query).map_err(|e| e.to_string())
+    }
+
+    fn summary(&self, from: i64, to: i64) -> Result<Summary, String> {
+        let query = [("from", from.to_string()), ("to", to.to_string())];
+        self.get("/api/summary", &query).map_err(|e| e.to_string())
+    }
+
+    fn hourly_harvest(&self, from: i64, to: i64) -> Result<Vec<HourlyEnergy>, String> {
+        let query = [("from", from.to_string()), ("to", to.to_string())];
+        self.get("/api/harvest", &query).map_err(|e| e.to_string())
+    }
+
+    fn alerts(&self, from: i64, to: i64, limit: usize) -> Result<Vec<AlertRecord>, String> {
+        let query = [
+            ("from", from.to_string()),
+            ("to", to.to_string()),
+            ("limit", limit.to_string()),
+        ];
+        self.get("/api/alerts", &query).map_err(|e| e.to_string())
+    }
+
+    fn acknowledge_alert(&self, id: i64) -> Result<(), String> {
+        let path = format!("/api/alerts/{}/ack", id);
+        self.request(ureq::post(format!("{}{}", self.url, path)))
+            .send_empty()
+            .map(|_| ())
+            .map_err(|e| format!("{}{}: {}", self.url, path, e))
+    }
+
+    fn battery_health(&self, from: i64, to: i64) -> Result<Vec<HealthDay>, String> {
+        let query = [("from", from.to_string()), ("to", to.to_string())];
+        self.get("/api/battery-health", &query)
+            .map(|trend: HealthTrend| trend.days)
+            .map_err(|e| e.to_string())
+    }
+
+    fn cycle_counts(&self) -> Result<Vec<CycleCounts>, String> {
+        self.get("/api/cycles", &[]).map_err(|e| e.to_string())
+    }
+
+    fn weather(&self, from: i64, to: i64) -> Result<Vec<WeatherHour>, String> {
+        let query = [("from", from.to_string()), ("to", to.to_string())];
+        self.get("/api/weather", &query).map_err(|e| e.to_string())
+    }
+
+    fn records(&self) -> Result<Vec<Record>, String> {
+        self.get("/api/records", &[]).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,258 @@
+use crate::batch_uploader::{BatchLimits, BatchUploader, BatchWriter};
+use crate::config::RemoteWriteConfig;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::Value;
+use solar_core::datapoint::DataPoint;
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+};
+
+//
+// Structs
+//
+/// Pushes datapoints to a Prometheus remote-write endpoint (Prometheus,
+/// Mimir, VictoriaMetrics, Grafana Cloud) from a background thread. While the
+/// endpoint is unreachable datapoints are kept in a bounded backlog and
+/// appended to a write-ahead log, so they survive a restart.
+pub(crate) struct RemoteWriteWriter {
+    uploader: BatchUploader<DataPoint>,
+}
+
+/// Writes batches as `WriteRequest`s and mirrors the backlog to the
+/// write-ahead log during an outage.
+struct SeriesWriter {
+    config: RemoteWriteConfig,
+    /// Open while the backlog is mirrored to `config.wal_path`.
+    wal: Option<File>,
+}
+
+//
+// implementations
+//
+impl RemoteWriteWriter {
+    pub(crate) fn new(config: &RemoteWriteConfig) -> io::Result<Self> {
+        let limits = BatchLimits {
+            batch_size: config.batch_size,
+            max_backlog: config.max_backlog,
+        };
+        let writer = SeriesWriter {
+            config: config.clone(),
+            wal: None,
+        };
+        let replayed = writer.replay_wal()?;
+        let uploader =
+            BatchUploader::spawn("remote-write", "Remote write", limits, writer, replayed)?;
+        info!("Pushing datapoints to {} with remote write", config.url);
+        Ok(Self { uploader })
+    }
+
+    ///
+    /// Queue a datapoint for the next batch.
+    ///
+    pub(crate) fn push(&self, datapoint: &DataPoint) {
+        self.uploader.push(*datapoint);
+    }
+}
+
+impl SeriesWriter {
+    ///
+    /// Load datapoints left in the write-ahead log by an earlier run, they are
+    /// sent before any new one.
+    ///
+    fn replay_wal(&self) -> io::Result<Vec<DataPoint>> {
+        let Some(path) = &self.config.wal_path else {
+            return Ok(Vec::new());
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io::Error::other(format!("{}: {}", path.display(), e))),
+        };
+        let mut datapoints = Vec::new();
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(datapoint) => datapoints.push(datapoint),
+                // The last line is cut short if the process died while writing it.
+                Err(e) => warn!("{}: skipping invalid entry: {}", path.display(), e),
+            }
+        }
+        if !datapoints.is_empty() {
+            info!(
+                "Resending {} datapoints from {}",
+                datapoints.len(),
+                path.display()
+            );
+        }
+        Ok(datapoints)
+    }
+
+    ///
+    /// Start mirroring the backlog to the write-ahead log, on the first
+    /// failure of an outage.
+    ///
+    fn open_wal(&mut self, backlog: &VecDeque<DataPoint>) {
+        let Some(path) = &self.config.wal_path else {
+            return;
+        };
+        if self.wal.is_some() {
+            return;
+        }
+        // Rewritten from the backlog, which includes what was replayed.
+        let result = File::create(path).and_then(|mut file| {
+            append(&mut file, backlog)?;
+            Ok(file)
+        });
+        match result {
+            Ok(file) => self.wal = Some(file),
+            Err(e) => warn!("Remote write WAL {}: {}", path.display(), e),
+        }
+    }
+
+    ///
+    /// Remove the write-ahead log once the backlog has been sent.
+    ///
+    fn close_wal(&mut self) {
+        let Some(path) = &self.config.wal_path else {
+            return;
+        };
+        if self.wal.take().is_some() || path.exists() {
+            if let Err(e) = fs::remove_file(path) {
+                warn!("Remote write WAL {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    ///
+    /// A snappy compressed `WriteRequest` with one series per field, labelled
+    /// with the device.
+    ///
+    fn encode(&self, datapoints: &[DataPoint]) -> Vec<u8> {
+        let rows: Vec<_> = datapoints
+            .iter()
+            .filter_map(|datapoint| match serde_json::to_value(datapoint) {
+                Ok(Value::Object(values)) => Some((datapoint.get_time(), values)),
+                _ => None,
+            })
+            .collect();
+        let Some((_, fields)) = rows.first() else {
+            return Vec::new();
+        };
+        let mut request = Vec::new();
+        for field in fields.keys().filter(|field| *field != "timestamp") {
+            let mut series = Vec::new();
+            // Labels must be sorted by name.
+            for (name, value) in [
+                (
+                    "__name__",
+                    format!("{}{}", self.config.metric_prefix, field),
+                ),
+                ("device", self.config.device.clone()),
+            ] {
+                let mut label = Vec::new();
+                protobuf::bytes(&mut label, 1, name.as_bytes());
+                protobuf::bytes(&mut label, 2, value.as_bytes());
+                protobuf::bytes(&mut series, 1, &label);
+            }
+            for (time, values) in &rows {
+                let mut sample = Vec::new();
+                protobuf::double(&mut sample, 1, values[field].as_f64().unwrap_or(f64::NAN));
+                protobuf::int64(&mut sample, 2, time * 1000);
+                protobuf::bytes(&mut series, 2, &sample);
+            }
+            protobuf::bytes(&mut request, 1, &series);
+        }
+        snap::raw::Encoder::new()
+            .compress_vec(&request)
+            .unwrap_or_default()
+    }
+
+    fn post(&self, body: Vec<u8>) -> Result<(), ureq::Error> {
+        let mut request = ureq::post(&self.config.url)
+            .header("Content-Type", "application/x-protobuf")
+            .header("Content-Encoding", "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0");
+        if !self.config.token.is_empty() {
+            request = request.header("Authorization", &format!("Bearer {}", self.config.token));
+        } else if !self.config.username.is_empty() {
+            let credentials = format!("{}:{}", self.config.username, self.config.password);
+            request = request.header(
+                "Authorization",
+                &format!("Basic {}", BASE64_STANDARD.encode(credentials)),
+            );
+        }
+        request.send(&body[..])?;
+        Ok(())
+    }
+}
+
+impl BatchWriter for SeriesWriter {
+    type Item = DataPoint;
+
+    fn write(&mut self, batch: &[DataPoint]) -> Result<(), ureq::Error> {
+        self.post(self.encode(batch))
+    }
+
+    fn queued(&mut self, datapoint: &DataPoint) {
+        if let Some(wal) = &mut self.wal {
+            if let Err(e) = append(wal, [datapoint]) {
+                warn!("Remote write WAL: {}", e);
+            }
+        }
+    }
+
+    fn failed(&mut self, backlog: &VecDeque<DataPoint>) {
+        self.open_wal(backlog);
+    }
+
+    fn emptied(&mut self) {
+        self.close_wal();
+    }
+
+    fn keeps_unwritten(&self) -> bool {
+        self.wal.is_some()
+    }
+}
+
+///
+/// Append datapoints to the write-ahead log, one JSON object per line.
+///
+fn append<'a>(
+    wal: &mut File,
+    datapoints: impl IntoIterator<Item = &'a DataPoint>,
+) -> io::Result<()> {
+    let mut lines = String::new();
+    for datapoint in datapoints {
+        lines += &serde_json::to_string(datapoint)?;
+        lines.push('\n');
+    }
+    wal.write_all(lines.as_bytes())
+}
+
+/// The few protobuf encodings needed for a `WriteRequest`.
+mod protobuf {
+    fn varint(buffer: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buffer.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        buffer.push(value as u8);
+    }
+
+    pub(super) fn bytes(buffer: &mut Vec<u8>, field: u64, value: &[u8]) {
+        varint(buffer, field << 3 | 2);
+        varint(buffer, value.len() as u64);
+        buffer.extend_from_slice(value);
+    }
+
+    pub(super) fn double(buffer: &mut Vec<u8>, field: u64, value: f64) {
+        varint(buffer, field << 3 | 1);
+        buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(super) fn int64(buffer: &mut Vec<u8>, field: u64, value: i64) {
+        varint(buffer, field << 3);
+        varint(buffer, value as u64);
+    }
+}
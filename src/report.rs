@@ -0,0 +1,227 @@
+use crate::loads::LoadEnergy;
+use crate::stats::local_midnight;
+use crate::tariff::Tariff;
+use crate::ui::HistorySource;
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use solar_core::rollup::Summary;
+use std::error::Error;
+
+/// A period of a report as (label, start, end) in unix seconds.
+type Period = (String, i64, i64);
+
+//
+// Structs
+//
+/// The calendar periods a report has a row for, in local time. Weeks start
+/// on Monday.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReportPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+/// A row of a report: the energy and voltage extremes of one period.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ReportRow {
+    /// e.g. `2024-05-17`, `2024-W20` or `2024-05`.
+    pub(crate) period: String,
+    #[serde(flatten)]
+    pub(crate) summary: Summary,
+    /// What the harvested energy would have cost from the grid, with a tariff.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) currency: Option<String>,
+    /// The energy consumed by each named load, see `loads::attribute`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) loads: Vec<LoadEnergy>,
+}
+
+/// How well the solar forecast matched the harvest over a number of days.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ForecastAccuracy {
+    /// Days with a forecast the accuracy is taken over.
+    pub(crate) days: usize,
+    /// 100 less the mean deviation of the harvest from the forecast in
+    /// percent of it, at least 0.
+    pub(crate) accuracy_percent: f64,
+    /// The harvest of all days in percent of their forecast. Steady near 100
+    /// the forecast is biased by weather only, well below it on sunny days
+    /// too the system harvests less than it should.
+    pub(crate) achieved_percent: f64,
+}
+
+//
+// Constants
+//
+/// Days with less forecast harvest are left out of the accuracy, their
+/// percentages are all noise.
+const MIN_FORECAST_WH: f64 = 1.0;
+
+//
+// implementations
+//
+impl ReportPeriod {
+    pub(crate) const ALL: [ReportPeriod; 3] =
+        [ReportPeriod::Day, ReportPeriod::Week, ReportPeriod::Month];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ReportPeriod::Day => "Daily",
+            ReportPeriod::Week => "Weekly",
+            ReportPeriod::Month => "Monthly",
+        }
+    }
+
+    ///
+    /// Rows in a report unless a count is given: two weeks of days, eight
+    /// weeks or a year of months.
+    ///
+    pub(crate) fn default_count(self) -> usize {
+        match self {
+            ReportPeriod::Day => 14,
+            ReportPeriod::Week => 8,
+            ReportPeriod::Month => 12,
+        }
+    }
+
+    ///
+    /// The first day of the period containing `date`.
+    ///
+    pub(crate) fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            ReportPeriod::Day => date,
+            ReportPeriod::Week => date - Days::new(date.weekday().num_days_from_monday().into()),
+            ReportPeriod::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    pub(crate) fn next(self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            ReportPeriod::Day => start.checked_add_days(Days::new(1)),
+            ReportPeriod::Week => start.checked_add_days(Days::new(7)),
+            ReportPeriod::Month => start.checked_add_months(Months::new(1)),
+        }
+    }
+
+    pub(crate) fn previous(self, start: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            ReportPeriod::Day => start.checked_sub_days(Days::new(1)),
+            ReportPeriod::Week => start.checked_sub_days(Days::new(7)),
+            ReportPeriod::Month => start.checked_sub_months(Months::new(1)),
+        }
+    }
+
+    pub(crate) fn label(self, start: NaiveDate) -> String {
+        match self {
+            ReportPeriod::Day => start.format("%Y-%m-%d").to_string(),
+            ReportPeriod::Week => {
+                let week = start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            ReportPeriod::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+///
+/// The last `count` periods up to and including the current one, the most
+/// recent first.
+///
+pub(crate) fn periods(
+    period: ReportPeriod,
+    count: usize,
+    today: NaiveDate,
+) -> Result<Vec<Period>, Box<dyn Error>> {
+    let mut start = period.start(today);
+    let mut periods = Vec::with_capacity(count);
+    for _ in 0..count {
+        let end = period.next(start).ok_or("Invalid date")?;
+        periods.push((
+            period.label(start),
+            local_midnight(start)?,
+            local_midnight(end)?,
+        ));
+        match period.previous(start) {
+            Some(previous) => start = previous,
+            None => break,
+        }
+    }
+    Ok(periods)
+}
+
+///
+/// Energy harvested and consumed, charge hours and voltage extremes of the
+/// last `count` periods, summarized from the hourly rollups, and the value of
+/// the harvest with a tariff.
+///
+pub(crate) fn report(
+    source: &dyn HistorySource,
+    period: ReportPeriod,
+    count: usize,
+    tariff: Option<&Tariff>,
+) -> Result<Vec<ReportRow>, String> {
+    let today = Local::now().date_naive();
+    periods(period, count, today)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(label, from, to)| row(source, label, from, to, tariff))
+        .collect()
+}
+
+///
+/// The row of a report for `from..to`.
+///
+pub(crate) fn row(
+    source: &dyn HistorySource,
+    period: String,
+    from: i64,
+    to: i64,
+    tariff: Option<&Tariff>,
+) -> Result<ReportRow, String> {
+    let value = match tariff {
+        Some(tariff) => Some(tariff.value(&source.hourly_harvest(from, to)?)),
+        None => None,
+    };
+    Ok(ReportRow {
+        period,
+        summary: source.summary(from, to)?,
+        value,
+        currency: tariff.map(|tariff| tariff.currency().to_string()),
+        loads: Vec::new(),
+    })
+}
+
+///
+/// The accuracy of the forecast over the rows of a daily report that have
+/// one, if any do. Pass complete days only, the current one is harvested
+/// short of its forecast until the sun sets.
+///
+pub(crate) fn forecast_accuracy(rows: &[ReportRow]) -> Option<ForecastAccuracy> {
+    let days: Vec<(f64, f64)> = rows
+        .iter()
+        .filter_map(|row| {
+            let forecast = row.summary.energy_forecast_wh?;
+            (forecast >= MIN_FORECAST_WH).then_some((forecast, row.summary.energy_harvested_wh))
+        })
+        .collect();
+    if days.is_empty() {
+        return None;
+    }
+    let deviation = days
+        .iter()
+        .map(|(forecast, harvested)| (harvested - forecast).abs() / forecast)
+        .sum::<f64>()
+        / days.len() as f64;
+    let forecast: f64 = days.iter().map(|(forecast, _)| forecast).sum();
+    let harvested: f64 = days.iter().map(|(_, harvested)| harvested).sum();
+    Some(ForecastAccuracy {
+        days: days.len(),
+        accuracy_percent: (100.0 - deviation * 100.0).max(0.0),
+        achieved_percent: 100.0 * harvested / forecast,
+    })
+}
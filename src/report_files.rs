@@ -0,0 +1,491 @@
+use crate::config::Config;
+use crate::loads::{attribute, NamedLoad};
+use crate::report::{row, ReportPeriod, ReportRow};
+use crate::stats::local_midnight;
+use crate::tariff::Tariff;
+use crate::ui::HistorySource;
+use chrono::{DateTime, Local, NaiveDate};
+use solar_core::database::Database;
+use std::{
+    error::Error,
+    fmt::Write,
+    fs, io,
+    path::Path,
+    slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// A value of a report row, if it has one.
+type RowValue = fn(&ReportRow) -> Option<f64>;
+
+//
+// Constants
+//
+/// Time between checks whether a period was completed.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 200.0;
+/// Room left of and below the plot for the axis labels.
+const CHART_MARGIN: f64 = 40.0;
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { padding: 0.3em 0.8em; border-bottom: 1px solid #ccc; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+svg { display: block; margin-bottom: 2em; }
+svg text { font-size: 10px; }
+</style>
+</head>
+<body>
+"#;
+
+///
+/// Write the files of every completed period of `[report_files]` from a
+/// background thread until `running` is cleared.
+///
+pub(crate) fn spawn_writer(
+    config: &Config,
+    running: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    let settings = config.report_files.clone();
+    let database_config = config.database.clone();
+    let tariff = Tariff::new(&config.tariff)?;
+    let loads = NamedLoad::from_config(&config.load);
+    fs::create_dir_all(&settings.directory).map_err(|e| {
+        io::Error::other(format!(
+            "report_files.directory {}: {}",
+            settings.directory.display(),
+            e
+        ))
+    })?;
+    info!(
+        "Writing {:?} reports to {}.",
+        settings.periods,
+        settings.directory.display()
+    );
+    thread::Builder::new()
+        .name("reports".into())
+        .spawn(move || {
+            let database = match Database::new(&database_config) {
+                Ok(database) => database,
+                Err(e) => return error!("Not writing reports: {}", e),
+            };
+            while running.load(Ordering::SeqCst) {
+                let today = Local::now().date_naive();
+                for period in &settings.periods {
+                    let written = write_completed(
+                        &database,
+                        *period,
+                        today,
+                        &settings.directory,
+                        tariff.as_ref(),
+                        &loads,
+                    );
+                    if let Err(e) = written {
+                        warn!("{} report: {}", period.name(), e);
+                    }
+                }
+                let start = Instant::now();
+                while running.load(Ordering::SeqCst) && start.elapsed() < CHECK_INTERVAL {
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        })
+}
+
+///
+/// Write the CSV file and HTML page of the period before the one containing
+/// `today`, unless they were written already.
+///
+fn write_completed(
+    source: &dyn HistorySource,
+    period: ReportPeriod,
+    today: NaiveDate,
+    directory: &Path,
+    tariff: Option<&Tariff>,
+    loads: &[NamedLoad],
+) -> Result<(), Box<dyn Error>> {
+    let start = period.previous(period.start(today)).ok_or("Invalid date")?;
+    let end = period.next(start).ok_or("Invalid date")?;
+    let label = period.label(start);
+    let name = format!("{}-{}", period.name().to_lowercase(), label);
+    let csv_path = directory.join(format!("{}.csv", name));
+    let html_path = directory.join(format!("{}.html", name));
+    if csv_path.exists() && html_path.exists() {
+        return Ok(());
+    }
+    let (from, to) = (local_midnight(start)?, local_midnight(end)?);
+    let mut total = row(source, label, from, to, tariff)?;
+    let mut rows = breakdown(source, period, start, end, tariff)?;
+    attribute(loads, slice::from_mut(&mut total));
+    attribute(loads, &mut rows);
+    fs::write(&csv_path, csv(&total, &rows)?)?;
+    fs::write(&html_path, html(period, &total, &rows)?)?;
+    info!("Wrote {}.csv and .html to {}.", name, directory.display());
+    Ok(())
+}
+
+///
+/// The rows of a period: one per hour of a day, else one per day.
+///
+fn breakdown(
+    source: &dyn HistorySource,
+    period: ReportPeriod,
+    start: NaiveDate,
+    end: NaiveDate,
+    tariff: Option<&Tariff>,
+) -> Result<Vec<ReportRow>, Box<dyn Error>> {
+    let mut rows = Vec::new();
+    match period {
+        ReportPeriod::Day => {
+            let (from, to) = (local_midnight(start)?, local_midnight(end)?);
+            for hour in (from..to).step_by(3600) {
+                let label = DateTime::from_timestamp(hour, 0)
+                    .ok_or("Invalid time")?
+                    .with_timezone(&Local)
+                    .format("%H:%M")
+                    .to_string();
+                rows.push(row(source, label, hour, hour + 3600, tariff)?);
+            }
+        }
+        ReportPeriod::Week | ReportPeriod::Month => {
+            for day in start.iter_days().take_while(|day| *day < end) {
+                let next = day.succ_opt().ok_or("Invalid date")?;
+                let label = ReportPeriod::Day.label(day);
+                let (from, to) = (local_midnight(day)?, local_midnight(next)?);
+                rows.push(row(source, label, from, to, tariff)?);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+///
+/// A CSV record per row and a last one of the whole period.
+///
+fn csv(total: &ReportRow, rows: &[ReportRow]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut header = vec![
+        "period",
+        "from",
+        "to",
+        "samples",
+        "energy_harvested_wh",
+        "energy_consumed_wh",
+        "efficiency_percent",
+        "charge_hours",
+        "battery_voltage_min",
+        "battery_voltage_max",
+        "pv_voltage_max",
+    ];
+    let load_columns: Vec<String> = total
+        .loads
+        .iter()
+        .flat_map(|load| {
+            let rated = load
+                .rated_hours
+                .map(|_| format!("{}_rated_hours", load.name));
+            [Some(format!("{}_wh", load.name)), rated]
+        })
+        .flatten()
+        .collect();
+    header.extend(load_columns.iter().map(String::as_str));
+    if total.value.is_some() {
+        header.push("value");
+        header.push("currency");
+    }
+    writer.write_record(&header)?;
+    let optional = |value: Option<f64>| value.map_or(String::new(), |v| v.to_string());
+    for row in rows.iter().chain([total]) {
+        let summary = &row.summary;
+        let mut record = vec![
+            row.period.clone(),
+            summary.from.to_string(),
+            summary.to.to_string(),
+            summary.samples.to_string(),
+            summary.energy_harvested_wh.to_string(),
+            summary.energy_consumed_wh.to_string(),
+            optional(summary.efficiency_percent),
+            summary.charge_hours.to_string(),
+            optional(summary.battery_voltage_min),
+            optional(summary.battery_voltage_max),
+            optional(summary.pv_voltage_max),
+        ];
+        for load in &row.loads {
+            record.push(load.energy_wh.to_string());
+            if load.rated_hours.is_some() {
+                record.push(optional(load.rated_hours));
+            }
+        }
+        if total.value.is_some() {
+            record.push(optional(row.value));
+            record.push(row.currency.clone().unwrap_or_default());
+        }
+        writer.write_record(&record)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+///
+/// A page with the totals of the period, charts of the energy and battery
+/// voltage per row, and a table of the rows.
+///
+fn html(
+    period: ReportPeriod,
+    total: &ReportRow,
+    rows: &[ReportRow],
+) -> Result<String, std::fmt::Error> {
+    let title = format!("{} solar report {}", period.name(), escape(&total.period));
+    let mut page = HTML_HEAD.replace("{title}", &title);
+    let summary = &total.summary;
+    writeln!(page, "<h1>{}</h1>", title)?;
+    writeln!(page, "<table>")?;
+    let mut totals = vec![
+        (
+            "Energy harvested",
+            format!("{:.1} Wh", summary.energy_harvested_wh),
+        ),
+        (
+            "Energy consumed",
+            format!("{:.1} Wh", summary.energy_consumed_wh),
+        ),
+        ("Efficiency", percent(summary.efficiency_percent)),
+        ("Charge hours", format!("{:.2} h", summary.charge_hours)),
+        ("Battery min", volts(summary.battery_voltage_min)),
+        ("Battery max", volts(summary.battery_voltage_max)),
+        ("PV max", volts(summary.pv_voltage_max)),
+    ];
+    if let Some(value) = total.value {
+        let currency = total.currency.as_deref().unwrap_or_default();
+        totals.push(("Value", format!("{:.2} {}", value, escape(currency))));
+    }
+    for (label, value) in totals {
+        writeln!(page, "<tr><th>{}</th><td>{}</td></tr>", label, value)?;
+    }
+    for load in &total.loads {
+        writeln!(
+            page,
+            "<tr><th>Consumed by {}</th><td>{}</td></tr>",
+            escape(&load.name),
+            match load.rated_hours {
+                Some(hours) => format!("{:.1} Wh, {:.1} h at rated power", load.energy_wh, hours),
+                None => format!("{:.1} Wh", load.energy_wh),
+            }
+        )?;
+    }
+    writeln!(page, "</table>")?;
+    writeln!(page, "<h2>Energy</h2>")?;
+    page.push_str(&energy_chart(rows)?);
+    writeln!(page, "<h2>Battery voltage</h2>")?;
+    page.push_str(&voltage_chart(rows)?);
+    writeln!(page, "<table>")?;
+    writeln!(
+        page,
+        "<tr><th></th><th>Harvested Wh</th><th>Consumed Wh</th><th>Efficiency</th>\
+         <th>Battery min</th><th>Battery max</th><th>PV max</th></tr>"
+    )?;
+    for row in rows {
+        let summary = &row.summary;
+        writeln!(
+            page,
+            "<tr><td>{}</td><td>{:.1}</td><td>{:.1}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&row.period),
+            summary.energy_harvested_wh,
+            summary.energy_consumed_wh,
+            percent(summary.efficiency_percent),
+            volts(summary.battery_voltage_min),
+            volts(summary.battery_voltage_max),
+            volts(summary.pv_voltage_max)
+        )?;
+    }
+    writeln!(page, "</table>\n</body>\n</html>")?;
+    Ok(page)
+}
+
+///
+/// Bars of the energy harvested (green) and consumed (orange) per row.
+///
+fn energy_chart(rows: &[ReportRow]) -> Result<String, std::fmt::Error> {
+    let max = rows
+        .iter()
+        .map(|row| {
+            row.summary
+                .energy_harvested_wh
+                .max(row.summary.energy_consumed_wh)
+        })
+        .fold(0.0, f64::max)
+        .max(1.0);
+    let mut svg = chart_start(0.0, max, "Wh")?;
+    let slot = (CHART_WIDTH - CHART_MARGIN) / rows.len().max(1) as f64;
+    for (i, row) in rows.iter().enumerate() {
+        let x = CHART_MARGIN + i as f64 * slot;
+        let bars = [
+            (row.summary.energy_harvested_wh, "#3a3", 0.1),
+            (row.summary.energy_consumed_wh, "#e80", 0.5),
+        ];
+        for (wh, color, offset) in bars {
+            let height = wh / max * (CHART_HEIGHT - CHART_MARGIN);
+            writeln!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#,
+                x + slot * offset,
+                CHART_HEIGHT - CHART_MARGIN - height,
+                slot * 0.4,
+                height,
+                color
+            )?;
+        }
+        x_label(&mut svg, i, rows.len(), x + slot / 2.0, &row.period)?;
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+///
+/// Lines of the lowest (red) and highest (blue) battery voltage per row.
+///
+fn voltage_chart(rows: &[ReportRow]) -> Result<String, std::fmt::Error> {
+    let values = rows.iter().flat_map(|row| {
+        [
+            row.summary.battery_voltage_min,
+            row.summary.battery_voltage_max,
+        ]
+    });
+    let (low, high) = values
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), v| {
+            (low.min(v), high.max(v))
+        });
+    let (low, high) = match low <= high {
+        true => ((low - 0.1).floor(), (high + 0.1).ceil()),
+        false => (0.0, 1.0),
+    };
+    let mut svg = chart_start(low, high, "V")?;
+    let slot = (CHART_WIDTH - CHART_MARGIN) / rows.len().max(1) as f64;
+    let lines: [(RowValue, &str); 2] = [
+        (|row| row.summary.battery_voltage_min, "#c33"),
+        (|row| row.summary.battery_voltage_max, "#36c"),
+    ];
+    for (value, color) in lines {
+        let points: Vec<(f64, f64)> = rows
+            .iter()
+            .enumerate()
+            .filter_map(|(i, row)| {
+                let v = value(row)?;
+                let x = CHART_MARGIN + (i as f64 + 0.5) * slot;
+                let y = (CHART_HEIGHT - CHART_MARGIN)
+                    - (v - low) / (high - low) * (CHART_HEIGHT - CHART_MARGIN);
+                Some((x, y))
+            })
+            .collect();
+        let line: Vec<String> = points
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+            .collect();
+        writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+            line.join(" "),
+            color
+        )?;
+        // Rows between gaps in the data show as dots.
+        for (x, y) in points {
+            writeln!(
+                svg,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="2.5" fill="{}"/>"#,
+                x, y, color
+            )?;
+        }
+    }
+    for (i, row) in rows.iter().enumerate() {
+        let x = CHART_MARGIN + (i as f64 + 0.5) * slot;
+        x_label(&mut svg, i, rows.len(), x, &row.period)?;
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+///
+/// Open a chart with its axes and the lowest and highest value labelled.
+///
+fn chart_start(low: f64, high: f64, unit: &str) -> Result<String, std::fmt::Error> {
+    let mut svg = String::new();
+    let bottom = CHART_HEIGHT - CHART_MARGIN;
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+        CHART_WIDTH, CHART_HEIGHT
+    )?;
+    writeln!(
+        svg,
+        r##"<path d="M{m},0 V{b} H{w}" fill="none" stroke="#888"/>"##,
+        m = CHART_MARGIN,
+        b = bottom,
+        w = CHART_WIDTH
+    )?;
+    writeln!(
+        svg,
+        r#"<text x="{}" y="10" text-anchor="end">{} {}</text>"#,
+        CHART_MARGIN - 4.0,
+        high,
+        unit
+    )?;
+    writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="end">{} {}</text>"#,
+        CHART_MARGIN - 4.0,
+        bottom,
+        low,
+        unit
+    )?;
+    Ok(svg)
+}
+
+///
+/// Label row `index` of `rows` below the x axis, only every fourth of many.
+///
+fn x_label(svg: &mut String, index: usize, rows: usize, x: f64, label: &str) -> std::fmt::Result {
+    let step = match rows > 12 {
+        true => 4,
+        false => 1,
+    };
+    if !index.is_multiple_of(step) {
+        return Ok(());
+    }
+    // Days are labelled without their year.
+    let label = label
+        .get(5..)
+        .filter(|_| label.len() == 10)
+        .unwrap_or(label);
+    writeln!(
+        svg,
+        r#"<text x="{:.1}" y="{}" text-anchor="middle">{}</text>"#,
+        x,
+        CHART_HEIGHT - CHART_MARGIN + 14.0,
+        escape(label)
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn volts(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.2} V", v))
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.0}%", v))
+}
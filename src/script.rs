@@ -0,0 +1,173 @@
+use solar_core::datapoint::DataPoint;
+use std::{io, path::Path};
+#[cfg(feature = "scripting")]
+use {
+    rhai::{Dynamic, Engine, Map, Scope, AST},
+    solar_core::database::DATA_FIELDS,
+    std::{
+        collections::VecDeque,
+        mem,
+        sync::{Arc, Mutex, PoisonError},
+    },
+};
+
+//
+// Constants
+//
+/// Operations a script may run per sample, so an endless loop cannot stall
+/// the datalogger.
+#[cfg(feature = "scripting")]
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+//
+// Structs
+//
+/// A rhai script evaluated with every datapoint, which may switch the load.
+#[cfg(feature = "scripting")]
+pub(crate) struct ScriptRules {
+    engine: Engine,
+    ast: AST,
+    /// The latest datapoints the rolling statistics are computed from.
+    window: VecDeque<DataPoint>,
+    window_size: usize,
+    /// The `state` map, kept between samples.
+    state: Map,
+    /// Load state requested with `load()` during the current run.
+    request: Arc<Mutex<Option<bool>>>,
+    /// The last load state requested by the script.
+    requested: Option<bool>,
+    last_error: Option<String>,
+}
+
+#[cfg(not(feature = "scripting"))]
+pub(crate) struct ScriptRules;
+
+//
+// implementations
+//
+#[cfg(feature = "scripting")]
+impl ScriptRules {
+    ///
+    /// Compile the script at `path`, failing on a syntax error. Rolling
+    /// statistics cover the last `window_size` datapoints.
+    ///
+    pub(crate) fn new(path: &Path, window_size: usize) -> io::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|s| info!("Script: {}", s));
+        engine.on_debug(|s, _, _| debug!("Script: {}", s));
+        let request = Arc::new(Mutex::new(None));
+        let requested = Arc::clone(&request);
+        engine.register_fn("load", move |on: bool| {
+            *requested.lock().unwrap_or_else(PoisonError::into_inner) = Some(on);
+        });
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|e| io::Error::other(format!("automation.script: {}", e)))?;
+        info!("Running script {} on every datapoint.", path.display());
+        Ok(Self {
+            engine,
+            ast,
+            window: VecDeque::with_capacity(window_size),
+            window_size: window_size.max(1),
+            state: Map::new(),
+            request,
+            requested: None,
+            last_error: None,
+        })
+    }
+
+    ///
+    /// Run the script on a datapoint and return the load state it asked for
+    /// with `load()`, if that differs from the one it asked for before and
+    /// from the current one. The load can thus be switched by hand until the
+    /// script changes its mind. Runtime errors are logged once until the
+    /// script succeeds again.
+    ///
+    pub(crate) fn check(&mut self, datapoint: &DataPoint) -> Option<bool> {
+        if self.window.len() == self.window_size {
+            self.window.pop_front();
+        }
+        self.window.push_back(*datapoint);
+        let load_on = datapoint.get_load_onoff() > 0.0;
+        let mut scope = Scope::new();
+        scope.push("data", data_map(datapoint));
+        scope.push("stats", self.stats());
+        scope.push("load_on", load_on);
+        scope.push("state", mem::take(&mut self.state));
+        *self.request.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        match self.engine.run_ast_with_scope(&mut scope, &self.ast) {
+            Ok(()) => self.last_error = None,
+            Err(e) => {
+                let e = e.to_string();
+                if self.last_error.as_ref() != Some(&e) {
+                    warn!("automation.script: {}", e);
+                }
+                self.last_error = Some(e);
+            }
+        }
+        self.state = scope.get_value("state").unwrap_or_default();
+        let request = self
+            .request
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()?;
+        let previous = self.requested.replace(request);
+        (previous != Some(request) && request != load_on).then_some(request)
+    }
+
+    ///
+    /// `min`, `max`, `avg` and `change` (last minus first value) of each field
+    /// over the window.
+    ///
+    fn stats(&self) -> Map {
+        let mut stats = Map::new();
+        for field in DATA_FIELDS {
+            let values: Vec<f64> = self
+                .window
+                .iter()
+                .filter_map(|datapoint| datapoint.get(field))
+                .collect();
+            let (Some(first), Some(last)) = (values.first(), values.last()) else {
+                continue;
+            };
+            let mut summary = Map::new();
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let avg = values.iter().sum::<f64>() / values.len() as f64;
+            summary.insert("min".into(), min.into());
+            summary.insert("max".into(), max.into());
+            summary.insert("avg".into(), avg.into());
+            summary.insert("change".into(), (last - first).into());
+            stats.insert(field.into(), summary.into());
+        }
+        stats
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+impl ScriptRules {
+    pub(crate) fn new(_path: &Path, _window_size: usize) -> io::Result<Self> {
+        Err(io::Error::other(
+            "automation.script requires building with the `scripting` feature.",
+        ))
+    }
+
+    pub(crate) fn check(&mut self, _datapoint: &DataPoint) -> Option<bool> {
+        None
+    }
+}
+
+///
+/// The fields of a datapoint and its `time` as a script object.
+///
+#[cfg(feature = "scripting")]
+fn data_map(datapoint: &DataPoint) -> Map {
+    let mut data = Map::new();
+    data.insert("time".into(), Dynamic::from(datapoint.get_time()));
+    for field in DATA_FIELDS {
+        let value = datapoint.get(field).unwrap_or(f64::NAN);
+        data.insert(field.into(), value.into());
+    }
+    data
+}
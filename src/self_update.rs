@@ -0,0 +1,160 @@
+use crate::cli::SelfUpdateArgs;
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+//
+// Constants
+//
+const RELEASES_URL: &str = "https://api.github.com/repos/javachaos/rust-solar/releases/latest";
+/// Upper bound for a downloaded binary, well above the size of a release build.
+const MAX_DOWNLOAD_SIZE: u64 = 200 * 1024 * 1024;
+/// Minisign public key the release binaries are signed with, embedded from
+/// RUST_SOLAR_UPDATE_KEY when building a release. Builds without it cannot
+/// update themselves.
+const UPDATE_PUBLIC_KEY: Option<&str> = option_env!("RUST_SOLAR_UPDATE_KEY");
+
+//
+// Structs
+//
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+///
+/// Check the latest GitHub release and, if it is newer than this build,
+/// download the binary for this platform, verify it against the published
+/// SHA-256 checksum and its minisign signature and replace the running
+/// executable with it.
+///
+pub(crate) fn run_self_update(args: &SelfUpdateArgs) -> Result<(), Box<dyn Error>> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = ureq::get(RELEASES_URL)
+        .header("Accept", "application/vnd.github+json")
+        .call()?
+        .body_mut()
+        .read_json()?;
+    let latest = release.tag_name.trim_start_matches('v');
+    if !is_newer(latest, current) && !args.force {
+        println!("rust-solar {} is up to date.", current);
+        return Ok(());
+    }
+    println!("Update available: {} -> {}", current, latest);
+    if args.check {
+        return Ok(());
+    }
+
+    let public_key = UPDATE_PUBLIC_KEY
+        .ok_or("This build has no update signing key, install the release manually.")?;
+    let public_key = PublicKey::from_base64(public_key)?;
+    let binary_name = format!("rust-solar-{}{}", env!("TARGET"), env::consts::EXE_SUFFIX);
+    let checksum_name = format!("{}.sha256", binary_name);
+    let signature_name = format!("{}.minisig", binary_name);
+    let find = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| format!("Release {} has no asset {}.", release.tag_name, name))
+    };
+    let binary = find(&binary_name)?;
+    let checksum = find(&checksum_name)?;
+    let signature = find(&signature_name)?;
+
+    let expected = ureq::get(&checksum.browser_download_url)
+        .call()?
+        .body_mut()
+        .read_to_string()?;
+    // sha256sum format: "<hex digest>  <file name>"
+    let expected = expected
+        .split_whitespace()
+        .next()
+        .ok_or("Empty checksum file.")?
+        .to_ascii_lowercase();
+    let signature = ureq::get(&signature.browser_download_url)
+        .call()?
+        .body_mut()
+        .read_to_string()?;
+    let signature = Signature::decode(&signature)?;
+
+    info!("Downloading {}", binary.browser_download_url);
+    let data = ureq::get(&binary.browser_download_url)
+        .call()?
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD_SIZE)
+        .read_to_vec()?;
+    let actual = hex::encode(Sha256::digest(&data));
+    if actual != expected {
+        return Err(format!(
+            "Checksum mismatch for {}: expected {}, got {}.",
+            binary_name, expected, actual
+        )
+        .into());
+    }
+    // The checksum only catches a corrupted download, the signature that the
+    // binary was released by the holder of the signing key.
+    public_key
+        .verify(&data, &signature, false)
+        .map_err(|e| format!("Signature of {} not valid: {}.", binary_name, e))?;
+
+    let exe = env::current_exe()?;
+    replace_executable(&exe, &data)?;
+    info!("Updated {} to {}", exe.display(), latest);
+    println!("Updated {} to {}.", exe.display(), latest);
+    Ok(())
+}
+
+///
+/// Compare dotted numeric versions, e.g. `0.10.0` is newer than `0.9.3`.
+///
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split(['.', '-', '+'])
+            .map_while(|p| p.parse().ok())
+            .collect()
+    };
+    parse(candidate) > parse(current)
+}
+
+///
+/// Write the new binary next to the executable and rename it into place, so
+/// a failed download never leaves a half written executable behind.
+///
+fn replace_executable(exe: &Path, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    let with_suffix = |suffix: &str| {
+        let mut name = exe.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    let new = with_suffix(".new");
+    fs::write(&new, data)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new, fs::Permissions::from_mode(0o755))?;
+    }
+    // A running executable cannot be overwritten on Windows but it can be renamed.
+    #[cfg(windows)]
+    {
+        let old = with_suffix(".old");
+        let _ = fs::remove_file(&old);
+        fs::rename(exe, &old)?;
+    }
+    fs::rename(&new, exe)?;
+    Ok(())
+}
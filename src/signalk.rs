@@ -0,0 +1,202 @@
+use crate::config::SignalKConfig;
+use chrono::{DateTime, SecondsFormat};
+use serde_json::{json, Value};
+use solar_core::datapoint::DataPoint;
+use std::{
+    io::{self, ErrorKind, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    client::IntoClientRequest, http::HeaderValue, stream::MaybeTlsStream, Message, WebSocket,
+};
+
+//
+// Constants
+//
+/// Delay before reconnecting to the server, deltas are dropped meanwhile.
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// How long to wait for messages from the server after each delta.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(10);
+/// Signal K temperatures are in kelvin.
+const ZERO_CELSIUS: f64 = 273.15;
+
+//
+// Structs
+//
+/// Sends datapoints as Signal K deltas to a server such as signalk-server,
+/// over its WebSocket stream (`ws://`, `wss://`) or TCP port (`tcp://`).
+pub(crate) struct SignalKWriter {
+    deltas: Sender<String>,
+    battery: String,
+    solar: String,
+}
+
+enum Connection {
+    WebSocket(Box<WebSocket<MaybeTlsStream<TcpStream>>>),
+    Tcp(TcpStream),
+}
+
+//
+// implementations
+//
+impl SignalKWriter {
+    pub(crate) fn new(config: &SignalKConfig) -> io::Result<Self> {
+        if !["ws://", "wss://", "tcp://"]
+            .iter()
+            .any(|scheme| config.url.starts_with(scheme))
+        {
+            return Err(io::Error::other(format!(
+                "signalk.url: {} must start with ws://, wss:// or tcp://.",
+                config.url
+            )));
+        }
+        info!("Sending datapoints to Signal K at {}", config.url);
+        let battery = format!("electrical.batteries.{}", config.battery_id);
+        let solar = format!("electrical.solar.{}", config.solar_id);
+        let (tx, rx) = mpsc::channel::<String>();
+        let config = config.clone();
+        thread::Builder::new()
+            .name("signalk".into())
+            .spawn(move || {
+                let mut connection: Option<Connection> = None;
+                let mut next_attempt = Instant::now();
+                for delta in rx {
+                    if connection.is_none() && Instant::now() >= next_attempt {
+                        match Connection::open(&config) {
+                            Ok(c) => {
+                                info!("Connected to Signal K server {}", config.url);
+                                connection = Some(c);
+                            }
+                            Err(e) => {
+                                warn!("Signal K {}: {}", config.url, e);
+                                next_attempt = Instant::now() + RECONNECT_DELAY;
+                            }
+                        }
+                    }
+                    if let Some(c) = &mut connection {
+                        if let Err(e) = c.send(delta) {
+                            warn!("Signal K {}: {}", config.url, e);
+                            connection = None;
+                            next_attempt = Instant::now() + RECONNECT_DELAY;
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            deltas: tx,
+            battery,
+            solar,
+        })
+    }
+
+    pub(crate) fn push(&self, datapoint: &DataPoint) {
+        let _ = self.deltas.send(self.delta(datapoint).to_string());
+    }
+
+    ///
+    /// The delta for a datapoint, in SI units as required by the specification.
+    ///
+    fn delta(&self, datapoint: &DataPoint) -> Value {
+        let charging_mode = if datapoint.get_battery_full() > 0.0 {
+            "float"
+        } else if datapoint.get_charging() > 0.0 {
+            "bulk"
+        } else {
+            "off"
+        };
+        let load = if datapoint.get_load_onoff() > 0.0 {
+            "on"
+        } else {
+            "off"
+        };
+        let values = [
+            (
+                format!("{}.voltage", self.battery),
+                json!(datapoint.get_battery_voltage()),
+            ),
+            (
+                format!("{}.current", self.battery),
+                json!(datapoint.get_charge_current() - datapoint.get_load_current()),
+            ),
+            (
+                format!("{}.temperature", self.battery),
+                json!(datapoint.get_battery_temp() + ZERO_CELSIUS),
+            ),
+            (
+                format!("{}.panelVoltage", self.solar),
+                json!(datapoint.get_pv_voltage()),
+            ),
+            (
+                format!("{}.current", self.solar),
+                json!(datapoint.get_charge_current()),
+            ),
+            (format!("{}.chargingMode", self.solar), json!(charging_mode)),
+            (format!("{}.load", self.solar), json!(load)),
+            (
+                format!("{}.loadCurrent", self.solar),
+                json!(datapoint.get_load_current()),
+            ),
+        ];
+        let timestamp = DateTime::from_timestamp(datapoint.get_time(), 0)
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+        json!({
+            "context": "vessels.self",
+            "updates": [{
+                "source": { "label": "rust-solar" },
+                "timestamp": timestamp,
+                "values": values
+                    .into_iter()
+                    .map(|(path, value)| json!({ "path": path, "value": value }))
+                    .collect::<Vec<_>>(),
+            }]
+        })
+    }
+}
+
+impl Connection {
+    fn open(config: &SignalKConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(address) = config.url.strip_prefix("tcp://") {
+            return Ok(Connection::Tcp(TcpStream::connect(address)?));
+        }
+        let mut request = config.url.as_str().into_client_request()?;
+        if !config.token.is_empty() {
+            let value = HeaderValue::from_str(&format!("Bearer {}", config.token))?;
+            request.headers_mut().insert("Authorization", value);
+        }
+        let (socket, _) = tungstenite::connect(request)?;
+        let stream = match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream,
+            MaybeTlsStream::Rustls(stream) => &stream.sock,
+            _ => return Err(io::Error::other("unsupported WebSocket stream type").into()),
+        };
+        stream.set_read_timeout(Some(DRAIN_TIMEOUT))?;
+        Ok(Connection::WebSocket(Box::new(socket)))
+    }
+
+    fn send(&mut self, delta: String) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Connection::Tcp(stream) => stream.write_all(format!("{}\n", delta).as_bytes())?,
+            Connection::WebSocket(socket) => {
+                socket.send(Message::text(delta))?;
+                // Read what the server sent meanwhile (hello, pings), so that
+                // pongs are answered and its buffer does not fill up.
+                loop {
+                    match socket.read() {
+                        Ok(_) => {}
+                        Err(tungstenite::Error::Io(e))
+                            if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                        {
+                            break
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use crate::config::SocketConfig;
+use crate::datalogger::{ControlCommand, ControlSender};
+use solar_core::datapoint::DataPoint;
+use solar_core::shutdown::Shutdown;
+use std::{io, thread::JoinHandle};
+use tokio::sync::watch;
+
+#[cfg(unix)]
+use {
+    crate::datalogger::{CommandSource, LoadCommand, MAX_POLL_INTERVAL_MS, MIN_POLL_INTERVAL_MS},
+    serde_json::json,
+    std::{
+        fs,
+        os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            net::UnixStream,
+        },
+        path::Path,
+        thread,
+    },
+    tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+};
+
+///
+/// Serve newline-delimited JSON datapoints on a unix socket from a background
+/// thread until `running` is cleared. Clients get the latest datapoint on
+/// connecting, then every new one, and may send one command per line:
+/// `load on`, `load off` or `poll_interval <ms>`. Each command is answered
+/// with `{"ok":true}` or `{"ok":false,"error":"..."}` between the datapoints.
+///
+#[cfg(unix)]
+pub(crate) fn spawn_socket_server(
+    config: &SocketConfig,
+    latest: watch::Receiver<Option<DataPoint>>,
+    control: ControlSender,
+    shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    let path = config.path.clone();
+    let context = |e: io::Error| io::Error::other(format!("{}: {}", path.display(), e));
+    remove_stale_socket(&path).map_err(context)?;
+    let listener = std::os::unix::net::UnixListener::bind(&path).map_err(context)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(config.mode)).map_err(context)?;
+    listener.set_nonblocking(true)?;
+    info!("Unix socket listening on {}", path.display());
+    let control = config.control.then_some(control);
+
+    thread::Builder::new().name("socket".into()).spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(r) => r,
+            Err(e) => return error!("{}", e),
+        };
+        runtime.block_on(async move {
+            let listener = match tokio::net::UnixListener::from_std(listener) {
+                Ok(l) => l,
+                Err(e) => return error!("{}", e),
+            };
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => {
+                            let latest = latest.clone();
+                            let control = control.clone();
+                            tokio::spawn(async move {
+                                if let Err(e) = serve_client(stream, latest, control).await {
+                                    debug!("Unix socket client: {}", e);
+                                }
+                            });
+                        }
+                        Err(e) => warn!("{}", e),
+                    },
+                    _ = shutdown.stopped() => break,
+                }
+            }
+        });
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("{}: {}", path.display(), e);
+        }
+        info!("Unix socket stopped.");
+    })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn spawn_socket_server(
+    _config: &SocketConfig,
+    _latest: watch::Receiver<Option<DataPoint>>,
+    _control: ControlSender,
+    _shutdown: Shutdown,
+) -> io::Result<JoinHandle<()>> {
+    Err(io::Error::other(
+        "The unix socket is not supported on this platform, set enabled = false in the [socket] section.",
+    ))
+}
+
+///
+/// Remove a socket left behind by a daemon which did not shut down cleanly,
+/// refusing to touch other files or a socket still in use.
+///
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "exists and is not a socket",
+        )),
+        Ok(_) if UnixStream::connect(path).is_ok() => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another instance is listening",
+        )),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+///
+/// Stream datapoints to a client and answer its commands until it disconnects.
+///
+#[cfg(unix)]
+async fn serve_client(
+    stream: tokio::net::UnixStream,
+    mut latest: watch::Receiver<Option<DataPoint>>,
+    control: Option<ControlSender>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    latest.mark_changed();
+    loop {
+        let message = tokio::select! {
+            changed = latest.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+                let Some(datapoint) = *latest.borrow_and_update() else {
+                    continue;
+                };
+                serde_json::to_string(&datapoint)?
+            }
+            line = lines.next_line() => match line? {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => match run_command(line.trim(), control.as_ref()) {
+                    Ok(()) => json!({ "ok": true }).to_string(),
+                    Err(e) => json!({ "ok": false, "error": e }).to_string(),
+                },
+                None => return Ok(()),
+            },
+        };
+        writer
+            .write_all(format!("{}\n", message).as_bytes())
+            .await?;
+    }
+}
+
+#[cfg(unix)]
+fn run_command(line: &str, control: Option<&ControlSender>) -> Result<(), String> {
+    let mut words = line.split_whitespace();
+    let command = match (words.next(), words.next(), words.next()) {
+        (Some("load"), Some(state @ ("on" | "off")), None) => LoadCommand {
+            on: state == "on",
+            source: CommandSource::Socket,
+        }
+        .into(),
+        (Some("poll_interval"), Some(ms), None) => ControlCommand::PollInterval {
+            interval_ms: ms
+                .parse()
+                .ok()
+                .filter(|ms| (MIN_POLL_INTERVAL_MS..=MAX_POLL_INTERVAL_MS).contains(ms))
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid poll interval: {}, expected {} to {} ms.",
+                        ms, MIN_POLL_INTERVAL_MS, MAX_POLL_INTERVAL_MS
+                    )
+                })?,
+            source: CommandSource::Socket,
+        },
+        _ => {
+            return Err(format!(
+                "Unknown command: {}, expected {}.",
+                line, "\"load on\", \"load off\" or \"poll_interval <ms>\""
+            ))
+        }
+    };
+    let Some(control) = control else {
+        return Err(String::from(
+            "Commands are disabled, set control = true in the [socket] section.",
+        ));
+    };
+    control
+        .send(command)
+        .map_err(|_| String::from("The datalogger has stopped."))?;
+    info!("Applied \"{}\" from the unix socket.", line);
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use crate::cli::{OutputFormat, StatsArgs};
+use crate::config::Config;
+use crate::loads::{attribute, NamedLoad};
+use crate::report::{report, ReportPeriod, ReportRow};
+use crate::tariff::Tariff;
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, TimeZone};
+use solar_core::database::Database;
+use std::error::Error;
+
+///
+/// Print summary statistics for today or the current month, or a report.
+///
+pub(crate) fn run_stats(config: &Config, args: &StatsArgs) -> Result<(), Box<dyn Error>> {
+    if let Some(period) = args.report {
+        let count = args.count.unwrap_or(period.default_count());
+        return run_report(config, period, count, args.format);
+    }
+    let label = if args.month { "This month" } else { "Today" };
+    let (from, to) = period_bounds(args.month)?;
+    let database = Database::new(&config.database)?;
+    let summary = database.summary(from, to)?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+        OutputFormat::Table => {
+            let volts = |v: Option<f64>| v.map_or(String::from("-"), |v| format!("{:.2} V", v));
+            println!(
+                "{} ({} - {})",
+                label,
+                format_time(summary.from),
+                format_time(summary.to)
+            );
+            println!("  Samples:            {}", summary.samples);
+            println!(
+                "  Energy harvested:   {:.1} Wh",
+                summary.energy_harvested_wh
+            );
+            if let Some(forecast) = summary.energy_forecast_wh {
+                println!(
+                    "  Energy forecast:    {:.1} Wh ({:.0}% achieved)",
+                    forecast,
+                    100.0 * summary.energy_harvested_wh / forecast.max(f64::EPSILON)
+                );
+            }
+            println!("  Energy consumed:    {:.1} Wh", summary.energy_consumed_wh);
+            for load in NamedLoad::from_config(&config.load) {
+                println!("    by {}", load.energy(&summary));
+            }
+            println!("  Charge hours:       {:.2} h", summary.charge_hours);
+            println!(
+                "  Efficiency:         {}",
+                percent(summary.efficiency_percent)
+            );
+            println!(
+                "  Battery min:        {}",
+                volts(summary.battery_voltage_min)
+            );
+            println!(
+                "  Battery max:        {}",
+                volts(summary.battery_voltage_max)
+            );
+            println!("  PV max:             {}", volts(summary.pv_voltage_max));
+        }
+    }
+    Ok(())
+}
+
+///
+/// Print a row per period with the energy, charge hours and voltage extremes.
+///
+fn run_report(
+    config: &Config,
+    period: ReportPeriod,
+    count: usize,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let database = Database::new(&config.database)?;
+    let tariff = Tariff::new(&config.tariff)?;
+    let loads = NamedLoad::from_config(&config.load);
+    let mut rows = report(&database, period, count, tariff.as_ref())?;
+    attribute(&loads, &mut rows);
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+        OutputFormat::Table => {
+            let volts = |v: Option<f64>| v.map_or(String::from("-"), |v| format!("{:.2}", v));
+            let value = |row: &ReportRow| match (&tariff, row.value) {
+                (Some(_), Some(value)) => format!(" {:>10.2}", value),
+                _ => String::new(),
+            };
+            let by_load = |row: &ReportRow| {
+                row.loads
+                    .iter()
+                    .map(|load| format!(" {:>12.1}", load.energy_wh))
+                    .collect::<String>()
+            };
+            println!("{} report", period.name());
+            println!(
+                "{:<10} {:>10} {:>10} {:>10} {:>8} {:>8} {:>8} {:>8}{}{}",
+                "Period",
+                "Harvest Wh",
+                "Consume Wh",
+                "Efficiency",
+                "Charge h",
+                "Batt min",
+                "Batt max",
+                "PV max",
+                loads
+                    .iter()
+                    .map(|load| format!(" {:>12.12}", format!("{} Wh", load.name())))
+                    .collect::<String>(),
+                tariff
+                    .as_ref()
+                    .map_or(String::new(), |t| format!(" {:>10}", t.currency()))
+            );
+            for row in &rows {
+                let summary = &row.summary;
+                println!(
+                    "{:<10} {:>10.1} {:>10.1} {:>10} {:>8.2} {:>8} {:>8} {:>8}{}{}",
+                    row.period,
+                    summary.energy_harvested_wh,
+                    summary.energy_consumed_wh,
+                    percent(summary.efficiency_percent),
+                    summary.charge_hours,
+                    volts(summary.battery_voltage_min),
+                    volts(summary.battery_voltage_max),
+                    volts(summary.pv_voltage_max),
+                    by_load(row),
+                    value(row)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Print the cumulative charge cycles of every device by depth of discharge.
+///
+pub(crate) fn run_cycles(config: &Config, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let database = Database::new(&config.database)?;
+    let counts = database.cycle_counts()?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&counts)?),
+        OutputFormat::Table => {
+            if counts.is_empty() {
+                println!("No charge cycles counted yet, see the [cycles] section of the config.");
+            }
+            for device in &counts {
+                println!(
+                    "{}: {} cycles, {:.1} Ah discharged ({:.1} equivalent full cycles)",
+                    device.device,
+                    device.cycles,
+                    device.discharged_ah,
+                    device.equivalent_full_cycles
+                );
+                for class in &device.by_depth {
+                    println!("  {:<9} {}", class.depth, class.cycles);
+                }
+                if let Some(capacity) = device.estimated_capacity_ah {
+                    println!(
+                        "  Usable capacity {:.1} Ah, measured in {} discharges",
+                        capacity, device.capacity_measurements
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Start and end (exclusive) of the current day or month as unix seconds.
+///
+pub(crate) fn period_bounds(month: bool) -> Result<(i64, i64), Box<dyn Error>> {
+    let today = Local::now().date_naive();
+    let (start, end) = if month {
+        let start = today.with_day(1).ok_or("Invalid date")?;
+        (start, start + Months::new(1))
+    } else {
+        (today, today.succ_opt().ok_or("Invalid date")?)
+    };
+    Ok((local_midnight(start)?, local_midnight(end)?))
+}
+
+pub(crate) fn local_midnight(date: NaiveDate) -> Result<i64, Box<dyn Error>> {
+    let midnight = date.and_hms_opt(0, 0, 0).ok_or("Invalid date")?;
+    Ok(Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .ok_or("Invalid local time")?
+        .timestamp())
+}
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.0}%", v))
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+//
+// Constants
+//
+/// Julian day of 2000-01-01 12:00 UTC.
+const J2000: f64 = 2451545.0;
+/// Julian day of the Unix epoch.
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+/// Altitude of the sun's centre at sunrise and sunset, accounting for
+/// refraction and the size of its disc.
+const HORIZON_DEG: f64 = -0.833;
+/// Tilt of the earth's axis.
+const OBLIQUITY_DEG: f64 = 23.4397;
+
+///
+/// Sunrise and sunset in UTC on the given date, with the sunrise equation,
+/// which is accurate to about a minute. `None` during polar day or night.
+///
+pub(crate) fn sunrise_sunset(
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let days = date.and_hms_opt(0, 0, 0)?.and_utc().timestamp() / 86400;
+    // Days since J2000 at local solar noon.
+    let n = (days as f64 + UNIX_EPOCH_JD + 0.5 - J2000).round() - longitude / 360.0;
+    let anomaly = (357.5291 + 0.98560028 * n).rem_euclid(360.0).to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let transit = J2000 + n + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic).sin();
+    let declination = (ecliptic.sin() * OBLIQUITY_DEG.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = (HORIZON_DEG.to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees() / 360.0;
+    let to_utc = |jd: f64| DateTime::from_timestamp(((jd - UNIX_EPOCH_JD) * 86400.0) as i64, 0);
+    Some((to_utc(transit - hour_angle)?, to_utc(transit + hour_angle)?))
+}
+
+///
+/// Elevation above the horizon and azimuth of the sun in degrees at a unix
+/// time, the azimuth from south and positive towards west.
+///
+pub(crate) fn position(time: i64, latitude: f64, longitude: f64) -> (f64, f64) {
+    // Days since J2000.
+    let n = time as f64 / 86400.0 + UNIX_EPOCH_JD - J2000;
+    let anomaly = (357.5291 + 0.98560028 * n).rem_euclid(360.0).to_radians();
+    let center =
+        1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic = (anomaly.to_degrees() + center + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let obliquity = OBLIQUITY_DEG.to_radians();
+    let declination = (ecliptic.sin() * obliquity.sin()).asin();
+    let right_ascension = (ecliptic.sin() * obliquity.cos()).atan2(ecliptic.cos());
+    let sidereal = (280.16 + 360.9856235 * n + longitude).to_radians();
+    let hour_angle = sidereal - right_ascension;
+    let latitude = latitude.to_radians();
+    let elevation = (latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos())
+    .asin();
+    let azimuth = hour_angle
+        .sin()
+        .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos());
+    (elevation.to_degrees(), azimuth.to_degrees())
+}
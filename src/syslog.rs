@@ -0,0 +1,194 @@
+use crate::config::LoggingConfig;
+use chrono::{Local, SecondsFormat};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use simplelog::{Config, SharedLogger};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+};
+
+//
+// Constants
+//
+const APP_NAME: &str = "rust-solar";
+/// Messages kept while the host name of the syslog daemon is looked up.
+const PENDING_LIMIT: usize = 256;
+
+//
+// Structs
+//
+/// Sends log records as RFC 5424 messages to a syslog daemon, over UDP
+/// (`host:port`) or a local unix datagram socket (a path such as `/dev/log`).
+pub(crate) struct SyslogLogger {
+    level: LevelFilter,
+    transport: Arc<Mutex<Transport>>,
+    facility: u8,
+    hostname: String,
+    pid: u32,
+}
+
+enum Transport {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    /// Looking up the host name, with the messages logged meanwhile.
+    Resolving(Vec<String>),
+    /// The host name could not be looked up, messages are dropped.
+    Failed,
+}
+
+//
+// implementations
+//
+impl SyslogLogger {
+    pub(crate) fn new(config: &LoggingConfig) -> io::Result<Self> {
+        let address = &config.syslog_address;
+        let context =
+            |e: io::Error| io::Error::other(format!("logging.syslog_address {}: {}", address, e));
+        let transport = if address.starts_with('/') {
+            #[cfg(unix)]
+            {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(address).map_err(context)?;
+                Transport::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            return Err(context(io::Error::other(
+                "unix sockets are not supported here",
+            )));
+        } else if address.parse::<SocketAddr>().is_ok() {
+            let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+            socket.connect(address).map_err(context)?;
+            Transport::Udp(socket)
+        } else {
+            Transport::Resolving(Vec::new())
+        };
+        let transport = Arc::new(Mutex::new(transport));
+        if matches!(*lock(&transport), Transport::Resolving(_)) {
+            // Looking up a host name can take seconds without a network, so
+            // it must not hold up the start.
+            let address = address.clone();
+            let resolving = Arc::clone(&transport);
+            thread::Builder::new()
+                .name("syslog".into())
+                .spawn(move || resolve(&address, &resolving))?;
+        }
+        Ok(Self {
+            level: config.syslog_level,
+            transport,
+            facility: config.syslog_facility as u8,
+            hostname: hostname(),
+            pid: std::process::id(),
+        })
+    }
+
+    ///
+    /// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG`
+    ///
+    fn format(&self, record: &Record) -> String {
+        let severity = match record.level() {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        format!(
+            "<{}>1 {} {} {} {} - - {}",
+            u16::from(self.facility) * 8 + severity,
+            Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            self.hostname,
+            APP_NAME,
+            self.pid,
+            record.args()
+        )
+    }
+}
+
+impl Log for SyslogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = self.format(record);
+        // A missing syslog daemon must not stop the logger, messages are lost meanwhile.
+        let _ = match &mut *lock(&self.transport) {
+            Transport::Udp(socket) => socket.send(message.as_bytes()),
+            #[cfg(unix)]
+            Transport::Unix(socket) => socket.send(message.as_bytes()),
+            Transport::Resolving(pending) if pending.len() < PENDING_LIMIT => {
+                pending.push(message);
+                Ok(0)
+            }
+            Transport::Resolving(_) | Transport::Failed => Ok(0),
+        };
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for SyslogLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+///
+/// Connect to the syslog daemon at `address`, a `host:port` to look up, and
+/// send the messages logged meanwhile.
+///
+fn resolve(address: &str, transport: &Mutex<Transport>) {
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).and_then(|socket| {
+        socket.connect(address)?;
+        Ok(socket)
+    });
+    let mut current = lock(transport);
+    let pending = match std::mem::replace(&mut *current, Transport::Failed) {
+        Transport::Resolving(pending) => pending,
+        _ => Vec::new(),
+    };
+    match socket {
+        Ok(socket) => {
+            for message in pending {
+                let _ = socket.send(message.as_bytes());
+            }
+            *current = Transport::Udp(socket);
+        }
+        Err(e) => {
+            drop(current);
+            error!("logging.syslog_address {}: {}", address, e);
+        }
+    }
+}
+
+fn lock(transport: &Mutex<Transport>) -> MutexGuard<'_, Transport> {
+    transport.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+///
+/// The host name for the HOSTNAME field, or the nil value `-` if unknown.
+///
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty() && !name.contains(' '))
+        .unwrap_or_else(|| String::from("-"))
+}
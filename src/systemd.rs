@@ -0,0 +1,68 @@
+use std::net::TcpListener;
+
+// All functions are no-ops when not started by systemd (no `$NOTIFY_SOCKET`
+// or `$LISTEN_FDS`) and on non-unix platforms.
+
+///
+/// Tell systemd the service finished starting up.
+///
+pub(crate) fn notify_ready() {
+    #[cfg(unix)]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        warn!("sd_notify READY failed: {}", e);
+    }
+}
+
+///
+/// Tell systemd the service is shutting down.
+///
+pub(crate) fn notify_stopping() {
+    #[cfg(unix)]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]) {
+        warn!("sd_notify STOPPING failed: {}", e);
+    }
+}
+
+///
+/// Ping the systemd watchdog, should be called for every datapoint so a hung
+/// serial link gets the service restarted.
+///
+pub(crate) fn notify_watchdog() {
+    #[cfg(unix)]
+    if watchdog_enabled() {
+        if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]) {
+            warn!("sd_notify WATCHDOG failed: {}", e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn watchdog_enabled() -> bool {
+    use std::sync::OnceLock;
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        let mut usec = 0;
+        let enabled = sd_notify::watchdog_enabled(false, &mut usec);
+        if enabled {
+            info!("systemd watchdog enabled, timeout {} us.", usec);
+        }
+        enabled
+    })
+}
+
+///
+/// The first socket passed by systemd socket activation, if any.
+///
+#[cfg(unix)]
+pub(crate) fn activated_listener() -> Option<TcpListener> {
+    use std::os::fd::FromRawFd;
+    let fd = sd_notify::listen_fds().ok()?.next()?;
+    info!("Using socket passed by systemd (fd {}).", fd);
+    // SAFETY: systemd hands over ownership of the passed file descriptors.
+    Some(unsafe { TcpListener::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn activated_listener() -> Option<TcpListener> {
+    None
+}
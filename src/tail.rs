@@ -0,0 +1,135 @@
+use crate::cli::{StreamFormat, TailArgs};
+use crate::config::Config;
+use crate::datalogger::spawn_datalogger;
+use solar_core::datapoint::DataPoint;
+use solar_core::shutdown::Shutdown;
+use std::{
+    error::Error,
+    io::{self, Write},
+    thread::sleep,
+    time::Duration,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+//
+// Structs
+//
+/// Writes datapoints to stdout one line at a time, flushing after each one.
+struct LinePrinter {
+    format: StreamFormat,
+    csv: csv::Writer<io::Stdout>,
+}
+
+//
+// implementations
+//
+impl LinePrinter {
+    fn new(format: StreamFormat) -> Self {
+        Self {
+            format,
+            csv: csv::Writer::from_writer(io::stdout()),
+        }
+    }
+
+    fn print(&mut self, dp: &DataPoint) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            StreamFormat::Csv => {
+                self.csv.serialize(dp)?;
+                self.csv.flush()?;
+            }
+            StreamFormat::Json => {
+                let mut out = io::stdout().lock();
+                serde_json::to_writer(&mut out, dp)?;
+                writeln!(out)?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// Stream every new datapoint to stdout, either straight from the device or
+/// from a running daemon, until interrupted or stdout is closed.
+///
+pub(crate) fn run_tail(config: &Config, args: &TailArgs) -> Result<(), Box<dyn Error>> {
+    let shutdown = Shutdown::new();
+    {
+        let shutdown = shutdown.clone();
+        ctrlc::set_handler(move || shutdown.stop())?;
+    }
+    let mut printer = LinePrinter::new(args.format);
+    let res = match &args.daemon {
+        Some(url) => tail_daemon(
+            url,
+            args.api_key.as_deref(),
+            config,
+            &shutdown,
+            &mut printer,
+        ),
+        None => tail_device(config, &shutdown, &mut printer),
+    };
+    shutdown.stop();
+    res
+}
+
+fn tail_device(
+    config: &Config,
+    shutdown: &Shutdown,
+    printer: &mut LinePrinter,
+) -> Result<(), Box<dyn Error>> {
+    let Some(port) = &config.serial.port else {
+        return Err("No serial port configured, use --port or set serial.port.".into());
+    };
+    let datalogger = spawn_datalogger(port, config, shutdown.clone())?;
+    printer.print(&datalogger.initial)?;
+    let mut datapoints = datalogger.datapoints;
+    loop {
+        match datapoints.blocking_recv() {
+            Ok(dp) => {
+                if let Err(e) = printer.print(&dp) {
+                    info!("Stopping tail: {}", e);
+                    break;
+                }
+            }
+            Err(RecvError::Lagged(missed)) => warn!("Missed {} datapoints.", missed),
+            Err(RecvError::Closed) => break,
+        }
+    }
+    datalogger.thread.stop();
+    Ok(())
+}
+
+fn tail_daemon(
+    url: &str,
+    api_key: Option<&str>,
+    config: &Config,
+    shutdown: &Shutdown,
+    printer: &mut LinePrinter,
+) -> Result<(), Box<dyn Error>> {
+    let latest_url = format!("{}/api/latest", url.trim_end_matches('/'));
+    let poll_interval = Duration::from_millis(config.serial.poll_interval_ms);
+    let mut last_time = None;
+    while shutdown.running() {
+        let mut request = ureq::get(&latest_url);
+        if let Some(key) = api_key {
+            request = request.header("Authorization", &format!("Bearer {}", key));
+        }
+        match request
+            .call()
+            .and_then(|mut r| r.body_mut().read_json::<DataPoint>())
+        {
+            Ok(dp) if last_time != Some(dp.get_time()) => {
+                last_time = Some(dp.get_time());
+                if let Err(e) = printer.print(&dp) {
+                    info!("Stopping tail: {}", e);
+                    break;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("{}", e),
+        }
+        sleep(poll_interval);
+    }
+    Ok(())
+}
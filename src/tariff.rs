@@ -0,0 +1,70 @@
+use crate::config::TariffConfig;
+use chrono::{DateTime, Local, NaiveTime};
+use solar_core::alerts::{parse_hours, within};
+use solar_core::rollup::HourlyEnergy;
+use std::io;
+
+//
+// Structs
+//
+/// Prices the harvested energy would have cost from the grid.
+#[derive(Debug, Clone)]
+pub(crate) struct Tariff {
+    currency: String,
+    price_per_kwh: f64,
+    /// Time-of-use bands as (start, end, price per kWh).
+    bands: Vec<(NaiveTime, NaiveTime, f64)>,
+}
+
+//
+// implementations
+//
+impl Tariff {
+    ///
+    /// The tariff of the `[tariff]` section, `None` unless enabled.
+    ///
+    pub(crate) fn new(config: &TariffConfig) -> io::Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let mut bands = Vec::with_capacity(config.bands.len());
+        for band in &config.bands {
+            let (start, end) = parse_hours(&band.hours)
+                .map_err(|e| io::Error::other(format!("tariff.bands.hours: {}.", e)))?;
+            bands.push((start, end, band.price_per_kwh));
+        }
+        Ok(Some(Self {
+            currency: config.currency.clone(),
+            price_per_kwh: config.price_per_kwh,
+            bands,
+        }))
+    }
+
+    pub(crate) fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    ///
+    /// The price of energy at a local time of day.
+    ///
+    fn price_at(&self, time: NaiveTime) -> f64 {
+        self.bands
+            .iter()
+            .find(|(start, end, _)| within(time, *start, *end))
+            .map_or(self.price_per_kwh, |(_, _, price)| *price)
+    }
+
+    ///
+    /// The value of the energy harvested in the hours, each at the price of
+    /// the local time it starts at.
+    ///
+    pub(crate) fn value(&self, hours: &[HourlyEnergy]) -> f64 {
+        hours
+            .iter()
+            .filter_map(|hour| {
+                let start = DateTime::from_timestamp(hour.hour, 0)?.with_timezone(&Local);
+                Some(hour.energy_harvested_wh / 1000.0 * self.price_at(start.time()))
+            })
+            .fold(0.0, |sum, value| sum + value)
+    }
+}
@@ -0,0 +1,148 @@
+use crate::config::ThingSpeakConfig;
+use solar_core::database::DATA_FIELDS;
+use solar_core::datapoint::DataPoint;
+use std::{
+    io,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// ThingSpeak channels have at most eight fields.
+const MAX_FIELDS: usize = 8;
+
+//
+// Structs
+//
+/// Sends the average of the datapoints read since the last update to a
+/// ThingSpeak channel, at most once per `min_interval_s`.
+pub(crate) struct ThingSpeakWriter {
+    datapoints: Sender<DataPoint>,
+}
+
+/// Running sums of the configured fields between two updates.
+struct Averages {
+    sums: Vec<f64>,
+    samples: u32,
+}
+
+//
+// implementations
+//
+impl ThingSpeakWriter {
+    pub(crate) fn new(config: &ThingSpeakConfig) -> io::Result<Self> {
+        if config.api_key.is_empty() {
+            return Err(io::Error::other("thingspeak.api_key is not set."));
+        }
+        if config.fields.len() > MAX_FIELDS {
+            return Err(io::Error::other(format!(
+                "thingspeak.fields: a channel has at most {} fields.",
+                MAX_FIELDS
+            )));
+        }
+        if let Some(field) = config
+            .fields
+            .iter()
+            .find(|f| !DATA_FIELDS.contains(&f.as_str()))
+        {
+            return Err(io::Error::other(format!(
+                "thingspeak.fields: unknown field {}, expected one of {}.",
+                field,
+                DATA_FIELDS.join(", ")
+            )));
+        }
+
+        info!(
+            "Sending {} to ThingSpeak every {} seconds",
+            config.fields.join(", "),
+            config.min_interval_s.max(1)
+        );
+        let (tx, rx) = mpsc::channel::<DataPoint>();
+        let config = config.clone();
+        let interval = Duration::from_secs(config.min_interval_s.max(1));
+        thread::Builder::new()
+            .name("thingspeak".into())
+            .spawn(move || {
+                let mut averages = Averages::new(config.fields.len());
+                let mut last_update = Instant::now();
+                loop {
+                    match rx.recv_timeout(Duration::from_secs(1)) {
+                        Ok(datapoint) => averages.add(&config.fields, &datapoint),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    if averages.samples > 0 && last_update.elapsed() >= interval {
+                        // Failed updates are not retried, the next one follows soon enough.
+                        if let Err(e) = update(&config, &averages.take()) {
+                            warn!("ThingSpeak update failed: {}", e);
+                        }
+                        last_update = Instant::now();
+                    }
+                }
+            })?;
+        Ok(Self { datapoints: tx })
+    }
+
+    pub(crate) fn push(&self, datapoint: &DataPoint) {
+        let _ = self.datapoints.send(*datapoint);
+    }
+}
+
+impl Averages {
+    fn new(fields: usize) -> Self {
+        Self {
+            sums: vec![0.0; fields],
+            samples: 0,
+        }
+    }
+
+    fn add(&mut self, fields: &[String], datapoint: &DataPoint) {
+        let Ok(serde_json::Value::Object(values)) = serde_json::to_value(datapoint) else {
+            return;
+        };
+        for (sum, field) in self.sums.iter_mut().zip(fields) {
+            *sum += values
+                .get(field)
+                .and_then(|v| v.as_f64())
+                .unwrap_or_default();
+        }
+        self.samples += 1;
+    }
+
+    ///
+    /// The averages since the last call, resetting the sums.
+    ///
+    fn take(&mut self) -> Vec<f64> {
+        let samples = f64::from(self.samples.max(1));
+        let averages = self.sums.iter().map(|sum| sum / samples).collect();
+        *self = Self::new(self.sums.len());
+        averages
+    }
+}
+
+///
+/// Write one channel update, `field1` being the first configured field.
+///
+fn update(config: &ThingSpeakConfig, values: &[f64]) -> Result<(), String> {
+    let fields: Vec<(String, String)> = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (format!("field{}", i + 1), format!("{:.3}", value)))
+        .collect();
+    let form = fields
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .chain([("api_key", config.api_key.as_str())]);
+    let entry = ureq::post(&config.url)
+        .send_form(form)
+        .and_then(|mut r| r.body_mut().read_to_string())
+        .map_err(|e| e.to_string())?;
+    // The id of the new entry, or 0 when the update was rejected.
+    if entry.trim() == "0" {
+        return Err("update rejected, check the write key and rate limit.".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use chrono::{DateTime, Local, NaiveTime};
+use solar_core::alerts::{parse_hours, within, Event, EventKind, Severity};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+//
+// Constants
+//
+/// Seconds over which `max_per_hour` is counted.
+const HOUR: i64 = 60 * 60;
+
+//
+// Structs
+//
+/// Holds back the notifications of one backend during its quiet hours, when
+/// an event repeats within `repeat_after_s` or beyond `max_per_hour`, and
+/// sends what was held back as one summary later, so a flapping alert does
+/// not send hundreds of messages.
+pub(crate) struct Throttle {
+    quiet_hours: Option<(NaiveTime, NaiveTime)>,
+    repeat_after_s: i64,
+    max_per_hour: usize,
+    /// Times of the notifications sent within the last hour.
+    sent: VecDeque<i64>,
+    /// Time each event name and kind was last sent.
+    last_sent: HashMap<(String, EventKind), i64>,
+    held: Option<Held>,
+}
+
+/// Notifications held back since the first of them.
+struct Held {
+    since: i64,
+    /// Number held back per event name.
+    counts: BTreeMap<String, u32>,
+    severity: Severity,
+    last_message: String,
+}
+
+//
+// implementations
+//
+impl Throttle {
+    ///
+    /// Parse the `<HH:MM>-<HH:MM>` quiet hours, which may span midnight.
+    /// Zero disables the repeat and rate limits.
+    ///
+    pub(crate) fn new(
+        quiet_hours: Option<&str>,
+        repeat_after_s: u64,
+        max_per_hour: u32,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            quiet_hours: quiet_hours.map(parse_hours).transpose()?,
+            repeat_after_s: repeat_after_s as i64,
+            max_per_hour: max_per_hour as usize,
+            sent: VecDeque::new(),
+            last_sent: HashMap::new(),
+            held: None,
+        })
+    }
+
+    ///
+    /// Whether to send the event now, holding it back otherwise. Critical
+    /// events are sent during the quiet hours, but still limited. With a
+    /// repeat time, an event held back stays held until the summary is sent,
+    /// even when it no longer repeats.
+    ///
+    pub(crate) fn admit(&mut self, event: &Event, now: i64) -> bool {
+        self.sent.retain(|&time| now - time < HOUR);
+        let key = (event.name.clone(), event.kind);
+        let quiet = event.severity < Severity::Critical && self.quiet(now);
+        // Held back until the summary once any of it is, as it flaps.
+        let repeated = self
+            .last_sent
+            .get(&key)
+            .is_some_and(|&time| now - time < self.repeat_after_s)
+            || self.repeat_after_s > 0
+                && self
+                    .held
+                    .as_ref()
+                    .is_some_and(|held| held.counts.contains_key(&event.name));
+        if quiet || repeated || self.limited() {
+            let held = self.held.get_or_insert_with(|| Held {
+                since: now,
+                counts: BTreeMap::new(),
+                severity: Severity::Info,
+                last_message: String::new(),
+            });
+            *held.counts.entry(event.name.clone()).or_default() += 1;
+            held.severity = held.severity.max(event.severity);
+            held.last_message = event.message.clone();
+            return false;
+        }
+        self.sent.push_back(now);
+        self.last_sent.insert(key, now);
+        true
+    }
+
+    ///
+    /// The summary of the held back notifications, once the quiet hours are
+    /// over, the repeat time has passed since the first of them and the rate
+    /// allows another one.
+    ///
+    pub(crate) fn summary(&mut self, now: i64) -> Option<Event> {
+        self.sent.retain(|&time| now - time < HOUR);
+        let held = self.held.as_ref()?;
+        if self.quiet(now) || now - held.since < self.repeat_after_s || self.limited() {
+            return None;
+        }
+        let held = self.held.take()?;
+        self.sent.push_back(now);
+        // The summary counts as sending the held events.
+        for ((name, _), time) in &mut self.last_sent {
+            if held.counts.contains_key(name) {
+                *time = now;
+            }
+        }
+        let total: u32 = held.counts.values().sum();
+        let since = DateTime::from_timestamp(held.since, 0)
+            .map(|t| t.with_timezone(&Local).format("%H:%M").to_string())
+            .unwrap_or_default();
+        let counts: Vec<String> = held
+            .counts
+            .iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect();
+        Some(Event {
+            kind: EventKind::Summary,
+            name: "notification_summary".to_string(),
+            severity: held.severity,
+            message: format!(
+                "{} notifications held back since {} ({}), the last: {}",
+                total,
+                since,
+                counts.join(", "),
+                held.last_message
+            ),
+            value: total as f64,
+            timestamp: now,
+        })
+    }
+
+    fn quiet(&self, now: i64) -> bool {
+        let Some((start, end)) = self.quiet_hours else {
+            return false;
+        };
+        DateTime::from_timestamp(now, 0)
+            .is_some_and(|t| within(t.with_timezone(&Local).time(), start, end))
+    }
+
+    fn limited(&self) -> bool {
+        self.max_per_hour > 0 && self.sent.len() >= self.max_per_hour
+    }
+}
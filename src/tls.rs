@@ -0,0 +1,172 @@
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::{io, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+//
+// Constants
+//
+/// Clients which do not finish the handshake in time are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// No further connections are accepted while this many handshakes run.
+const MAX_HANDSHAKES: usize = 256;
+
+//
+// Structs
+//
+/// A TCP listener which completes the TLS handshake before handing
+/// connections to axum. Each handshake runs in its own task, so a client
+/// which never sends its hello does not hold up the others.
+pub(crate) struct TlsListener {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    handshakes: JoinSet<Option<(TlsStream<TcpStream>, SocketAddr)>>,
+}
+
+//
+// implementations
+//
+impl TlsListener {
+    pub(crate) fn new(listener: TcpListener, config: Arc<ServerConfig>) -> Self {
+        Self {
+            listener,
+            acceptor: TlsAcceptor::from(config),
+            handshakes: JoinSet::new(),
+        }
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept(), if self.handshakes.len() < MAX_HANDSHAKES => {
+                    match accepted {
+                        Ok((stream, addr)) => {
+                            let acceptor = self.acceptor.clone();
+                            self.handshakes.spawn(handshake(acceptor, stream, addr));
+                        }
+                        Err(e) => {
+                            warn!("{}", e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    }
+                }
+                Some(finished) = self.handshakes.join_next() => {
+                    if let Ok(Some(connection)) = finished {
+                        return connection;
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.listener.local_addr()
+    }
+}
+
+///
+/// Complete the TLS handshake with a client, `None` if it fails or does not
+/// finish in time.
+///
+async fn handshake(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> Option<(TlsStream<TcpStream>, SocketAddr)> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+        Ok(Ok(stream)) => Some((stream, addr)),
+        Ok(Err(e)) => {
+            debug!("TLS handshake with {} failed: {}", addr, e);
+            None
+        }
+        Err(_) => {
+            debug!("TLS handshake with {} timed out.", addr);
+            None
+        }
+    }
+}
+
+///
+/// Load a PEM certificate chain and private key into a server config.
+///
+pub(crate) fn server_config(cert: &Path, key: &Path) -> io::Result<Arc<ServerConfig>> {
+    let context = |path: &Path, e: &dyn std::fmt::Display| {
+        io::Error::other(format!("{}: {}", path.display(), e))
+    };
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| context(cert, &e))?;
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|e| context(key, &e))?;
+    let config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| context(cert, &e))?;
+    Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::serve::Listener;
+    use rustls::{pki_types::ServerName, ClientConfig, RootCertStore};
+    use std::fs;
+    use tokio_rustls::TlsConnector;
+
+    ///
+    /// A server config with a self-signed certificate for `localhost`, loaded
+    /// like `api.tls_cert` and `api.tls_key`, and a client config trusting it.
+    ///
+    fn configs() -> (Arc<ServerConfig>, Arc<ClientConfig>) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .expect("generate a certificate");
+        let dir = std::env::temp_dir().join(format!("rust-solar-tls-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create the certificate directory");
+        let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+        fs::write(&cert, certified.cert.pem()).expect("write the certificate");
+        fs::write(&key, certified.signing_key.serialize_pem()).expect("write the key");
+        let server = server_config(&cert, &key).expect("load the certificate");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(certified.cert.der().clone())
+            .expect("trust the certificate");
+        let client =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .expect("the default protocol versions")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        (server, Arc::new(client))
+    }
+
+    #[tokio::test]
+    async fn stalled_handshake_does_not_block_others() {
+        let (server, client) = configs();
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("the bound address");
+        let mut listener = TlsListener::new(listener, server);
+        // Connects first, but never sends its hello.
+        let _stalled = TcpStream::connect(addr).await.expect("connect");
+        let connect = async {
+            let stream = TcpStream::connect(addr).await?;
+            let name = ServerName::try_from("localhost").map_err(io::Error::other)?;
+            TlsConnector::from(client).connect(name, stream).await
+        };
+        let both = async { tokio::join!(listener.accept(), connect) };
+        // Well before the stalled handshake times out.
+        let ((_, peer), connected) = tokio::time::timeout(HANDSHAKE_TIMEOUT / 2, both)
+            .await
+            .expect("the second client is accepted while the first stalls");
+        let connected = connected.expect("complete the handshake");
+        assert_eq!(Some(peer), connected.get_ref().0.local_addr().ok());
+    }
+}
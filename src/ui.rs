@@ -0,0 +1,1471 @@
+use crate::array::ArrayModel;
+use crate::charge_targets::ChargeTargets;
+use crate::config::AlertThresholds;
+use crate::datalogger::LinkHealth;
+use crate::load_toggle_switch::LoadToggleSwitch;
+use crate::loads::{attribute, NamedLoad};
+use crate::report::{forecast_accuracy, report, ForecastAccuracy, ReportPeriod, ReportRow};
+use crate::stats::period_bounds;
+use crate::tariff::Tariff;
+use chrono::{DateTime, Local};
+use crossterm::event::KeyCode;
+use solar_core::alerts::{Event, EventKind, Severity};
+use solar_core::config::DatabaseConfig;
+use solar_core::cycles::CycleCounts;
+use solar_core::database::{AlertRecord, Database};
+use solar_core::datapoint::DataPoint;
+use solar_core::health::{self, HealthDay, TREND_DAYS};
+use solar_core::recent::RecentData;
+use solar_core::records::{self, Record, RecordKind, ALL_TIME, RECORD_KINDS};
+use solar_core::rollup::{HourlyEnergy, Summary};
+use solar_core::timings::{Stage, TIMINGS};
+use solar_core::weather::WeatherHour;
+use std::{
+    cell::OnceCell,
+    time::{Duration, Instant},
+};
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    symbols,
+    text::{Span, Spans},
+    widgets::{
+        Axis, Block, BorderType, Borders, Cell, Chart, Dataset, GraphType, Paragraph, Row, Table,
+        TableState, Tabs,
+    },
+    Frame,
+};
+
+//
+// Constants
+//
+/// Selectable history ranges as (label, range) in seconds.
+const HISTORY_RANGES: [(&str, i64); 3] = [
+    ("24h", 24 * 3600),
+    ("7d", 7 * 24 * 3600),
+    ("30d", 30 * 24 * 3600),
+];
+/// Most datapoints loaded for the history charts, whatever the range.
+const CHART_POINTS: usize = 1500;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Most alerts listed in the Alarms tab.
+const ALARMS_LIMIT: usize = 500;
+/// Percentage points the efficiency of a report period is highlighted at
+/// when below that of the period before.
+const EFFICIENCY_DROP: f64 = 10.0;
+/// Days on the Forecast tab, including today.
+const FORECAST_DAYS: usize = 30;
+/// The fixed alert limits editable on the Alarms tab as (label, unit, step).
+const LIMITS: [(&str, &str, f64); 4] = [
+    ("Battery voltage low", "V", 0.1),
+    ("Battery voltage high", "V", 0.1),
+    ("Battery temperature high", "C", 1.0),
+    ("Load current high", "A", 0.5),
+];
+
+/// A chart line as (name, color, points).
+type Series<'a> = (&'a str, Color, &'a [(f64, f64)]);
+/// A statistics row as (label, formatter).
+type StatRow = (&'static str, fn(&Summary) -> String);
+
+//
+// Traits
+//
+/// Where the History, Stats, Reports, Forecast, Battery and Alarms tabs read
+/// their data from: the local database, or a remote daemon in `connect` mode.
+pub(crate) trait HistorySource {
+    /// At most `points` datapoints of the range, downsampled with LTTB.
+    fn history(&self, from: i64, to: i64, points: usize) -> Result<Vec<DataPoint>, String>;
+    fn summary(&self, from: i64, to: i64) -> Result<Summary, String>;
+    /// The energy harvested per hour of the range.
+    fn hourly_harvest(&self, from: i64, to: i64) -> Result<Vec<HourlyEnergy>, String>;
+    /// Alerts active at some time in the range, the most recent first.
+    fn alerts(&self, from: i64, to: i64, limit: usize) -> Result<Vec<AlertRecord>, String>;
+    fn acknowledge_alert(&self, id: i64) -> Result<(), String>;
+    /// Battery health measurements of the days starting in the range.
+    fn battery_health(&self, from: i64, to: i64) -> Result<Vec<HealthDay>, String>;
+    /// The charge cycles counted per device.
+    fn cycle_counts(&self) -> Result<Vec<CycleCounts>, String>;
+    /// The weather observed in the hours of the range.
+    fn weather(&self, from: i64, to: i64) -> Result<Vec<WeatherHour>, String>;
+    /// The all-time and monthly records.
+    fn records(&self) -> Result<Vec<Record>, String>;
+}
+
+//
+// Structs
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tab {
+    Live,
+    History,
+    Stats,
+    Reports,
+    Forecast,
+    Battery,
+    Alarms,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    from: i64,
+    to: i64,
+    battery_voltage: Vec<(f64, f64)>,
+    pv_voltage: Vec<(f64, f64)>,
+    charge_current: Vec<(f64, f64)>,
+    load_current: Vec<(f64, f64)>,
+    /// Charge power in percent of its peak in the range, and that peak in W.
+    charge_power: Vec<(f64, f64)>,
+    peak_power: f64,
+    /// Cloud cover at the start of each hour, empty without weather data.
+    cloud_cover: Vec<(f64, f64)>,
+    /// Charge power and the clear-sky output of the array in W, empty
+    /// without an array model.
+    pv_power: Vec<(f64, f64)>,
+    clear_sky: Vec<(f64, f64)>,
+}
+
+/// State of the tabbed views which is kept between frames.
+pub(crate) struct ViewState {
+    tabs: Vec<Tab>,
+    selected: usize,
+    history_range: usize,
+    history: History,
+    today: Option<Summary>,
+    month: Option<Summary>,
+    /// The selected period of `ReportPeriod::ALL` and its report.
+    report_period: usize,
+    report: Vec<ReportRow>,
+    /// Daily forecast and harvest of the Forecast tab, today first.
+    forecast: Vec<ReportRow>,
+    /// Battery health measurements of the last weeks, the oldest first.
+    battery_health: Vec<HealthDay>,
+    cycles: Vec<CycleCounts>,
+    records: Vec<Record>,
+    refreshed: Option<Instant>,
+    /// Alerts raised and not cleared yet, the newest first.
+    alerts: Vec<Event>,
+    /// Stored alerts of the history range and the selected one.
+    alarms: Vec<AlertRecord>,
+    selected_alarm: usize,
+    /// Alert to acknowledge with the next refresh.
+    acknowledge: Option<i64>,
+    /// Values the harvest of the reports, if enabled.
+    tariff: Option<Tariff>,
+    /// Loads the energy consumed in the reports is attributed to.
+    loads: Vec<NamedLoad>,
+    /// Charge voltages the live battery voltage is compared with, if enabled.
+    charge_targets: Option<ChargeTargets>,
+    /// Models the clear-sky output charted over the charge power, if enabled.
+    array: Option<ArrayModel>,
+    /// Alert limits the values are highlighted by.
+    thresholds: AlertThresholds,
+    limits_editable: bool,
+    /// While editing the limits, the selected one and the edited values.
+    editing: Option<(usize, AlertThresholds)>,
+    /// Set when edited limits were saved, until taken.
+    limits_changed: bool,
+}
+
+/// The local database, opened when the History, Stats or Alarms tab first
+/// needs it, so the live view does not wait for it.
+pub(crate) struct LazyDatabase<'a> {
+    config: &'a DatabaseConfig,
+    database: OnceCell<Database>,
+}
+
+//
+// implementations
+//
+impl HistorySource for Database {
+    fn history(&self, from: i64, to: i64, points: usize) -> Result<Vec<DataPoint>, String> {
+        Database::downsampled(self, from, to, points).map_err(|e| e.to_string())
+    }
+
+    fn summary(&self, from: i64, to: i64) -> Result<Summary, String> {
+        Database::summary(self, from, to).map_err(|e| e.to_string())
+    }
+
+    fn hourly_harvest(&self, from: i64, to: i64) -> Result<Vec<HourlyEnergy>, String> {
+        Database::hourly_harvest(self, from, to).map_err(|e| e.to_string())
+    }
+
+    fn alerts(&self, from: i64, to: i64, limit: usize) -> Result<Vec<AlertRecord>, String> {
+        Database::alerts(self, from, to, limit).map_err(|e| e.to_string())
+    }
+
+    fn acknowledge_alert(&self, id: i64) -> Result<(), String> {
+        let time = Local::now().timestamp();
+        match Database::acknowledge_alert(self, id, time) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn battery_health(&self, from: i64, to: i64) -> Result<Vec<HealthDay>, String> {
+        Database::battery_health(self, from, to).map_err(|e| e.to_string())
+    }
+
+    fn cycle_counts(&self) -> Result<Vec<CycleCounts>, String> {
+        Database::cycle_counts(self).map_err(|e| e.to_string())
+    }
+
+    fn weather(&self, from: i64, to: i64) -> Result<Vec<WeatherHour>, String> {
+        Database::weather(self, from, to).map_err(|e| e.to_string())
+    }
+
+    fn records(&self) -> Result<Vec<Record>, String> {
+        Database::records(self).map_err(|e| e.to_string())
+    }
+}
+
+impl<'a> LazyDatabase<'a> {
+    pub(crate) fn new(config: &'a DatabaseConfig) -> Self {
+        Self {
+            config,
+            database: OnceCell::new(),
+        }
+    }
+
+    ///
+    /// The database, opening it unless it is open already. A failed open is
+    /// tried again with the next refresh.
+    ///
+    fn get(&self) -> Result<&Database, String> {
+        if let Some(database) = self.database.get() {
+            return Ok(database);
+        }
+        let database = Database::new(self.config).map_err(|e| e.to_string())?;
+        Ok(self.database.get_or_init(|| database))
+    }
+}
+
+impl HistorySource for LazyDatabase<'_> {
+    fn history(&self, from: i64, to: i64, points: usize) -> Result<Vec<DataPoint>, String> {
+        HistorySource::history(self.get()?, from, to, points)
+    }
+
+    fn summary(&self, from: i64, to: i64) -> Result<Summary, String> {
+        HistorySource::summary(self.get()?, from, to)
+    }
+
+    fn hourly_harvest(&self, from: i64, to: i64) -> Result<Vec<HourlyEnergy>, String> {
+        HistorySource::hourly_harvest(self.get()?, from, to)
+    }
+
+    fn alerts(&self, from: i64, to: i64, limit: usize) -> Result<Vec<AlertRecord>, String> {
+        HistorySource::alerts(self.get()?, from, to, limit)
+    }
+
+    fn acknowledge_alert(&self, id: i64) -> Result<(), String> {
+        HistorySource::acknowledge_alert(self.get()?, id)
+    }
+
+    fn battery_health(&self, from: i64, to: i64) -> Result<Vec<HealthDay>, String> {
+        HistorySource::battery_health(self.get()?, from, to)
+    }
+
+    fn cycle_counts(&self) -> Result<Vec<CycleCounts>, String> {
+        HistorySource::cycle_counts(self.get()?)
+    }
+
+    fn weather(&self, from: i64, to: i64) -> Result<Vec<WeatherHour>, String> {
+        HistorySource::weather(self.get()?, from, to)
+    }
+
+    fn records(&self) -> Result<Vec<Record>, String> {
+        HistorySource::records(self.get()?)
+    }
+}
+
+impl Tab {
+    fn title(self) -> &'static str {
+        match self {
+            Tab::Live => "Live",
+            Tab::History => "History",
+            Tab::Stats => "Stats",
+            Tab::Reports => "Reports",
+            Tab::Forecast => "Forecast",
+            Tab::Battery => "Battery",
+            Tab::Alarms => "Alarms",
+        }
+    }
+}
+
+impl ViewState {
+    ///
+    /// The alert limits can be edited on the Alarms tab if `limits_editable`.
+    ///
+    pub(crate) fn new(
+        tabs: Vec<Tab>,
+        tariff: Option<Tariff>,
+        loads: Vec<NamedLoad>,
+        charge_targets: Option<ChargeTargets>,
+        array: Option<ArrayModel>,
+        thresholds: AlertThresholds,
+        limits_editable: bool,
+    ) -> Self {
+        Self {
+            tabs,
+            selected: 0,
+            history_range: 0,
+            history: History::default(),
+            today: None,
+            month: None,
+            report_period: 0,
+            report: Vec::new(),
+            forecast: Vec::new(),
+            battery_health: Vec::new(),
+            cycles: Vec::new(),
+            records: Vec::new(),
+            refreshed: None,
+            alerts: Vec::new(),
+            alarms: Vec::new(),
+            selected_alarm: 0,
+            acknowledge: None,
+            tariff,
+            loads,
+            charge_targets,
+            array,
+            thresholds,
+            limits_editable,
+            editing: None,
+            limits_changed: false,
+        }
+    }
+
+    pub(crate) fn current(&self) -> Tab {
+        self.tabs[self.selected]
+    }
+
+    ///
+    /// Handle tab navigation keys: Tab/Right and BackTab/Left cycle tabs,
+    /// 1-9 select a tab directly and `[`/`]` change the history range, or on
+    /// the Reports tab between daily, weekly and monthly reports. On the
+    /// Alarms tab Up/Down select an alert and `a` acknowledges it, and `e`
+    /// edits the alert limits.
+    ///
+    pub(crate) fn handle_key(&mut self, key: KeyCode) {
+        if self.editing.is_some() && self.current() == Tab::Alarms {
+            return self.edit_limits(key);
+        }
+        let count = self.tabs.len();
+        match key {
+            KeyCode::Tab | KeyCode::Right => self.selected = (self.selected + 1) % count,
+            KeyCode::BackTab | KeyCode::Left => self.selected = (self.selected + count - 1) % count,
+            KeyCode::Char(c @ '1'..='9') => {
+                let index = c as usize - '1' as usize;
+                if index < count {
+                    self.selected = index;
+                }
+            }
+            KeyCode::Char('[') if self.current() == Tab::Reports => {
+                let count = ReportPeriod::ALL.len();
+                self.report_period = (self.report_period + count - 1) % count;
+                self.refreshed = None;
+            }
+            KeyCode::Char(']') if self.current() == Tab::Reports => {
+                self.report_period = (self.report_period + 1) % ReportPeriod::ALL.len();
+                self.refreshed = None;
+            }
+            KeyCode::Char('[') => {
+                self.history_range =
+                    (self.history_range + HISTORY_RANGES.len() - 1) % HISTORY_RANGES.len();
+                self.refreshed = None;
+            }
+            KeyCode::Char(']') => {
+                self.history_range = (self.history_range + 1) % HISTORY_RANGES.len();
+                self.refreshed = None;
+            }
+            KeyCode::Up if self.current() == Tab::Alarms => {
+                self.selected_alarm = self.selected_alarm.saturating_sub(1);
+            }
+            KeyCode::Down
+                if self.current() == Tab::Alarms && self.selected_alarm + 1 < self.alarms.len() =>
+            {
+                self.selected_alarm += 1;
+            }
+            KeyCode::Char('a') if self.current() == Tab::Alarms => {
+                self.acknowledge = self.alarms.get(self.selected_alarm).map(|alarm| alarm.id);
+                self.refreshed = None;
+            }
+            KeyCode::Char('e') if self.current() == Tab::Alarms && self.limits_editable => {
+                self.editing = Some((0, self.thresholds.clone()));
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// Keys while editing the alert limits: Up/Down select a limit, `+`/`-`
+    /// change it, Enter saves and Esc discards the changes.
+    ///
+    fn edit_limits(&mut self, key: KeyCode) {
+        let Some((selected, edited)) = &mut self.editing else {
+            return;
+        };
+        let (_, _, step) = LIMITS[*selected];
+        match key {
+            KeyCode::Up => *selected = selected.saturating_sub(1),
+            KeyCode::Down => *selected = (*selected + 1).min(LIMITS.len() - 1),
+            KeyCode::Char('+') | KeyCode::Char('=') => adjust(limit_mut(edited, *selected), step),
+            KeyCode::Char('-') => adjust(limit_mut(edited, *selected), -step),
+            KeyCode::Enter => {
+                if let Some((_, edited)) = self.editing.take() {
+                    self.thresholds = edited;
+                    self.limits_changed = true;
+                }
+            }
+            KeyCode::Esc => self.editing = None,
+            _ => {}
+        }
+    }
+
+    ///
+    /// The alert limits, if they were edited since the last call.
+    ///
+    pub(crate) fn take_changed_limits(&mut self) -> Option<&AlertThresholds> {
+        match std::mem::take(&mut self.limits_changed) {
+            true => Some(&self.thresholds),
+            false => None,
+        }
+    }
+
+    ///
+    /// Track which alerts are active from the events of the datalogger, and
+    /// reload the Alarms tab when it shows.
+    ///
+    pub(crate) fn record_event(&mut self, event: Event) {
+        if event.kind != EventKind::Load && self.current() == Tab::Alarms {
+            self.refreshed = None;
+        }
+        match event.kind {
+            EventKind::Alert => {
+                self.alerts.retain(|alert| alert.name != event.name);
+                self.alerts.insert(0, event);
+            }
+            EventKind::Cleared => self.alerts.retain(|alert| alert.name != event.name),
+            EventKind::Load | EventKind::Summary => {}
+        }
+    }
+
+    ///
+    /// Reload the history, statistics, reports, battery health and alarms when a tab showing them is
+    /// selected and the data is older than the refresh interval.
+    ///
+    pub(crate) fn refresh(&mut self, source: &dyn HistorySource) {
+        if self.current() == Tab::Live
+            || self
+                .refreshed
+                .is_some_and(|r| r.elapsed() < REFRESH_INTERVAL)
+        {
+            return;
+        }
+        self.refreshed = Some(Instant::now());
+        let (_, range) = HISTORY_RANGES[self.history_range];
+        let to = Local::now().timestamp();
+        let from = to - range;
+        let datapoints = source.history(from, to, CHART_POINTS).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        let series = |value: fn(&DataPoint) -> f64| -> Vec<(f64, f64)> {
+            datapoints
+                .iter()
+                .map(|dp| (dp.get_time() as f64, value(dp)))
+                .collect()
+        };
+        let power = |dp: &DataPoint| dp.get_battery_voltage() * dp.get_charge_current();
+        let peak_power = datapoints.iter().map(power).fold(0.0, f64::max);
+        let weather = source.weather(from, to).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        let (pv_power, clear_sky) = match &self.array {
+            Some(array) => {
+                let step = ((to - from) / CHART_POINTS as i64).max(60) as usize;
+                let clear_sky = (from..=to)
+                    .step_by(step)
+                    .map(|time| (time as f64, array.clear_sky_watts(time)))
+                    .collect();
+                (series(power), clear_sky)
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+        self.history = History {
+            from,
+            to,
+            battery_voltage: series(DataPoint::get_battery_voltage),
+            pv_voltage: series(DataPoint::get_pv_voltage),
+            charge_current: series(DataPoint::get_charge_current),
+            load_current: series(DataPoint::get_load_current),
+            charge_power: datapoints
+                .iter()
+                .map(|dp| {
+                    let percent = 100.0 * power(dp) / peak_power.max(f64::EPSILON);
+                    (dp.get_time() as f64, percent)
+                })
+                .collect(),
+            peak_power,
+            cloud_cover: weather
+                .iter()
+                .filter_map(|hour| Some((hour.hour as f64, hour.cloud_cover_percent?)))
+                .collect(),
+            pv_power,
+            clear_sky,
+        };
+        let summary = |month: bool| {
+            period_bounds(month)
+                .ok()
+                .and_then(|(from, to)| source.summary(from, to).ok())
+        };
+        self.today = summary(false);
+        self.month = summary(true);
+        let period = ReportPeriod::ALL[self.report_period];
+        let tariff = self.tariff.as_ref();
+        self.report = report(source, period, period.default_count(), tariff).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        attribute(&self.loads, &mut self.report);
+        self.forecast =
+            report(source, ReportPeriod::Day, FORECAST_DAYS, None).unwrap_or_else(|e| {
+                warn!("{}", e);
+                Vec::new()
+            });
+        let health_from = to - TREND_DAYS as i64 * 24 * 3600;
+        self.battery_health = source.battery_health(health_from, to).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        self.cycles = source.cycle_counts().unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        self.records = source.records().unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        if let Some(id) = self.acknowledge.take() {
+            if let Err(e) = source.acknowledge_alert(id) {
+                warn!("{}", e);
+            }
+        }
+        self.alarms = source.alerts(from, to, ALARMS_LIMIT).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Vec::new()
+        });
+        self.selected_alarm = self.selected_alarm.min(self.alarms.len().saturating_sub(1));
+    }
+}
+
+///
+/// Draw the tab bar and the selected view. `live` holds the latest datapoints
+/// and the load switch and is `None` in offline mode.
+///
+pub(crate) fn draw<B: Backend>(
+    f: &mut Frame<B>,
+    title: &str,
+    state: &ViewState,
+    live: Option<(&RecentData, &LinkHealth, LoadToggleSwitch<'_>)>,
+) {
+    let size = f.size();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title_alignment(Alignment::Center)
+        .border_type(BorderType::Rounded);
+    f.render_widget(block, size);
+
+    let titles = state
+        .tabs
+        .iter()
+        .enumerate()
+        .map(|(i, t)| Spans::from(format!("{} {}", i + 1, t.title())))
+        .collect();
+    let tabs = Tabs::new(titles)
+        .select(state.selected)
+        .style(Style::default().fg(Color::White))
+        .highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .divider(symbols::line::VERTICAL);
+    f.render_widget(
+        tabs,
+        Rect::new(size.x + 12, size.y + 1, size.width.saturating_sub(14), 1),
+    );
+
+    let content = Layout::default()
+        .margin(2)
+        .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(size)[1];
+    match state.current() {
+        Tab::Live => {
+            if let Some((recent, health, _)) = live {
+                live_view(f, recent, health, state);
+            }
+        }
+        Tab::History => history_view(f, content, state),
+        Tab::Stats => stats_view(f, content, state),
+        Tab::Reports => reports_view(f, content, state),
+        Tab::Forecast => forecast_view(f, content, state),
+        Tab::Battery => battery_view(f, content, state),
+        Tab::Alarms => alarms_view(f, content, state),
+    }
+    // The load switch stays clickable on every tab while the datalogger runs.
+    if let Some((_, _, load_switch)) = live {
+        f.render_widget(load_switch, Rect::new(size.x, size.y, 10, 2));
+    }
+    status_bar(f, size);
+}
+
+///
+/// The last duration of every measured stage, on the bottom border.
+///
+fn status_bar<B: Backend>(f: &mut Frame<B>, size: Rect) {
+    if size.height < 3 {
+        return;
+    }
+    let mut spans = Vec::new();
+    for stage in Stage::ALL {
+        let timing = TIMINGS.stage(stage);
+        if timing.count == 0 {
+            continue;
+        }
+        spans.push(Span::styled(
+            format!(" {} ", stage.name()),
+            Style::default().fg(Color::Green),
+        ));
+        spans.push(Span::raw(format!("{:.1} ms ", timing.last_ms)));
+    }
+    let area = Rect::new(
+        size.x + 2,
+        size.y + size.height - 1,
+        size.width.saturating_sub(4),
+        1,
+    );
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+}
+
+fn live_view<B: Backend>(
+    f: &mut Frame<B>,
+    recent: &RecentData,
+    health: &LinkHealth,
+    state: &ViewState,
+) {
+    let thresholds = &state.thresholds;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(4)
+        .constraints(
+            [
+                Constraint::Length(13 + 2 * state.charge_targets.is_some() as u16),
+                Constraint::Percentage(50),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(f.size());
+    let datapoint = recent.latest().unwrap_or_default();
+
+    let top_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(100), Constraint::Percentage(100)].as_ref())
+        .split(chunks[0]);
+    let load = if datapoint.get_load_onoff() < 1.0 {
+        "Off"
+    } else {
+        "On"
+    };
+    let load_current = datapoint.get_load_current().to_string();
+    let battery_voltage = datapoint.get_battery_voltage().to_string();
+    let battery_temp = datapoint.get_battery_temp().to_string();
+    let pv_voltage = datapoint.get_pv_voltage().to_string();
+    let charging = if datapoint.get_charging() < 1.0 {
+        "No"
+    } else {
+        "Yes"
+    };
+    let charge_current = datapoint.get_charge_current().to_string();
+    let over_discharge = datapoint.get_over_discharge().to_string();
+    let battery_max = datapoint.get_battery_max().to_string();
+    let battery_full = if datapoint.get_battery_full() < 1.0 {
+        "No"
+    } else {
+        "Yes"
+    };
+    let time = datapoint
+        .get_time_formatted()
+        .unwrap_or_else(|e| e.to_string());
+    let value_style = |out_of_range: bool| {
+        Style::default().fg(if out_of_range {
+            Color::Red
+        } else {
+            Color::White
+        })
+    };
+    let battery_voltage_style = value_style(
+        datapoint.get_battery_voltage() < thresholds.battery_low_voltage
+            || datapoint.get_battery_voltage() > thresholds.battery_high_voltage,
+    );
+    let battery_temp_style =
+        value_style(datapoint.get_battery_temp() > thresholds.battery_max_temp);
+    let load_current_style =
+        value_style(datapoint.get_load_current() > thresholds.load_max_current);
+    let mut rows = vec![
+        Row::new(vec![
+            Cell::from("Load: ").style(Style::default().fg(Color::Green)),
+            Cell::from(load),
+        ]),
+        Row::new(vec![
+            Cell::from("Load Current: ").style(Style::default().fg(Color::Green)),
+            Cell::from(load_current).style(load_current_style),
+        ]),
+        Row::new(vec![
+            Cell::from("Battery Voltage: ").style(Style::default().fg(Color::Green)),
+            Cell::from(battery_voltage).style(battery_voltage_style),
+        ]),
+        Row::new(vec![
+            Cell::from("Battery Full: ").style(Style::default().fg(Color::Green)),
+            Cell::from(battery_full),
+        ]),
+        Row::new(vec![
+            Cell::from("Battery Temp: ").style(Style::default().fg(Color::Green)),
+            Cell::from(battery_temp).style(battery_temp_style),
+        ]),
+        Row::new(vec![
+            Cell::from("PV Voltage: ").style(Style::default().fg(Color::Green)),
+            Cell::from(pv_voltage),
+        ]),
+        Row::new(vec![
+            Cell::from("Charging: ").style(Style::default().fg(Color::Green)),
+            Cell::from(charging),
+        ]),
+        Row::new(vec![
+            Cell::from("Charge Current: ").style(Style::default().fg(Color::Green)),
+            Cell::from(charge_current),
+        ]),
+        Row::new(vec![
+            Cell::from("Over Discharge: ").style(Style::default().fg(Color::Green)),
+            Cell::from(over_discharge),
+        ]),
+        Row::new(vec![
+            Cell::from("Battery Max: ").style(Style::default().fg(Color::Green)),
+            Cell::from(battery_max),
+        ]),
+        Row::new(vec![
+            Cell::from("Timestamp: ").style(Style::default().fg(Color::Green)),
+            Cell::from(time),
+        ]),
+        Row::new(vec![
+            Cell::from("Status: ").style(Style::default().fg(Color::Green)),
+            match (health.alive(), health.last_error(), health.last_restart()) {
+                (false, _, _) => {
+                    Cell::from("Datalogger stopped, see the log").style(value_style(true))
+                }
+                (true, Some(error), _) => Cell::from(error).style(value_style(true)),
+                (true, None, Some(restart)) => {
+                    Cell::from(format!("OK, {}", restart)).style(Style::default().fg(Color::Yellow))
+                }
+                (true, None, None) => Cell::from("OK"),
+            },
+        ]),
+    ];
+    if let Some(targets) = &state.charge_targets {
+        let (absorption, float) = targets.at(datapoint.get_battery_temp());
+        // The controller holds the absorption voltage while charging, and
+        // the float voltage once the battery is full.
+        let full = datapoint.get_battery_full() >= 1.0;
+        let charging = datapoint.get_charging() >= 1.0;
+        for (label, target, active) in [
+            ("Absorption Target: ", absorption, charging && !full),
+            ("Float Target: ", float, full),
+        ] {
+            let style = Style::default().fg(match active {
+                true => Color::Yellow,
+                false => Color::White,
+            });
+            rows.push(Row::new(vec![
+                Cell::from(label).style(Style::default().fg(Color::Green)),
+                Cell::from(format!(
+                    "{:.2} V, battery {:+.2} V",
+                    target,
+                    datapoint.get_battery_voltage() - target
+                ))
+                .style(style),
+            ]));
+        }
+    }
+    let table = Table::new(rows)
+        .style(Style::default().fg(Color::White))
+        .block(Block::default().title("MPPT Data"))
+        .widths(&[
+            Constraint::Length(25),
+            Constraint::Length(50),
+            Constraint::Length(10),
+        ])
+        .column_spacing(1);
+    f.render_widget(table, top_chunks[0]);
+    let battery_voltage = recent.map(|dp| (dp.get_time() as f64, dp.get_battery_voltage()));
+    let recent = History {
+        from: battery_voltage.first().map_or(0, |&(time, _)| time as i64),
+        to: datapoint.get_time(),
+        pv_voltage: recent.map(|dp| (dp.get_time() as f64, dp.get_pv_voltage())),
+        battery_voltage,
+        ..History::default()
+    };
+    let voltages = [
+        ("Battery V", Color::Green, recent.battery_voltage.as_slice()),
+        ("PV V", Color::Yellow, recent.pv_voltage.as_slice()),
+    ];
+    render_chart(
+        f,
+        chunks[1],
+        "Recent voltages",
+        (recent.from, recent.to),
+        &voltages,
+    );
+    alerts_panel(f, chunks[2], &state.alerts);
+}
+
+fn alerts_panel<B: Backend>(f: &mut Frame<B>, area: Rect, alerts: &[Event]) {
+    let rows: Vec<Row> = if alerts.is_empty() {
+        vec![Row::new(vec![Cell::from("No active alerts.")])]
+    } else {
+        alerts
+            .iter()
+            .map(|alert| {
+                let color = match alert.severity {
+                    Severity::Critical => Color::Red,
+                    Severity::Warning => Color::Yellow,
+                    Severity::Info => Color::Cyan,
+                };
+                Row::new(vec![
+                    Cell::from(format_time(alert.timestamp)),
+                    Cell::from(alert.severity.name()).style(Style::default().fg(color)),
+                    Cell::from(alert.message.as_str()),
+                ])
+            })
+            .collect()
+    };
+    let table = Table::new(rows)
+        .style(Style::default().fg(Color::White))
+        .block(Block::default().title("Alerts"))
+        .widths(&[
+            Constraint::Length(11),
+            Constraint::Length(8),
+            Constraint::Percentage(100),
+        ])
+        .column_spacing(1);
+    f.render_widget(table, area);
+}
+
+fn history_view<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let history = &state.history;
+    let charts = 2 + !history.cloud_cover.is_empty() as u32 + !history.clear_sky.is_empty() as u32;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Ratio(1, charts); charts as usize])
+        .split(area);
+    let (label, _) = HISTORY_RANGES[state.history_range];
+    let voltages = [
+        (
+            "Battery V",
+            Color::Green,
+            history.battery_voltage.as_slice(),
+        ),
+        ("PV V", Color::Yellow, history.pv_voltage.as_slice()),
+    ];
+    let currents = [
+        ("Charge A", Color::Cyan, history.charge_current.as_slice()),
+        ("Load A", Color::Magenta, history.load_current.as_slice()),
+    ];
+    let range = (history.from, history.to);
+    render_chart(
+        f,
+        chunks[0],
+        &format!("Voltages, last {} ([ ] to change)", label),
+        range,
+        &voltages,
+    );
+    render_chart(f, chunks[1], "Currents", range, &currents);
+    if !history.cloud_cover.is_empty() {
+        let weather = [
+            ("Charge %", Color::Yellow, history.charge_power.as_slice()),
+            ("Cloud %", Color::Gray, history.cloud_cover.as_slice()),
+        ];
+        let title = format!(
+            "Charge power in % of {:.0} W (yellow) and cloud cover % (gray)",
+            history.peak_power
+        );
+        render_chart(f, chunks[2], &title, range, &weather);
+    }
+    if !history.clear_sky.is_empty() {
+        let peak = |series: &[(f64, f64)]| series.iter().map(|(_, w)| *w).fold(0.0, f64::max);
+        let output = [
+            ("Charge W", Color::Yellow, history.pv_power.as_slice()),
+            ("Clear sky W", Color::Gray, history.clear_sky.as_slice()),
+        ];
+        let title = format!(
+            "Charge power (yellow), peak {:.0} W, and clear-sky output of the array (gray), peak {:.0} W",
+            peak(&history.pv_power),
+            peak(&history.clear_sky)
+        );
+        render_chart(f, chunks[chunks.len() - 1], &title, range, &output);
+    }
+}
+
+fn render_chart<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    title: &str,
+    (from, to): (i64, i64),
+    series: &[Series<'_>],
+) {
+    let (min, max) = series
+        .iter()
+        .flat_map(|(_, _, data)| data.iter().map(|(_, v)| *v))
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(v), hi.max(v))
+        });
+    let (min, max) = if min.is_finite() {
+        (min.min(0.0), max + (max - min).abs() * 0.1 + 0.1)
+    } else {
+        (0.0, 1.0)
+    };
+    let datasets = series
+        .iter()
+        .map(|(name, color, data)| {
+            Dataset::default()
+                .name(*name)
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(*color))
+                .data(data)
+        })
+        .collect();
+    let chart = Chart::new(datasets)
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .x_axis(
+            Axis::default()
+                .bounds([from as f64, to as f64])
+                .labels(vec![
+                    Span::raw(format_time(from)),
+                    Span::raw(format_time(to)),
+                ]),
+        )
+        .y_axis(Axis::default().bounds([min, max]).labels(vec![
+            Span::raw(format!("{:.1}", min)),
+            Span::raw(format!("{:.1}", max)),
+        ]));
+    f.render_widget(chart, area);
+}
+
+fn stats_view<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let value = |summary: Option<&Summary>, get: fn(&Summary) -> String| {
+        summary.map_or(String::from("-"), get)
+    };
+    let rows: [StatRow; 9] = [
+        ("Energy harvested", |s| {
+            format!("{:.1} Wh", s.energy_harvested_wh)
+        }),
+        ("Energy forecast", |s| {
+            s.energy_forecast_wh
+                .map_or(String::from("-"), |wh| format!("{:.1} Wh", wh))
+        }),
+        ("Energy consumed", |s| {
+            format!("{:.1} Wh", s.energy_consumed_wh)
+        }),
+        ("Efficiency", |s| percent(s.efficiency_percent)),
+        ("Charge hours", |s| format!("{:.2} h", s.charge_hours)),
+        ("Battery min", |s| volts(s.battery_voltage_min)),
+        ("Battery max", |s| volts(s.battery_voltage_max)),
+        ("PV max", |s| volts(s.pv_voltage_max)),
+        ("Samples", |s| s.samples.to_string()),
+    ];
+    let table = Table::new(rows.iter().map(|(label, get)| {
+        Row::new(vec![
+            Cell::from(*label).style(Style::default().fg(Color::Green)),
+            Cell::from(value(state.today.as_ref(), *get)),
+            Cell::from(value(state.month.as_ref(), *get)),
+        ])
+    }))
+    .header(Row::new(vec!["", "Today", "This month"]).style(Style::default().fg(Color::Yellow)))
+    .style(Style::default().fg(Color::White))
+    .block(Block::default().title("Statistics").borders(Borders::ALL))
+    .widths(&[
+        Constraint::Length(20),
+        Constraint::Length(16),
+        Constraint::Length(16),
+    ])
+    .column_spacing(1);
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(56), Constraint::Min(0)].as_ref())
+        .split(area);
+    f.render_widget(table, chunks[0]);
+    records_view(f, chunks[1], state);
+}
+
+fn records_view<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let month = records::month(Local::now().timestamp()).unwrap_or_default();
+    let record = |period: &str, kind: &RecordKind| {
+        state
+            .records
+            .iter()
+            .find(|r| r.period == period && r.name == kind.name)
+            .map_or(String::from("-"), |r| {
+                format!("{:.2} {} {}", r.value, kind.unit, format_time(r.time))
+            })
+    };
+    let rows = RECORD_KINDS.iter().map(|kind| {
+        Row::new(vec![
+            Cell::from(kind.label).style(Style::default().fg(Color::Green)),
+            Cell::from(record(ALL_TIME, kind)),
+            Cell::from(record(&month, kind)),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(
+            Row::new(vec!["", "All time", "This month"]).style(Style::default().fg(Color::Yellow)),
+        )
+        .style(Style::default().fg(Color::White))
+        .block(Block::default().title("Records").borders(Borders::ALL))
+        .widths(&[
+            Constraint::Length(14),
+            Constraint::Length(20),
+            Constraint::Length(20),
+        ])
+        .column_spacing(1);
+    f.render_widget(table, area);
+}
+
+fn reports_view<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let period = ReportPeriod::ALL[state.report_period];
+    let rows = state.report.iter().enumerate().map(|(i, row)| {
+        let summary = &row.summary;
+        // The rows are the most recent first, compare with the period before.
+        let before = state.report.get(i + 1);
+        let dropped = match (
+            summary.efficiency_percent,
+            before.and_then(|row| row.summary.efficiency_percent),
+        ) {
+            (Some(efficiency), Some(before)) => efficiency < before - EFFICIENCY_DROP,
+            _ => false,
+        };
+        let efficiency_color = match dropped {
+            true => Color::Red,
+            false => Color::White,
+        };
+        let mut cells = vec![
+            Cell::from(row.period.as_str()).style(Style::default().fg(Color::Green)),
+            Cell::from(format!("{:.1} Wh", summary.energy_harvested_wh)),
+            Cell::from(format!("{:.1} Wh", summary.energy_consumed_wh)),
+            Cell::from(percent(summary.efficiency_percent))
+                .style(Style::default().fg(efficiency_color)),
+            Cell::from(format!("{:.2} h", summary.charge_hours)),
+            Cell::from(volts(summary.battery_voltage_min)),
+            Cell::from(volts(summary.battery_voltage_max)),
+            Cell::from(volts(summary.pv_voltage_max)),
+        ];
+        for load in &row.loads {
+            cells.push(Cell::from(match load.rated_hours {
+                Some(hours) => format!("{:.1} Wh {:.1} h", load.energy_wh, hours),
+                None => format!("{:.1} Wh", load.energy_wh),
+            }));
+        }
+        if let (Some(value), Some(currency)) = (row.value, &row.currency) {
+            cells.push(Cell::from(format!("{:.2} {}", value, currency)));
+        }
+        Row::new(cells)
+    });
+    let mut header = vec![
+        "Period",
+        "Harvested",
+        "Consumed",
+        "Efficiency",
+        "Charge",
+        "Battery min",
+        "Battery max",
+        "PV max",
+    ];
+    let mut widths = vec![
+        Constraint::Length(10),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(10),
+        Constraint::Length(8),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(8),
+    ];
+    for load in &state.loads {
+        header.push(load.name());
+        widths.push(Constraint::Length(18));
+    }
+    if state.tariff.is_some() {
+        header.push("Value");
+        widths.push(Constraint::Length(14));
+    }
+    let table = Table::new(rows)
+        .header(Row::new(header).style(Style::default().fg(Color::Yellow)))
+        .style(Style::default().fg(Color::White))
+        .block(
+            Block::default()
+                .title(format!("{} report ([ ] to change)", period.name()))
+                .borders(Borders::ALL),
+        )
+        .widths(&widths)
+        .column_spacing(1);
+    f.render_widget(table, area);
+}
+
+///
+/// The expected and actual harvest of the last days as pairs of bars, and how
+/// well the forecast matched over the complete ones.
+///
+fn forecast_view<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(0)].as_ref())
+        .split(area);
+    // Today is still being harvested.
+    let summary = match forecast_accuracy(state.forecast.get(1..).unwrap_or_default()) {
+        Some(ForecastAccuracy {
+            days,
+            accuracy_percent,
+            achieved_percent,
+        }) => vec![
+            Spans::from(vec![
+                Span::raw(format!(
+                    "Accuracy: {:.0}% over {} days   Harvest: ",
+                    accuracy_percent, days
+                )),
+                Span::styled(
+                    format!("{:.0}% of the forecast", achieved_percent),
+                    Style::default().fg(achieved_color(achieved_percent)),
+                ),
+            ]),
+            Spans::from(
+                "Clouds lower the accuracy, a harvest well below the forecast on clear days points to the system",
+            ),
+        ],
+        None => vec![Spans::from(
+            "No forecast stored yet, see the [forecast] section of the config",
+        )],
+    };
+    f.render_widget(
+        Paragraph::new(summary).block(
+            Block::default()
+                .title(format!(
+                    "Forecast accuracy, last {} days",
+                    FORECAST_DAYS - 1
+                ))
+                .borders(Borders::ALL),
+        ),
+        chunks[0],
+    );
+    let scale = state
+        .forecast
+        .iter()
+        .map(|row| {
+            let summary = &row.summary;
+            summary
+                .energy_forecast_wh
+                .unwrap_or(0.0)
+                .max(summary.energy_harvested_wh)
+        })
+        .fold(0.0, f64::max);
+    // Room for the day, the series name and the value beside the bars.
+    let width = chunks[1].width.saturating_sub(2 + 11 + 9 + 16) as f64;
+    let bar = |wh: f64| "█".repeat((wh / scale.max(f64::EPSILON) * width).round() as usize);
+    let mut lines = Vec::with_capacity(2 * state.forecast.len());
+    for row in &state.forecast {
+        let summary = &row.summary;
+        let harvested = summary.energy_harvested_wh;
+        // Days from before the logger or the forecast ran.
+        if summary.energy_forecast_wh.is_none() && harvested <= 0.0 {
+            continue;
+        }
+        let (expected, achieved) = match summary.energy_forecast_wh {
+            Some(forecast) => (
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{:<11}", row.period),
+                        Style::default().fg(Color::Green),
+                    ),
+                    Span::raw("Forecast "),
+                    Span::styled(bar(forecast), Style::default().fg(Color::Gray)),
+                    Span::raw(format!(" {:.0} Wh", forecast)),
+                ]),
+                Some(100.0 * harvested / forecast.max(f64::EPSILON)),
+            ),
+            None => (
+                Spans::from(vec![
+                    Span::styled(
+                        format!("{:<11}", row.period),
+                        Style::default().fg(Color::Green),
+                    ),
+                    Span::raw("Forecast -"),
+                ]),
+                None,
+            ),
+        };
+        lines.push(expected);
+        lines.push(Spans::from(vec![
+            Span::raw(format!("{:<11}Actual   ", "")),
+            Span::styled(
+                bar(harvested),
+                Style::default().fg(achieved.map_or(Color::Yellow, achieved_color)),
+            ),
+            Span::raw(format!(" {:.0} Wh {}", harvested, percent(achieved))),
+        ]));
+    }
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title("Expected (gray) vs. actual harvest per day")
+                .borders(Borders::ALL),
+        ),
+        chunks[1],
+    );
+}
+
+///
+/// Green from 80% of the forecast harvested, yellow from 50%, else red.
+///
+fn achieved_color(percent: f64) -> Color {
+    match percent {
+        p if p >= 80.0 => Color::Green,
+        p if p >= 50.0 => Color::Yellow,
+        _ => Color::Red,
+    }
+}
+
+fn battery_view<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let days = &state.battery_health;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(3 + state.cycles.len() as u16),
+                Constraint::Percentage(50),
+                Constraint::Percentage(50),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    let latest = |value: fn(&HealthDay) -> Option<f64>, unit: &str| {
+        days.iter()
+            .rev()
+            .find_map(value)
+            .map_or(String::from("-"), |v| format!("{:.1} {}", v, unit))
+    };
+    let (score, color) = match health::score(days) {
+        Some(score) if score >= 80.0 => (format!("{:.0} of 100", score), Color::Green),
+        Some(score) if score >= 50.0 => (format!("{:.0} of 100", score), Color::Yellow),
+        Some(score) => (format!("{:.0} of 100", score), Color::Red),
+        None => (String::from("not enough data yet"), Color::White),
+    };
+    let mut summary = vec![Spans::from(vec![
+        Span::raw("Health score: "),
+        Span::styled(score, Style::default().fg(color)),
+        Span::raw(format!(
+            "   Sag: {}   Self-discharge: {}",
+            latest(|d| d.sag_mohm, "mOhm"),
+            latest(|d| d.self_discharge_mv_h, "mV/h")
+        )),
+    ])];
+    for device in &state.cycles {
+        let by_depth: Vec<String> = device
+            .by_depth
+            .iter()
+            .map(|class| format!("{} {}", class.depth, class.cycles))
+            .collect();
+        let capacity = device
+            .estimated_capacity_ah
+            .map_or(String::new(), |capacity| {
+                format!(", usable capacity {:.1} Ah", capacity)
+            });
+        summary.push(Spans::from(format!(
+            "Cycles of {}: {} ({}), {:.1} equivalent full{}",
+            device.device,
+            device.cycles,
+            by_depth.join(", "),
+            device.equivalent_full_cycles,
+            capacity
+        )));
+    }
+    f.render_widget(
+        Paragraph::new(summary).block(
+            Block::default()
+                .title(format!("Battery health, last {} weeks", TREND_DAYS / 7))
+                .borders(Borders::ALL),
+        ),
+        chunks[0],
+    );
+    let series = |value: fn(&HealthDay) -> Option<f64>| -> Vec<(f64, f64)> {
+        days.iter()
+            .filter_map(|d| Some((d.day as f64, value(d)?)))
+            .collect()
+    };
+    let sag = series(|d| d.sag_mohm);
+    let self_discharge = series(|d| d.self_discharge_mv_h);
+    let range = (
+        state.history.to - TREND_DAYS as i64 * 24 * 3600,
+        state.history.to,
+    );
+    render_chart(
+        f,
+        chunks[1],
+        "Voltage sag per amp of load (rises with internal resistance)",
+        range,
+        &[("Sag mOhm", Color::Cyan, sag.as_slice())],
+    );
+    render_chart(
+        f,
+        chunks[2],
+        "Overnight self-discharge",
+        range,
+        &[("mV/h", Color::Magenta, self_discharge.as_slice())],
+    );
+}
+
+fn alarms_view<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(LIMITS.len() as u16 + 2),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    limits_panel(f, layout[0], state);
+    let area = layout[1];
+    let (label, _) = HISTORY_RANGES[state.history_range];
+    let rows = state.alarms.iter().map(|alarm| {
+        let color = match alarm.severity.as_str() {
+            "critical" => Color::Red,
+            "warning" => Color::Yellow,
+            _ => Color::Cyan,
+        };
+        let duration = match alarm.duration_s {
+            Some(seconds) => format_duration(seconds),
+            None => String::from("active"),
+        };
+        let acknowledged = alarm.acknowledged.map(format_time).unwrap_or_default();
+        Row::new(vec![
+            Cell::from(format_time(alarm.raised)),
+            Cell::from(duration),
+            Cell::from(alarm.severity.as_str()).style(Style::default().fg(color)),
+            Cell::from(alarm.rule.as_str()),
+            Cell::from(alarm.message.as_str()),
+            Cell::from(acknowledged),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(
+            Row::new(vec![
+                "Raised", "Duration", "Severity", "Rule", "Message", "Acked",
+            ])
+            .style(Style::default().fg(Color::Yellow)),
+        )
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(
+            Block::default()
+                .title(format!(
+                    "Alarms, last {} (Up/Down to select, a to acknowledge, [ ] to change)",
+                    label
+                ))
+                .borders(Borders::ALL),
+        )
+        .widths(&[
+            Constraint::Length(11),
+            Constraint::Length(9),
+            Constraint::Length(8),
+            Constraint::Length(24),
+            Constraint::Percentage(100),
+            Constraint::Length(11),
+        ])
+        .column_spacing(1);
+    let mut table_state = TableState::default();
+    if !state.alarms.is_empty() {
+        table_state.select(Some(state.selected_alarm));
+    }
+    f.render_stateful_widget(table, area, &mut table_state);
+}
+
+fn limits_panel<B: Backend>(f: &mut Frame<B>, area: Rect, state: &ViewState) {
+    let (selected, mut thresholds) = match &state.editing {
+        Some((selected, edited)) => (Some(*selected), edited.clone()),
+        None => (None, state.thresholds.clone()),
+    };
+    let rows = LIMITS.iter().enumerate().map(|(i, (label, unit, _))| {
+        let value = *limit_mut(&mut thresholds, i);
+        let row = Row::new(vec![label.to_string(), format!("{:.2} {}", value, unit)]);
+        match selected == Some(i) {
+            true => row.style(Style::default().add_modifier(Modifier::REVERSED)),
+            false => row,
+        }
+    });
+    let title = match (state.editing.is_some(), state.limits_editable) {
+        (true, _) => {
+            "Alert limits (Up/Down to select, +/- to change, Enter to save, Esc to cancel)"
+        }
+        (false, true) => "Alert limits (e to edit)",
+        (false, false) => "Alert limits",
+    };
+    let table = Table::new(rows)
+        .style(Style::default().fg(Color::White))
+        .block(Block::default().title(title).borders(Borders::ALL))
+        .widths(&[Constraint::Length(26), Constraint::Length(10)])
+        .column_spacing(1);
+    f.render_widget(table, area);
+}
+
+///
+/// The fixed alert limit listed at `index` of `LIMITS`.
+///
+fn limit_mut(thresholds: &mut AlertThresholds, index: usize) -> &mut f64 {
+    match index {
+        0 => &mut thresholds.battery_low_voltage,
+        1 => &mut thresholds.battery_high_voltage,
+        2 => &mut thresholds.battery_max_temp,
+        _ => &mut thresholds.load_max_current,
+    }
+}
+
+///
+/// Change a limit by a step, rounded so repeated steps do not drift.
+///
+fn adjust(limit: &mut f64, step: f64) {
+    *limit = ((*limit + step) * 100.0).round() / 100.0;
+}
+
+pub(crate) fn format_duration(seconds: i64) -> String {
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m {:02}s", s / 60, s % 60),
+        s => format!("{}h {:02}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+fn volts(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.2} V", v))
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.0}%", v))
+}
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.with_timezone(&Local).format("%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,119 @@
+use crate::config::{Config, WeatherConfig};
+use chrono::Local;
+use serde::Deserialize;
+use solar_core::database::Database;
+use solar_core::weather::WeatherHour;
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+//
+// Constants
+//
+/// Delay before fetching again after a failed request.
+const RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+//
+// Structs
+//
+#[derive(Debug, Deserialize)]
+struct OpenMeteoResponse {
+    hourly: OpenMeteoHourly,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenMeteoHourly {
+    /// Unix seconds of each value.
+    time: Vec<i64>,
+    /// Percent of the sky covered at the time.
+    cloud_cover: Vec<Option<f64>>,
+    /// Mean irradiance in W/m² of the hour before the time.
+    shortwave_radiation: Vec<Option<f64>>,
+}
+
+///
+/// Fetch the weather of the last days every `refresh_interval_min` from a
+/// background thread until `running` is cleared.
+///
+pub(crate) fn spawn_fetcher(
+    config: &Config,
+    running: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    let weather = config.weather.clone();
+    let database_config = config.database.clone();
+    info!(
+        "Fetching weather observations for {}, {} from open-meteo",
+        weather.latitude, weather.longitude
+    );
+    thread::Builder::new()
+        .name("weather".into())
+        .spawn(move || {
+            let mut database = match Database::new(&database_config) {
+                Ok(database) => database,
+                Err(e) => return error!("Not fetching weather observations: {}", e),
+            };
+            let interval = Duration::from_secs(weather.refresh_interval_min.max(1) * 60);
+            while running.load(Ordering::SeqCst) {
+                let delay = match fetch(&weather) {
+                    Ok(hours) => {
+                        debug!("Stored the weather of {} hours.", hours.len());
+                        database.store_weather(&hours);
+                        interval
+                    }
+                    Err(e) => {
+                        warn!("Weather: {}", e);
+                        RETRY_DELAY.min(interval)
+                    }
+                };
+                let start = Instant::now();
+                while running.load(Ordering::SeqCst) && start.elapsed() < delay {
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        })
+}
+
+///
+/// Request the weather of the last `past_days` and today, and keep the
+/// hours which are over.
+///
+fn fetch(config: &WeatherConfig) -> Result<Vec<WeatherHour>, String> {
+    let url = format!(
+        "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}\
+         &hourly=cloud_cover,shortwave_radiation&past_days={}&forecast_days=1\
+         &timeformat=unixtime",
+        config.latitude, config.longitude, config.past_days
+    );
+    let response: OpenMeteoResponse = ureq::get(&url)
+        .call()
+        .and_then(|mut r| r.body_mut().read_json())
+        .map_err(|e| e.to_string())?;
+    let hourly = response.hourly;
+    let mut hours = BTreeMap::new();
+    let empty = |hour| WeatherHour {
+        hour,
+        cloud_cover_percent: None,
+        irradiance_w_m2: None,
+    };
+    for (i, time) in hourly.time.iter().copied().enumerate() {
+        let start = hours.entry(time).or_insert_with(|| empty(time));
+        start.cloud_cover_percent = hourly.cloud_cover.get(i).copied().flatten();
+        // The irradiance is that of the hour ending at the time.
+        let before = hours
+            .entry(time - 3600)
+            .or_insert_with(|| empty(time - 3600));
+        before.irradiance_w_m2 = hourly.shortwave_radiation.get(i).copied().flatten();
+    }
+    let now = Local::now().timestamp();
+    Ok(hours
+        .into_values()
+        .filter(|hour| hour.hour + 3600 <= now)
+        .collect())
+}